@@ -0,0 +1,962 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::{self, File},
+    io::{Read, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    client::Client,
+    commands::registry::{AclCategories, Command, CommandFlags, CommandRegistry},
+    glob::glob_match,
+    resp::{string_to_bytes, RespType},
+};
+
+/// User every connection starts as, which can't be removed.
+pub const DEFAULT_USER: &str = "default";
+
+/// Default of `acllog-max-len`, how many entries are kept in the ACL log.
+pub const DEFAULT_LOG_MAX_LEN: usize = 128;
+
+/// How long a denied command is grouped with the same denied command in the ACL log, in milliseconds.
+const LOG_GROUPING_MAX_DELTA: u128 = 60_000;
+
+/// Allowed or denied commands, by category, command, or the first argument of a command.
+#[derive(Debug, Clone, PartialEq)]
+enum CommandRule {
+    All,
+    Category(&'static str, AclCategories),
+    Command(String),
+    Subcommand(String, String),
+}
+
+impl CommandRule {
+    fn matches(&self, command: &dyn Command, subcommand: Option<&str>) -> bool {
+        match self {
+            Self::All => true,
+            Self::Category(_, category) => command.acl_categories().contains(*category),
+            Self::Command(name) => name == command.name(),
+            Self::Subcommand(name, first_arg) => {
+                name == command.name() && subcommand == Some(first_arg.as_str())
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::All => "@all".into(),
+            Self::Category(name, _) => format!("@{}", name),
+            Self::Command(name) => name.clone(),
+            Self::Subcommand(name, first_arg) => format!("{}|{}", name, first_arg),
+        }
+    }
+}
+
+/// Key pattern a user can access, either to read, to write, or both.
+#[derive(Debug, Clone, PartialEq)]
+struct KeyPattern {
+    pattern: String,
+    read: bool,
+    write: bool,
+}
+
+impl KeyPattern {
+    fn describe(&self) -> String {
+        match (self.read, self.write) {
+            (true, true) => format!("~{}", self.pattern),
+            (true, false) => format!("%R~{}", self.pattern),
+            _ => format!("%W~{}", self.pattern),
+        }
+    }
+}
+
+/// A user of the ACL, with its passwords, and everything it's allowed to do.
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    pub name: String,
+    pub enabled: bool,
+    /// Whether any password is accepted for the user.
+    pub nopass: bool,
+    /// SHA256 of every password, in lowercase hex.
+    pub passwords: Vec<String>,
+    /// Allowed (`true`) and denied commands, in the order they're given, the last rule matching a command
+    /// decides whether the command is allowed. Every command is denied when there's no matching rule.
+    commands: Vec<(bool, CommandRule)>,
+    keys: Vec<KeyPattern>,
+    channels: Vec<String>,
+}
+
+impl User {
+    /// A new user, which is disabled, without any password, and not allowed to do anything.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            enabled: false,
+            nopass: false,
+            passwords: Vec::new(),
+            commands: Vec::new(),
+            keys: Vec::new(),
+            channels: Vec::new(),
+        }
+    }
+
+    /// The `default` user of a new server, allowed to do everything without any password.
+    pub fn new_default() -> Self {
+        let mut user = Self::new(DEFAULT_USER);
+        for rule in ["on", "nopass", "~*", "&*", "+@all"] {
+            // NOTE: Every rule here is known to be valid, and doesn't need the registry
+            let _ = user.apply_rule(rule, &CommandRegistry::empty());
+        }
+
+        user
+    }
+
+    /// Apply one rule of `ACL SETUSER` to the user, e.g. `on`, `>password`, `~key:*`, or `+@read`.
+    pub fn apply_rule(&mut self, rule: &str, commands: &CommandRegistry) -> Result<(), String> {
+        match rule.to_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => return self.apply_rule("~*", commands),
+            "resetkeys" => self.keys.clear(),
+            "allchannels" => return self.apply_rule("&*", commands),
+            "resetchannels" => self.channels.clear(),
+            "allcommands" => return self.apply_rule("+@all", commands),
+            "nocommands" => return self.apply_rule("-@all", commands),
+            "reset" => {
+                for rule in ["resetpass", "resetkeys", "resetchannels", "off", "-@all"] {
+                    self.apply_rule(rule, commands)?;
+                }
+            }
+            _ => return self.apply_pattern_rule(rule, commands),
+        }
+
+        Ok(())
+    }
+
+    /// Apply a rule with a value, e.g. a password, a pattern, or a command.
+    fn apply_pattern_rule(&mut self, rule: &str, commands: &CommandRegistry) -> Result<(), String> {
+        if let Some(password) = rule.strip_prefix('>') {
+            self.add_password(hash_password(password));
+        } else if let Some(hash) = rule.strip_prefix('#') {
+            self.add_password(validate_hash(hash)?);
+        } else if let Some(password) = rule.strip_prefix('<') {
+            self.remove_password(&hash_password(password))?;
+        } else if let Some(hash) = rule.strip_prefix('!') {
+            self.remove_password(&validate_hash(hash)?)?;
+        } else if rule.starts_with('~') || rule.starts_with('%') {
+            self.add_key_pattern(rule)?;
+        } else if let Some(pattern) = rule.strip_prefix('&') {
+            if self.channels.iter().any(|channel| channel == "*") {
+                return Err("Adding a pattern after the * pattern (or the 'allchannels' flag) is not valid and does not have any effect. Try 'resetchannels' to start with an empty list of channels".into());
+            }
+
+            if pattern == "*" {
+                self.channels.clear();
+            }
+            if !self.channels.iter().any(|channel| channel == pattern) {
+                self.channels.push(pattern.into());
+            }
+        } else if let Some(target) = rule.strip_prefix('+') {
+            self.add_command_rule(true, target, commands)?;
+        } else if let Some(target) = rule.strip_prefix('-') {
+            self.add_command_rule(false, target, commands)?;
+        } else {
+            return Err("Syntax error".into());
+        }
+
+        Ok(())
+    }
+
+    fn add_password(&mut self, hash: String) {
+        self.nopass = false;
+        if !self.passwords.contains(&hash) {
+            self.passwords.push(hash);
+        }
+    }
+
+    fn remove_password(&mut self, hash: &str) -> Result<(), String> {
+        let len = self.passwords.len();
+        self.passwords.retain(|password| password != hash);
+
+        if self.passwords.len() == len {
+            return Err(
+                "The password you are trying to remove from the user does not exist".into(),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn add_key_pattern(&mut self, rule: &str) -> Result<(), String> {
+        let (permissions, pattern) = match rule.strip_prefix('%') {
+            Some(rule) => rule.split_once('~').ok_or("Syntax error")?,
+            None => ("RW", &rule[1..]),
+        };
+
+        let permissions = permissions.to_uppercase();
+        if permissions.is_empty() || permissions.contains(|c| c != 'R' && c != 'W') {
+            return Err("Syntax error".into());
+        }
+
+        let key_pattern = KeyPattern {
+            pattern: pattern.into(),
+            read: permissions.contains('R'),
+            write: permissions.contains('W'),
+        };
+
+        if self.has_all_keys() {
+            return Err("Adding a pattern after the * pattern (or the 'allkeys' flag) is not valid and does not have any effect. Try 'resetkeys' to start with an empty list of patterns".into());
+        }
+
+        if key_pattern.pattern == "*" && key_pattern.read && key_pattern.write {
+            self.keys.clear();
+        }
+        if !self.keys.contains(&key_pattern) {
+            self.keys.push(key_pattern);
+        }
+
+        Ok(())
+    }
+
+    fn add_command_rule(
+        &mut self,
+        allow: bool,
+        target: &str,
+        commands: &CommandRegistry,
+    ) -> Result<(), String> {
+        let target = target.to_lowercase();
+        let unknown = || "Unknown command or category name in ACL".to_string();
+
+        let rule = if let Some(category) = target.strip_prefix('@') {
+            if category == "all" {
+                CommandRule::All
+            } else {
+                AclCategories::NAMES
+                    .iter()
+                    .find(|(name, _)| *name == category)
+                    .map(|(name, category)| CommandRule::Category(name, *category))
+                    .ok_or_else(unknown)?
+            }
+        } else if let Some((name, first_arg)) = target.split_once('|') {
+            if commands.get(name).is_none() || first_arg.is_empty() || first_arg.contains('|') {
+                return Err(unknown());
+            }
+
+            CommandRule::Subcommand(name.into(), first_arg.into())
+        } else {
+            commands.get(&target).ok_or_else(unknown)?;
+
+            CommandRule::Command(target)
+        };
+
+        if rule == CommandRule::All {
+            self.commands.clear();
+        } else {
+            self.commands.retain(|(_, existing)| *existing != rule);
+        }
+
+        // NOTE: Every command is denied by default, `-@all` is the same as no rule at all
+        if rule != CommandRule::All || allow {
+            self.commands.push((allow, rule));
+        }
+
+        Ok(())
+    }
+
+    fn has_all_keys(&self) -> bool {
+        self.keys
+            .iter()
+            .any(|key| key.pattern == "*" && key.read && key.write)
+    }
+
+    /// Whether the password is one of the passwords of the user, any password is accepted for `nopass`.
+    pub fn check_password(&self, password: &str) -> bool {
+        self.nopass || self.passwords.contains(&hash_password(password))
+    }
+
+    /// Check whether the user can run the command, with every key and channel in it.
+    pub fn check(&self, command: &dyn Command, command_arr: &[RespType]) -> Result<(), Denied> {
+        if !command.flags().contains(CommandFlags::NO_AUTH) {
+            let subcommand = match command_arr.get(1) {
+                Some(RespType::BulkString(arg)) => Some(arg.to_lowercase()),
+                _ => None,
+            };
+
+            let allowed = self
+                .commands
+                .iter()
+                .rev()
+                .find(|(_, rule)| rule.matches(command, subcommand.as_deref()))
+                .is_some_and(|(allow, _)| *allow);
+            if !allowed {
+                return Err(Denied::Command(command.name().into()));
+            }
+        }
+
+        self.check_keys(command, command_arr)?;
+        self.check_channels(command, command_arr)
+    }
+
+    fn check_keys(&self, command: &dyn Command, command_arr: &[RespType]) -> Result<(), Denied> {
+        let key_spec = command.key_spec();
+        if self.has_all_keys() || key_spec.flags.contains(&"NOT_KEY") {
+            return Ok(());
+        }
+
+        // NOTE: Following Redis, reading needs `ACCESS`, while modifying needs `INSERT`, `DELETE`, or `UPDATE`
+        let read = key_spec.flags.contains(&"ACCESS");
+        let write = key_spec
+            .flags
+            .iter()
+            .any(|flag| ["INSERT", "DELETE", "UPDATE"].contains(flag));
+
+        for position in key_spec.positions(command_arr).unwrap_or_default() {
+            if let Some(RespType::BulkString(key)) = command_arr.get(position) {
+                let allowed = self.keys.iter().any(|pattern| {
+                    (pattern.read || !read)
+                        && (pattern.write || !write)
+                        && glob_match(&pattern.pattern, key)
+                });
+
+                if !allowed {
+                    return Err(Denied::Key(key.clone()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_channels(
+        &self,
+        command: &dyn Command,
+        command_arr: &[RespType],
+    ) -> Result<(), Denied> {
+        if self.channels.iter().any(|channel| channel == "*") {
+            return Ok(());
+        }
+
+        let (channels, is_pattern) = match command.name() {
+            "publish" | "spublish" => (&command_arr[1..2.min(command_arr.len())], false),
+            "subscribe" | "ssubscribe" => (&command_arr[1..], false),
+            "psubscribe" => (&command_arr[1..], true),
+            _ => return Ok(()),
+        };
+
+        for channel in channels {
+            if let RespType::BulkString(channel) = channel {
+                // NOTE: A pattern subscribed to must be one of the patterns of the user as is
+                let allowed = self.channels.iter().any(|pattern| {
+                    if is_pattern {
+                        pattern == channel
+                    } else {
+                        glob_match(pattern, channel)
+                    }
+                });
+
+                if !allowed {
+                    return Err(Denied::Channel(channel.clone()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every rule of the user, the same way as Redis shows them in `ACL LIST`.
+    pub fn describe(&self) -> String {
+        let mut rules = vec![if self.enabled { "on" } else { "off" }.to_string()];
+
+        if self.nopass {
+            rules.push("nopass".into());
+        }
+        rules.extend(self.passwords.iter().map(|hash| format!("#{}", hash)));
+
+        rules.extend(self.keys.iter().map(KeyPattern::describe));
+        rules.push(self.describe_channels());
+        rules.push(self.describe_commands());
+
+        rules.join(" ")
+    }
+
+    pub fn describe_keys(&self) -> String {
+        self.keys
+            .iter()
+            .map(KeyPattern::describe)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn describe_channels(&self) -> String {
+        if self.channels.iter().any(|channel| channel == "*") {
+            return "&*".into();
+        }
+
+        let mut rules = vec!["resetchannels".to_string()];
+        rules.extend(self.channels.iter().map(|channel| format!("&{}", channel)));
+
+        rules.join(" ")
+    }
+
+    pub fn describe_commands(&self) -> String {
+        let mut rules = Vec::new();
+        if !matches!(self.commands.first(), Some((true, CommandRule::All))) {
+            rules.push("-@all".to_string());
+        }
+
+        rules.extend(
+            self.commands.iter().map(|(allow, rule)| {
+                format!("{}{}", if *allow { '+' } else { '-' }, rule.describe())
+            }),
+        );
+
+        rules.join(" ")
+    }
+}
+
+fn requires_auth(users: &BTreeMap<String, User>) -> bool {
+    users
+        .get(DEFAULT_USER)
+        .is_none_or(|user| !user.nopass || !user.enabled)
+}
+
+/// SHA256 of the password, in lowercase hex.
+pub fn hash_password(password: &str) -> String {
+    Sha256::digest(string_to_bytes(password))
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn validate_hash(hash: &str) -> Result<String, String> {
+    if hash.len() != 64 || !hash.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+        return Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters".into());
+    }
+
+    Ok(hash.into())
+}
+
+/// Why a command is denied to a user.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Denied {
+    Command(String),
+    Key(String),
+    Channel(String),
+}
+
+impl Denied {
+    /// Error message of the denial, without the `NOPERM` prefix.
+    pub fn message(&self, username: &str) -> String {
+        match self {
+            Self::Command(name) => format!(
+                "User {} has no permissions to run the '{}' command",
+                username, name
+            ),
+            Self::Key(_) => "No permissions to access a key".into(),
+            Self::Channel(_) => "No permissions to access a channel".into(),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self {
+            Self::Command(_) => "command",
+            Self::Key(_) => "key",
+            Self::Channel(_) => "channel",
+        }
+    }
+
+    fn object(&self) -> &str {
+        match self {
+            Self::Command(object) | Self::Key(object) | Self::Channel(object) => object,
+        }
+    }
+}
+
+/// Where a denied command is run, shown in the ACL log.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogContext {
+    Toplevel,
+    Multi,
+    Lua,
+}
+
+impl LogContext {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Toplevel => "toplevel",
+            Self::Multi => "multi",
+            Self::Lua => "lua",
+        }
+    }
+}
+
+/// A denied command or a failed authentication, shown by `ACL LOG`.
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub count: u64,
+    pub reason: &'static str,
+    pub context: &'static str,
+    pub object: String,
+    pub username: String,
+    pub client_info: String,
+    pub entry_id: u64,
+    /// Unix time in milliseconds of the first and the last time it happened.
+    pub created: u128,
+    pub updated: u128,
+}
+
+/// Users of the server, and the log of everything denied to them.
+#[derive(Debug)]
+pub struct Acl {
+    users: RwLock<BTreeMap<String, User>>,
+    /// `requirepass`, the password of the `default` user set with `CONFIG SET`, empty when there's none.
+    requirepass: Mutex<String>,
+    log: Mutex<VecDeque<LogEntry>>,
+    next_log_id: AtomicU64,
+    /// `acllog-max-len`, how many entries are kept in the ACL log.
+    pub log_max_len: AtomicUsize,
+    /// `aclfile`, where the users are loaded from and saved to, only set at startup.
+    pub file: Mutex<Option<PathBuf>>,
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        Self {
+            users: RwLock::new(BTreeMap::from([(DEFAULT_USER.into(), User::new_default())])),
+            requirepass: Mutex::new(String::new()),
+            log: Mutex::new(VecDeque::new()),
+            next_log_id: AtomicU64::new(0),
+            log_max_len: AtomicUsize::new(DEFAULT_LOG_MAX_LEN),
+            file: Mutex::new(None),
+        }
+    }
+}
+
+impl Acl {
+    pub fn user(&self, name: &str) -> Option<User> {
+        self.read_users().get(name).cloned()
+    }
+
+    /// Every user, sorted by name.
+    pub fn users(&self) -> Vec<User> {
+        self.read_users().values().cloned().collect()
+    }
+
+    /// Whether a new connection has to authenticate before running any command, following Redis, which is
+    /// whenever the `default` user needs a password, or is disabled.
+    pub fn requires_auth(&self) -> bool {
+        requires_auth(&self.read_users())
+    }
+
+    /// Whether the client is allowed to run commands, other than the ones to authenticate.
+    ///
+    /// NOTE: A client authenticated as a user that's removed afterward has to authenticate again.
+    pub fn is_authenticated(&self, client: &Client) -> bool {
+        match &client.user {
+            None => true,
+            Some(name) => {
+                let users = self.read_users();
+
+                users.contains_key(name) && (client.authenticated || !requires_auth(&users))
+            }
+        }
+    }
+
+    /// Authenticate the client as the user, the failure is recorded in the ACL log.
+    pub fn authenticate(&self, client: &mut Client, username: &str, password: &str) -> bool {
+        let valid = self
+            .read_users()
+            .get(username)
+            .is_some_and(|user| user.enabled && user.check_password(password));
+
+        if valid {
+            client.user = Some(username.into());
+            client.authenticated = true;
+        } else {
+            self.add_log_entry(client, "auth", LogContext::Toplevel, "AUTH", username);
+        }
+
+        valid
+    }
+
+    /// Check whether the client can run the command, recording the denial in the ACL log.
+    ///
+    /// Returns the error message without the `NOPERM` prefix, as the prefix depends on where it's run.
+    pub fn check(
+        &self,
+        client: &Client,
+        command: &dyn Command,
+        command_arr: &[RespType],
+        context: LogContext,
+    ) -> Result<(), String> {
+        let username = match &client.user {
+            Some(username) => username,
+            None => return Ok(()),
+        };
+
+        let result = match self.read_users().get(username) {
+            Some(user) => user.check(command, command_arr),
+            None => Err(Denied::Command(command.name().into())),
+        };
+
+        result.map_err(|denied| {
+            self.add_log_entry(client, denied.reason(), context, denied.object(), username);
+
+            denied.message(username)
+        })
+    }
+
+    /// Create the user if there isn't one, then apply every rule to it. Nothing is changed when any of
+    /// the rules is not valid.
+    pub fn set_user(
+        &self,
+        name: &str,
+        rules: &[&str],
+        commands: &CommandRegistry,
+    ) -> Result<(), String> {
+        let mut users = self.write_users();
+        let mut user = users.get(name).cloned().unwrap_or_else(|| User::new(name));
+
+        for rule in rules {
+            user.apply_rule(rule, commands)
+                .map_err(|err| format!("Error in ACL SETUSER modifier '{}': {}", rule, err))?;
+        }
+
+        users.insert(name.into(), user);
+
+        Ok(())
+    }
+
+    /// Remove every user given, returning how many users are removed.
+    pub fn delete_users(&self, names: &[&str]) -> Result<usize, String> {
+        if names.contains(&DEFAULT_USER) {
+            return Err("The 'default' user cannot be removed".into());
+        }
+
+        let mut users = self.write_users();
+
+        Ok(names
+            .iter()
+            .filter(|name| users.remove(**name).is_some())
+            .count())
+    }
+
+    pub fn requirepass(&self) -> String {
+        self.requirepass
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Set `requirepass`, replacing every password of the `default` user, or removing the need of any
+    /// password when it's empty.
+    pub fn set_requirepass(&self, password: &str) {
+        *self
+            .requirepass
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = password.into();
+
+        let mut users = self.write_users();
+        let user = users
+            .entry(DEFAULT_USER.into())
+            .or_insert_with(User::new_default);
+        user.passwords.clear();
+        if password.is_empty() {
+            user.nopass = true;
+        } else {
+            user.add_password(hash_password(password));
+        }
+    }
+
+    /// Newest entries of the ACL log first, up to `count` entries.
+    pub fn log_entries(&self, count: usize) -> Vec<LogEntry> {
+        self.lock_log().iter().take(count).cloned().collect()
+    }
+
+    pub fn reset_log(&self) {
+        self.lock_log().clear();
+    }
+
+    /// Record a denial in the ACL log, grouped with the same recent denial if there's any.
+    fn add_log_entry(
+        &self,
+        client: &Client,
+        reason: &'static str,
+        context: LogContext,
+        object: &str,
+        username: &str,
+    ) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let client_info = format!(
+            "id={} addr={} user={} resp={}",
+            client.id,
+            client.addr,
+            client.user.as_deref().unwrap_or(DEFAULT_USER),
+            client.protocol
+        );
+
+        let mut log = self.lock_log();
+        let similar = log.iter_mut().find(|entry| {
+            entry.reason == reason
+                && entry.context == context.name()
+                && entry.object == object
+                && entry.username == username
+                && now - entry.updated < LOG_GROUPING_MAX_DELTA
+        });
+
+        match similar {
+            Some(entry) => {
+                entry.count += 1;
+                entry.updated = now;
+                entry.client_info = client_info;
+            }
+            None => {
+                log.push_front(LogEntry {
+                    count: 1,
+                    reason,
+                    context: context.name(),
+                    object: object.into(),
+                    username: username.into(),
+                    client_info,
+                    entry_id: self.next_log_id.fetch_add(1, Ordering::Relaxed),
+                    created: now,
+                    updated: now,
+                });
+                log.truncate(self.log_max_len.load(Ordering::Relaxed));
+            }
+        }
+    }
+
+    /// Replace every user with the users in the ACL file, nothing is changed when any line is not valid.
+    ///
+    /// Every line is `user <name> [rule ...]`, the `default` user is created with every permission when it's
+    /// not in the file.
+    pub fn load_file(&self, commands: &CommandRegistry) -> Result<(), String> {
+        let path = self.file_path()?;
+
+        let mut content = Vec::new();
+        File::open(&path)
+            .and_then(|mut file| file.read_to_end(&mut content))
+            .map_err(|err| {
+                format!(
+                    "Error loading ACLs, opening file '{}': {}",
+                    path.display(),
+                    err
+                )
+            })?;
+
+        let mut users = BTreeMap::new();
+        for (index, line) in String::from_utf8_lossy(&content).lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |err: &str| format!("{}:{}: {}", path.display(), index + 1, err);
+
+            let mut words = line.split_whitespace();
+            if words.next() != Some("user") {
+                return Err(error("line should start with user keyword"));
+            }
+            let name = words.next().ok_or_else(|| error("missing user name"))?;
+            if users.contains_key(name) {
+                return Err(error(&format!("Duplicate user '{}' found", name)));
+            }
+
+            let mut user = User::new(name);
+            for rule in words {
+                user.apply_rule(rule, commands).map_err(|err| {
+                    error(&format!("Error in applying operation '{}': {}", rule, err))
+                })?;
+            }
+
+            users.insert(name.to_string(), user);
+        }
+
+        users
+            .entry(DEFAULT_USER.into())
+            .or_insert_with(User::new_default);
+        *self.write_users() = users;
+
+        Ok(())
+    }
+
+    /// Write every user into the ACL file, replacing the file only once it's fully written.
+    pub fn save_file(&self) -> Result<(), String> {
+        let path = self.file_path()?;
+
+        let content = self
+            .users()
+            .iter()
+            .map(|user| format!("user {} {}\n", user.name, user.describe()))
+            .collect::<String>();
+
+        let temp_path = path.with_extension("tmp");
+        File::create(&temp_path)
+            .and_then(|mut file| {
+                file.write_all(content.as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temp_path, &path))
+            .map_err(|err| {
+                println!("[ACL] Failed saving the ACL file: {:#?}", err);
+
+                "There was an error trying to save the ACLs. Please check the server logs for more information".to_string()
+            })
+    }
+
+    fn file_path(&self) -> Result<PathBuf, String> {
+        self.file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+            .ok_or_else(|| "This Redis instance is not configured to use an ACL file. You may want to specify users via the ACL SETUSER command and then issue a CONFIG REWRITE (assuming you have a Redis configuration file set) in order to store users in the Redis configuration.".to_string())
+    }
+
+    // NOTE: Users are only replaced once they're fully updated, so it's fine to keep on using them
+    fn read_users(&self) -> RwLockReadGuard<'_, BTreeMap<String, User>> {
+        self.users
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write_users(&self) -> RwLockWriteGuard<'_, BTreeMap<String, User>> {
+        self.users
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_log(&self) -> MutexGuard<'_, VecDeque<LogEntry>> {
+        self.log
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod acl_tests {
+    use super::{hash_password, User};
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    #[test]
+    fn applies_and_describes_rules() {
+        let server = Server::new();
+        let mut user = User::new("alice");
+
+        for rule in [
+            "on",
+            ">secret",
+            "%R~cache:*",
+            "&news:*",
+            "+@read",
+            "-hgetall",
+        ] {
+            user.apply_rule(rule, &server.commands).unwrap();
+        }
+        assert_eq!(
+            user.describe(),
+            format!(
+                "on #{} %R~cache:* resetchannels &news:* -@all +@read -hgetall",
+                hash_password("secret")
+            )
+        );
+        assert!(user.check_password("secret"));
+        assert!(!user.check_password("wrong"));
+
+        assert_eq!(
+            user.apply_rule("<wrong", &server.commands),
+            Err("The password you are trying to remove from the user does not exist".into())
+        );
+        assert_eq!(
+            user.apply_rule("+@nope", &server.commands),
+            Err("Unknown command or category name in ACL".into())
+        );
+
+        user.apply_rule("reset", &server.commands).unwrap();
+        assert_eq!(user.describe(), "off resetchannels -@all");
+    }
+
+    #[test]
+    fn checks_permissions_of_users() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        handle_commands(
+            command(&[
+                "ACL", "SETUSER", "alice", "on", ">secret", "~cache:*", "%R~ro:*", "-@all",
+                "+@read", "+set",
+            ]),
+            &mut client,
+            &server,
+        );
+        assert_eq!(
+            handle_commands(command(&["AUTH", "alice", "secret"]), &mut client, &server),
+            RespType::String("OK".into())
+        );
+
+        assert_eq!(
+            handle_commands(command(&["SET", "cache:a", "1"]), &mut client, &server),
+            RespType::String("OK".into())
+        );
+        assert_eq!(
+            handle_commands(command(&["GET", "ro:a"]), &mut client, &server),
+            RespType::Null
+        );
+        assert_eq!(
+            handle_commands(command(&["SET", "ro:a", "1"]), &mut client, &server),
+            RespType::Error("NOPERM No permissions to access a key".into())
+        );
+        assert_eq!(
+            handle_commands(command(&["DEL", "cache:a"]), &mut client, &server),
+            RespType::Error("NOPERM User alice has no permissions to run the 'del' command".into())
+        );
+        assert_eq!(server.acl.log_entries(10).len(), 2);
+    }
+
+    #[test]
+    fn requires_authentication_with_requirepass() {
+        let server = Server::new();
+        server.acl.set_requirepass("secret");
+        let mut client = connect(&server);
+
+        assert_eq!(
+            handle_commands(command(&["GET", "key"]), &mut client, &server),
+            RespType::Error("NOAUTH Authentication required.".into())
+        );
+        assert_eq!(
+            handle_commands(command(&["AUTH", "wrong"]), &mut client, &server),
+            RespType::Error("WRONGPASS invalid username-password pair or user is disabled.".into())
+        );
+        assert_eq!(
+            handle_commands(command(&["AUTH", "secret"]), &mut client, &server),
+            RespType::String("OK".into())
+        );
+        assert_eq!(
+            handle_commands(command(&["GET", "key"]), &mut client, &server),
+            RespType::Null
+        );
+    }
+}
@@ -0,0 +1,839 @@
+use std::{
+    error::Error,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, ErrorKind, Seek, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    client::Client,
+    commands::commands::handle_commands,
+    latency::LatencyMonitor,
+    rdb::{decode_snapshot, encode_snapshot},
+    resp::RespType,
+    server::Server,
+    storage::Snapshot,
+};
+
+/// When the append only file is flushed to the disk, configured with `appendfsync`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AppendFsync {
+    /// After every write command, before the reply is sent.
+    Always,
+    /// Once every second, in the background.
+    #[default]
+    EverySec,
+    /// Whenever the operating system decides to.
+    No,
+}
+
+impl AppendFsync {
+    pub fn parse(policy: &str) -> Option<Self> {
+        match policy.to_lowercase().as_str() {
+            "always" => Some(Self::Always),
+            "everysec" => Some(Self::EverySec),
+            "no" => Some(Self::No),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AppendFsync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Always => write!(f, "always"),
+            Self::EverySec => write!(f, "everysec"),
+            Self::No => write!(f, "no"),
+        }
+    }
+}
+
+/// Configuration of the append only file.
+#[derive(Debug, Clone)]
+pub struct AofConfig {
+    /// `appendonly`, whether write commands are appended at all.
+    pub enabled: bool,
+    /// `appenddirname`, directory of every append only file, inside of `dir`.
+    pub dirname: String,
+    /// `appendfilename`, prefix of the name of every append only file.
+    pub filename: String,
+    pub fsync: AppendFsync,
+    /// `aof-load-truncated`, load whatever can be loaded from a truncated append only file, instead of
+    /// refusing to start.
+    pub load_truncated: bool,
+}
+
+impl Default for AofConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dirname: "appendonlydir".into(),
+            filename: "appendonly.aof".into(),
+            fsync: AppendFsync::default(),
+            load_truncated: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AofFileKind {
+    /// Snapshot of every key when the append only file is rewritten, in the RDB format.
+    Base,
+    /// Write commands appended after the base, in RESP.
+    Incremental,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AofFile {
+    pub name: String,
+    pub seq: u64,
+    pub kind: AofFileKind,
+}
+
+/// List of files making the append only file, the same as the Redis 7 multi part append only file.
+///
+/// Every line of the manifest is in the format of `file <name> seq <seq> type <b|i|h>`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AofManifest {
+    pub base: Option<AofFile>,
+    pub incrementals: Vec<AofFile>,
+}
+
+impl AofManifest {
+    pub fn parse(manifest: &str) -> Result<Self, Box<dyn Error>> {
+        let mut parsed = Self::default();
+
+        for line in manifest.lines().filter(|line| !line.trim().is_empty()) {
+            let words: Vec<&str> = line.split_whitespace().collect();
+
+            let (mut name, mut seq, mut kind) = (None, None, None);
+            for pair in words.chunks(2) {
+                match pair {
+                    ["file", value] => name = Some(value.to_string()),
+                    ["seq", value] => seq = Some(value.parse::<u64>()?),
+                    ["type", value] => kind = Some(*value),
+                    _ => return Err(format!("invalid AOF manifest line: {}", line).into()),
+                }
+            }
+
+            let (name, seq) = name
+                .zip(seq)
+                .ok_or("AOF manifest line without file or seq")?;
+            match kind {
+                Some("b") => {
+                    parsed.base = Some(AofFile {
+                        name,
+                        seq,
+                        kind: AofFileKind::Base,
+                    })
+                }
+                Some("i") => parsed.incrementals.push(AofFile {
+                    name,
+                    seq,
+                    kind: AofFileKind::Incremental,
+                }),
+                // NOTE: History files are waiting to be deleted, they're not part of the append only file
+                Some("h") => {}
+                _ => return Err(format!("invalid AOF manifest file type: {}", line).into()),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &AofFile> {
+        self.base.iter().chain(self.incrementals.iter())
+    }
+
+    fn next_base(&self, filename: &str) -> AofFile {
+        let seq = self.base.as_ref().map_or(1, |base| base.seq + 1);
+
+        AofFile {
+            name: format!("{}.{}.base.rdb", filename, seq),
+            seq,
+            kind: AofFileKind::Base,
+        }
+    }
+
+    fn next_incremental(&self, filename: &str) -> AofFile {
+        let seq = self.incrementals.last().map_or(1, |last| last.seq + 1);
+
+        AofFile {
+            name: format!("{}.{}.incr.aof", filename, seq),
+            seq,
+            kind: AofFileKind::Incremental,
+        }
+    }
+}
+
+impl fmt::Display for AofManifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for file in self.files() {
+            let kind = match file.kind {
+                AofFileKind::Base => "b",
+                AofFileKind::Incremental => "i",
+            };
+
+            writeln!(f, "file {} seq {} type {}", file.name, file.seq, kind)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct AofState {
+    /// Directory of every append only file, from `dir` and `appenddirname` when the file was opened.
+    dir: PathBuf,
+    manifest: AofManifest,
+    /// Last incremental file, where write commands are appended, `None` while the append only file is off.
+    file: Option<File>,
+    fsync_pending: bool,
+}
+
+/// Append only file persistence, every write command is appended to the file, to be replayed on startup.
+///
+/// Commands are appended while the storage lock is still held, so the order of the commands in the file
+/// is the same order they're run. See [`Server::propagate`].
+#[derive(Debug, Default)]
+pub struct Aof {
+    pub config: RwLock<AofConfig>,
+    state: Mutex<AofState>,
+    rewrite_in_progress: AtomicBool,
+    /// Held for the whole fsync, so the flushed offset is only recorded once the file is really flushed.
+    fsync_lock: Mutex<()>,
+    /// Replication offset of the last write command flushed to the disk, used by `WAITAOF`.
+    fsynced_offset: AtomicU64,
+}
+
+impl Aof {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.read_config().enabled
+    }
+
+    pub fn is_rewriting(&self) -> bool {
+        self.rewrite_in_progress.load(Ordering::Relaxed)
+    }
+
+    /// Directory of every append only file, inside the directory of the snapshot.
+    pub fn dir(&self, server: &Server) -> PathBuf {
+        let dir = match server.persistence.config.read() {
+            Ok(config) => config.dir.clone(),
+            Err(err) => {
+                println!("[AOF] Got poisoned persistence config for read: {:#?}", err);
+
+                PathBuf::from(".")
+            }
+        };
+
+        dir.join(&self.read_config().dirname)
+    }
+
+    /// Append a write command to the file, flushed to the disk right away with `appendfsync always`.
+    ///
+    /// Slow writes and flushes are sampled as the `aof-write` and `aof-fsync-always` latency events.
+    pub fn append(&self, command: &[RespType], latency: &LatencyMonitor) {
+        let fsync = self.read_config().fsync;
+
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(err) => {
+                println!("[AOF] Got poisoned state: {:#?}", err);
+
+                return;
+            }
+        };
+
+        if let Some(file) = state.file.as_mut() {
+            let bytes = RespType::Array(command.to_vec()).serialize();
+
+            let started = Instant::now();
+            let result = file.write_all(&bytes).and_then(|_| {
+                latency.add_sample_if_needed("aof-write", started.elapsed());
+
+                match fsync {
+                    AppendFsync::Always => {
+                        let started = Instant::now();
+                        let result = file.sync_data();
+                        latency.add_sample_if_needed("aof-fsync-always", started.elapsed());
+
+                        result
+                    }
+                    _ => Ok(()),
+                }
+            });
+
+            match result {
+                Ok(_) => state.fsync_pending = fsync != AppendFsync::Always,
+                Err(err) => println!("[AOF] Failed to append to the file: {:#?}", err),
+            }
+        }
+    }
+
+    /// Replication offset of the last write command flushed to the disk.
+    pub fn fsynced_offset(&self) -> u64 {
+        self.fsynced_offset.load(Ordering::Relaxed)
+    }
+
+    /// Flush the appended commands to the disk, called every second for `appendfsync everysec`, or with
+    /// `force` whatever the policy is, e.g. for `WAITAOF`.
+    ///
+    /// `offset` is the replication offset when called, every write command up to it is appended already,
+    /// see [`Server::fsync_aof`].
+    pub fn fsync(&self, offset: u64, force: bool) {
+        let _fsync_locked = self
+            .fsync_lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let fsync = self.read_config().fsync;
+
+        let file = match self.state.lock() {
+            Ok(state) if state.file.is_none() => return,
+            Ok(mut state) if state.fsync_pending => {
+                if !force && fsync != AppendFsync::EverySec {
+                    return;
+                }
+
+                state.fsync_pending = false;
+                state.file.as_ref().map(File::try_clone)
+            }
+            Ok(_) => None,
+            Err(err) => {
+                println!("[AOF] Got poisoned state: {:#?}", err);
+
+                return;
+            }
+        };
+
+        // NOTE: Synced outside of the lock, so commands can keep on being appended in the meantime
+        if let Some(Err(err)) = file.map(|file| file.and_then(|file| file.sync_data())) {
+            println!("[AOF] Failed to fsync the file: {:#?}", err);
+
+            if let Ok(mut state) = self.state.lock() {
+                state.fsync_pending = true;
+            }
+
+            return;
+        }
+
+        // NOTE: While rewriting, the new base file might be the only file with some keys (e.g. right after
+        // `appendonly` is turned on), so nothing is known to be flushed until it's written
+        if !self.is_rewriting() {
+            self.fsynced_offset.fetch_max(offset, Ordering::Relaxed);
+        }
+    }
+
+    /// Start appending to the last incremental file in the manifest, creating one if there's none.
+    ///
+    /// Called on startup once the append only file has been loaded, see [`load`].
+    pub fn open(&self, dir: &Path, manifest: AofManifest) -> io::Result<()> {
+        let filename = self.read_config().filename;
+        fs::create_dir_all(dir)?;
+
+        let mut state = self.lock_state()?;
+        state.dir = dir.to_path_buf();
+        state.manifest = manifest;
+
+        if state.manifest.incrementals.is_empty() {
+            let incremental = state.manifest.next_incremental(&filename);
+            state.manifest.incrementals.push(incremental);
+            write_manifest(dir, &filename, &state.manifest)?;
+        }
+
+        let last = state
+            .manifest
+            .incrementals
+            .last()
+            .map(|file| file.name.clone());
+        if let Some(last) = last {
+            state.file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(dir.join(last))?,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Turn the append only file on while the server is running, rewriting it from the snapshot.
+    ///
+    /// Should be called while the storage is locked, see [`Aof::background_rewrite`].
+    pub fn enable(self: &Arc<Self>, dir: &Path, snapshot: Snapshot) -> Result<(), Box<dyn Error>> {
+        if self.is_rewriting() {
+            return Err("Background append only file rewriting already in progress".into());
+        }
+
+        let manifest = read_manifest(dir, &self.read_config().filename)?;
+        self.open(dir, manifest.unwrap_or_default())?;
+        self.set_enabled(true);
+
+        if !self.background_rewrite(dir, snapshot)? {
+            return Err("Background append only file rewriting already in progress".into());
+        }
+
+        Ok(())
+    }
+
+    /// Turn the append only file off while the server is running.
+    pub fn disable(&self) -> io::Result<()> {
+        self.set_enabled(false);
+
+        self.close()
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        match self.config.write() {
+            Ok(mut config) => config.enabled = enabled,
+            Err(err) => println!("[AOF] Got poisoned config for write: {:#?}", err),
+        }
+    }
+
+    /// Stop appending, flushing what's been appended so far to the disk.
+    pub fn close(&self) -> io::Result<()> {
+        let mut state = self.lock_state()?;
+        state.fsync_pending = false;
+
+        match state.file.take() {
+            Some(file) => file.sync_data(),
+            None => Ok(()),
+        }
+    }
+
+    /// Start appending to a new incremental file, and rewrite the append only file from the snapshot in
+    /// the background. Returns `false` if there's already a rewrite in progress.
+    ///
+    /// Should be called while the storage is locked, so nothing is written to the storage between the
+    /// snapshot and the switch to the new incremental file.
+    pub fn background_rewrite(
+        self: &Arc<Self>,
+        dir: &Path,
+        snapshot: Snapshot,
+    ) -> io::Result<bool> {
+        if self
+            .rewrite_in_progress
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Ok(false);
+        }
+
+        let result = self.start_rewrite(dir);
+        let (base, first_incremental) = match result {
+            Ok(started) => started,
+            Err(err) => {
+                self.rewrite_in_progress.store(false, Ordering::Release);
+
+                return Err(err);
+            }
+        };
+
+        let aof = Arc::clone(self);
+        let dir = dir.to_path_buf();
+        thread::spawn(move || {
+            if let Err(err) = aof.finish_rewrite(&dir, base, first_incremental, &snapshot) {
+                println!("[AOF BGREWRITEAOF] Failed to rewrite the file: {:#?}", err);
+            }
+
+            aof.rewrite_in_progress.store(false, Ordering::Release);
+        });
+
+        Ok(true)
+    }
+
+    /// Switch to a new incremental file, returning the new base file to be written along with the sequence
+    /// of the first incremental file after it.
+    fn start_rewrite(&self, dir: &Path) -> io::Result<(AofFile, Option<u64>)> {
+        let filename = self.read_config().filename;
+        fs::create_dir_all(dir)?;
+
+        let mut state = self.lock_state()?;
+        if state.dir != dir {
+            state.dir = dir.to_path_buf();
+            state.manifest = read_manifest(dir, &filename)
+                .map_err(|err| io::Error::new(ErrorKind::InvalidData, err.to_string()))?
+                .unwrap_or_default();
+        }
+
+        let base = state.manifest.next_base(&filename);
+        if state.file.is_none() {
+            return Ok((base, None));
+        }
+
+        let incremental = state.manifest.next_incremental(&filename);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(&incremental.name))?;
+
+        let seq = incremental.seq;
+        state.manifest.incrementals.push(incremental);
+        write_manifest(dir, &filename, &state.manifest)?;
+
+        if let Some(previous) = state.file.replace(file) {
+            previous.sync_data()?;
+        }
+
+        Ok((base, Some(seq)))
+    }
+
+    fn finish_rewrite(
+        &self,
+        dir: &Path,
+        base: AofFile,
+        first_incremental: Option<u64>,
+        snapshot: &Snapshot,
+    ) -> io::Result<()> {
+        let temp_path = dir.join(format!("temp-rewriteaof-{}.aof", std::process::id()));
+
+        let result = (|| {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            encode_snapshot(snapshot, &mut writer)?;
+            writer.into_inner()?.sync_all()?;
+
+            fs::rename(&temp_path, dir.join(&base.name))
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+
+            return result;
+        }
+
+        let filename = self.read_config().filename;
+        let mut state = self.lock_state()?;
+
+        // Every file before the new base is not needed anymore
+        let previous: Vec<AofFile> = state
+            .manifest
+            .files()
+            .filter(|file| match file.kind {
+                AofFileKind::Base => true,
+                AofFileKind::Incremental => first_incremental.is_none_or(|first| file.seq < first),
+            })
+            .cloned()
+            .collect();
+
+        state.manifest.base = Some(base);
+        state
+            .manifest
+            .incrementals
+            .retain(|file| first_incremental.is_some_and(|first| file.seq >= first));
+        write_manifest(dir, &filename, &state.manifest)?;
+        drop(state);
+
+        for file in previous {
+            if let Err(err) = fs::remove_file(dir.join(&file.name)) {
+                println!("[AOF] Failed to remove `{}`: {:#?}", file.name, err);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_config(&self) -> AofConfig {
+        match self.config.read() {
+            Ok(config) => config.clone(),
+            Err(err) => {
+                println!("[AOF] Got poisoned config for read: {:#?}", err);
+
+                AofConfig::default()
+            }
+        }
+    }
+
+    fn lock_state(&self) -> io::Result<std::sync::MutexGuard<'_, AofState>> {
+        self.state
+            .lock()
+            .map_err(|err| io::Error::other(format!("poisoned AOF state: {}", err)))
+    }
+}
+
+fn manifest_path(dir: &Path, filename: &str) -> PathBuf {
+    dir.join(format!("{}.manifest", filename))
+}
+
+/// Read the manifest, `None` if there's no manifest yet.
+pub fn read_manifest(dir: &Path, filename: &str) -> Result<Option<AofManifest>, Box<dyn Error>> {
+    match fs::read_to_string(manifest_path(dir, filename)) {
+        Ok(manifest) => AofManifest::parse(&manifest).map(Some),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Write the manifest atomically, into a temporary file renamed over the manifest.
+fn write_manifest(dir: &Path, filename: &str, manifest: &AofManifest) -> io::Result<()> {
+    let path = manifest_path(dir, filename);
+    let temp_path = dir.join(format!("temp-{}.manifest", filename));
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(manifest.to_string().as_bytes())?;
+    file.sync_all()?;
+
+    fs::rename(temp_path, path)
+}
+
+/// Load every file in the manifest into the storage, replaying the commands in the incremental files
+/// through the normal command path. Returns the manifest, `None` if there's no append only file yet.
+///
+/// A truncated command at the end of the last file is removed from the file when `aof-load-truncated`
+/// is set, otherwise nothing is loaded.
+pub fn load(server: &Server, dir: &Path) -> Result<Option<AofManifest>, Box<dyn Error>> {
+    let config = server.aof.read_config();
+    let manifest = match read_manifest(dir, &config.filename)? {
+        Some(manifest) => manifest,
+        None => return Ok(None),
+    };
+
+    let last = manifest.files().last().cloned();
+    for file in manifest.files() {
+        let path = dir.join(&file.name);
+
+        if file.kind == AofFileKind::Base && file.name.ends_with(".rdb") {
+            let snapshot = decode_snapshot(BufReader::new(File::open(&path)?))?;
+
+            match server.storage.write() {
+                Ok(mut storage_locked) => server.restore_snapshot(&mut storage_locked, snapshot),
+                Err(err) => return Err(format!("poisoned storage: {}", err).into()),
+            }
+        } else {
+            let can_truncate = config.load_truncated && last.as_ref() == Some(file);
+            replay(server, &path, can_truncate)?;
+        }
+    }
+
+    Ok(Some(manifest))
+}
+
+/// Run every command in the file, as if it's sent by a client, see [`Client::loading`]. Fails on the first
+/// command that fails, as the storage would not be the same as the one written into the file.
+fn replay(server: &Server, path: &Path, can_truncate: bool) -> Result<(), Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut client = Client::new();
+    client.loading = true;
+    // End of the last command that's not inside a transaction, everything after it is not loaded yet
+    let mut valid_len = 0;
+    let mut truncated = false;
+
+    while !reader.fill_buf()?.is_empty() {
+        match RespType::deserialize(&mut reader) {
+            Ok((RespType::Array(command), _)) => {
+                let name = match command.first() {
+                    Some(RespType::BulkString(name)) => name.clone(),
+                    _ => String::new(),
+                };
+
+                if let RespType::Error(err) = handle_commands(command, &mut client, server) {
+                    client.disconnect(server);
+
+                    return Err(format!(
+                        "`{}` failed reading the append only file `{}`: {}",
+                        name,
+                        path.display(),
+                        err
+                    )
+                    .into());
+                }
+
+                if client.transaction.is_none() {
+                    valid_len = reader.stream_position()?;
+                }
+            }
+            Ok(_) => return Err("bad file format reading the append only file".into()),
+            Err(err) => match err.downcast_ref::<io::Error>() {
+                Some(io_err) if io_err.kind() == ErrorKind::UnexpectedEof => {
+                    truncated = true;
+                    break;
+                }
+                _ => return Err(err),
+            },
+        }
+    }
+
+    // NOTE: Transaction without `EXEC` at the end of the file is handled the same as a truncated command
+    truncated |= client.transaction.is_some();
+    client.transaction = None;
+    client.disconnect(server);
+
+    if truncated {
+        if !can_truncate {
+            return Err(format!(
+                "unexpected end of file reading the append only file `{}`",
+                path.display()
+            )
+            .into());
+        }
+
+        println!(
+            "[AOF] Truncated command at the end of `{}`, truncating the file to {} bytes",
+            path.display(),
+            valid_len
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(valid_len)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod aof_tests {
+    use std::{
+        fs,
+        sync::{atomic::Ordering, Arc},
+    };
+
+    use super::{load, AofManifest};
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    fn get(server: &Server, key: &str) -> RespType {
+        handle_commands(command(&["GET", key]), &mut connect(server), server)
+    }
+
+    #[test]
+    fn parses_and_formats_manifest() {
+        let manifest = "file appendonly.aof.2.base.rdb seq 2 type b\n\
+                        file appendonly.aof.1.incr.aof seq 1 type h\n\
+                        file appendonly.aof.3.incr.aof seq 3 type i\n";
+
+        let parsed = AofManifest::parse(manifest).unwrap();
+        assert_eq!(parsed.base.as_ref().map(|base| base.seq), Some(2));
+        assert_eq!(parsed.incrementals.len(), 1);
+        assert_eq!(
+            parsed.to_string(),
+            "file appendonly.aof.2.base.rdb seq 2 type b\nfile appendonly.aof.3.incr.aof seq 3 type i\n"
+        );
+    }
+
+    #[test]
+    fn appends_replays_and_truncates() {
+        let dir = std::env::temp_dir().join(format!("rust-eez-aof-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let server = Arc::new(Server::new());
+        server.aof.open(&dir, AofManifest::default()).unwrap();
+
+        let mut client = connect(&server);
+        handle_commands(command(&["SET", "key", "caf\u{e9}"]), &mut client, &server);
+        handle_commands(command(&["GET", "key"]), &mut client, &server);
+        handle_commands(command(&["MULTI"]), &mut client, &server);
+        handle_commands(command(&["HSET", "hash", "a", "1"]), &mut client, &server);
+        handle_commands(command(&["DEL", "missing"]), &mut client, &server);
+        handle_commands(command(&["EXEC"]), &mut client, &server);
+        server.aof.close().unwrap();
+
+        let incremental = dir.join("appendonly.aof.1.incr.aof");
+        let appended = fs::read(&incremental).unwrap();
+        assert_eq!(
+            appended,
+            b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$4\r\ncaf\xe9\r\n*4\r\n$4\r\nHSET\r\n$4\r\nhash\r\n$1\r\na\r\n$1\r\n1\r\n"
+        );
+
+        // Truncated in the middle of a command
+        let mut truncated = appended.clone();
+        truncated.extend_from_slice(b"*3\r\n$3\r\nSET\r\n$1\r\nk");
+        fs::write(&incremental, &truncated).unwrap();
+
+        let restarted = Server::new();
+        assert!(load(&restarted, &dir).unwrap().is_some());
+        assert_eq!(
+            get(&restarted, "key"),
+            RespType::BulkString("caf\u{e9}".into())
+        );
+        assert_eq!(fs::read(&incremental).unwrap(), appended);
+
+        // Truncated files are not loaded without `aof-load-truncated`
+        fs::write(&incremental, &truncated).unwrap();
+        let restarted = Server::new();
+        restarted.aof.config.write().unwrap().load_truncated = false;
+        assert!(load(&restarted, &dir).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn replays_on_read_only_follower_without_side_effects() {
+        let dir =
+            std::env::temp_dir().join(format!("rust-eez-aof-follower-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let server = Arc::new(Server::new());
+        server.aof.open(&dir, AofManifest::default()).unwrap();
+        let mut client = connect(&server);
+        handle_commands(command(&["SET", "key", "value"]), &mut client, &server);
+        handle_commands(command(&["MULTI"]), &mut client, &server);
+        handle_commands(command(&["HSET", "hash", "a", "1"]), &mut client, &server);
+        handle_commands(command(&["SET", "other", "value"]), &mut client, &server);
+        handle_commands(command(&["EXEC"]), &mut client, &server);
+        server.aof.close().unwrap();
+
+        let restarted = Server::new();
+        restarted.replication.follow("127.0.0.1", 1);
+        restarted
+            .slowlog
+            .log_slower_than
+            .store(0, Ordering::Relaxed);
+        assert!(load(&restarted, &dir).unwrap().is_some());
+
+        assert_eq!(get(&restarted, "key"), RespType::BulkString("value".into()));
+        assert_eq!(
+            get(&restarted, "other"),
+            RespType::BulkString("value".into())
+        );
+        assert_eq!(restarted.replication.offset(), 0);
+        assert_eq!(
+            restarted
+                .stats
+                .total_commands_processed
+                .load(Ordering::Relaxed),
+            2
+        );
+        assert_eq!(restarted.slowlog.entries(None).len(), 2);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fails_loading_on_failed_command() {
+        let dir = std::env::temp_dir().join(format!("rust-eez-aof-failed-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let server = Arc::new(Server::new());
+        server.aof.open(&dir, AofManifest::default()).unwrap();
+        handle_commands(
+            command(&["SET", "key", "value"]),
+            &mut connect(&server),
+            &server,
+        );
+        server.aof.close().unwrap();
+
+        let incremental = dir.join("appendonly.aof.1.incr.aof");
+        let mut appended = fs::read(&incremental).unwrap();
+        appended.extend_from_slice(b"*4\r\n$4\r\nHSET\r\n$3\r\nkey\r\n$1\r\na\r\n$1\r\n1\r\n");
+        fs::write(&incremental, &appended).unwrap();
+
+        let error = load(&Server::new(), &dir).unwrap_err().to_string();
+        assert!(error.starts_with("`HSET` failed reading the append only file"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
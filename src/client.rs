@@ -0,0 +1,619 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver},
+        Arc, Condvar, Mutex, MutexGuard, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    pubsub::MessageSender,
+    replication::ReplicaLink,
+    resp::RespType,
+    server::Server,
+    storage::Storage,
+    tracking::{TrackingOptions, INVALIDATE_CHANNEL},
+};
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Commands queued by a client after `MULTI`, waiting for `EXEC` or `DISCARD`.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    pub commands: Vec<Vec<RespType>>,
+    /// Set when one of the queued command is rejected, e.g. unknown command or wrong number of arguments.
+    /// The transaction will then be aborted on `EXEC`.
+    pub aborted: bool,
+}
+
+/// State of a single connection, living as long as the connection itself.
+#[derive(Debug)]
+pub struct Client {
+    /// Unique id of the client, never reused while the server is running.
+    pub id: u64,
+    /// Address of the client, `ip:port`, empty when unknown.
+    pub addr: String,
+    /// RESP protocol version used by the client, switched with `HELLO`.
+    pub protocol: u8,
+    /// Transaction started by `MULTI`, `None` if the client is not in a transaction.
+    pub transaction: Option<Transaction>,
+    /// Keys `WATCH`-ed by the client, with the modification version of the key when it's watched.
+    pub watched_keys: HashMap<String, u64>,
+    /// Channels subscribed with `SUBSCRIBE`.
+    pub channels: HashSet<String>,
+    /// Patterns subscribed with `PSUBSCRIBE`.
+    pub patterns: HashSet<String>,
+    /// Shard channels subscribed with `SSUBSCRIBE`.
+    pub shard_channels: HashSet<String>,
+    /// Replies to be sent right after the reply of the current command, used by commands replying more
+    /// than once, e.g. `SUBSCRIBE` with multiple channels.
+    pub extra_replies: Vec<RespType>,
+    /// Sending end of the client messages, given to whoever needs to push messages to the client.
+    pub message_sender: MessageSender,
+    message_receiver: Receiver<Vec<RespType>>,
+    /// Set on the client applying the write commands streamed by the leader, which is allowed to write
+    /// to a read only follower.
+    pub from_leader: bool,
+    /// Set on the client replaying the append only file on startup. Like [`Client::from_leader`], it's
+    /// allowed to write to a read only follower, and its commands are not passed on, monitored, nor
+    /// recorded into the stats.
+    pub loading: bool,
+    /// Port the follower behind this client listens on, announced with `REPLCONF listening-port`.
+    pub listening_port: Option<u16>,
+    /// Set by `PSYNC` once the client is accepted as a follower, the connection is then used to stream
+    /// write commands to the follower.
+    pub replica: Option<ReplicaLink>,
+    /// Replication offset right after the last write command of the client, waited for by `WAIT`.
+    pub last_write_offset: u64,
+    /// Set while the commands of a transaction are run, blocking commands then return right away.
+    pub deny_blocking: bool,
+    /// Write commands to pass on in place of the command being run, set by commands running other
+    /// commands, e.g. the write commands run by a script for `EVAL`.
+    pub propagated: Option<Vec<Vec<RespType>>>,
+    /// ACL user the client runs commands as, `None` for clients run by the server itself (e.g. replaying
+    /// the append only file), which are allowed to run anything.
+    pub user: Option<String>,
+    /// Whether the client is authenticated, either with `AUTH`, or because no password is needed when the
+    /// client connected.
+    pub authenticated: bool,
+    /// Name of the connection set with `CLIENT SETNAME`, empty when there's none.
+    pub name: String,
+    /// Name and version of the library used by the client, set with `CLIENT SETINFO`.
+    pub lib_name: String,
+    pub lib_ver: String,
+    pub created: Instant,
+    /// Last time the client sent a command.
+    pub last_interaction: Instant,
+    /// Name of the last command sent by the client, along with the subcommand, e.g. `client|list`.
+    pub last_command: String,
+    /// Set once the connection is used to stream write commands to a follower.
+    pub is_replica: bool,
+    /// Set with `CLIENT NO-EVICT`, nothing is evicted yet, so it's only shown in `CLIENT LIST`.
+    pub no_evict: bool,
+    /// Set with `CLIENT NO-TOUCH`, the access time of keys isn't tracked yet, so it's only shown in
+    /// `CLIENT LIST`.
+    pub no_touch: bool,
+    /// Whether the replies are sent to the client, switched with `CLIENT REPLY`.
+    pub reply_mode: ReplyMode,
+    /// Set with `CLIENT TRACKING ON`, see [`crate::tracking::Tracking`].
+    pub tracking: Option<TrackingOptions>,
+    /// Set with `CLIENT CACHING`, whether the keys read by the next command are tracked in the `OPTIN` or
+    /// `OPTOUT` mode.
+    pub tracking_caching: Option<bool>,
+    /// Set by `MONITOR`, the client then receives every command run by the server, see
+    /// [`crate::monitor::Monitor`].
+    pub monitor: bool,
+    /// Set by `CLIENT KILL` (from any client), the connection is then closed as soon as possible.
+    killed: Arc<AtomicBool>,
+}
+
+/// Whether the replies are sent to the client, see `CLIENT REPLY`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyMode {
+    #[default]
+    On,
+    Off,
+    /// Set by `CLIENT REPLY SKIP`, its own reply is skipped, along with the reply of the next command.
+    SkipNext,
+    /// Skip the reply of the current command only.
+    Skip,
+}
+
+impl Client {
+    pub fn new() -> Self {
+        let (message_sender, message_receiver) = mpsc::channel();
+
+        Self {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            addr: String::new(),
+            protocol: 2,
+            transaction: None,
+            watched_keys: HashMap::new(),
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+            shard_channels: HashSet::new(),
+            extra_replies: Vec::new(),
+            message_sender,
+            message_receiver,
+            from_leader: false,
+            loading: false,
+            listening_port: None,
+            replica: None,
+            last_write_offset: 0,
+            deny_blocking: false,
+            propagated: None,
+            user: None,
+            authenticated: false,
+            name: String::new(),
+            lib_name: String::new(),
+            lib_ver: String::new(),
+            created: Instant::now(),
+            last_interaction: Instant::now(),
+            last_command: "NULL".into(),
+            is_replica: false,
+            no_evict: false,
+            no_touch: false,
+            reply_mode: ReplyMode::On,
+            tracking: None,
+            tracking_caching: None,
+            monitor: false,
+            killed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn with_addr(addr: String) -> Self {
+        Self {
+            addr,
+            ..Self::new()
+        }
+    }
+
+    /// IP address of the client, without the port.
+    pub fn ip(&self) -> &str {
+        self.addr
+            .rsplit_once(':')
+            .map_or(self.addr.as_str(), |(ip, _)| ip)
+    }
+
+    /// Whether the client can write to a read only follower, only the leader and the server itself (e.g.
+    /// replaying the append only file) can.
+    pub fn ignores_read_only(&self) -> bool {
+        self.from_leader || self.loading
+    }
+
+    /// Whether the client was killed with `CLIENT KILL`, the connection should then be closed.
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::Relaxed)
+    }
+
+    /// Whether the reply of the command just run should be sent, moving on to the next command for
+    /// `CLIENT REPLY SKIP`.
+    pub fn take_reply_mode(&mut self) -> bool {
+        match self.reply_mode {
+            ReplyMode::On => true,
+            ReplyMode::Off => false,
+            ReplyMode::SkipNext => {
+                self.reply_mode = ReplyMode::Skip;
+                false
+            }
+            ReplyMode::Skip => {
+                self.reply_mode = ReplyMode::On;
+                false
+            }
+        }
+    }
+
+    pub fn kind(&self) -> ClientKind {
+        if self.is_replica {
+            ClientKind::Replica
+        } else if self.from_leader {
+            ClientKind::Master
+        } else if self.is_subscribed() {
+            ClientKind::PubSub
+        } else {
+            ClientKind::Normal
+        }
+    }
+
+    /// Snapshot of the client, as seen by the other clients with `CLIENT LIST`.
+    pub fn info(&self) -> ClientInfo {
+        let mut flags = String::new();
+        match self.kind() {
+            ClientKind::Replica => flags.push('S'),
+            ClientKind::Master => flags.push('M'),
+            ClientKind::PubSub => flags.push('P'),
+            ClientKind::Normal => {}
+        }
+        if self.transaction.is_some() {
+            flags.push('x');
+        }
+        if let Some(tracking) = &self.tracking {
+            flags.push('t');
+            if tracking.bcast {
+                flags.push('B');
+            }
+        }
+        if self.monitor {
+            flags.push('O');
+        }
+        if self.no_touch {
+            flags.push('T');
+        }
+        if self.no_evict {
+            flags.push('e');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+
+        ClientInfo {
+            id: self.id,
+            addr: self.addr.clone(),
+            name: self.name.clone(),
+            created: self.created,
+            last_interaction: self.last_interaction,
+            flags,
+            kind: self.kind(),
+            sub: self.channels.len(),
+            psub: self.patterns.len(),
+            ssub: self.shard_channels.len(),
+            multi: self
+                .transaction
+                .as_ref()
+                .map_or(-1, |transaction| transaction.commands.len() as i64),
+            watch: self.watched_keys.len(),
+            redir: self.tracking.as_ref().map_or(-1, |tracking| {
+                tracking.redirect.map_or(0, |redirect| redirect as i64)
+            }),
+            last_command: self.last_command.clone(),
+            user: self.user.clone().unwrap_or_default(),
+            protocol: self.protocol,
+            lib_name: self.lib_name.clone(),
+            lib_ver: self.lib_ver.clone(),
+        }
+    }
+
+    /// Stop watching every key watched by the client, should be called whenever the client is done with
+    /// the watched keys (`EXEC`, `DISCARD`, `UNWATCH`, or when the connection is closed).
+    pub fn unwatch_all(&mut self, storage: &mut Storage) {
+        for (key, _) in self.watched_keys.drain() {
+            storage.unwatch(&key);
+        }
+    }
+
+    /// Number of channels and patterns the client subscribed to.
+    pub fn subscription_count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    /// Whether the client subscribed to any channel, pattern, or shard channel.
+    pub fn is_subscribed(&self) -> bool {
+        self.subscription_count() > 0 || !self.shard_channels.is_empty()
+    }
+
+    /// Unsubscribe from every channel, pattern, and shard channel.
+    pub fn unsubscribe_all(&mut self, server: &Server) {
+        for channel in self.channels.drain() {
+            server.pubsub.channels.unsubscribe(&channel, self.id);
+        }
+
+        for pattern in self.patterns.drain() {
+            server.pubsub.patterns.unsubscribe(&pattern, self.id);
+        }
+
+        for shard_channel in self.shard_channels.drain() {
+            server
+                .pubsub
+                .shard_channels
+                .unsubscribe(&shard_channel, self.id);
+        }
+    }
+
+    /// Wrap the content of a push to the type understood by the client, RESP2 client does not know about
+    /// push, so an array is used instead.
+    pub fn push(&self, content: Vec<RespType>) -> RespType {
+        if self.protocol >= 3 {
+            RespType::Push(content)
+        } else {
+            RespType::Array(content)
+        }
+    }
+
+    /// Every message pushed to the client that is not yet sent.
+    pub fn pending_messages(&self) -> Vec<RespType> {
+        self.message_receiver
+            .try_iter()
+            .filter_map(|content| self.tracking_message(content))
+            .map(|mut content| match content.as_slice() {
+                // NOTE: Lines of `MONITOR` are sent as they are, instead of a push
+                [RespType::String(_)] => content.remove(0),
+                _ => self.push(content),
+            })
+            .collect()
+    }
+
+    /// RESP2 clients can't receive the tracking messages as they are, invalidation messages are sent as
+    /// messages of [`INVALIDATE_CHANNEL`] instead, only when the client subscribed to it.
+    fn tracking_message(&self, mut content: Vec<RespType>) -> Option<Vec<RespType>> {
+        if self.protocol >= 3 {
+            return Some(content);
+        }
+
+        match content.first() {
+            Some(RespType::BulkString(kind)) if kind == "invalidate" => {
+                if !self.channels.contains(INVALIDATE_CHANNEL) {
+                    return None;
+                }
+
+                content[0] = RespType::BulkString("message".into());
+                content.insert(1, RespType::BulkString(INVALIDATE_CHANNEL.into()));
+
+                Some(content)
+            }
+            Some(RespType::BulkString(kind)) if kind == "tracking-redir-broken" => None,
+            _ => Some(content),
+        }
+    }
+
+    /// Clean up everything owned by the client on the shared state, called when the connection is closed.
+    pub fn disconnect(&mut self, server: &Server) {
+        if !self.watched_keys.is_empty() {
+            if let Ok(mut storage_locked) = server.storage.write() {
+                self.unwatch_all(&mut storage_locked);
+            }
+        }
+
+        self.unsubscribe_all(server);
+        server.replication.detach_replica(self.id);
+
+        if self.tracking.take().is_some() {
+            server.tracking.disable(self.id);
+        }
+        self.tracking_caching = None;
+
+        if self.monitor {
+            self.monitor = false;
+            server.monitor.remove(self.id);
+        }
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Type of a client, used to filter the clients of `CLIENT LIST` and `CLIENT KILL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientKind {
+    Normal,
+    /// Follower connected to this server.
+    Replica,
+    /// Leader this server is following.
+    Master,
+    /// Client subscribed to any channel, pattern, or shard channel.
+    PubSub,
+}
+
+impl ClientKind {
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind.to_lowercase().as_str() {
+            "normal" => Some(Self::Normal),
+            "replica" | "slave" => Some(Self::Replica),
+            "master" => Some(Self::Master),
+            "pubsub" => Some(Self::PubSub),
+            _ => None,
+        }
+    }
+}
+
+/// Snapshot of a client, see [`Client::info`].
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub addr: String,
+    pub name: String,
+    pub created: Instant,
+    pub last_interaction: Instant,
+    pub flags: String,
+    pub kind: ClientKind,
+    pub sub: usize,
+    pub psub: usize,
+    pub ssub: usize,
+    /// Number of commands queued in the transaction, -1 outside of a transaction.
+    pub multi: i64,
+    pub watch: usize,
+    /// Client receiving the invalidation messages, 0 for the client itself, -1 when not tracking.
+    pub redir: i64,
+    pub last_command: String,
+    pub user: String,
+    pub protocol: u8,
+    pub lib_name: String,
+    pub lib_ver: String,
+}
+
+impl Display for ClientInfo {
+    /// Line of the client in `CLIENT LIST`, with the fields named the same as Redis.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "id={} addr={} name={} age={} idle={} flags={} db=0 sub={} psub={} ssub={} multi={} watch={} cmd={} user={} redir={} resp={} lib-name={} lib-ver={}",
+            self.id,
+            self.addr,
+            self.name,
+            self.created.elapsed().as_secs(),
+            self.last_interaction.elapsed().as_secs(),
+            self.flags,
+            self.sub,
+            self.psub,
+            self.ssub,
+            self.multi,
+            self.watch,
+            self.last_command,
+            self.user,
+            self.redir,
+            self.protocol,
+            self.lib_name,
+            self.lib_ver
+        )
+    }
+}
+
+/// Which commands are paused by `CLIENT PAUSE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PauseMode {
+    /// Only the commands that may write, e.g. `SET`, `EVAL`, or `PUBLISH`.
+    Write,
+    All,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pause {
+    mode: PauseMode,
+    until: Instant,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    info: ClientInfo,
+    killed: Arc<AtomicBool>,
+    message_sender: MessageSender,
+}
+
+/// Every client connected to the server, shown by `CLIENT LIST` and killed with `CLIENT KILL`.
+///
+/// Clients aren't shared between the threads, so a snapshot of each client is kept here, updated after
+/// every command it runs.
+#[derive(Debug, Default)]
+pub struct Clients {
+    clients: RwLock<BTreeMap<u64, Entry>>,
+    pause: Mutex<Option<Pause>>,
+    unpaused: Condvar,
+}
+
+impl Clients {
+    pub fn register(&self, client: &Client) {
+        self.write_clients().insert(
+            client.id,
+            Entry {
+                info: client.info(),
+                killed: Arc::clone(&client.killed),
+                message_sender: client.message_sender.clone(),
+            },
+        );
+    }
+
+    pub fn unregister(&self, client_id: u64) {
+        self.write_clients().remove(&client_id);
+    }
+
+    /// Update the snapshot of the client, if it's registered.
+    pub fn update(&self, client: &Client) {
+        if let Some(entry) = self.write_clients().get_mut(&client.id) {
+            entry.info = client.info();
+        }
+    }
+
+    /// Every client not killed yet, sorted by id.
+    pub fn list(&self) -> Vec<ClientInfo> {
+        self.clients
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .values()
+            .filter(|entry| !entry.killed.load(Ordering::Relaxed))
+            .map(|entry| entry.info.clone())
+            .collect()
+    }
+
+    pub fn contains(&self, client_id: u64) -> bool {
+        self.clients
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .contains_key(&client_id)
+    }
+
+    /// Push a message to the client, returns false when the client is gone.
+    pub fn send(&self, client_id: u64, content: Vec<RespType>) -> bool {
+        self.clients
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(&client_id)
+            .is_some_and(|entry| entry.message_sender.send(content).is_ok())
+    }
+
+    pub fn len(&self) -> usize {
+        self.list().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Kill every client matching the filter, returns the number of clients killed.
+    pub fn kill(&self, filter: impl Fn(&ClientInfo) -> bool) -> usize {
+        self.clients
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .values()
+            .filter(|entry| !entry.killed.load(Ordering::Relaxed) && filter(&entry.info))
+            .map(|entry| entry.killed.store(true, Ordering::Relaxed))
+            .count()
+    }
+
+    /// Pause the commands of the clients, an ongoing pause is only extended, the same as Redis.
+    pub fn pause(&self, mode: PauseMode, duration: Duration) {
+        let until = Instant::now() + duration;
+
+        let mut pause = self.lock_pause();
+        *pause = Some(match *pause {
+            Some(current) => Pause {
+                mode: current.mode.max(mode),
+                until: current.until.max(until),
+            },
+            None => Pause { mode, until },
+        });
+    }
+
+    pub fn unpause(&self) {
+        *self.lock_pause() = None;
+        self.unpaused.notify_all();
+    }
+
+    /// Wait until the clients aren't paused anymore, when the pause applies to the command.
+    pub fn wait_unpaused(&self, is_write: bool) {
+        let mut pause = self.lock_pause();
+
+        while let Some(current) = *pause {
+            let now = Instant::now();
+            if current.until <= now {
+                *pause = None;
+                break;
+            }
+            if current.mode == PauseMode::Write && !is_write {
+                break;
+            }
+
+            pause = self
+                .unpaused
+                .wait_timeout(pause, current.until - now)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+    }
+
+    fn write_clients(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<u64, Entry>> {
+        self.clients
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_pause(&self) -> MutexGuard<'_, Option<Pause>> {
+        self.pause
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
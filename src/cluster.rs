@@ -0,0 +1,63 @@
+use crate::resp::string_to_bytes;
+
+/// Number of hash slots keys are distributed into, the same as Redis Cluster.
+pub const HASH_SLOTS: u16 = 16384;
+
+/// CRC16 (XMODEM variant) used by Redis Cluster to hash keys into slots.
+pub fn crc16(bytes: impl IntoIterator<Item = u8>) -> u16 {
+    let mut crc = 0u16;
+
+    for byte in bytes {
+        crc ^= (byte as u16) << 8;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+/// Hash slot of a key (or a shard channel), following the Redis Cluster specification.
+///
+/// If the key contains a non-empty hash tag (the part between the first `{` and the next `}`), only the
+/// hash tag is hashed. This allows related keys to be put in the same slot, e.g. `{user:1}:name` and
+/// `{user:1}:email`.
+pub fn key_hash_slot(key: &str) -> u16 {
+    let bytes = string_to_bytes(key);
+
+    let hashed = match bytes.iter().position(|byte| *byte == b'{') {
+        Some(open) => match bytes[open + 1..].iter().position(|byte| *byte == b'}') {
+            Some(tag_len) if tag_len > 0 => &bytes[open + 1..open + 1 + tag_len],
+            _ => &bytes[..],
+        },
+        None => &bytes[..],
+    };
+
+    crc16(hashed.iter().copied()) % HASH_SLOTS
+}
+
+#[cfg(test)]
+mod cluster_tests {
+    use super::{crc16, key_hash_slot};
+
+    #[test]
+    fn crc16_matches_reference() {
+        assert_eq!(crc16(*b"123456789"), 0x31C3);
+    }
+
+    #[test]
+    fn key_hash_slot_uses_hash_tag() {
+        assert_eq!(key_hash_slot("foo"), 12182);
+        assert_eq!(
+            key_hash_slot("{user1000}.following"),
+            key_hash_slot("{user1000}.followers")
+        );
+        assert_eq!(key_hash_slot("foo{}{bar}"), crc16(*b"foo{}{bar}") % 16384);
+        assert_eq!(key_hash_slot("foo{{bar}}zap"), key_hash_slot("{bar"));
+    }
+}
@@ -0,0 +1,399 @@
+use std::{
+    fs::File,
+    io::Read,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    acl::{LogEntry, DEFAULT_USER},
+    client::Client,
+    commands::registry::AclCategories,
+    resp::RespType,
+    server::Server,
+};
+
+use super::{commands::help_reply, registry::Subcommand, scripting::map_reply};
+
+/// Default number of bits of the passwords generated by `ACL GENPASS`.
+const DEFAULT_GENPASS_BITS: i64 = 256;
+
+/// Number of entries shown by `ACL LOG` when no count is given.
+const DEFAULT_LOG_COUNT: usize = 10;
+
+/// Subcommands of `ACL`, see [`acl`].
+pub(super) const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand::new(
+        "cat",
+        -2,
+        "Lists the ACL categories, or the commands inside a category.",
+    ),
+    Subcommand::new(
+        "deluser",
+        -3,
+        "Deletes ACL users, and terminates their connections.",
+    ),
+    Subcommand::new(
+        "dryrun",
+        -4,
+        "Simulates the execution of a command by a user, without executing the command.",
+    ),
+    Subcommand::new(
+        "genpass",
+        -2,
+        "Generates a pseudorandom, secure password that can be used to identify ACL users.",
+    ),
+    Subcommand::new("getuser", 3, "Lists the ACL rules of a user."),
+    Subcommand::new(
+        "help",
+        2,
+        "Returns helpful text about the different subcommands.",
+    ),
+    Subcommand::new("list", 2, "Dumps the effective rules in ACL file format."),
+    Subcommand::new("load", 2, "Reloads the rules from the configured ACL file."),
+    Subcommand::new(
+        "log",
+        -2,
+        "Lists recent security events generated due to ACL rules.",
+    ),
+    Subcommand::new(
+        "save",
+        2,
+        "Saves the effective ACL rules in the configured ACL file.",
+    ),
+    Subcommand::new(
+        "setuser",
+        -3,
+        "Creates and modifies an ACL user and its rules.",
+    ),
+    Subcommand::new("users", 2, "Lists all ACL users."),
+    Subcommand::new(
+        "whoami",
+        2,
+        "Returns the authenticated username of the current connection.",
+    ),
+];
+
+/// AUTH Command
+///
+/// Authenticate the connection as a user, the `default` user when no username is given.
+///
+/// Currently implemented syntax
+/// `AUTH [username] password`
+pub fn auth(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    let (username, password) = match args {
+        [RespType::BulkString(password)] => {
+            if !server.acl.requires_auth() {
+                return RespType::Error("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".into());
+            }
+
+            (DEFAULT_USER, password)
+        }
+        [RespType::BulkString(username), RespType::BulkString(password)] => {
+            (username.as_str(), password)
+        }
+        _ => return RespType::Error("ERR syntax error".into()),
+    };
+
+    if server.acl.authenticate(client, username, password) {
+        RespType::String("OK".into())
+    } else {
+        RespType::Error("WRONGPASS invalid username-password pair or user is disabled.".into())
+    }
+}
+
+/// ACL Command
+///
+/// Manage the users allowed to connect, and what each of them is allowed to do.
+///
+/// Currently implemented syntax
+/// `ACL SETUSER username [rule [rule ...]]`
+/// `ACL GETUSER username`
+/// `ACL DELUSER username [username ...]`
+/// `ACL LIST`
+/// `ACL USERS`
+/// `ACL WHOAMI`
+/// `ACL CAT [category]`
+/// `ACL LOG [count | RESET]`
+/// `ACL DRYRUN username command [arg [arg ...]]`
+/// `ACL GENPASS [bits]`
+/// `ACL LOAD`
+/// `ACL SAVE`
+/// `ACL HELP`
+pub fn acl(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    let subcommand = match args.first() {
+        Some(RespType::BulkString(subcommand)) => subcommand.to_uppercase(),
+        _ => return RespType::Error("ERR syntax error".into()),
+    };
+
+    let strings = args[1..]
+        .iter()
+        .map(|arg| match arg {
+            RespType::BulkString(arg) => Some(arg.as_str()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    let strings = match strings {
+        Some(strings) => strings,
+        None => return RespType::Error("ERR syntax error".into()),
+    };
+
+    match (subcommand.as_str(), strings.as_slice()) {
+        ("SETUSER", [username, rules @ ..]) => {
+            match server.acl.set_user(username, rules, &server.commands) {
+                Ok(_) => RespType::String("OK".into()),
+                Err(err) => RespType::Error(format!("ERR {}", err)),
+            }
+        }
+        ("GETUSER", [username]) => getuser(username, client, server),
+        ("DELUSER", usernames) if !usernames.is_empty() => {
+            match server.acl.delete_users(usernames) {
+                Ok(deleted) => {
+                    // NOTE: Clients authenticated as a deleted user are killed, the same as Redis
+                    server
+                        .clients
+                        .kill(|info| usernames.contains(&info.user.as_str()));
+
+                    RespType::Integer(deleted as i64)
+                }
+                Err(err) => RespType::Error(format!("ERR {}", err)),
+            }
+        }
+        ("LIST", []) => RespType::Array(
+            server
+                .acl
+                .users()
+                .into_iter()
+                .map(|user| RespType::BulkString(format!("user {} {}", user.name, user.describe())))
+                .collect(),
+        ),
+        ("USERS", []) => RespType::Array(
+            server
+                .acl
+                .users()
+                .into_iter()
+                .map(|user| RespType::BulkString(user.name))
+                .collect(),
+        ),
+        ("WHOAMI", []) => {
+            RespType::BulkString(client.user.as_deref().unwrap_or(DEFAULT_USER).into())
+        }
+        ("CAT", []) => RespType::Array(
+            AclCategories::NAMES
+                .iter()
+                .map(|(name, _)| RespType::BulkString(name.to_string()))
+                .collect(),
+        ),
+        ("CAT", [category]) => {
+            let category = match AclCategories::NAMES
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(category))
+            {
+                Some((_, category)) => *category,
+                None => {
+                    return RespType::Error(format!("ERR Unknown category '{}'", category))
+                }
+            };
+
+            let mut names = server
+                .commands
+                .iter()
+                .filter(|command| command.acl_categories().contains(category))
+                .map(|command| command.name().to_string())
+                .collect::<Vec<_>>();
+            names.sort();
+
+            RespType::Array(names.into_iter().map(RespType::BulkString).collect())
+        }
+        ("LOG", []) => log(DEFAULT_LOG_COUNT, client, server),
+        ("LOG", [reset]) if reset.eq_ignore_ascii_case("RESET") => {
+            server.acl.reset_log();
+
+            RespType::String("OK".into())
+        }
+        ("LOG", [count]) => match count.parse::<usize>() {
+            Ok(count) => log(count, client, server),
+            Err(_) => RespType::Error("ERR value is out of range, must be positive".into()),
+        },
+        ("DRYRUN", [username, _, ..]) => dryrun(username, &args[2..], server),
+        ("GENPASS", []) => genpass(DEFAULT_GENPASS_BITS),
+        ("GENPASS", [bits]) => match bits.parse::<i64>() {
+            Ok(bits) if bits > 0 && bits <= 4096 => genpass(bits),
+            _ => RespType::Error("ERR ACL GENPASS argument must be the number of bits for the output password, a positive number up to 4096".into()),
+        },
+        ("LOAD", []) => match server.acl.load_file(&server.commands) {
+            Ok(_) => RespType::String("OK".into()),
+            Err(err) => RespType::Error(format!("ERR {}", err)),
+        },
+        ("SAVE", []) => match server.acl.save_file() {
+            Ok(_) => RespType::String("OK".into()),
+            Err(err) => RespType::Error(format!("ERR {}", err)),
+        },
+        ("HELP", []) => help_reply(
+            "ACL",
+            &[
+                "CAT [<category>]",
+                "    List all commands that belong to <category>, or all command categories",
+                "    when no category is specified.",
+                "DELUSER <username> [<username> ...]",
+                "    Delete a list of users.",
+                "DRYRUN <username> <command> [<arg> ...]",
+                "    Returns whether the user can execute the given command without executing the",
+                "    command.",
+                "GETUSER <username>",
+                "    Get the user's details.",
+                "GENPASS [<bits>]",
+                "    Generate a secure 256-bit user password. The optional `bits` argument can",
+                "    be used to specify a different size.",
+                "LIST",
+                "    Show users details in config file format.",
+                "LOAD",
+                "    Reload users from the ACL file.",
+                "LOG [<count> | RESET]",
+                "    Show the ACL log entries.",
+                "SAVE",
+                "    Save the current config to the ACL file.",
+                "SETUSER <username> <attribute> [<attribute> ...]",
+                "    Create or modify a user with the specified attributes.",
+                "USERS",
+                "    List all the registered usernames.",
+                "WHOAMI",
+                "    Return the current connection username.",
+            ],
+        ),
+        (
+            "SETUSER" | "GETUSER" | "DELUSER" | "LIST" | "USERS" | "WHOAMI" | "CAT" | "LOG"
+            | "DRYRUN" | "GENPASS" | "LOAD" | "SAVE" | "HELP",
+            _,
+        ) => RespType::Error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try ACL HELP.",
+            subcommand
+        )),
+        _ => RespType::Error(format!(
+            "ERR unknown subcommand '{}'. Try ACL HELP.",
+            subcommand
+        )),
+    }
+}
+
+fn getuser(username: &str, client: &Client, server: &Server) -> RespType {
+    let user = match server.acl.user(username) {
+        Some(user) => user,
+        None => return RespType::Null,
+    };
+
+    let mut flags = vec![RespType::BulkString(
+        if user.enabled { "on" } else { "off" }.into(),
+    )];
+    if user.nopass {
+        flags.push(RespType::BulkString("nopass".into()));
+    }
+
+    map_reply(
+        vec![
+            ("flags", RespType::Array(flags)),
+            (
+                "passwords",
+                RespType::Array(
+                    user.passwords
+                        .iter()
+                        .map(|hash| RespType::BulkString(hash.clone()))
+                        .collect(),
+                ),
+            ),
+            ("commands", RespType::BulkString(user.describe_commands())),
+            ("keys", RespType::BulkString(user.describe_keys())),
+            ("channels", RespType::BulkString(user.describe_channels())),
+            ("selectors", RespType::Array(Vec::new())),
+        ],
+        client,
+    )
+}
+
+fn log(count: usize, client: &Client, server: &Server) -> RespType {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    RespType::Array(
+        server
+            .acl
+            .log_entries(count)
+            .into_iter()
+            .map(|entry| log_entry(entry, now, client))
+            .collect(),
+    )
+}
+
+fn log_entry(entry: LogEntry, now: u128, client: &Client) -> RespType {
+    let age = now.saturating_sub(entry.created) as f64 / 1000.0;
+
+    map_reply(
+        vec![
+            ("count", RespType::Integer(entry.count as i64)),
+            ("reason", RespType::BulkString(entry.reason.into())),
+            ("context", RespType::BulkString(entry.context.into())),
+            ("object", RespType::BulkString(entry.object)),
+            ("username", RespType::BulkString(entry.username)),
+            ("age-seconds", RespType::BulkString(format!("{:.3}", age))),
+            ("client-info", RespType::BulkString(entry.client_info)),
+            ("entry-id", RespType::Integer(entry.entry_id as i64)),
+            ("timestamp-created", RespType::Integer(entry.created as i64)),
+            (
+                "timestamp-last-updated",
+                RespType::Integer(entry.updated as i64),
+            ),
+        ],
+        client,
+    )
+}
+
+/// `ACL DRYRUN`, whether the user can run the command, without running it nor logging it.
+fn dryrun(username: &str, command_arr: &[RespType], server: &Server) -> RespType {
+    let user = match server.acl.user(username) {
+        Some(user) => user,
+        None => return RespType::Error(format!("ERR User '{}' not found", username)),
+    };
+
+    let command = match &command_arr[0] {
+        RespType::BulkString(name) => match server.commands.get(name) {
+            Some(command) => command,
+            None => return RespType::Error(format!("ERR Command '{}' not found", name)),
+        },
+        _ => return RespType::Error("ERR syntax error".into()),
+    };
+
+    if !command.accepts_arity(command_arr.len()) {
+        return RespType::Error(format!(
+            "ERR wrong number of arguments for '{}' command",
+            command.name()
+        ));
+    }
+
+    match user.check(command, command_arr) {
+        Ok(_) => RespType::String("OK".into()),
+        Err(denied) => RespType::BulkString(denied.message(username)),
+    }
+}
+
+/// `ACL GENPASS`, a random password from `/dev/urandom`, in hex, with at least as many bits as asked.
+fn genpass(bits: i64) -> RespType {
+    let chars = (bits as usize).div_ceil(4);
+    let mut bytes = vec![0; chars.div_ceil(2)];
+
+    match File::open("/dev/urandom").and_then(|mut file| file.read_exact(&mut bytes)) {
+        Ok(_) => {
+            let password = bytes
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>();
+
+            RespType::BulkString(password[..chars].into())
+        }
+        Err(err) => {
+            println!("[ACL GENPASS] Failed reading random bytes: {:#?}", err);
+
+            RespType::Error("ERR system error while generating the password".into())
+        }
+    }
+}
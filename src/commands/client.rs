@@ -0,0 +1,631 @@
+use std::time::Duration;
+
+use crate::{
+    client::{Client, ClientInfo, ClientKind, PauseMode, ReplyMode},
+    resp::RespType,
+    server::Server,
+    tracking::TrackingOptions,
+};
+
+use super::{commands::help_reply, registry::Subcommand, scripting::map_reply};
+
+/// Subcommands of `CLIENT`, see [`client`].
+pub(super) const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand::new(
+        "caching",
+        3,
+        "Instructs the server whether to track the keys in the next request.",
+    ),
+    Subcommand::new("getname", 2, "Returns the name of the connection."),
+    Subcommand::new(
+        "getredir",
+        2,
+        "Returns the client ID to which the connection's tracking notifications are redirected.",
+    ),
+    Subcommand::new(
+        "help",
+        2,
+        "Returns helpful text about the different subcommands.",
+    ),
+    Subcommand::new("id", 2, "Returns the unique client ID of the connection."),
+    Subcommand::new("info", 2, "Returns information about the connection."),
+    Subcommand::new("kill", -3, "Terminates open connections."),
+    Subcommand::new("list", -2, "Lists open connections."),
+    Subcommand::new(
+        "no-evict",
+        3,
+        "Sets the client eviction mode of the connection.",
+    ),
+    Subcommand::new(
+        "no-touch",
+        3,
+        "Controls whether commands sent by the client affect the LRU/LFU of accessed keys.",
+    ),
+    Subcommand::new("pause", -3, "Suspends commands processing."),
+    Subcommand::new(
+        "reply",
+        3,
+        "Instructs the server whether to reply to commands.",
+    ),
+    Subcommand::new(
+        "setinfo",
+        4,
+        "Sets information specific to the client or connection.",
+    ),
+    Subcommand::new("setname", 3, "Sets the connection name."),
+    Subcommand::new(
+        "tracking",
+        -3,
+        "Controls server-assisted client-side caching for the connection.",
+    ),
+    Subcommand::new(
+        "trackinginfo",
+        2,
+        "Returns information about server-assisted client-side caching for the connection.",
+    ),
+    Subcommand::new(
+        "unpause",
+        2,
+        "Resumes processing commands from paused clients.",
+    ),
+];
+
+/// CLIENT Command
+///
+/// Inspect or change the connection of the client, or the connections of every other client.
+///
+/// Currently implemented syntax
+/// `CLIENT ID`
+/// `CLIENT INFO`
+/// `CLIENT LIST [TYPE NORMAL | MASTER | REPLICA | PUBSUB] [ID client-id [client-id ...]]`
+/// `CLIENT SETNAME connection-name`
+/// `CLIENT GETNAME`
+/// `CLIENT SETINFO LIB-NAME libname | LIB-VER libver`
+/// `CLIENT KILL ip:port`
+/// `CLIENT KILL [ID client-id] [TYPE type] [USER username] [ADDR ip:port] [SKIPME YES | NO] [MAXAGE seconds]`
+/// `CLIENT PAUSE timeout [WRITE | ALL]`
+/// `CLIENT UNPAUSE`
+/// `CLIENT REPLY ON | OFF | SKIP`
+/// `CLIENT NO-EVICT ON | OFF`
+/// `CLIENT NO-TOUCH ON | OFF`
+/// `CLIENT TRACKING ON | OFF [REDIRECT client-id] [PREFIX prefix [PREFIX prefix ...]] [BCAST] [OPTIN] [OPTOUT] [NOLOOP]`
+/// `CLIENT CACHING YES | NO`
+/// `CLIENT GETREDIR`
+/// `CLIENT TRACKINGINFO`
+/// `CLIENT HELP`
+pub fn client(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    let subcommand = match args.first() {
+        Some(RespType::BulkString(subcommand)) => subcommand.to_uppercase(),
+        _ => return RespType::Error("ERR syntax error".into()),
+    };
+
+    let strings = args[1..]
+        .iter()
+        .map(|arg| match arg {
+            RespType::BulkString(arg) => Some(arg.as_str()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    let strings = match strings {
+        Some(strings) => strings,
+        None => return RespType::Error("ERR syntax error".into()),
+    };
+
+    match (subcommand.as_str(), strings.as_slice()) {
+        ("ID", []) => RespType::Integer(client.id as i64),
+        ("INFO", []) => RespType::BulkString(format!("{}\n", client.info())),
+        ("LIST", filters) => list(filters, client, server),
+        ("SETNAME", [name]) if !is_valid_info(name) => RespType::Error(
+            "ERR Client names cannot contain spaces, newlines or special characters.".into(),
+        ),
+        ("SETNAME", [name]) => {
+            client.name = name.to_string();
+
+            RespType::String("OK".into())
+        }
+        ("GETNAME", []) if client.name.is_empty() => RespType::Null,
+        ("GETNAME", []) => RespType::BulkString(client.name.clone()),
+        ("SETINFO", [attribute, value]) => setinfo(attribute, value, client),
+        ("KILL", [addr]) => {
+            if server.clients.kill(|info| info.addr == *addr) == 0 {
+                RespType::Error("ERR No such client".into())
+            } else {
+                RespType::String("OK".into())
+            }
+        }
+        ("KILL", filters) if !filters.is_empty() => kill(filters, client, server),
+        ("PAUSE", [timeout, mode @ ..]) if mode.len() <= 1 => {
+            let mode = match mode.first().map(|mode| mode.to_uppercase()).as_deref() {
+                None | Some("ALL") => PauseMode::All,
+                Some("WRITE") => PauseMode::Write,
+                Some(_) => return RespType::Error("ERR syntax error".into()),
+            };
+
+            match timeout.parse::<i64>() {
+                Ok(timeout) if timeout < 0 => RespType::Error("ERR timeout is negative".into()),
+                Ok(timeout) => {
+                    server
+                        .clients
+                        .pause(mode, Duration::from_millis(timeout as u64));
+
+                    RespType::String("OK".into())
+                }
+                Err(_) => RespType::Error("ERR timeout is not an integer or out of range".into()),
+            }
+        }
+        ("UNPAUSE", []) => {
+            server.clients.unpause();
+
+            RespType::String("OK".into())
+        }
+        ("REPLY", [mode]) => {
+            match mode.to_uppercase().as_str() {
+                "ON" => client.reply_mode = ReplyMode::On,
+                "OFF" => client.reply_mode = ReplyMode::Off,
+                "SKIP" => client.reply_mode = ReplyMode::SkipNext,
+                _ => return RespType::Error("ERR syntax error".into()),
+            }
+
+            RespType::String("OK".into())
+        }
+        ("NO-EVICT", [switch]) => match parse_switch(switch) {
+            Some(on) => {
+                client.no_evict = on;
+
+                RespType::String("OK".into())
+            }
+            None => RespType::Error("ERR syntax error".into()),
+        },
+        ("NO-TOUCH", [switch]) => match parse_switch(switch) {
+            Some(on) => {
+                client.no_touch = on;
+
+                RespType::String("OK".into())
+            }
+            None => RespType::Error("ERR syntax error".into()),
+        },
+        ("TRACKING", [switch, options @ ..]) => tracking(switch, options, client, server),
+        ("CACHING", [switch]) => caching(switch, client),
+        ("GETREDIR", []) => RespType::Integer(client.info().redir),
+        ("TRACKINGINFO", []) => trackinginfo(client, server),
+        ("HELP", []) => help_reply(
+            "CLIENT",
+            &[
+                "CACHING (YES|NO)",
+                "    Enable/disable tracking of the keys for next command in OPTIN/OPTOUT modes.",
+                "GETREDIR",
+                "    Return the client ID we are redirecting to when tracking is enabled.",
+                "GETNAME",
+                "    Return the name of the current connection.",
+                "ID",
+                "    Return the ID of the current connection.",
+                "INFO",
+                "    Return information about the current client connection.",
+                "KILL <ip:port>",
+                "    Kill connection made from <ip:port>.",
+                "KILL <option> <value> [<option> <value> [...]]",
+                "    Kill connections. Options are:",
+                "    * ADDR (<ip:port>|<unixsocket>:0)",
+                "      Kill connections made from the specified address",
+                "    * TYPE (NORMAL|MASTER|REPLICA|PUBSUB)",
+                "      Kill connections by type.",
+                "    * USER <username>",
+                "      Kill connections authenticated by <username>.",
+                "    * SKIPME (YES|NO)",
+                "      Skip killing current connection (default: yes).",
+                "    * ID <client-id>",
+                "      Kill connections by client id.",
+                "    * MAXAGE <maxage>",
+                "      Kill connections older than the specified age.",
+                "LIST [options ...]",
+                "    Return information about client connections. Options:",
+                "    * TYPE (NORMAL|MASTER|REPLICA|PUBSUB)",
+                "      Return clients of specified type.",
+                "    * ID <client-id> [<client-id> ...]",
+                "      Return clients of specified IDs only.",
+                "PAUSE <timeout> [WRITE|ALL]",
+                "    Suspend all, or just write, clients for <timeout> milliseconds.",
+                "UNPAUSE",
+                "    Stop the current client pause, resuming traffic.",
+                "REPLY (ON|OFF|SKIP)",
+                "    Control the replies sent to the current connection.",
+                "SETNAME <name>",
+                "    Assign the name <name> to the current connection.",
+                "SETINFO <option> <value>",
+                "    Set client meta attr. Options are:",
+                "    * LIB-NAME: the client lib name.",
+                "    * LIB-VER: the client lib version.",
+                "NO-EVICT (ON|OFF)",
+                "    Protect current client connection from eviction.",
+                "NO-TOUCH (ON|OFF)",
+                "    Will not touch LRU/LFU stats when this mode is on.",
+                "TRACKING (ON|OFF) [REDIRECT <id>] [BCAST] [PREFIX <prefix> [...]]",
+                "         [OPTIN] [OPTOUT] [NOLOOP]",
+                "    Control server assisted client side caching.",
+                "TRACKINGINFO",
+                "    Report tracking status for the current connection.",
+            ],
+        ),
+        (
+            "ID" | "INFO" | "SETNAME" | "GETNAME" | "SETINFO" | "KILL" | "PAUSE" | "UNPAUSE"
+            | "REPLY" | "NO-EVICT" | "NO-TOUCH" | "TRACKING" | "CACHING" | "GETREDIR"
+            | "TRACKINGINFO" | "HELP",
+            _,
+        ) => RespType::Error(format!(
+            "ERR wrong number of arguments for 'client|{}' command",
+            subcommand.to_lowercase()
+        )),
+        _ => RespType::Error(format!(
+            "ERR unknown subcommand '{}'. Try CLIENT HELP.",
+            subcommand
+        )),
+    }
+}
+
+/// `CLIENT LIST`, the client itself is always shown as it is right now.
+fn list(filters: &[&str], client: &Client, server: &Server) -> RespType {
+    let filter: Box<dyn Fn(&ClientInfo) -> bool> = match filters {
+        [] => Box::new(|_| true),
+        [option, kind] if option.eq_ignore_ascii_case("TYPE") => match ClientKind::parse(kind) {
+            Some(kind) => Box::new(move |info| info.kind == kind),
+            None => return RespType::Error(format!("ERR Unknown client type '{}'", kind)),
+        },
+        [option, ids @ ..] if option.eq_ignore_ascii_case("ID") && !ids.is_empty() => {
+            let ids = ids
+                .iter()
+                .map(|id| id.parse::<u64>().ok().filter(|id| *id > 0))
+                .collect::<Option<Vec<_>>>();
+
+            match ids {
+                Some(ids) => Box::new(move |info| ids.contains(&info.id)),
+                None => return RespType::Error("ERR Invalid client ID".into()),
+            }
+        }
+        _ => return RespType::Error("ERR syntax error".into()),
+    };
+
+    let lines = server
+        .clients
+        .list()
+        .into_iter()
+        .map(|info| {
+            if info.id == client.id {
+                client.info()
+            } else {
+                info
+            }
+        })
+        .filter(|info| filter(info))
+        .map(|info| format!("{}\n", info))
+        .collect::<String>();
+
+    RespType::BulkString(lines)
+}
+
+/// `CLIENT KILL` with filters, every filter has to match for a client to be killed.
+fn kill(filters: &[&str], client: &Client, server: &Server) -> RespType {
+    if !filters.len().is_multiple_of(2) {
+        return RespType::Error("ERR syntax error".into());
+    }
+
+    let mut id = None;
+    let mut kind = None;
+    let mut user = None;
+    let mut addr = None;
+    let mut skip_me = true;
+    let mut max_age = None;
+
+    for filter in filters.chunks(2) {
+        let (option, value) = (filter[0], filter[1]);
+
+        match option.to_uppercase().as_str() {
+            "ID" => match value.parse::<u64>() {
+                Ok(value) if value > 0 => id = Some(value),
+                _ => {
+                    return RespType::Error("ERR client-id should be greater than 0".into());
+                }
+            },
+            "TYPE" => match ClientKind::parse(value) {
+                Some(value) => kind = Some(value),
+                None => return RespType::Error(format!("ERR Unknown client type '{}'", value)),
+            },
+            "USER" => match server.acl.user(value) {
+                Some(_) => user = Some(value),
+                None => return RespType::Error(format!("ERR No such user '{}'", value)),
+            },
+            "ADDR" => addr = Some(value),
+            "SKIPME" => match parse_yes_no(value) {
+                Some(value) => skip_me = value,
+                None => return RespType::Error("ERR syntax error".into()),
+            },
+            "MAXAGE" => match value.parse::<u64>() {
+                Ok(value) => max_age = Some(value),
+                Err(_) => {
+                    return RespType::Error("ERR value is not an integer or out of range".into())
+                }
+            },
+            _ => return RespType::Error("ERR syntax error".into()),
+        }
+    }
+
+    let killed = server.clients.kill(|info| {
+        id.is_none_or(|id| info.id == id)
+            && kind.is_none_or(|kind| info.kind == kind)
+            && user.is_none_or(|user| info.user == user)
+            && addr.is_none_or(|addr| info.addr == addr)
+            && max_age.is_none_or(|max_age| info.created.elapsed().as_secs() >= max_age)
+            && !(skip_me && info.id == client.id)
+    });
+
+    RespType::Integer(killed as i64)
+}
+
+/// `CLIENT TRACKING`, turning it on again while it's already on updates the options, prefixes are added
+/// to the ones already there.
+fn tracking(switch: &str, options: &[&str], client: &mut Client, server: &Server) -> RespType {
+    let on = match parse_switch(switch) {
+        Some(on) => on,
+        None => return RespType::Error("ERR syntax error".into()),
+    };
+    if !on {
+        if client.tracking.take().is_some() {
+            server.tracking.disable(client.id);
+        }
+        client.tracking_caching = None;
+
+        return RespType::String("OK".into());
+    }
+
+    let mut tracking = TrackingOptions::default();
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.to_uppercase().as_str() {
+            "REDIRECT" => match options.next().map(|id| id.parse::<u64>()) {
+                Some(Ok(id)) => tracking.redirect = Some(id),
+                Some(Err(_)) => return RespType::Error("ERR Invalid client ID".into()),
+                None => return RespType::Error("ERR syntax error".into()),
+            },
+            "PREFIX" => match options.next() {
+                Some(prefix) => tracking.prefixes.push(prefix.to_string()),
+                None => return RespType::Error("ERR syntax error".into()),
+            },
+            "BCAST" => tracking.bcast = true,
+            "OPTIN" => tracking.optin = true,
+            "OPTOUT" => tracking.optout = true,
+            "NOLOOP" => tracking.noloop = true,
+            _ => return RespType::Error("ERR syntax error".into()),
+        }
+    }
+
+    if !tracking.bcast && !tracking.prefixes.is_empty() {
+        return RespType::Error("ERR PREFIX option requires BCAST mode to be enabled".into());
+    }
+    if tracking.bcast && (tracking.optin || tracking.optout) {
+        return RespType::Error("ERR OPTIN and OPTOUT are not compatible with BCAST".into());
+    }
+    if tracking.optin && tracking.optout {
+        return RespType::Error("ERR You can't use both OPTIN and OPTOUT".into());
+    }
+    if let Some(current) = &client.tracking {
+        if current.bcast != tracking.bcast {
+            return RespType::Error("ERR You can't switch BCAST mode on/off before disabling tracking for this client, and then re-enabling it with a different mode.".into());
+        }
+        if current.optin != tracking.optin || current.optout != tracking.optout {
+            return RespType::Error("ERR You can't switch OPTIN/OPTOUT mode before disabling tracking for this client, and then re-enabling it with a different mode.".into());
+        }
+
+        let mut prefixes = current.prefixes.clone();
+        prefixes.append(&mut tracking.prefixes);
+        tracking.prefixes = prefixes;
+    }
+    for (index, prefix) in tracking.prefixes.iter().enumerate() {
+        let overlapping = tracking.prefixes[..index]
+            .iter()
+            .find(|other| other.starts_with(prefix.as_str()) || prefix.starts_with(other.as_str()));
+        if let Some(other) = overlapping {
+            return RespType::Error(format!(
+                "ERR Prefix '{}' overlaps with an existing prefix '{}'. Prefixes for a single client must not overlap.",
+                prefix, other
+            ));
+        }
+    }
+    if let Some(redirect) = tracking.redirect {
+        if !server.clients.contains(redirect) {
+            return RespType::Error("ERR The client ID you want redirect to does not exist".into());
+        }
+    }
+
+    server.tracking.enable(client.id, tracking.clone());
+    client.tracking = Some(tracking);
+
+    RespType::String("OK".into())
+}
+
+/// `CLIENT CACHING`, only for the next command, see [`TrackingOptions::optin`].
+fn caching(switch: &str, client: &mut Client) -> RespType {
+    let (optin, optout) = match &client.tracking {
+        Some(tracking) if tracking.optin || tracking.optout => (tracking.optin, tracking.optout),
+        _ => return RespType::Error("ERR CLIENT CACHING can be called only when the client is in tracking mode with OPTIN or OPTOUT mode enabled".into()),
+    };
+
+    match switch.to_uppercase().as_str() {
+        "YES" if optin => client.tracking_caching = Some(true),
+        "YES" => {
+            return RespType::Error(
+                "ERR CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode."
+                    .into(),
+            )
+        }
+        "NO" if optout => client.tracking_caching = Some(false),
+        "NO" => {
+            return RespType::Error(
+                "ERR CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode."
+                    .into(),
+            )
+        }
+        _ => return RespType::Error("ERR syntax error".into()),
+    }
+
+    RespType::String("OK".into())
+}
+
+/// `CLIENT TRACKINGINFO`, with the flags named the same as Redis.
+fn trackinginfo(client: &Client, server: &Server) -> RespType {
+    let mut flags = Vec::new();
+    let mut prefixes = Vec::new();
+    match &client.tracking {
+        None => flags.push("off"),
+        Some(tracking) => {
+            flags.push("on");
+            for (flag, set) in [
+                ("bcast", tracking.bcast),
+                ("optin", tracking.optin),
+                ("optout", tracking.optout),
+                ("caching-yes", client.tracking_caching == Some(true)),
+                ("caching-no", client.tracking_caching == Some(false)),
+                ("noloop", tracking.noloop),
+                (
+                    "broken_redirect",
+                    server.tracking.is_redirect_broken(client.id),
+                ),
+            ] {
+                if set {
+                    flags.push(flag);
+                }
+            }
+
+            prefixes = tracking.prefixes.clone();
+        }
+    }
+
+    let to_array = |values: Vec<String>| {
+        RespType::Array(values.into_iter().map(RespType::BulkString).collect())
+    };
+
+    map_reply(
+        vec![
+            (
+                "flags",
+                to_array(flags.into_iter().map(String::from).collect()),
+            ),
+            ("redirect", RespType::Integer(client.info().redir)),
+            ("prefixes", to_array(prefixes)),
+        ],
+        client,
+    )
+}
+
+/// `CLIENT SETINFO`, the library names are shown by `CLIENT LIST`.
+fn setinfo(attribute: &str, value: &str, client: &mut Client) -> RespType {
+    let attribute = attribute.to_lowercase();
+    let field = match attribute.as_str() {
+        "lib-name" => &mut client.lib_name,
+        "lib-ver" => &mut client.lib_ver,
+        _ => {
+            return RespType::Error(format!("ERR Unrecognized option '{}'", attribute));
+        }
+    };
+
+    if !is_valid_info(value) {
+        return RespType::Error(format!(
+            "ERR {} cannot contain spaces, newlines or special characters.",
+            attribute
+        ));
+    }
+    *field = value.to_string();
+
+    RespType::String("OK".into())
+}
+
+/// Names shown by `CLIENT LIST` can only have printable characters other than a space, so every field is
+/// separated by spaces.
+fn is_valid_info(value: &str) -> bool {
+    value.chars().all(|char| char.is_ascii_graphic())
+}
+
+fn parse_switch(value: &str) -> Option<bool> {
+    match value.to_uppercase().as_str() {
+        "ON" => Some(true),
+        "OFF" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_yes_no(value: &str) -> Option<bool> {
+    match value.to_uppercase().as_str() {
+        "YES" => Some(true),
+        "NO" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod client_tests {
+    use crate::{
+        client::Client, commands::commands::handle_commands, resp::RespType, server::Server,
+        test_util::command,
+    };
+
+    #[test]
+    fn lists_and_kills_registered_clients() {
+        let server = Server::new();
+        let mut client = Client::with_addr("127.0.0.1:1000".into());
+        let other = Client::with_addr("127.0.0.1:2000".into());
+        server.clients.register(&client);
+        server.clients.register(&other);
+
+        assert_eq!(
+            handle_commands(
+                command(&["CLIENT", "SETNAME", "my name"]),
+                &mut client,
+                &server
+            ),
+            RespType::Error(
+                "ERR Client names cannot contain spaces, newlines or special characters.".into()
+            )
+        );
+        handle_commands(command(&["CLIENT", "SETNAME", "me"]), &mut client, &server);
+
+        let list = match handle_commands(command(&["CLIENT", "LIST"]), &mut client, &server) {
+            RespType::BulkString(list) => list,
+            reply => panic!("Unexpected reply {:?}", reply),
+        };
+        let lines = list.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(&format!(
+            "id={} addr=127.0.0.1:1000 name=me age=0 idle=0 flags=N db=0",
+            client.id
+        )));
+        assert!(lines[0].contains(" cmd=client|list "));
+
+        assert_eq!(
+            handle_commands(
+                command(&["CLIENT", "KILL", "ADDR", "127.0.0.1:1000"]),
+                &mut client,
+                &server
+            ),
+            RespType::Integer(0)
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["CLIENT", "KILL", "TYPE", "normal"]),
+                &mut client,
+                &server
+            ),
+            RespType::Integer(1)
+        );
+        assert!(other.is_killed());
+        assert!(!client.is_killed());
+        assert_eq!(server.clients.len(), 1);
+
+        match handle_commands(command(&["CLIENT", "HELP"]), &mut client, &server) {
+            RespType::Array(lines) => assert_eq!(
+                lines.first(),
+                Some(&RespType::String(
+                    "CLIENT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:".into()
+                ))
+            ),
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+    }
+}
@@ -0,0 +1,582 @@
+use crate::{client::Client, glob::glob_match, resp::RespType, server::Server};
+
+use super::{
+    commands::help_reply,
+    registry::{AclCategories, Command, KeySpec, Subcommand},
+    scripting::map_reply,
+};
+
+/// Subcommands of `COMMAND`, see [`command`].
+pub(super) const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand::new("count", 2, "Returns a count of commands."),
+    Subcommand::new(
+        "docs",
+        -2,
+        "Returns documentary information about one, multiple or all commands.",
+    ),
+    Subcommand::new(
+        "getkeys",
+        -3,
+        "Extracts the key names from an arbitrary command.",
+    ),
+    Subcommand::new(
+        "getkeysandflags",
+        -3,
+        "Extracts the key names and access flags for an arbitrary command.",
+    ),
+    Subcommand::new(
+        "help",
+        2,
+        "Returns helpful text about the different subcommands.",
+    ),
+    Subcommand::new(
+        "info",
+        -2,
+        "Returns information about one, multiple or all commands.",
+    ),
+    Subcommand::new("list", -2, "Returns a list of command names."),
+];
+
+/// COMMAND Command
+///
+/// Describe the commands of the server, generated from the metadata in the command registry, the same way
+/// as Redis. Client libraries use it to find the arity and the keys of every command.
+///
+/// Currently implemented syntax
+/// `COMMAND`
+/// `COMMAND COUNT`
+/// `COMMAND INFO [command-name [command-name ...]]`
+/// `COMMAND DOCS [command-name [command-name ...]]`
+/// `COMMAND LIST [FILTERBY MODULE module-name | ACLCAT category | PATTERN pattern]`
+/// `COMMAND GETKEYS command [arg [arg ...]]`
+/// `COMMAND GETKEYSANDFLAGS command [arg [arg ...]]`
+/// `COMMAND HELP`
+pub fn command(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    let subcommand = match args.first() {
+        Some(RespType::BulkString(subcommand)) => subcommand.to_uppercase(),
+        Some(_) => return RespType::Error("ERR syntax error".into()),
+        None => {
+            return RespType::Array(
+                sorted_commands(server)
+                    .into_iter()
+                    .map(|command| command_info(command, client))
+                    .collect(),
+            )
+        }
+    };
+
+    match (subcommand.as_str(), &args[1..]) {
+        ("COUNT", []) => RespType::Integer(server.commands.len() as i64),
+        ("INFO", []) => RespType::Array(
+            sorted_commands(server)
+                .into_iter()
+                .map(|command| command_info(command, client))
+                .collect(),
+        ),
+        ("INFO", names) => RespType::Array(
+            names
+                .iter()
+                .map(|name| match lookup(name, server) {
+                    Some(command) => command_info(command, client),
+                    None => RespType::NullArray,
+                })
+                .collect(),
+        ),
+        ("DOCS", []) => {
+            let docs = sorted_commands(server)
+                .into_iter()
+                .map(|command| (command.name(), command_docs(command, client)))
+                .collect();
+
+            map_reply(docs, client)
+        }
+        ("DOCS", names) => {
+            let docs = names
+                .iter()
+                .filter_map(|name| lookup(name, server))
+                .map(|command| (command.name(), command_docs(command, client)))
+                .collect();
+
+            map_reply(docs, client)
+        }
+        ("LIST", filter) => list(filter, server),
+        ("GETKEYS" | "GETKEYSANDFLAGS", [_, ..]) => {
+            getkeys(&args[1..], subcommand == "GETKEYSANDFLAGS", server)
+        }
+        ("HELP", []) => help_reply(
+            "COMMAND",
+            &[
+                "(no subcommand)",
+                "    Return details about all Redis commands.",
+                "COUNT",
+                "    Return the total number of commands in this Redis server.",
+                "LIST",
+                "    Return a list of all commands in this Redis server.",
+                "INFO [<command-name> ...]",
+                "    Return details about multiple Redis commands.",
+                "    If no command names are given, documentation details for all",
+                "    commands are returned.",
+                "DOCS [<command-name> ...]",
+                "    Return documentation details about multiple Redis commands.",
+                "    If no command names are given, documentation details for all",
+                "    commands are returned.",
+                "GETKEYS <full-command>",
+                "    Return the keys from a full Redis command.",
+                "GETKEYSANDFLAGS <full-command>",
+                "    Return the keys and the access flags from a full Redis command.",
+            ],
+        ),
+        ("COUNT" | "GETKEYS" | "GETKEYSANDFLAGS" | "HELP", _) => RespType::Error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try COMMAND HELP.",
+            subcommand
+        )),
+        _ => RespType::Error(format!(
+            "ERR unknown subcommand '{}'. Try COMMAND HELP.",
+            subcommand
+        )),
+    }
+}
+
+/// Every registered command, sorted by name so the replies are always in the same order.
+fn sorted_commands(server: &Server) -> Vec<&dyn Command> {
+    let mut commands = server.commands.iter().collect::<Vec<_>>();
+    commands.sort_by(|a, b| a.name().cmp(b.name()));
+
+    commands
+}
+
+fn lookup<'a>(name: &RespType, server: &'a Server) -> Option<&'a dyn Command> {
+    match name {
+        RespType::BulkString(name) => server.commands.get(name),
+        _ => None,
+    }
+}
+
+fn string_array(strings: &[&str]) -> RespType {
+    RespType::Array(
+        strings
+            .iter()
+            .map(|string| RespType::String(string.to_string()))
+            .collect(),
+    )
+}
+
+/// Reply of `COMMAND INFO` for a command, with the legacy key positions, and the key specifications.
+fn command_info(command: &dyn Command, client: &Client) -> RespType {
+    let subcommands = command
+        .subcommands()
+        .iter()
+        .map(|subcommand| {
+            let name = format!("{}|{}", command.name(), subcommand.name);
+
+            info_reply(name, subcommand.arity, command, Vec::new(), client)
+        })
+        .collect();
+
+    info_reply(
+        command.name().into(),
+        command.arity(),
+        command,
+        subcommands,
+        client,
+    )
+}
+
+/// Reply of `COMMAND INFO` for a command or one of its subcommands, as subcommands share everything else
+/// with the command.
+fn info_reply(
+    name: String,
+    arity: i64,
+    command: &dyn Command,
+    subcommands: Vec<RespType>,
+    client: &Client,
+) -> RespType {
+    let key_spec = command.key_spec();
+    let categories = command
+        .acl_categories()
+        .names()
+        .into_iter()
+        .map(|category| RespType::String(format!("@{}", category)))
+        .collect();
+
+    RespType::Array(vec![
+        RespType::BulkString(name),
+        RespType::Integer(arity),
+        string_array(&command.flags().names()),
+        RespType::Integer(key_spec.first),
+        RespType::Integer(key_spec.last),
+        RespType::Integer(key_spec.step),
+        RespType::Array(categories),
+        // NOTE: No command has any tips for now
+        RespType::Array(Vec::new()),
+        key_specifications(key_spec, client),
+        RespType::Array(subcommands),
+    ])
+}
+
+/// Key specifications in the format of Redis 7.
+fn key_specifications(key_spec: KeySpec, client: &Client) -> RespType {
+    let map = |entries| map_reply(entries, client);
+
+    let (index, find_keys) = if key_spec.numkeys > 0 {
+        (
+            key_spec.numkeys,
+            map(vec![
+                ("type", RespType::BulkString("keynum".into())),
+                (
+                    "spec",
+                    map(vec![
+                        ("keynumidx", RespType::Integer(0)),
+                        ("firstkey", RespType::Integer(1)),
+                        ("keystep", RespType::Integer(1)),
+                    ]),
+                ),
+            ]),
+        )
+    } else if key_spec.first > 0 {
+        let lastkey = if key_spec.last < 0 {
+            key_spec.last
+        } else {
+            key_spec.last - key_spec.first
+        };
+
+        (
+            key_spec.first,
+            map(vec![
+                ("type", RespType::BulkString("range".into())),
+                (
+                    "spec",
+                    map(vec![
+                        ("lastkey", RespType::Integer(lastkey)),
+                        ("keystep", RespType::Integer(key_spec.step)),
+                        ("limit", RespType::Integer(0)),
+                    ]),
+                ),
+            ]),
+        )
+    } else {
+        return RespType::Array(Vec::new());
+    };
+
+    RespType::Array(vec![map(vec![
+        ("flags", string_array(key_spec.flags)),
+        (
+            "begin_search",
+            map(vec![
+                ("type", RespType::BulkString("index".into())),
+                ("spec", map(vec![("index", RespType::Integer(index))])),
+            ]),
+        ),
+        ("find_keys", find_keys),
+    ])])
+}
+
+/// Reply of `COMMAND DOCS` for a command.
+fn command_docs(command: &dyn Command, client: &Client) -> RespType {
+    let docs = command.docs();
+    let mut entries = vec![
+        ("summary", RespType::BulkString(docs.summary.into())),
+        ("since", RespType::BulkString(docs.since.into())),
+        ("group", RespType::BulkString(docs.group.into())),
+    ];
+    if !docs.complexity.is_empty() {
+        entries.push(("complexity", RespType::BulkString(docs.complexity.into())));
+    }
+
+    let names = command
+        .subcommands()
+        .iter()
+        .map(|subcommand| format!("{}|{}", command.name(), subcommand.name))
+        .collect::<Vec<_>>();
+    if !names.is_empty() {
+        let subcommands = names
+            .iter()
+            .zip(command.subcommands())
+            .map(|(name, subcommand)| {
+                let subcommand_docs = vec![
+                    ("summary", RespType::BulkString(subcommand.summary.into())),
+                    ("since", RespType::BulkString(docs.since.into())),
+                    ("group", RespType::BulkString(docs.group.into())),
+                ];
+
+                (name.as_str(), map_reply(subcommand_docs, client))
+            })
+            .collect();
+
+        entries.push(("subcommands", map_reply(subcommands, client)));
+    }
+
+    map_reply(entries, client)
+}
+
+/// `COMMAND LIST`, the name of every command, optionally filtered.
+///
+/// NOTE: There's no module, so filtering by module always gives an empty list.
+fn list(filter: &[RespType], server: &Server) -> RespType {
+    let filter = match filter {
+        [] => None,
+        [RespType::BulkString(filterby), RespType::BulkString(kind), RespType::BulkString(value)]
+            if filterby.eq_ignore_ascii_case("FILTERBY") =>
+        {
+            Some((kind.to_uppercase(), value))
+        }
+        _ => return RespType::Error("ERR syntax error".into()),
+    };
+
+    let category = match &filter {
+        Some((kind, value)) if kind == "ACLCAT" => AclCategories::NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(value))
+            .map(|(_, category)| *category),
+        Some((kind, _)) if kind != "MODULE" && kind != "PATTERN" => {
+            return RespType::Error("ERR syntax error".into())
+        }
+        _ => None,
+    };
+
+    let names = sorted_commands(server)
+        .into_iter()
+        .filter(|command| match &filter {
+            None => true,
+            Some((kind, _)) if kind == "MODULE" => false,
+            Some((kind, _)) if kind == "ACLCAT" => {
+                category.is_some_and(|category| command.acl_categories().contains(category))
+            }
+            Some((_, pattern)) => glob_match(&pattern.to_lowercase(), command.name()),
+        })
+        .map(|command| RespType::BulkString(command.name().into()))
+        .collect();
+
+    RespType::Array(names)
+}
+
+/// `COMMAND GETKEYS` and `COMMAND GETKEYSANDFLAGS`, the keys of a command, found the same way as the
+/// command would find them.
+fn getkeys(command_arr: &[RespType], with_flags: bool, server: &Server) -> RespType {
+    let command = match lookup(&command_arr[0], server) {
+        Some(command) => command,
+        None => return RespType::Error("ERR Invalid command specified".into()),
+    };
+
+    if !command.accepts_arity(command_arr.len()) {
+        return RespType::Error("ERR Invalid number of arguments specified for command".into());
+    }
+
+    let key_spec = command.key_spec();
+    let positions = match key_spec.positions(command_arr) {
+        Some(positions) if positions.is_empty() => {
+            return RespType::Error("ERR The command has no key arguments".into())
+        }
+        Some(positions) => positions,
+        None => return RespType::Error("ERR Invalid arguments specified for command".into()),
+    };
+
+    RespType::Array(
+        positions
+            .into_iter()
+            .map(|position| {
+                let key = command_arr[position].clone();
+
+                if with_flags {
+                    RespType::Array(vec![key, string_array(key_spec.flags)])
+                } else {
+                    key
+                }
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod command_tests {
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    fn strings(strings: &[&str]) -> RespType {
+        RespType::Array(
+            strings
+                .iter()
+                .map(|string| RespType::String(string.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn describes_commands() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        let info = match handle_commands(
+            command(&["COMMAND", "INFO", "get", "nope"]),
+            &mut client,
+            &server,
+        ) {
+            RespType::Array(info) => info,
+            reply => panic!("Unexpected reply {:?}", reply),
+        };
+        assert_eq!(info[1], RespType::NullArray);
+
+        let get = match &info[0] {
+            RespType::Array(get) => get,
+            reply => panic!("Unexpected reply {:?}", reply),
+        };
+        assert_eq!(get[0], RespType::BulkString("get".into()));
+        assert_eq!(get[1], RespType::Integer(2));
+        assert_eq!(get[2], strings(&["readonly", "fast"]));
+        assert_eq!(
+            get[3..6],
+            [
+                RespType::Integer(1),
+                RespType::Integer(1),
+                RespType::Integer(1)
+            ]
+        );
+        assert_eq!(get[6], strings(&["@read", "@string", "@fast"]));
+        assert_eq!(get[9], RespType::Array(Vec::new()));
+
+        let slowlog = match handle_commands(
+            command(&["COMMAND", "INFO", "slowlog"]),
+            &mut client,
+            &server,
+        ) {
+            RespType::Array(info) => match &info[0] {
+                RespType::Array(slowlog) => slowlog.clone(),
+                reply => panic!("Unexpected reply {:?}", reply),
+            },
+            reply => panic!("Unexpected reply {:?}", reply),
+        };
+        let subcommands = match &slowlog[9] {
+            RespType::Array(subcommands) => subcommands,
+            reply => panic!("Unexpected reply {:?}", reply),
+        };
+        assert_eq!(subcommands.len(), 4);
+        match &subcommands[0] {
+            RespType::Array(get) => {
+                assert_eq!(get[0], RespType::BulkString("slowlog|get".into()));
+                assert_eq!(get[1], RespType::Integer(-2));
+                assert_eq!(get[2], slowlog[2]);
+            }
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+
+        match handle_commands(
+            command(&["COMMAND", "DOCS", "slowlog"]),
+            &mut client,
+            &server,
+        ) {
+            RespType::Array(docs) => match &docs[1] {
+                RespType::Array(docs) => {
+                    assert_eq!(docs[8], RespType::BulkString("subcommands".into()));
+                    assert!(matches!(
+                        &docs[9],
+                        RespType::Array(subcommands)
+                            if subcommands[0] == RespType::BulkString("slowlog|get".into())
+                    ));
+                }
+                reply => panic!("Unexpected reply {:?}", reply),
+            },
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+
+        assert_eq!(
+            handle_commands(command(&["COMMAND", "COUNT"]), &mut client, &server),
+            RespType::Integer(server.commands.len() as i64)
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["COMMAND", "LIST", "FILTERBY", "PATTERN", "H*ALL"]),
+                &mut client,
+                &server
+            ),
+            RespType::Array(vec![RespType::BulkString("hgetall".into())])
+        );
+
+        match handle_commands(command(&["COMMAND", "HELP"]), &mut client, &server) {
+            RespType::Array(lines) => assert_eq!(
+                lines.first(),
+                Some(&RespType::String(
+                    "COMMAND <subcommand> [<arg> [value] [opt] ...]. Subcommands are:".into()
+                ))
+            ),
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+        assert_eq!(
+            handle_commands(
+                command(&["COMMAND", "LIST", "FILTERBY", "ACLCAT", "transaction"]),
+                &mut client,
+                &server
+            ),
+            RespType::Array(
+                ["discard", "exec", "multi", "unwatch", "watch"]
+                    .into_iter()
+                    .map(|name| RespType::BulkString(name.into()))
+                    .collect()
+            )
+        );
+    }
+
+    #[test]
+    fn gets_keys_of_commands() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        assert_eq!(
+            handle_commands(
+                command(&["COMMAND", "GETKEYS", "DEL", "a", "b"]),
+                &mut client,
+                &server
+            ),
+            RespType::Array(vec![
+                RespType::BulkString("a".into()),
+                RespType::BulkString("b".into())
+            ])
+        );
+        assert_eq!(
+            handle_commands(
+                command(&[
+                    "COMMAND",
+                    "GETKEYSANDFLAGS",
+                    "EVAL",
+                    "return 1",
+                    "1",
+                    "key",
+                    "arg"
+                ]),
+                &mut client,
+                &server
+            ),
+            RespType::Array(vec![RespType::Array(vec![
+                RespType::BulkString("key".into()),
+                strings(&["RW", "ACCESS", "UPDATE"])
+            ])])
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["COMMAND", "GETKEYS", "EVAL", "return 1", "3", "key"]),
+                &mut client,
+                &server
+            ),
+            RespType::Error("ERR Invalid arguments specified for command".into())
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["COMMAND", "GETKEYS", "PING"]),
+                &mut client,
+                &server
+            ),
+            RespType::Error("ERR The command has no key arguments".into())
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["COMMAND", "GETKEYS", "GET"]),
+                &mut client,
+                &server
+            ),
+            RespType::Error("ERR Invalid number of arguments specified for command".into())
+        );
+    }
+}
@@ -1,32 +1,929 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock},
+use std::time::Instant;
+
+use crate::{
+    acl::LogContext, client::Client, monitor::Origin, resp::RespType, server::Server,
+    storage::Storage,
+};
+
+use super::{
+    acl, client, command,
+    config::{self, config},
+    hello::hello,
+    info::info,
+    key_op,
+    latency::{self, latency},
+    monitor::monitor,
+    persistence,
+    ping::ping,
+    pubsub,
+    registry::{AclCategories, Command, CommandFlags, CommandSpec},
+    replication,
+    reset::reset,
+    scripting, set_op,
+    slowlog::{self, slowlog},
+    string_op, transaction,
 };
 
-use crate::{resp::RespType, storage::StorageType};
+/// Handler of a command, grouped by what kind of access the command need to the storage.
+#[derive(Debug, Clone, Copy)]
+pub enum CommandHandler {
+    /// Command that only touch the state of the client itself.
+    Client(fn(&[RespType], &mut Client) -> RespType),
+    /// Command that touch the state shared by every client, other than the storage.
+    Server(fn(&[RespType], &mut Client, &Server) -> RespType),
+    /// Command that read the storage, along with the state shared by every client.
+    ServerRead(fn(&[RespType], &mut Client, &Server, &Storage) -> RespType),
+    /// Command that write the storage by running other commands, e.g. scripts. The write commands run are
+    /// left in [`Client::propagated`], to be passed on in place of the command itself.
+    ServerWrite(fn(&[RespType], &mut Client, &Server, &mut Storage) -> RespType),
+    Read(fn(&[RespType], &Storage) -> RespType),
+    Write(fn(&[RespType], &mut Storage) -> RespType),
+}
+
+impl CommandHandler {
+    /// Run the command, locking the storage only as much as the command needs.
+    ///
+    /// Write commands that modified the storage are passed on to the append only file, see
+    /// [`Server::propagate`], unless the append only file is being loaded.
+    pub fn call(self, command: &[RespType], client: &mut Client, server: &Server) -> RespType {
+        let args = &command[1..];
+
+        match self {
+            Self::Client(handler) => handler(args, client),
+            Self::Server(handler) => handler(args, client, server),
+            Self::ServerRead(handler) => match server.storage.read() {
+                Ok(storage_locked) => handler(args, client, server, &storage_locked),
+                Err(err) => {
+                    println!("[Commands] Got poisoned storage for read: {:#?}", err);
+
+                    RespType::Error("ERR system error while getting data".into())
+                }
+            },
+            Self::ServerWrite(handler) => match server.storage.write() {
+                Ok(mut storage_locked) => {
+                    let response = handler(args, client, server, &mut storage_locked);
+                    let written = client.propagated.take().unwrap_or_default();
+                    if !written.is_empty() && !client.loading {
+                        server.propagate_transaction(&written);
+                        client.last_write_offset = server.replication.offset();
+                    }
+                    let effects = storage_locked.take_effects();
+                    drop(storage_locked);
+
+                    server.apply_storage_effects(effects, client);
+
+                    response
+                }
+                Err(err) => {
+                    println!("[Commands] Got poisoned storage for write: {:#?}", err);
+
+                    RespType::Error("ERR system error while inserting data".into())
+                }
+            },
+            Self::Read(handler) => match server.storage.read() {
+                Ok(storage_locked) => {
+                    let response = handler(args, &storage_locked);
+                    let effects = storage_locked.take_effects();
+                    drop(storage_locked);
+
+                    server.apply_storage_effects(effects, client);
+
+                    response
+                }
+                Err(err) => {
+                    println!("[Commands] Got poisoned storage for read: {:#?}", err);
+
+                    RespType::Error("ERR system error while getting data".into())
+                }
+            },
+            Self::Write(handler) => match server.storage.write() {
+                Ok(mut storage_locked) => {
+                    let response = handler(args, &mut storage_locked);
+                    let effects = storage_locked.take_effects();
+                    if effects.changes > 0 && !client.loading {
+                        server.propagate(command);
+                        client.last_write_offset = server.replication.offset();
+                    }
+                    drop(storage_locked);
+
+                    server.apply_storage_effects(effects, client);
+
+                    response
+                }
+                Err(err) => {
+                    println!("[Commands] Got poisoned storage for write: {:#?}", err);
+
+                    RespType::Error("ERR system error while inserting data".into())
+                }
+            },
+        }
+    }
+
+    /// Run the command on an already locked storage, used to run multiple commands atomically.
+    ///
+    /// NOTE: `Server` handlers must not lock the storage themselves, as the storage is already locked here.
+    /// Keyspace events are left in the storage, to be published by the caller once the lock is released.
+    /// Write commands are not passed on to the append only file, that's up to the caller as well.
+    pub fn call_locked(
+        self,
+        args: &[RespType],
+        client: &mut Client,
+        server: &Server,
+        storage: &mut Storage,
+    ) -> RespType {
+        match self {
+            Self::Client(handler) => handler(args, client),
+            Self::Server(handler) => handler(args, client, server),
+            Self::ServerRead(handler) => handler(args, client, server, storage),
+            Self::ServerWrite(handler) => handler(args, client, server, storage),
+            Self::Read(handler) => handler(args, storage),
+            Self::Write(handler) => handler(args, storage),
+        }
+    }
+}
+
+/// Every command built into the server, with the same metadata Redis gives the command.
+pub fn builtin_commands() -> Vec<CommandSpec> {
+    use AclCategories as Acl;
+    use CommandFlags as Flags;
+    use CommandHandler::{Client, Read, Server, ServerRead, ServerWrite, Write};
+
+    let subscription =
+        Flags::PUBSUB | Flags::NOSCRIPT | Flags::LOADING | Flags::STALE | Flags::NO_MULTI;
+    let transaction =
+        Flags::NOSCRIPT | Flags::LOADING | Flags::STALE | Flags::FAST | Flags::ALLOW_BUSY;
+    let script = Flags::NOSCRIPT | Flags::SKIP_MONITOR | Flags::NO_MANDATORY_KEYS | Flags::STALE;
+
+    vec![
+        CommandSpec::new("ping", -1, Client(ping))
+            .flags(Flags::FAST)
+            .acl_categories(Acl::CONNECTION)
+            .docs(
+                "Returns the server's liveliness response.",
+                "1.0.0",
+                "connection",
+                "O(1)",
+            ),
+        CommandSpec::new("hello", -1, Server(hello))
+            .flags(
+                Flags::NOSCRIPT
+                    | Flags::LOADING
+                    | Flags::STALE
+                    | Flags::FAST
+                    | Flags::NO_AUTH
+                    | Flags::ALLOW_BUSY,
+            )
+            .acl_categories(Acl::CONNECTION)
+            .docs(
+                "Handshakes with the Redis server.",
+                "6.0.0",
+                "connection",
+                "O(1)",
+            ),
+        CommandSpec::new("auth", -2, Server(acl::auth))
+            .flags(
+                Flags::NOSCRIPT
+                    | Flags::LOADING
+                    | Flags::STALE
+                    | Flags::FAST
+                    | Flags::NO_AUTH
+                    | Flags::ALLOW_BUSY,
+            )
+            .acl_categories(Acl::CONNECTION)
+            .docs(
+                "Authenticates the connection.",
+                "1.0.0",
+                "connection",
+                "O(N) where N is the number of passwords defined for the user",
+            ),
+        CommandSpec::new("acl", -2, Server(acl::acl))
+            .flags(Flags::ADMIN | Flags::NOSCRIPT | Flags::LOADING | Flags::STALE)
+            .docs(
+                "A container for Access List Control commands.",
+                "6.0.0",
+                "server",
+                "Depends on subcommand.",
+            )
+            .subcommands(acl::SUBCOMMANDS),
+        CommandSpec::new("set", -3, Write(string_op::set))
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1, &["RW", "ACCESS", "UPDATE"])
+            .acl_categories(Acl::STRING)
+            .docs(
+                "Sets the string value of a key, ignoring its type. The key is created \
+                 if it doesn't exist.",
+                "1.0.0",
+                "string",
+                "O(1)",
+            ),
+        CommandSpec::new("get", 2, Read(string_op::get))
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1, &["RO", "ACCESS"])
+            .acl_categories(Acl::STRING)
+            .docs(
+                "Returns the string value of a key.",
+                "1.0.0",
+                "string",
+                "O(1)",
+            ),
+        CommandSpec::new("del", -2, Write(string_op::del))
+            .flags(Flags::WRITE)
+            .keys(1, -1, 1, &["RM", "DELETE"])
+            .acl_categories(Acl::KEYSPACE)
+            .docs(
+                "Deletes one or more keys.",
+                "1.0.0",
+                "generic",
+                "O(N) where N is the number of keys that will be removed.",
+            ),
+        CommandSpec::new("dump", 2, Read(key_op::dump))
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1, &["RO", "ACCESS"])
+            .acl_categories(Acl::KEYSPACE)
+            .docs(
+                "Returns a serialized representation of the value stored at a key.",
+                "2.6.0",
+                "generic",
+                "O(1) to access the key and additional O(N*M) to serialize it, where N \
+                 is the number of Redis objects composing the value and M their average \
+                 size.",
+            ),
+        CommandSpec::new("restore", -4, Write(key_op::restore))
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1, &["OW", "UPDATE"])
+            .acl_categories(Acl::KEYSPACE | Acl::DANGEROUS)
+            .docs(
+                "Creates a key from the serialized representation of a value. The TTL is ignored, as \
+                 keys cannot expire yet.",
+                "2.6.0",
+                "generic",
+                "O(1) to create the new key and additional O(N*M) to reconstruct the \
+                 serialized value, where N is the number of Redis objects composing the \
+                 value and M their average size.",
+            ),
+        CommandSpec::new("hset", -4, Write(set_op::hset))
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1, &["RW", "UPDATE"])
+            .acl_categories(Acl::HASH)
+            .docs(
+                "Creates or modifies the value of a field in a hash.",
+                "2.0.0",
+                "hash",
+                "O(1) for each field/value pair added, so O(N) to add N field/value \
+                 pairs when the command is called with multiple field/value pairs.",
+            ),
+        CommandSpec::new("hget", 3, Read(set_op::hget))
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1, &["RO", "ACCESS"])
+            .acl_categories(Acl::HASH)
+            .docs(
+                "Returns the value of a field in a hash.",
+                "2.0.0",
+                "hash",
+                "O(1)",
+            ),
+        CommandSpec::new("hgetall", 2, Read(set_op::hgetall))
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1, &["RO", "ACCESS"])
+            .acl_categories(Acl::HASH)
+            .docs(
+                "Returns all fields and values in a hash.",
+                "2.0.0",
+                "hash",
+                "O(N) where N is the size of the hash.",
+            ),
+        CommandSpec::new("hdel", -3, Write(set_op::hdel))
+            .flags(Flags::WRITE | Flags::FAST)
+            .keys(1, 1, 1, &["RW", "DELETE"])
+            .acl_categories(Acl::HASH)
+            .docs(
+                "Deletes one or more fields and their values from a hash. Deletes the \
+                 hash if no fields remain.",
+                "2.0.0",
+                "hash",
+                "O(N) where N is the number of fields to be removed.",
+            ),
+        CommandSpec::new("subscribe", -2, Server(pubsub::subscribe))
+            .flags(subscription)
+            .docs(
+                "Listens for messages published to channels.",
+                "2.0.0",
+                "pubsub",
+                "O(N) where N is the number of channels to subscribe to.",
+            ),
+        CommandSpec::new("unsubscribe", -1, Server(pubsub::unsubscribe))
+            .flags(subscription)
+            .docs(
+                "Stops listening to messages posted to channels.",
+                "2.0.0",
+                "pubsub",
+                "O(N) where N is the number of channels to unsubscribe.",
+            ),
+        CommandSpec::new("psubscribe", -2, Server(pubsub::psubscribe))
+            .flags(subscription)
+            .docs(
+                "Listens for messages published to channels that match one or more \
+                 patterns.",
+                "2.0.0",
+                "pubsub",
+                "O(N) where N is the number of patterns to subscribe to.",
+            ),
+        CommandSpec::new("punsubscribe", -1, Server(pubsub::punsubscribe))
+            .flags(subscription)
+            .docs(
+                "Stops listening to messages published to channels that match one or \
+                 more patterns.",
+                "2.0.0",
+                "pubsub",
+                "O(N) where N is the number of patterns to unsubscribe.",
+            ),
+        CommandSpec::new("publish", 3, Server(pubsub::publish))
+            .flags(
+                Flags::PUBSUB | Flags::LOADING | Flags::STALE | Flags::FAST | Flags::MAY_REPLICATE,
+            )
+            .docs(
+                "Posts a message to a channel.",
+                "2.0.0",
+                "pubsub",
+                "O(N+M) where N is the number of clients subscribed to the receiving \
+                 channel and M is the total number of subscribed patterns (by any \
+                 client).",
+            ),
+        CommandSpec::new("pubsub", -2, Server(pubsub::pubsub))
+            .flags(Flags::PUBSUB | Flags::LOADING | Flags::STALE)
+            .docs(
+                "A container for Pub/Sub commands.",
+                "2.8.0",
+                "pubsub",
+                "Depends on subcommand.",
+            )
+            .subcommands(pubsub::SUBCOMMANDS),
+        CommandSpec::new("ssubscribe", -2, Server(pubsub::ssubscribe))
+            .flags(subscription)
+            .keys(1, -1, 1, &["NOT_KEY"])
+            .docs(
+                "Listens for messages published to shard channels.",
+                "7.0.0",
+                "pubsub",
+                "O(N) where N is the number of shard channels to subscribe to.",
+            ),
+        CommandSpec::new("sunsubscribe", -1, Server(pubsub::sunsubscribe))
+            .flags(subscription)
+            .keys(1, -1, 1, &["NOT_KEY"])
+            .docs(
+                "Stops listening to messages posted to shard channels.",
+                "7.0.0",
+                "pubsub",
+                "O(N) where N is the number of shard channels to unsubscribe.",
+            ),
+        CommandSpec::new("spublish", 3, Server(pubsub::spublish))
+            .flags(
+                Flags::PUBSUB | Flags::LOADING | Flags::STALE | Flags::FAST | Flags::MAY_REPLICATE,
+            )
+            .keys(1, 1, 1, &["NOT_KEY"])
+            .docs(
+                "Post a message to a shard channel",
+                "7.0.0",
+                "pubsub",
+                "O(N) where N is the number of clients subscribed to the receiving \
+                 shard channel.",
+            ),
+        CommandSpec::new("client", -2, Server(client::client))
+            .flags(Flags::NOSCRIPT | Flags::LOADING | Flags::STALE)
+            .acl_categories(Acl::CONNECTION)
+            .docs(
+                "A container for client connection commands.",
+                "2.4.0",
+                "connection",
+                "Depends on subcommand.",
+            )
+            .subcommands(client::SUBCOMMANDS),
+        CommandSpec::new("config", -2, ServerRead(config))
+            .flags(Flags::ADMIN | Flags::NOSCRIPT | Flags::LOADING | Flags::STALE)
+            .docs(
+                "A container for server configuration commands.",
+                "2.0.0",
+                "server",
+                "Depends on subcommand.",
+            )
+            .subcommands(config::SUBCOMMANDS),
+        CommandSpec::new("slowlog", -2, Server(slowlog))
+            .flags(Flags::ADMIN | Flags::LOADING | Flags::STALE)
+            .docs(
+                "A container for slow log commands.",
+                "2.2.12",
+                "server",
+                "Depends on subcommand.",
+            )
+            .subcommands(slowlog::SUBCOMMANDS),
+        CommandSpec::new("latency", -2, Server(latency))
+            .flags(Flags::ADMIN | Flags::NOSCRIPT | Flags::LOADING | Flags::STALE)
+            .docs(
+                "A container for latency diagnostics commands.",
+                "2.8.13",
+                "server",
+                "Depends on subcommand.",
+            )
+            .subcommands(latency::SUBCOMMANDS),
+        CommandSpec::new("monitor", 1, Server(monitor))
+            .flags(Flags::ADMIN | Flags::NOSCRIPT | Flags::LOADING | Flags::STALE)
+            .docs(
+                "Listens for all requests received by the server in real-time.",
+                "1.0.0",
+                "server",
+                "",
+            ),
+        CommandSpec::new("info", -1, ServerRead(info))
+            .flags(Flags::LOADING | Flags::STALE)
+            .acl_categories(Acl::DANGEROUS)
+            .docs(
+                "Returns information and statistics about the server.",
+                "1.0.0",
+                "server",
+                "O(1)",
+            ),
+        CommandSpec::new("save", 1, ServerRead(persistence::save))
+            .flags(Flags::ADMIN | Flags::NOSCRIPT | Flags::NO_MULTI)
+            .docs(
+                "Synchronously saves the database(s) to disk. Keys are saved without their TTL, as \
+                 keys cannot expire yet.",
+                "1.0.0",
+                "server",
+                "O(N) where N is the total number of keys in all databases",
+            ),
+        CommandSpec::new("bgsave", -1, ServerRead(persistence::bgsave))
+            .flags(Flags::ADMIN | Flags::NOSCRIPT)
+            .docs(
+                "Asynchronously saves the database(s) to disk. Keys are saved without their TTL, as \
+                 keys cannot expire yet.",
+                "1.0.0",
+                "server",
+                "O(1)",
+            ),
+        CommandSpec::new("lastsave", 1, Server(persistence::lastsave))
+            .flags(Flags::LOADING | Flags::STALE | Flags::FAST)
+            .acl_categories(Acl::ADMIN | Acl::DANGEROUS)
+            .docs(
+                "Returns the Unix timestamp of the last successful save to disk.",
+                "1.0.0",
+                "server",
+                "O(1)",
+            ),
+        CommandSpec::new("bgrewriteaof", 1, ServerRead(persistence::bgrewriteaof))
+            .flags(Flags::ADMIN | Flags::NOSCRIPT)
+            .docs(
+                "Asynchronously rewrites the append-only file to disk.",
+                "1.0.0",
+                "server",
+                "O(1)",
+            ),
+        CommandSpec::new("replicaof", 3, Server(replication::replicaof))
+            .flags(Flags::ADMIN | Flags::NOSCRIPT | Flags::STALE)
+            .docs(
+                "Configures a server as replica of another, or promotes it to a master.",
+                "5.0.0",
+                "server",
+                "O(1)",
+            ),
+        CommandSpec::new("slaveof", 3, Server(replication::replicaof))
+            .flags(Flags::ADMIN | Flags::NOSCRIPT | Flags::STALE)
+            .docs(
+                "Sets a Redis server as a replica of another, or promotes it to being a \
+                 master.",
+                "1.0.0",
+                "server",
+                "O(1)",
+            ),
+        CommandSpec::new("role", 1, Server(replication::role))
+            .flags(Flags::NOSCRIPT | Flags::LOADING | Flags::STALE | Flags::FAST)
+            .acl_categories(Acl::ADMIN | Acl::DANGEROUS)
+            .docs("Returns the replication role.", "2.8.12", "server", "O(1)"),
+        CommandSpec::new("psync", -3, ServerRead(replication::psync))
+            .flags(Flags::ADMIN | Flags::NOSCRIPT | Flags::NO_MULTI)
+            .docs(
+                "An internal command used in replication.",
+                "2.8.0",
+                "server",
+                "",
+            ),
+        CommandSpec::new("replconf", -1, Server(replication::replconf))
+            .flags(
+                Flags::ADMIN | Flags::NOSCRIPT | Flags::LOADING | Flags::STALE | Flags::ALLOW_BUSY,
+            )
+            .docs(
+                "An internal command for configuring the replication stream.",
+                "3.0.0",
+                "server",
+                "O(1)",
+            ),
+        CommandSpec::new("wait", 3, Server(replication::wait))
+            .flags(Flags::NOSCRIPT)
+            .acl_categories(Acl::CONNECTION)
+            .docs(
+                "Blocks until the asynchronous replication of all preceding write \
+                 commands sent by the connection is completed.",
+                "3.0.0",
+                "generic",
+                "O(1)",
+            ),
+        CommandSpec::new("waitaof", 4, Server(replication::waitaof))
+            .flags(Flags::NOSCRIPT)
+            .acl_categories(Acl::CONNECTION)
+            .docs(
+                "Blocks until all of the preceding write commands sent by the \
+                 connection are written to the append-only file of the master and/or \
+                 replicas.",
+                "7.2.0",
+                "generic",
+                "O(1)",
+            ),
+        CommandSpec::new("eval", -3, ServerWrite(scripting::eval))
+            .flags(script | Flags::MAY_REPLICATE)
+            .numkeys(2, &["RW", "ACCESS", "UPDATE"])
+            .acl_categories(Acl::SCRIPTING)
+            .docs(
+                "Executes a server-side Lua script.",
+                "2.6.0",
+                "scripting",
+                "Depends on the script that is executed.",
+            ),
+        CommandSpec::new("evalsha", -3, ServerWrite(scripting::evalsha))
+            .flags(script | Flags::MAY_REPLICATE)
+            .numkeys(2, &["RW", "ACCESS", "UPDATE"])
+            .acl_categories(Acl::SCRIPTING)
+            .docs(
+                "Executes a server-side Lua script by SHA1 digest.",
+                "2.6.0",
+                "scripting",
+                "Depends on the script that is executed.",
+            ),
+        CommandSpec::new("eval_ro", -3, ServerWrite(scripting::eval_ro))
+            .flags(script | Flags::READONLY)
+            .numkeys(2, &["RO", "ACCESS"])
+            .acl_categories(Acl::SCRIPTING)
+            .docs(
+                "Executes a read-only server-side Lua script.",
+                "7.0.0",
+                "scripting",
+                "Depends on the script that is executed.",
+            ),
+        CommandSpec::new("evalsha_ro", -3, ServerWrite(scripting::evalsha_ro))
+            .flags(script | Flags::READONLY)
+            .numkeys(2, &["RO", "ACCESS"])
+            .acl_categories(Acl::SCRIPTING)
+            .docs(
+                "Executes a read-only server-side Lua script by SHA1 digest.",
+                "7.0.0",
+                "scripting",
+                "Depends on the script that is executed.",
+            ),
+        CommandSpec::new("script", -2, Server(scripting::script))
+            .flags(Flags::NOSCRIPT | Flags::STALE)
+            .acl_categories(Acl::SCRIPTING)
+            .docs(
+                "A container for Lua scripts management commands.",
+                "2.6.0",
+                "scripting",
+                "Depends on subcommand.",
+            )
+            .subcommands(scripting::SCRIPT_SUBCOMMANDS),
+        CommandSpec::new("fcall", -3, ServerWrite(scripting::fcall))
+            .flags(script | Flags::MAY_REPLICATE)
+            .numkeys(2, &["RW", "ACCESS", "UPDATE"])
+            .acl_categories(Acl::SCRIPTING)
+            .docs(
+                "Invokes a function.",
+                "7.0.0",
+                "scripting",
+                "Depends on the function that is executed.",
+            ),
+        CommandSpec::new("fcall_ro", -3, ServerWrite(scripting::fcall_ro))
+            .flags(script | Flags::READONLY)
+            .numkeys(2, &["RO", "ACCESS"])
+            .acl_categories(Acl::SCRIPTING)
+            .docs(
+                "Invokes a read-only function.",
+                "7.0.0",
+                "scripting",
+                "Depends on the function that is executed.",
+            ),
+        CommandSpec::new("function", -2, ServerWrite(scripting::function))
+            .flags(Flags::NOSCRIPT)
+            .acl_categories(Acl::SCRIPTING)
+            .docs(
+                "A container for function commands.",
+                "7.0.0",
+                "scripting",
+                "Depends on subcommand.",
+            )
+            .subcommands(scripting::FUNCTION_SUBCOMMANDS),
+        CommandSpec::new("multi", 1, Client(transaction::multi))
+            .flags(transaction)
+            .acl_categories(Acl::TRANSACTION)
+            .docs("Starts a transaction.", "1.2.0", "transactions", "O(1)"),
+        CommandSpec::new("exec", 1, Server(transaction::exec))
+            .flags(Flags::NOSCRIPT | Flags::LOADING | Flags::STALE | Flags::SKIP_SLOWLOG)
+            .acl_categories(Acl::TRANSACTION)
+            .docs(
+                "Executes all commands in a transaction.",
+                "1.2.0",
+                "transactions",
+                "Depends on commands in the transaction",
+            ),
+        CommandSpec::new("discard", 1, Server(transaction::discard))
+            .flags(transaction)
+            .acl_categories(Acl::TRANSACTION)
+            .docs(
+                "Discards a transaction.",
+                "2.0.0",
+                "transactions",
+                "O(N), when N is the number of queued commands",
+            ),
+        CommandSpec::new("watch", -2, Server(transaction::watch))
+            .flags(transaction)
+            .keys(1, -1, 1, &["RO"])
+            .acl_categories(Acl::TRANSACTION)
+            .docs(
+                "Monitors changes to keys to determine the execution of a transaction.",
+                "2.2.0",
+                "transactions",
+                "O(1) for every key.",
+            ),
+        CommandSpec::new("unwatch", 1, Server(transaction::unwatch))
+            .flags(transaction)
+            .acl_categories(Acl::TRANSACTION)
+            .docs(
+                "Forgets about watched keys of a transaction.",
+                "2.2.0",
+                "transactions",
+                "O(1)",
+            ),
+        CommandSpec::new("reset", 1, Server(reset))
+            .flags(transaction | Flags::NO_AUTH)
+            .acl_categories(Acl::CONNECTION)
+            .docs("Resets the connection.", "6.2.0", "connection", "O(1)"),
+        CommandSpec::new("command", -1, Server(command::command))
+            .flags(Flags::LOADING | Flags::STALE)
+            .acl_categories(Acl::CONNECTION)
+            .docs(
+                "Returns detailed information about all commands.",
+                "2.8.13",
+                "server",
+                "O(N) where N is the total number of Redis commands",
+            )
+            .subcommands(command::SUBCOMMANDS),
+    ]
+}
+
+/// Commands that can be run by a RESP2 client in subscribed mode, RESP3 client can run any command.
+const SUBSCRIBED_MODE_COMMANDS: [&str; 8] = [
+    "subscribe",
+    "unsubscribe",
+    "psubscribe",
+    "punsubscribe",
+    "ssubscribe",
+    "sunsubscribe",
+    "ping",
+    "reset",
+];
 
-use super::{hello::hello, ping::ping, set_op, string_op};
+/// Commands run right away even in the middle of a transaction, instead of being queued.
+const TRANSACTION_COMMANDS: [&str; 5] = ["multi", "exec", "discard", "watch", "reset"];
+
+/// Commands only used with a subcommand, shown along with the subcommand, e.g. `client|list`.
+const CONTAINER_COMMANDS: [&str; 9] = [
+    "acl", "client", "command", "config", "function", "latency", "pubsub", "script", "slowlog",
+];
 
 pub fn handle_commands(
     command_arr: Vec<RespType>,
-    storage: Arc<RwLock<HashMap<String, StorageType>>>,
+    client: &mut Client,
+    server: &Server,
 ) -> RespType {
-    if let Some(RespType::BulkString(command_name)) = command_arr.first() {
-        let command_args = &command_arr[1..];
-
-        match command_name.as_str() {
-            "PING" => ping(command_args),
-            "HELLO" => hello(command_args),
-            "SET" => string_op::set(command_args, storage),
-            "GET" => string_op::get(command_args, storage),
-            "DEL" => string_op::del(command_args, storage),
-            "HSET" => set_op::hset(command_args, storage),
-            "HGET" => set_op::hget(command_args, storage),
-            "HGETALL" => set_op::hgetall(command_args, storage),
-            "HDEL" => set_op::hdel(command_args, storage),
-            _ => RespType::Error(format!("ERR unknown command '{}'", command_name)),
-        }
-    } else {
-        RespType::Error("WRONGTYPE wrong type, expected command name as a Bulk strings".into())
+    let command_name = match command_arr.first() {
+        Some(RespType::BulkString(command_name)) => command_name,
+        _ => {
+            return RespType::Error(
+                "WRONGTYPE wrong type, expected command name as a Bulk strings".into(),
+            )
+        }
+    };
+
+    // NOTE: Only a few commands can be run while a script runs for too long, the leader can't wait
+    if server.scripting.is_busy() && !client.from_leader {
+        let reply = scripting::while_busy(&command_arr, client, server);
+        server.stats.record_error(&reply);
+
+        return reply;
     }
+
+    let command = match server.commands.get(command_name) {
+        Some(command) => command,
+        None => {
+            let reply =
+                abort_transaction(client, format!("ERR unknown command '{}'", command_name));
+            server.stats.record_error(&reply);
+
+            return reply;
+        }
+    };
+
+    client.last_interaction = Instant::now();
+    client.last_command = match command_arr.get(1) {
+        Some(RespType::BulkString(subcommand)) if CONTAINER_COMMANDS.contains(&command.name()) => {
+            format!("{}|{}", command.name(), subcommand.to_lowercase())
+        }
+        _ => command.name().to_string(),
+    };
+
+    if !command.accepts_arity(command_arr.len()) {
+        return reject(
+            client,
+            server,
+            command.name(),
+            format!(
+                "ERR wrong number of arguments for '{}' command",
+                command.name()
+            ),
+        );
+    }
+
+    if !command.flags().contains(CommandFlags::NO_AUTH) {
+        if !server.acl.is_authenticated(client) {
+            return reject(
+                client,
+                server,
+                command.name(),
+                "NOAUTH Authentication required.".into(),
+            );
+        }
+
+        if let Err(err) = server
+            .acl
+            .check(client, command, &command_arr, LogContext::Toplevel)
+        {
+            return reject(client, server, command.name(), format!("NOPERM {}", err));
+        }
+    }
+
+    if client.protocol < 3
+        && client.is_subscribed()
+        && !SUBSCRIBED_MODE_COMMANDS.contains(&command.name())
+    {
+        let reply = RespType::Error(format!(
+            "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+            command.name()
+        ));
+        server.stats.record_rejected(command.name(), &reply);
+
+        return reply;
+    }
+
+    // NOTE: Monitors are followers in Redis, which aren't allowed to touch the keys
+    let flags = command.flags();
+    if client.monitor
+        && (flags.contains(CommandFlags::READONLY)
+            || flags.contains(CommandFlags::WRITE)
+            || flags.contains(CommandFlags::MAY_REPLICATE))
+    {
+        return reject(
+            client,
+            server,
+            command.name(),
+            "ERR Replica can't interact with the keyspace".into(),
+        );
+    }
+
+    // NOTE: Unlike Redis, `CLIENT UNPAUSE` is never paused, so `CLIENT PAUSE ... ALL` can be ended early
+    if !client.from_leader
+        && !client.is_replica
+        && !client.monitor
+        && client.last_command != "client|unpause"
+    {
+        server
+            .clients
+            .wait_unpaused(may_write(command, client, server));
+    }
+
+    if TRANSACTION_COMMANDS.contains(&command.name()) {
+        return call(command, &command_arr, client, server);
+    }
+
+    if command.flags().contains(CommandFlags::WRITE)
+        && !client.ignores_read_only()
+        && server.replication.is_read_only_follower()
+    {
+        return reject(
+            client,
+            server,
+            command.name(),
+            "READONLY You can't write against a read only replica.".into(),
+        );
+    }
+
+    if let Some(transaction) = client.transaction.as_mut() {
+        if command.flags().contains(CommandFlags::NO_MULTI) {
+            transaction.aborted = true;
+
+            let reply = RespType::Error("ERR Command not allowed inside a transaction".into());
+            server.stats.record_rejected(command.name(), &reply);
+
+            return reply;
+        }
+
+        return transaction::queue(command_arr, transaction);
+    }
+
+    call(command, &command_arr, client, server)
+}
+
+/// Whether the command may write, or be passed on to the followers, paused by `CLIENT PAUSE WRITE`.
+fn may_write(command: &dyn Command, client: &Client, server: &Server) -> bool {
+    let writes = |command: &dyn Command| {
+        command.flags().contains(CommandFlags::WRITE)
+            || command.flags().contains(CommandFlags::MAY_REPLICATE)
+    };
+
+    if command.name() == "exec" {
+        return client.transaction.as_ref().is_some_and(|transaction| {
+            transaction
+                .commands
+                .iter()
+                .any(|queued| match queued.first() {
+                    Some(RespType::BulkString(name)) => {
+                        server.commands.get(name).is_some_and(writes)
+                    }
+                    _ => false,
+                })
+        });
+    }
+
+    writes(command)
+}
+
+/// Run the command once it's fed to the monitors, recording the call and how long it took into the stats
+/// of the server, and into the slow log when it took too long. None of it is done while loading, see
+/// [`Client::loading`].
+fn call(
+    command: &dyn Command,
+    command_arr: &[RespType],
+    client: &mut Client,
+    server: &Server,
+) -> RespType {
+    if client.loading {
+        return command.handler().call(command_arr, client, server);
+    }
+
+    server.monitor.feed(
+        client,
+        Origin::Client,
+        command,
+        command_arr,
+        &server.clients,
+    );
+
+    let started = Instant::now();
+    let reply = command.handler().call(command_arr, client, server);
+    let duration = started.elapsed();
+    server.stats.record_call(command.name(), duration, &reply);
+    server
+        .latency
+        .add_command_sample_if_needed(command, duration);
+    server
+        .slowlog
+        .record(command, command_arr, client, duration);
+    server.tracking.remember_keys(client, command, command_arr);
+
+    reply
+}
+
+/// Refuse to run the command, recording the rejected call, see [`abort_transaction`].
+fn reject(client: &mut Client, server: &Server, command: &str, error: String) -> RespType {
+    let reply = abort_transaction(client, error);
+    server.stats.record_rejected(command, &reply);
+
+    reply
+}
+
+/// Mark the transaction of the client (if there's any) to be aborted on `EXEC`, replying with the error.
+fn abort_transaction(client: &mut Client, error: String) -> RespType {
+    if let Some(transaction) = client.transaction.as_mut() {
+        transaction.aborted = true;
+    }
+
+    RespType::Error(error)
+}
+
+/// Reply of the `HELP` subcommand of a container command, the same layout as Redis: the usage, every
+/// subcommand along with its description, then `HELP` itself.
+pub(super) fn help_reply(command: &str, lines: &[&str]) -> RespType {
+    let mut reply = vec![RespType::String(format!(
+        "{} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+        command
+    ))];
+    reply.extend(lines.iter().map(|line| RespType::String(line.to_string())));
+    reply.push(RespType::String("HELP".into()));
+    reply.push(RespType::String("    Print this help.".into()));
+
+    RespType::Array(reply)
 }
@@ -0,0 +1,351 @@
+use std::sync::atomic::Ordering;
+
+use crate::{
+    client::Client,
+    config::{find_parameter, Config, PARAMETERS},
+    glob::glob_match,
+    resp::RespType,
+    server::Server,
+    storage::Storage,
+};
+
+use super::{commands::help_reply, registry::Subcommand, scripting::map_reply};
+
+/// Subcommands of `CONFIG`, see [`config`].
+pub(super) const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand::new(
+        "get",
+        -3,
+        "Returns the effective values of configuration parameters.",
+    ),
+    Subcommand::new(
+        "help",
+        2,
+        "Returns helpful text about the different subcommands.",
+    ),
+    Subcommand::new("resetstat", 2, "Resets the server's statistics."),
+    Subcommand::new(
+        "rewrite",
+        2,
+        "Persists the effective configuration to file.",
+    ),
+    Subcommand::new("set", -4, "Sets configuration parameters in-flight."),
+];
+
+/// CONFIG Command
+///
+/// Read or change the configuration of the server while it's running.
+///
+/// Currently implemented syntax
+/// `CONFIG GET parameter [parameter ...]`
+/// `CONFIG SET parameter value [parameter value ...]`
+/// `CONFIG REWRITE`
+/// `CONFIG RESETSTAT`
+/// `CONFIG HELP`
+///
+/// Every parameter can be read, but only the ones declared as mutable in [`PARAMETERS`] can be set, the
+/// others are only used on startup. Setting several parameters is atomic, either every one of them is
+/// set, or none of them when one is invalid.
+pub fn config(
+    args: &[RespType],
+    client: &mut Client,
+    server: &Server,
+    storage: &Storage,
+) -> RespType {
+    let subcommand = if let Some(RespType::BulkString(subcommand)) = args.first() {
+        subcommand.to_uppercase()
+    } else {
+        return RespType::Error("ERR wrong number of arguments for 'config' command".into());
+    };
+
+    let strings = args[1..]
+        .iter()
+        .map(|arg| match arg {
+            RespType::BulkString(arg) => Some(arg.as_str()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    let strings = match strings {
+        Some(strings) => strings,
+        None => return RespType::Error("ERR syntax error".into()),
+    };
+
+    match (subcommand.as_str(), strings.as_slice()) {
+        ("GET", patterns) if !patterns.is_empty() => get(patterns, client, server),
+        ("SET", pairs) if !pairs.is_empty() && pairs.len() % 2 == 0 => set(pairs, server, storage),
+        ("REWRITE", []) => rewrite(server),
+        ("RESETSTAT", []) => {
+            server.stats.reset();
+
+            RespType::String("OK".into())
+        }
+        ("HELP", []) => help_reply(
+            "CONFIG",
+            &[
+                "GET <pattern>",
+                "    Return parameters matching the glob-like <pattern> and their values.",
+                "SET <directive> <value>",
+                "    Set the configuration <directive> to <value>.",
+                "RESETSTAT",
+                "    Reset statistics reported by the INFO command.",
+                "REWRITE",
+                "    Rewrite the configuration file.",
+            ],
+        ),
+        ("GET" | "SET" | "REWRITE" | "RESETSTAT" | "HELP", _) => RespType::Error(format!(
+            "ERR wrong number of arguments for 'config|{}' command",
+            subcommand.to_lowercase()
+        )),
+        _ => RespType::Error(format!(
+            "ERR unknown subcommand '{}'. Try CONFIG HELP.",
+            subcommand
+        )),
+    }
+}
+
+/// `CONFIG GET`, every parameter matching any of the patterns, each one only once.
+fn get(patterns: &[&str], client: &Client, server: &Server) -> RespType {
+    let config = server.running_config(&read_config(server));
+
+    let values = PARAMETERS
+        .iter()
+        .filter(|parameter| {
+            patterns
+                .iter()
+                .any(|pattern| glob_match(&pattern.to_lowercase(), parameter.name))
+        })
+        .map(|parameter| {
+            let value = config.get(parameter.name).unwrap_or_default().join(" ");
+
+            (parameter.name, RespType::BulkString(value))
+        })
+        .collect();
+
+    map_reply(values, client)
+}
+
+/// `CONFIG SET`, every value is validated before any parameter is set, and the parameters already set
+/// are set back when setting one fails.
+fn set(pairs: &[&str], server: &Server, storage: &Storage) -> RespType {
+    let mut stored = server
+        .config
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let previous = server.running_config(&stored);
+    let mut config = previous.clone();
+
+    let mut names = Vec::<&str>::new();
+    for pair in pairs.chunks(2) {
+        let (name, value) = (pair[0], pair[1]);
+
+        let parameter = match find_parameter(name) {
+            Some(parameter) => parameter,
+            None => {
+                return RespType::Error(format!(
+                    "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+                    name
+                ))
+            }
+        };
+        if !parameter.mutable {
+            return set_failed(parameter.name, "can't set immutable config");
+        }
+        if names.contains(&parameter.canonical_name()) {
+            return set_failed(parameter.name, "duplicate parameter");
+        }
+        names.push(parameter.canonical_name());
+
+        // NOTE: `save` set at runtime replaces every rule, instead of adding to them
+        if parameter.name == "save" {
+            config.persistence.save_rules.clear();
+        }
+        if let Err(err) = config.apply(parameter.name, &[value.to_string()]) {
+            return set_failed(parameter.name, &err);
+        }
+    }
+
+    for (applied, name) in names.iter().enumerate() {
+        if let Err(err) = apply_parameter(name, &config, server, storage) {
+            for name in &names[..applied] {
+                if let Err(err) = apply_parameter(name, &previous, server, storage) {
+                    println!("[Config SET] Failed to set back {}: {}", name, err);
+                }
+            }
+
+            return set_failed(name, &err);
+        }
+    }
+
+    *stored = config;
+
+    RespType::String("OK".into())
+}
+
+fn set_failed(parameter: &str, reason: &str) -> RespType {
+    RespType::Error(format!(
+        "ERR CONFIG SET failed (possibly related to argument '{}') - {}",
+        parameter, reason
+    ))
+}
+
+/// Set the parameter kept by its own state to its value in the configuration, parameters only kept by
+/// [`Server::config`] have nothing to do.
+fn apply_parameter(
+    parameter: &str,
+    config: &Config,
+    server: &Server,
+    storage: &Storage,
+) -> Result<(), String> {
+    match parameter {
+        "save" | "dir" | "dbfilename" => {
+            *server
+                .persistence
+                .config
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()) = config.persistence.clone();
+        }
+        "appendonly" => set_appendonly(server, storage, config.aof.enabled)?,
+        "appendfsync" | "aof-load-truncated" => {
+            let mut aof_config = server
+                .aof
+                .config
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            aof_config.fsync = config.aof.fsync;
+            aof_config.load_truncated = config.aof.load_truncated;
+        }
+        "replica-read-only" => server
+            .replication
+            .read_only
+            .store(config.replica_read_only, Ordering::Relaxed),
+        "repl-backlog-size" => server
+            .replication
+            .set_backlog_size(config.repl_backlog_size),
+        "busy-reply-threshold" => server
+            .scripting
+            .busy_reply_threshold
+            .store(config.busy_reply_threshold, Ordering::Relaxed),
+        "notify-keyspace-events" => server.notifier.set_flags(config.notify_keyspace_events),
+        "requirepass" => server.acl.set_requirepass(&config.requirepass),
+        "acllog-max-len" => server
+            .acl
+            .log_max_len
+            .store(config.acllog_max_len, Ordering::Relaxed),
+        "slowlog-log-slower-than" => server
+            .slowlog
+            .log_slower_than
+            .store(config.slowlog_log_slower_than, Ordering::Relaxed),
+        "slowlog-max-len" => server.slowlog.set_max_len(config.slowlog_max_len),
+        "latency-monitor-threshold" => server
+            .latency
+            .threshold
+            .store(config.latency_monitor_threshold, Ordering::Relaxed),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Turn the append only file on or off, it's rewritten from the storage when it's turned on.
+fn set_appendonly(server: &Server, storage: &Storage, enabled: bool) -> Result<(), String> {
+    if enabled == server.aof.is_enabled() {
+        return Ok(());
+    }
+
+    let result = if enabled {
+        server
+            .aof
+            .enable(&server.aof.dir(server), server.snapshot(storage))
+    } else {
+        server.aof.disable().map_err(|err| err.into())
+    };
+
+    result.map_err(|err| err.to_string())
+}
+
+/// `CONFIG REWRITE`, write the running configuration back into the configuration file.
+fn rewrite(server: &Server) -> RespType {
+    let config = server.running_config(&read_config(server));
+
+    let path = match &config.file {
+        Some(path) => path,
+        None => return RespType::Error("ERR The server is running without a config file".into()),
+    };
+
+    match config.rewrite(path) {
+        Ok(_) => RespType::String("OK".into()),
+        Err(err) => {
+            println!(
+                "[Config REWRITE] Failed to rewrite the config file: {:#?}",
+                err
+            );
+
+            RespType::Error(format!("ERR Rewriting config file: {}", err))
+        }
+    }
+}
+
+fn read_config(server: &Server) -> Config {
+    server
+        .config
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+#[cfg(test)]
+mod config_tests {
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    #[test]
+    fn sets_parameters_atomically() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        assert_eq!(
+            handle_commands(
+                command(&["CONFIG", "SET", "maxmemory", "1mb", "appendfsync", "sometimes"]),
+                &mut client,
+                &server,
+            ),
+            RespType::Error("ERR CONFIG SET failed (possibly related to argument 'appendfsync') - argument must be one of always, everysec, no".into())
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["CONFIG", "SET", "maxmemory", "1mb", "lua-time-limit", "100"]),
+                &mut client,
+                &server,
+            ),
+            RespType::String("OK".into())
+        );
+
+        assert_eq!(
+            handle_commands(
+                command(&["CONFIG", "GET", "maxmemory", "busy-*"]),
+                &mut client,
+                &server,
+            ),
+            RespType::Array(
+                ["maxmemory", "1048576", "busy-reply-threshold", "100"]
+                    .iter()
+                    .map(|value| RespType::BulkString(value.to_string()))
+                    .collect()
+            )
+        );
+
+        match handle_commands(command(&["CONFIG", "HELP"]), &mut client, &server) {
+            RespType::Array(lines) => assert_eq!(
+                lines.first(),
+                Some(&RespType::String(
+                    "CONFIG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:".into()
+                ))
+            ),
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+    }
+}
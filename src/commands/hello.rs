@@ -1,25 +1,88 @@
-use crate::resp::RespType;
+use crate::{client::Client, resp::RespType, server::Server};
 
-pub fn hello(args: &[RespType]) -> RespType {
-    // Currently the standard are to use RESP2 for redis
+pub fn hello(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    // RESP2 is the default, RESP3 can be used to receive Pub/Sub messages as push
     // https://redis.io/commands/hello/
-    if let Some(RespType::BulkString(protover)) = args.first() {
-        if protover != "2" {
-            return RespType::Error(
-                "NOPROTO sorry, this protocol version is not supported.".into(),
-            );
+    let protocol = match args.first() {
+        Some(RespType::BulkString(protover)) => match protover.as_str() {
+            "2" => Some(2),
+            "3" => Some(3),
+            _ => {
+                return RespType::Error(
+                    "NOPROTO sorry, this protocol version is not supported.".into(),
+                )
+            }
+        },
+        _ => None,
+    };
+
+    // NOTE: The client can authenticate and switch the protocol at the same time with `AUTH`
+    let mut options = args.iter().skip(1);
+    while let Some(option) = options.next() {
+        match (option, options.next(), options.next()) {
+            (
+                RespType::BulkString(option),
+                Some(RespType::BulkString(username)),
+                Some(RespType::BulkString(password)),
+            ) if option.eq_ignore_ascii_case("AUTH") => {
+                if !server.acl.authenticate(client, username, password) {
+                    return RespType::Error(
+                        "WRONGPASS invalid username-password pair or user is disabled.".into(),
+                    );
+                }
+            }
+            (option, _, _) => {
+                return RespType::Error(format!(
+                    "ERR Syntax error in HELLO option '{}'",
+                    match option {
+                        RespType::BulkString(option) => option.as_str(),
+                        _ => "",
+                    }
+                ))
+            }
         }
     }
 
-    RespType::Array(vec![
-        RespType::BulkString("server".into()),
-        RespType::BulkString("rust-eez".into()),
-        RespType::BulkString("version".into()),
-        // TODO: Get this version from Cargo or some sort
-        RespType::BulkString("0.1.0".into()),
-        RespType::BulkString("version-name".into()),
-        RespType::BulkString("hanabi".into()),
-        RespType::BulkString("proto".into()),
-        RespType::BulkString("2".into()),
-    ])
+    if !server.acl.is_authenticated(client) {
+        return RespType::Error("NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time".into());
+    }
+
+    if let Some(protocol) = protocol {
+        client.protocol = protocol;
+    }
+
+    let server_info = vec![
+        (
+            RespType::BulkString("server".into()),
+            RespType::BulkString("rust-eez".into()),
+        ),
+        (
+            RespType::BulkString("version".into()),
+            // TODO: Get this version from Cargo or some sort
+            RespType::BulkString("0.1.0".into()),
+        ),
+        (
+            RespType::BulkString("version-name".into()),
+            RespType::BulkString("hanabi".into()),
+        ),
+        (
+            RespType::BulkString("proto".into()),
+            RespType::Integer(client.protocol.into()),
+        ),
+        (
+            RespType::BulkString("id".into()),
+            RespType::Integer(client.id as i64),
+        ),
+    ];
+
+    if client.protocol >= 3 {
+        RespType::Map(server_info)
+    } else {
+        RespType::Array(
+            server_info
+                .into_iter()
+                .flat_map(|(key, value)| [key, value])
+                .collect(),
+        )
+    }
 }
@@ -0,0 +1,397 @@
+use std::{fmt::Write, fs, sync::atomic::Ordering};
+
+use crate::{
+    client::Client, config::Config, replication::Role, resp::RespType, server::Server,
+    storage::Storage,
+};
+
+/// Version of Redis the server is compatible with, reported as `redis_version` for the clients checking
+/// which commands they can use.
+const REDIS_VERSION: &str = "7.2.0";
+
+/// Every section in the order they're shown, along with whether it's shown by default, i.e. without any
+/// argument, or with `default`.
+const SECTIONS: [(&str, bool); 9] = [
+    ("server", true),
+    ("clients", true),
+    ("memory", true),
+    ("persistence", true),
+    ("stats", true),
+    ("replication", true),
+    ("commandstats", false),
+    ("errorstats", true),
+    ("keyspace", true),
+];
+
+/// INFO Command
+///
+/// Information and statistics about the server, as `field:value` lines grouped into sections, the
+/// fields are named the same as Redis.
+///
+/// Currently implemented syntax
+/// `INFO [section [section ...]]`
+///
+/// A section is one of `server`, `clients`, `memory`, `persistence`, `stats`, `replication`,
+/// `errorstats`, `commandstats`, or `keyspace`, along with `default` for every section other than
+/// `commandstats`, and `all` or `everything` for every one of them.
+pub fn info(
+    args: &[RespType],
+    _client: &mut Client,
+    server: &Server,
+    storage: &Storage,
+) -> RespType {
+    let mut selected = Vec::new();
+    for arg in args {
+        match arg {
+            RespType::BulkString(arg) => selected.push(arg.to_lowercase()),
+            _ => return RespType::Error("ERR syntax error".into()),
+        }
+    }
+    if selected.is_empty() {
+        selected.push("default".into());
+    }
+
+    let sections = SECTIONS.iter().filter(|(name, default)| {
+        selected.iter().any(|arg| match arg.as_str() {
+            "default" => *default,
+            "all" | "everything" => true,
+            arg => arg == *name,
+        })
+    });
+
+    let config = server.running_config(
+        &server
+            .config
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()),
+    );
+
+    let sections = sections
+        .map(|(section, _)| {
+            let fields = match *section {
+                "server" => server_section(server, &config),
+                "clients" => clients_section(server, &config),
+                "memory" => memory_section(&config),
+                "persistence" => persistence_section(server),
+                "stats" => stats_section(server),
+                "replication" => replication_section(server),
+                "errorstats" => errorstats_section(server),
+                "commandstats" => commandstats_section(server),
+                _ => keyspace_section(storage),
+            };
+
+            let mut title = section.to_string();
+            title[..1].make_ascii_uppercase();
+
+            fields
+                .iter()
+                .fold(format!("# {}\r\n", title), |mut text, (field, value)| {
+                    let _ = write!(text, "{}:{}\r\n", field, value);
+                    text
+                })
+        })
+        .collect::<Vec<_>>();
+
+    RespType::BulkString(sections.join("\r\n"))
+}
+
+fn server_section(server: &Server, config: &Config) -> Vec<(String, String)> {
+    let uptime = server.stats.started.elapsed().as_secs();
+
+    vec![
+        field("redis_version", REDIS_VERSION),
+        field("rust_eez_version", env!("CARGO_PKG_VERSION")),
+        field("redis_mode", "standalone"),
+        field(
+            "os",
+            format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+        ),
+        field("arch_bits", usize::BITS),
+        field("process_id", std::process::id()),
+        field("tcp_port", config.port),
+        field("uptime_in_seconds", uptime),
+        field("uptime_in_days", uptime / (24 * 60 * 60)),
+        field(
+            "config_file",
+            config
+                .file
+                .as_ref()
+                .map(|file| file.display().to_string())
+                .unwrap_or_default(),
+        ),
+    ]
+}
+
+fn clients_section(server: &Server, config: &Config) -> Vec<(String, String)> {
+    vec![
+        field(
+            "connected_clients",
+            server.stats.connected_clients.load(Ordering::Relaxed),
+        ),
+        field("blocked_clients", 0),
+        field("maxclients", config.maxclients),
+    ]
+}
+
+/// NOTE: Memory isn't tracked by the allocator, so `used_memory` is the resident memory of the process
+fn memory_section(config: &Config) -> Vec<(String, String)> {
+    let rss = resident_memory();
+
+    vec![
+        field("used_memory", rss),
+        field("used_memory_human", human_bytes(rss)),
+        field("used_memory_rss", rss),
+        field("used_memory_rss_human", human_bytes(rss)),
+        field("maxmemory", config.maxmemory),
+        field("maxmemory_human", human_bytes(config.maxmemory)),
+        field("maxmemory_policy", "noeviction"),
+    ]
+}
+
+fn persistence_section(server: &Server) -> Vec<(String, String)> {
+    let status = |ok: bool| if ok { "ok" } else { "err" };
+
+    vec![
+        field("loading", 0),
+        field("rdb_changes_since_last_save", server.persistence.dirty()),
+        field(
+            "rdb_bgsave_in_progress",
+            server.persistence.is_saving() as u8,
+        ),
+        field("rdb_last_save_time", server.persistence.last_save()),
+        field(
+            "rdb_last_bgsave_status",
+            status(server.persistence.last_save_ok()),
+        ),
+        field("aof_enabled", server.aof.is_enabled() as u8),
+        field("aof_rewrite_in_progress", server.aof.is_rewriting() as u8),
+    ]
+}
+
+fn stats_section(server: &Server) -> Vec<(String, String)> {
+    let stats = &server.stats;
+
+    vec![
+        field(
+            "total_connections_received",
+            stats.total_connections_received.load(Ordering::Relaxed),
+        ),
+        field(
+            "total_commands_processed",
+            stats.total_commands_processed.load(Ordering::Relaxed),
+        ),
+        field(
+            "instantaneous_ops_per_sec",
+            stats.instantaneous_ops_per_sec(),
+        ),
+        field("rejected_connections", 0),
+        field("expired_keys", 0),
+        field("evicted_keys", 0),
+        field("keyspace_hits", stats.keyspace_hits.load(Ordering::Relaxed)),
+        field(
+            "keyspace_misses",
+            stats.keyspace_misses.load(Ordering::Relaxed),
+        ),
+        field(
+            "total_error_replies",
+            stats.total_error_replies.load(Ordering::Relaxed),
+        ),
+    ]
+}
+
+fn replication_section(server: &Server) -> Vec<(String, String)> {
+    let replication = &server.replication;
+
+    let mut fields = match replication.role() {
+        Role::Leader => {
+            let replicas = replication.replicas();
+            let offset = replication.offset();
+
+            let mut fields = vec![
+                field("role", "master"),
+                field("connected_slaves", replicas.len()),
+            ];
+            for (index, replica) in replicas.iter().enumerate() {
+                fields.push(field(
+                    &format!("slave{}", index),
+                    format!(
+                        "ip={},port={},state=online,offset={},lag={}",
+                        replica.ip,
+                        replica.listening_port,
+                        replica.ack_offset,
+                        offset.saturating_sub(replica.ack_offset)
+                    ),
+                ));
+            }
+
+            fields
+        }
+        Role::Follower { host, port } => {
+            let link_state = replication.link_state();
+            let link_status = if link_state.name() == "connected" {
+                "up"
+            } else {
+                "down"
+            };
+
+            vec![
+                field("role", "slave"),
+                field("master_host", host),
+                field("master_port", port),
+                field("master_link_status", link_status),
+                field(
+                    "master_sync_in_progress",
+                    (link_state.name() == "sync") as u8,
+                ),
+                field(
+                    "slave_read_only",
+                    replication.read_only.load(Ordering::Relaxed) as u8,
+                ),
+                field("connected_slaves", 0),
+            ]
+        }
+    };
+
+    fields.push(field("master_replid", replication.replid()));
+    fields.push(field("master_repl_offset", replication.offset()));
+
+    fields
+}
+
+fn errorstats_section(server: &Server) -> Vec<(String, String)> {
+    server
+        .stats
+        .error_stats()
+        .into_iter()
+        .map(|(kind, count)| field(&format!("errorstat_{}", kind), format!("count={}", count)))
+        .collect()
+}
+
+fn commandstats_section(server: &Server) -> Vec<(String, String)> {
+    server
+        .stats
+        .command_stats()
+        .into_iter()
+        .map(|(name, stats)| {
+            let usec_per_call = if stats.calls == 0 {
+                0.0
+            } else {
+                stats.usec as f64 / stats.calls as f64
+            };
+
+            field(
+                &format!("cmdstat_{}", name),
+                format!(
+                    "calls={},usec={},usec_per_call={:.2},rejected_calls={},failed_calls={}",
+                    stats.calls,
+                    stats.usec,
+                    usec_per_call,
+                    stats.rejected_calls,
+                    stats.failed_calls
+                ),
+            )
+        })
+        .collect()
+}
+
+/// NOTE: There's only one database, and keys can't expire yet
+fn keyspace_section(storage: &Storage) -> Vec<(String, String)> {
+    if storage.is_empty() {
+        return Vec::new();
+    }
+
+    vec![field(
+        "db0",
+        format!("keys={},expires=0,avg_ttl=0", storage.len()),
+    )]
+}
+
+fn field(name: &str, value: impl ToString) -> (String, String) {
+    (name.to_string(), value.to_string())
+}
+
+/// Resident memory of the process in bytes, from `/proc`, 0 where it's not available.
+fn resident_memory() -> u64 {
+    fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("VmRSS:"))
+                .and_then(|value| {
+                    value
+                        .trim()
+                        .trim_end_matches("kB")
+                        .trim()
+                        .parse::<u64>()
+                        .ok()
+                })
+        })
+        .map_or(0, |kilobytes| kilobytes * 1024)
+}
+
+/// Bytes in the largest unit keeping at least 1 of it, e.g. `1.50M`, the same as Redis.
+fn human_bytes(bytes: u64) -> String {
+    let units = ["B", "K", "M", "G", "T"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{}B", bytes)
+    } else {
+        format!("{:.2}{}", value, units[unit])
+    }
+}
+
+#[cfg(test)]
+mod info_tests {
+    use crate::{
+        client::Client,
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    fn info(section: &str, client: &mut Client, server: &Server) -> String {
+        match handle_commands(command(&["INFO", section]), client, server) {
+            RespType::BulkString(info) => info,
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+    }
+
+    #[test]
+    fn counts_commands_and_keyspace_lookups() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        handle_commands(command(&["SET", "key", "value"]), &mut client, &server);
+        handle_commands(command(&["GET", "key"]), &mut client, &server);
+        handle_commands(command(&["GET", "missing"]), &mut client, &server);
+        handle_commands(command(&["GET"]), &mut client, &server);
+        handle_commands(command(&["NOPE"]), &mut client, &server);
+
+        let stats = info("stats", &mut client, &server);
+        assert!(stats.starts_with("# Stats\r\n"));
+        assert!(stats.contains("\r\ntotal_commands_processed:3\r\n"));
+        assert!(stats.contains("\r\nkeyspace_hits:1\r\nkeyspace_misses:1\r\n"));
+        assert!(stats.contains("\r\ntotal_error_replies:2\r\n"));
+
+        let commandstats = info("commandstats", &mut client, &server);
+        assert!(commandstats.contains("\r\ncmdstat_get:calls=2,"));
+        assert!(commandstats.contains(",rejected_calls=1,failed_calls=0\r\n"));
+        assert!(info("errorstats", &mut client, &server).contains("\r\nerrorstat_ERR:count=2\r\n"));
+        assert_eq!(
+            info("keyspace", &mut client, &server),
+            "# Keyspace\r\ndb0:keys=1,expires=0,avg_ttl=0\r\n"
+        );
+
+        handle_commands(command(&["CONFIG", "RESETSTAT"]), &mut client, &server);
+        assert!(!info("commandstats", &mut client, &server).contains("cmdstat_get"));
+    }
+}
@@ -0,0 +1,288 @@
+use crate::{
+    notification::NotifyFlags,
+    persistence::unix_time_ms,
+    rdb::{dump_value, restore_value, verify_dump_payload},
+    resp::{bytes_to_string, string_to_bytes, RespType},
+    storage::Storage,
+};
+
+/// DUMP Command
+///
+/// Serialize the value of a key in the same format as Redis, to be restored with `RESTORE`, either here
+/// or on Redis itself.
+///
+/// Currently implemented syntax
+/// `DUMP key`
+pub fn dump(args: &[RespType], storage: &Storage) -> RespType {
+    let key = if let Some(RespType::BulkString(key)) = args.first() {
+        key
+    } else {
+        return RespType::Error("ARGERR no key are given for DUMP command".into());
+    };
+
+    match storage.lookup_read(key) {
+        Some(value) => RespType::BulkString(bytes_to_string(&dump_value(value))),
+        None => RespType::Null,
+    }
+}
+
+/// RESTORE Command
+///
+/// Create a key from a value serialized with `DUMP`. `IDLETIME` and `FREQ` are only validated, and as keys
+/// cannot expire yet, the key is not created at all if the TTL already passed, otherwise the TTL is ignored.
+///
+/// Currently implemented syntax
+/// `RESTORE key ttl serialized-value [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency]`
+pub fn restore(args: &[RespType], storage: &mut Storage) -> RespType {
+    let (key, ttl, payload, options) = match args {
+        [RespType::BulkString(key), RespType::BulkString(ttl), RespType::BulkString(payload), options @ ..] => {
+            (key, ttl, payload, options)
+        }
+        _ => return RespType::Error("ERR wrong number of arguments for 'restore' command".into()),
+    };
+
+    let ttl = match ttl.parse::<i64>() {
+        Ok(ttl) if ttl >= 0 => ttl as u64,
+        Ok(_) => return RespType::Error("ERR Invalid TTL value, must be >= 0".into()),
+        Err(_) => return RespType::Error("ERR value is not an integer or out of range".into()),
+    };
+
+    let mut replace = false;
+    let mut absolute_ttl = false;
+    let (mut idle_time, mut frequency) = (None, None);
+
+    let mut index = 0;
+    while index < options.len() {
+        let option = match &options[index] {
+            RespType::BulkString(option) => option.to_uppercase(),
+            _ => return RespType::Error("ERR syntax error".into()),
+        };
+
+        match (option.as_str(), options.get(index + 1)) {
+            ("REPLACE", _) => replace = true,
+            ("ABSTTL", _) => absolute_ttl = true,
+            ("IDLETIME", Some(RespType::BulkString(value))) if frequency.is_none() => {
+                match value.parse::<i64>() {
+                    Ok(value) if value >= 0 => idle_time = Some(value),
+                    Ok(_) => {
+                        return RespType::Error("ERR Invalid IDLETIME value, must be >= 0".into())
+                    }
+                    Err(_) => {
+                        return RespType::Error(
+                            "ERR value is not an integer or out of range".into(),
+                        )
+                    }
+                }
+
+                index += 1;
+            }
+            ("FREQ", Some(RespType::BulkString(value))) if idle_time.is_none() => {
+                match value.parse::<i64>() {
+                    Ok(value) if (0..=255).contains(&value) => frequency = Some(value),
+                    Ok(_) => {
+                        return RespType::Error(
+                            "ERR Invalid FREQ value, must be >= 0 and <= 255".into(),
+                        )
+                    }
+                    Err(_) => {
+                        return RespType::Error(
+                            "ERR value is not an integer or out of range".into(),
+                        )
+                    }
+                }
+
+                index += 1;
+            }
+            _ => return RespType::Error("ERR syntax error".into()),
+        }
+
+        index += 1;
+    }
+
+    if !replace && storage.contains_key(key) {
+        return RespType::Error("BUSYKEY Target key name already exists.".into());
+    }
+
+    let payload = string_to_bytes(payload);
+    if !verify_dump_payload(&payload) {
+        return RespType::Error("ERR DUMP payload version or checksum are wrong".into());
+    }
+
+    let value = match restore_value(&payload) {
+        Ok(value) => value,
+        Err(err) => {
+            println!("[Key RESTORE] Got invalid payload: {:#?}", err);
+
+            return RespType::Error("ERR Bad data format".into());
+        }
+    };
+    let value = match value.into_storage_type() {
+        Some(value) => value,
+        None => return RespType::Error("ERR Bad data format, type is not supported yet".into()),
+    };
+
+    let deleted = replace && storage.remove(key).is_some();
+
+    if absolute_ttl && ttl > 0 && ttl <= unix_time_ms() {
+        if deleted {
+            storage.notify(NotifyFlags::GENERIC, "del", key);
+        }
+
+        return RespType::String("OK".into());
+    }
+
+    storage.insert(key.to_string(), value);
+    storage.notify(NotifyFlags::GENERIC, "restore", key);
+
+    RespType::String("OK".into())
+}
+
+#[cfg(test)]
+mod key_op_tests {
+    use super::{dump, restore};
+    use crate::{
+        rdb::crc64,
+        resp::{bytes_to_string, RespType},
+        storage::{Storage, StorageType},
+    };
+
+    fn args(args: &[&str]) -> Vec<RespType> {
+        args.iter()
+            .map(|arg| RespType::BulkString(arg.to_string()))
+            .collect()
+    }
+
+    /// Payload of `SET mykey 10` then `DUMP mykey` on Redis 7.0, from the Redis documentation.
+    const REDIS_PAYLOAD: &str = "\x00\u{c0}\n\n\x00n\u{9f}WE\x0e\u{ae}c\u{bb}";
+
+    /// Payload of `DUMP` with the value, followed by the RDB version and a valid checksum.
+    fn payload(value: &[u8]) -> String {
+        let mut payload = value.to_vec();
+        payload.extend_from_slice(&10u16.to_le_bytes());
+        let checksum = crc64(0, &payload);
+        payload.extend_from_slice(&checksum.to_le_bytes());
+
+        bytes_to_string(&payload)
+    }
+
+    #[test]
+    fn restores_redis_payload_and_dumps_back() {
+        let mut storage = Storage::new();
+
+        assert_eq!(
+            restore(&args(&["mykey", "0", REDIS_PAYLOAD]), &mut storage),
+            RespType::String("OK".into())
+        );
+        assert!(matches!(storage.get("mykey"), Some(StorageType::String(value)) if value == "10"));
+
+        let payload = match dump(&args(&["mykey"]), &storage) {
+            RespType::BulkString(payload) => payload,
+            other => panic!("unexpected DUMP reply: {:?}", other),
+        };
+        assert_eq!(
+            restore(&args(&["copy", "0", &payload]), &mut storage),
+            RespType::String("OK".into())
+        );
+        assert!(matches!(storage.get("copy"), Some(StorageType::String(value)) if value == "10"));
+        assert_eq!(dump(&args(&["missing"]), &storage), RespType::Null);
+    }
+
+    #[test]
+    fn restore_checks_payload_and_key() {
+        let mut storage = Storage::new();
+        restore(&args(&["key", "0", REDIS_PAYLOAD]), &mut storage);
+
+        assert_eq!(
+            restore(&args(&["key", "0", REDIS_PAYLOAD]), &mut storage),
+            RespType::Error("BUSYKEY Target key name already exists.".into())
+        );
+        assert_eq!(
+            restore(
+                &args(&["key", "0", REDIS_PAYLOAD, "REPLACE", "IDLETIME", "10"]),
+                &mut storage
+            ),
+            RespType::String("OK".into())
+        );
+        assert_eq!(
+            restore(
+                &args(&["other", "0", &REDIS_PAYLOAD.replace('n', "m")]),
+                &mut storage
+            ),
+            RespType::Error("ERR DUMP payload version or checksum are wrong".into())
+        );
+        assert_eq!(
+            restore(
+                &args(&["other", "0", REDIS_PAYLOAD, "IDLETIME", "1", "FREQ", "1"]),
+                &mut storage
+            ),
+            RespType::Error("ERR syntax error".into())
+        );
+        assert_eq!(
+            restore(&args(&["other", "-1", REDIS_PAYLOAD]), &mut storage),
+            RespType::Error("ERR Invalid TTL value, must be >= 0".into())
+        );
+        assert_eq!(
+            restore(
+                &args(&["other", "1", REDIS_PAYLOAD, "ABSTTL"]),
+                &mut storage
+            ),
+            RespType::String("OK".into())
+        );
+        assert!(!storage.contains_key("other"));
+    }
+
+    #[test]
+    fn restore_rejects_oversized_lzf_length() {
+        let mut storage = Storage::new();
+        // String, LZF compressed from 2 bytes, claiming to decompress to 2^62 bytes
+        let value = [
+            &[0x00, 0xC3, 0x02, 0x81][..],
+            &(1u64 << 62).to_be_bytes(),
+            &[0x00, b'a'],
+        ]
+        .concat();
+
+        assert_eq!(
+            restore(&args(&["key", "0", &payload(&value)]), &mut storage),
+            RespType::Error("ERR Bad data format".into())
+        );
+        assert!(!storage.contains_key("key"));
+    }
+
+    #[test]
+    fn restore_rejects_oversized_lengths() {
+        let mut storage = Storage::new();
+        let huge = [&[0x81][..], &(1u64 << 62).to_be_bytes()].concat();
+
+        for value in [
+            // Raw string
+            [&[0x00][..], &huge, b"a"].concat(),
+            // List, set, and hash
+            [&[0x01][..], &huge, &[0x01, b'a']].concat(),
+            [&[0x02][..], &huge, &[0x01, b'a']].concat(),
+            [&[0x04][..], &huge, &[0x01, b'a']].concat(),
+            // Quicklist with that many nodes
+            [&[0x12][..], &huge, &[0x01, 0x01, b'a']].concat(),
+            // Intset with 2^32 - 1 integers of 16 bits
+            vec![
+                0x0B, 0x0C, 0x02, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x00,
+            ],
+            // Listpack and ziplist with an element of 2^32 - 1 bytes
+            vec![
+                0x14, 0x0C, 0x0C, 0x00, 0x00, 0x00, 0x01, 0x00, 0xF0, 0xFF, 0xFF, 0xFF, 0xFF,
+            ],
+            vec![
+                0x0A, 0x10, 0x10, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x80,
+                0xFF, 0xFF, 0xFF, 0xFF,
+            ],
+        ] {
+            assert_eq!(
+                restore(&args(&["key", "0", &payload(&value)]), &mut storage),
+                RespType::Error("ERR Bad data format".into()),
+                "{:?}",
+                value
+            );
+        }
+        assert!(!storage.contains_key("key"));
+    }
+}
@@ -0,0 +1,281 @@
+use std::sync::atomic::Ordering;
+
+use crate::{client::Client, resp::RespType, server::Server};
+
+use super::{commands::help_reply, registry::Subcommand, scripting::map_reply};
+
+/// Subcommands of `LATENCY`, see [`latency`].
+pub(super) const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand::new(
+        "doctor",
+        2,
+        "Returns a human-readable latency analysis report.",
+    ),
+    Subcommand::new(
+        "help",
+        2,
+        "Returns helpful text about the different subcommands.",
+    ),
+    Subcommand::new(
+        "histogram",
+        -2,
+        "Returns the cumulative distribution of latencies of a subset or all commands.",
+    ),
+    Subcommand::new(
+        "history",
+        3,
+        "Returns timestamp-latency samples for an event.",
+    ),
+    Subcommand::new(
+        "latest",
+        2,
+        "Returns the latest latency samples for all events.",
+    ),
+    Subcommand::new(
+        "reset",
+        -2,
+        "Resets the latency data for one or more events.",
+    ),
+];
+
+/// LATENCY Command
+///
+/// Inspect the latency spikes sampled by the latency monitor (see `latency-monitor-threshold`), and the
+/// distribution of the latency of every command.
+///
+/// Currently implemented syntax
+/// `LATENCY LATEST`
+/// `LATENCY HISTORY event`
+/// `LATENCY RESET [event [event ...]]`
+/// `LATENCY DOCTOR`
+/// `LATENCY HISTOGRAM [command [command ...]]`
+/// `LATENCY HELP`
+///
+/// The events are `command`, `fast-command`, `aof-write`, and `aof-fsync-always`.
+pub fn latency(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    let subcommand = if let Some(RespType::BulkString(subcommand)) = args.first() {
+        subcommand.to_uppercase()
+    } else {
+        return RespType::Error("ERR wrong number of arguments for 'latency' command".into());
+    };
+
+    let strings = args[1..]
+        .iter()
+        .map(|arg| match arg {
+            RespType::BulkString(arg) => Some(arg.clone()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>();
+    let strings = match strings {
+        Some(strings) => strings,
+        None => return RespType::Error("ERR syntax error".into()),
+    };
+
+    match (subcommand.as_str(), strings.as_slice()) {
+        ("LATEST", []) => RespType::Array(
+            server
+                .latency
+                .latest()
+                .into_iter()
+                .map(|(event, sample, max)| {
+                    RespType::Array(vec![
+                        RespType::BulkString(event),
+                        RespType::Integer(sample.time as i64),
+                        RespType::Integer(sample.latency as i64),
+                        RespType::Integer(max as i64),
+                    ])
+                })
+                .collect(),
+        ),
+        ("HISTORY", [event]) => RespType::Array(
+            server
+                .latency
+                .history(event)
+                .into_iter()
+                .map(|sample| {
+                    RespType::Array(vec![
+                        RespType::Integer(sample.time as i64),
+                        RespType::Integer(sample.latency as i64),
+                    ])
+                })
+                .collect(),
+        ),
+        ("RESET", events) => RespType::Integer(server.latency.reset(events) as i64),
+        ("DOCTOR", []) => RespType::BulkString(
+            server
+                .latency
+                .doctor(server.slowlog.log_slower_than.load(Ordering::Relaxed)),
+        ),
+        ("HISTOGRAM", commands) => histogram(commands, client, server),
+        ("HELP", []) => help_reply(
+            "LATENCY",
+            &[
+                "DOCTOR",
+                "    Return a human readable latency analysis report.",
+                "HISTORY <event>",
+                "    Return time-latency samples for the <event> class.",
+                "LATEST",
+                "    Return the latest latency samples for all events.",
+                "RESET [<event> ...]",
+                "    Reset latency data of one or more <event> classes.",
+                "    (default: reset all data for all event classes)",
+                "HISTOGRAM [COMMAND ...]",
+                "    Return a cumulative distribution of latencies in the format of a histogram",
+                "    for the specified command names.",
+                "    If no commands are specified then all histograms are replied.",
+            ],
+        ),
+        ("LATEST" | "HISTORY" | "DOCTOR" | "HELP", _) => RespType::Error(format!(
+            "ERR wrong number of arguments for 'latency|{}' command",
+            subcommand.to_lowercase()
+        )),
+        _ => RespType::Error(format!(
+            "ERR unknown subcommand '{}'. Try LATENCY HELP.",
+            subcommand
+        )),
+    }
+}
+
+/// `LATENCY HISTOGRAM`, the number of calls of the commands (or of every command when there's none), and
+/// the cumulative number of calls that took at most each duration in microseconds. Commands never called
+/// are left out.
+fn histogram(commands: &[String], client: &Client, server: &Server) -> RespType {
+    let commands = commands
+        .iter()
+        .map(|command| command.to_lowercase())
+        .collect::<Vec<_>>();
+
+    let stats = server
+        .stats
+        .command_stats()
+        .into_iter()
+        .filter(|(name, stats)| stats.calls > 0 && (commands.is_empty() || commands.contains(name)))
+        .collect::<Vec<_>>();
+
+    let entries = stats
+        .iter()
+        .map(|(name, stats)| {
+            let buckets = stats.latency_histogram().into_iter().map(|(usec, calls)| {
+                (
+                    RespType::Integer(usec as i64),
+                    RespType::Integer(calls as i64),
+                )
+            });
+            let histogram_usec = if client.protocol >= 3 {
+                RespType::Map(buckets.collect())
+            } else {
+                RespType::Array(buckets.flat_map(|(usec, calls)| [usec, calls]).collect())
+            };
+
+            (
+                name.as_str(),
+                map_reply(
+                    vec![
+                        ("calls", RespType::Integer(stats.calls as i64)),
+                        ("histogram_usec", histogram_usec),
+                    ],
+                    client,
+                ),
+            )
+        })
+        .collect();
+
+    map_reply(entries, client)
+}
+
+#[cfg(test)]
+mod latency_tests {
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    #[test]
+    fn samples_slow_commands_and_counts_every_call() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        handle_commands(command(&["SET", "key", "value"]), &mut client, &server);
+        handle_commands(
+            command(&["CONFIG", "SET", "latency-monitor-threshold", "1"]),
+            &mut client,
+            &server,
+        );
+        handle_commands(
+            command(&[
+                "EVAL",
+                "local i = 0 while i < 10000000 do i = i + 1 end",
+                "0",
+            ]),
+            &mut client,
+            &server,
+        );
+
+        let latest = handle_commands(command(&["LATENCY", "LATEST"]), &mut client, &server);
+        match latest {
+            RespType::Array(events) => match events.as_slice() {
+                [RespType::Array(fields)] => {
+                    assert_eq!(fields[0], RespType::BulkString("command".into()))
+                }
+                events => panic!("Unexpected events {:?}", events),
+            },
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+        assert!(matches!(
+            handle_commands(command(&["LATENCY", "DOCTOR"]), &mut client, &server),
+            RespType::BulkString(report) if report.contains("1. command: 1 latency spikes")
+        ));
+
+        // NOTE: The threshold is in milliseconds, and compared in microseconds by the doctor
+        handle_commands(
+            command(&[
+                "CONFIG",
+                "SET",
+                "latency-monitor-threshold",
+                "18446744073709551615",
+            ]),
+            &mut client,
+            &server,
+        );
+        assert!(matches!(
+            handle_commands(command(&["LATENCY", "DOCTOR"]), &mut client, &server),
+            RespType::BulkString(report) if report.contains("1. command: 1 latency spikes")
+        ));
+
+        client.protocol = 3;
+        match handle_commands(
+            command(&["LATENCY", "HISTOGRAM", "SET", "GET"]),
+            &mut client,
+            &server,
+        ) {
+            RespType::Map(entries) => {
+                assert_eq!(entries.len(), 1);
+                assert_eq!(entries[0].0, RespType::BulkString("set".into()));
+            }
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+
+        assert_eq!(
+            handle_commands(command(&["LATENCY", "RESET"]), &mut client, &server),
+            RespType::Integer(1)
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["LATENCY", "HISTORY", "command"]),
+                &mut client,
+                &server
+            ),
+            RespType::Array(Vec::new())
+        );
+
+        match handle_commands(command(&["LATENCY", "HELP"]), &mut client, &server) {
+            RespType::Array(lines) => assert_eq!(
+                lines.last(),
+                Some(&RespType::String("    Print this help.".into()))
+            ),
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+    }
+}
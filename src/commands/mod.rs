@@ -1,6 +1,23 @@
+#[allow(clippy::module_inception)]
 pub mod commands;
+pub mod registry;
 
+mod acl;
+mod client;
+mod command;
+mod config;
 mod hello;
+mod info;
+mod key_op;
+mod latency;
+mod monitor;
+mod persistence;
 mod ping;
-mod string_op;
+mod pubsub;
+mod replication;
+mod reset;
+mod scripting;
 mod set_op;
+mod slowlog;
+mod string_op;
+mod transaction;
@@ -0,0 +1,88 @@
+use crate::{client::Client, resp::RespType, server::Server};
+
+/// MONITOR Command
+///
+/// Switch the connection to the monitor mode, where it receives a line for every command run by the
+/// server, e.g. `+1700000000.123456 [0 127.0.0.1:50000] "SET" "key" "value"`, see
+/// [`crate::monitor::Monitor`]. The mode is left with `RESET`.
+///
+/// Currently implemented syntax
+/// `MONITOR`
+pub fn monitor(_: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    if client.deny_blocking {
+        return RespType::Error("ERR MONITOR isn't allowed for DENY BLOCKING client".into());
+    }
+
+    if !client.monitor {
+        client.monitor = true;
+        server.monitor.add(client.id);
+    }
+
+    RespType::String("OK".into())
+}
+
+#[cfg(test)]
+mod monitor_tests {
+    use crate::{
+        client::Client,
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    fn monitored(monitor: &Client) -> Vec<String> {
+        monitor
+            .pending_messages()
+            .into_iter()
+            .map(|message| match message {
+                RespType::String(line) => line.split_once(' ').unwrap().1.to_string(),
+                message => panic!("Unexpected message {:?}", message),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn streams_escaped_and_redacted_commands() {
+        let server = Server::new();
+        let mut monitor = connect(&server);
+        let mut client = Client::with_addr("127.0.0.1:5000".into());
+        server.clients.register(&monitor);
+
+        assert_eq!(
+            handle_commands(command(&["MONITOR"]), &mut monitor, &server),
+            RespType::String("OK".into())
+        );
+
+        handle_commands(
+            command(&["SET", "key", "a \"quoted\"\nvalue\u{1}"]),
+            &mut client,
+            &server,
+        );
+        handle_commands(command(&["AUTH", "user", "secret"]), &mut client, &server);
+        handle_commands(
+            command(&["EVAL", "return redis.call('GET', 'key')", "0"]),
+            &mut client,
+            &server,
+        );
+        handle_commands(command(&["CONFIG", "GET", "port"]), &mut client, &server);
+
+        assert_eq!(
+            monitored(&monitor),
+            vec![
+                r#"[0 127.0.0.1:5000] "SET" "key" "a \"quoted\"\nvalue\x01""#,
+                r#"[0 127.0.0.1:5000] "AUTH" "(redacted)" "(redacted)""#,
+                r#"[0 127.0.0.1:5000] "EVAL" "return redis.call('GET', 'key')" "0""#,
+                r#"[0 lua] "GET" "key""#,
+            ]
+        );
+
+        assert_eq!(
+            handle_commands(command(&["GET", "key"]), &mut monitor, &server),
+            RespType::Error("ERR Replica can't interact with the keyspace".into())
+        );
+        handle_commands(command(&["RESET"]), &mut monitor, &server);
+        handle_commands(command(&["PING"]), &mut client, &server);
+        assert_eq!(monitored(&monitor), vec![r#"[0 ] "RESET""#]);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{client::Client, resp::RespType, server::Server, storage::Storage};
+
+/// SAVE Command
+///
+/// Save a snapshot of the storage into the snapshot file, blocking every other client until it's done.
+/// As keys cannot expire yet, the TTL of keys loaded from a snapshot file are not saved back.
+///
+/// Currently implemented syntax
+/// `SAVE`
+pub fn save(
+    _args: &[RespType],
+    _client: &mut Client,
+    server: &Server,
+    storage: &Storage,
+) -> RespType {
+    if server.persistence.is_saving() {
+        return RespType::Error("ERR Background save already in progress".into());
+    }
+
+    match server.persistence.save(&server.snapshot(storage)) {
+        Ok(_) => RespType::String("OK".into()),
+        Err(err) => {
+            println!("[Persistence SAVE] Failed to save the snapshot: {:#?}", err);
+
+            RespType::Error("ERR".into())
+        }
+    }
+}
+
+/// BGSAVE Command
+///
+/// Save a snapshot of the storage into the snapshot file in the background, other clients can keep on
+/// running commands while it's being saved.
+///
+/// Currently implemented syntax
+/// `BGSAVE [SCHEDULE]`
+pub fn bgsave(
+    args: &[RespType],
+    _client: &mut Client,
+    server: &Server,
+    storage: &Storage,
+) -> RespType {
+    let schedule = match args {
+        [] => false,
+        [RespType::BulkString(option)] if option.eq_ignore_ascii_case("SCHEDULE") => true,
+        _ => return RespType::Error("ERR syntax error".into()),
+    };
+
+    if server
+        .persistence
+        .background_save(server.snapshot(storage), server.persistence.dirty())
+    {
+        RespType::String("Background saving started".into())
+    } else if schedule {
+        server.persistence.schedule_background_save();
+
+        RespType::String("Background saving scheduled".into())
+    } else {
+        RespType::Error("ERR Background save already in progress".into())
+    }
+}
+
+/// LASTSAVE Command
+///
+/// Unix time of the last successful save, or of the server startup if nothing has been saved yet.
+///
+/// Currently implemented syntax
+/// `LASTSAVE`
+pub fn lastsave(_args: &[RespType], _client: &mut Client, server: &Server) -> RespType {
+    RespType::Integer(server.persistence.last_save() as i64)
+}
+
+/// BGREWRITEAOF Command
+///
+/// Rewrite the append only file in the background, into a snapshot of the storage as the new base file.
+/// Write commands after this are appended to a new incremental file.
+///
+/// Currently implemented syntax
+/// `BGREWRITEAOF`
+pub fn bgrewriteaof(
+    _args: &[RespType],
+    _client: &mut Client,
+    server: &Server,
+    storage: &Storage,
+) -> RespType {
+    match server
+        .aof
+        .background_rewrite(&server.aof.dir(server), server.snapshot(storage))
+    {
+        Ok(true) => RespType::String("Background append only file rewriting started".into()),
+        Ok(false) => {
+            RespType::Error("ERR Background append only file rewriting already in progress".into())
+        }
+        Err(err) => {
+            println!(
+                "[Persistence BGREWRITEAOF] Failed to start the rewrite: {:#?}",
+                err
+            );
+
+            RespType::Error("ERR Can't execute an AOF background rewriting".into())
+        }
+    }
+}
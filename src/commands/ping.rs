@@ -1,7 +1,21 @@
-use crate::resp::RespType;
+use crate::{client::Client, resp::RespType};
 
-pub fn ping(args: &[RespType]) -> RespType {
-    if let Some(RespType::BulkString(message)) = args.first() {
+pub fn ping(args: &[RespType], client: &mut Client) -> RespType {
+    let message = if let Some(RespType::BulkString(message)) = args.first() {
+        Some(message)
+    } else {
+        None
+    };
+
+    // RESP2 client in subscribed mode can only receive push-like replies
+    if client.protocol < 3 && client.is_subscribed() {
+        return RespType::Array(vec![
+            RespType::BulkString("pong".into()),
+            RespType::BulkString(message.cloned().unwrap_or_default()),
+        ]);
+    }
+
+    if let Some(message) = message {
         return RespType::BulkString(message.into());
     }
 
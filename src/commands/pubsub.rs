@@ -0,0 +1,507 @@
+use std::collections::HashSet;
+
+use crate::{
+    client::Client, cluster::key_hash_slot, pubsub::Subscriptions, resp::RespType, server::Server,
+};
+
+use super::{commands::help_reply, registry::Subcommand};
+
+/// Subcommands of `PUBSUB`, see [`pubsub`].
+pub(super) const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand::new("channels", -2, "Returns the active channels."),
+    Subcommand::new(
+        "help",
+        2,
+        "Returns helpful text about the different subcommands.",
+    ),
+    Subcommand::new(
+        "numpat",
+        2,
+        "Returns a count of unique pattern subscriptions.",
+    ),
+    Subcommand::new("numsub", -2, "Returns a count of subscribers to channels."),
+    Subcommand::new("shardchannels", -2, "Returns the active shard channels."),
+    Subcommand::new(
+        "shardnumsub",
+        -2,
+        "Returns the count of subscribers of shard channels.",
+    ),
+];
+
+/// Kind of subscription a client can make, each with its own registry and reply names.
+#[derive(Clone, Copy)]
+enum SubscriptionKind {
+    Channel,
+    Pattern,
+    ShardChannel,
+}
+
+impl SubscriptionKind {
+    fn subscribe_reply(self) -> &'static str {
+        match self {
+            Self::Channel => "subscribe",
+            Self::Pattern => "psubscribe",
+            Self::ShardChannel => "ssubscribe",
+        }
+    }
+
+    fn unsubscribe_reply(self) -> &'static str {
+        match self {
+            Self::Channel => "unsubscribe",
+            Self::Pattern => "punsubscribe",
+            Self::ShardChannel => "sunsubscribe",
+        }
+    }
+
+    fn registry(self, server: &Server) -> &Subscriptions {
+        match self {
+            Self::Channel => &server.pubsub.channels,
+            Self::Pattern => &server.pubsub.patterns,
+            Self::ShardChannel => &server.pubsub.shard_channels,
+        }
+    }
+
+    fn subscribed(self, client: &mut Client) -> &mut HashSet<String> {
+        match self {
+            Self::Channel => &mut client.channels,
+            Self::Pattern => &mut client.patterns,
+            Self::ShardChannel => &mut client.shard_channels,
+        }
+    }
+
+    /// Number of subscriptions reported on the confirmations, shard channels are counted on their own.
+    fn count(self, client: &Client) -> i64 {
+        match self {
+            Self::Channel | Self::Pattern => client.subscription_count() as i64,
+            Self::ShardChannel => client.shard_channels.len() as i64,
+        }
+    }
+}
+
+/// Shard channels given in one command must all be hashed to the same slot, as they can only be served by
+/// the node owning the slot.
+fn check_same_slot(args: &[RespType]) -> Result<(), RespType> {
+    let mut slots = args.iter().filter_map(|arg| match arg {
+        RespType::BulkString(shard_channel) => Some(key_hash_slot(shard_channel)),
+        _ => None,
+    });
+
+    if let Some(first_slot) = slots.next() {
+        if slots.any(|slot| slot != first_slot) {
+            return Err(RespType::Error(
+                "CROSSSLOT Keys in request don't hash to the same slot".into(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Reply every confirmation, the first one as the reply of the command, and the rest right after it.
+fn reply_many(client: &mut Client, mut replies: Vec<RespType>) -> RespType {
+    let first_reply = replies.remove(0);
+    client.extra_replies.append(&mut replies);
+
+    first_reply
+}
+
+fn subscribe_to(
+    kind: SubscriptionKind,
+    args: &[RespType],
+    client: &mut Client,
+    server: &Server,
+) -> RespType {
+    let (client_id, message_sender) = (client.id, client.message_sender.clone());
+    let mut replies = Vec::<RespType>::with_capacity(args.len());
+
+    for name in args {
+        if let RespType::BulkString(name) = name {
+            if kind.subscribed(client).insert(name.into()) {
+                kind.registry(server)
+                    .subscribe(name, client_id, &message_sender);
+            }
+
+            replies.push(client.push(vec![
+                RespType::BulkString(kind.subscribe_reply().into()),
+                RespType::BulkString(name.into()),
+                RespType::Integer(kind.count(client)),
+            ]));
+        }
+    }
+
+    if replies.is_empty() {
+        return RespType::Error(format!(
+            "ERR wrong number of arguments for '{}' command",
+            kind.subscribe_reply()
+        ));
+    }
+
+    reply_many(client, replies)
+}
+
+fn unsubscribe_from(
+    kind: SubscriptionKind,
+    args: &[RespType],
+    client: &mut Client,
+    server: &Server,
+) -> RespType {
+    // No argument means unsubscribing from everything subscribed
+    let names: Vec<String> = if args.is_empty() {
+        kind.subscribed(client).iter().cloned().collect()
+    } else {
+        args.iter()
+            .filter_map(|name| match name {
+                RespType::BulkString(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    if names.is_empty() {
+        return client.push(vec![
+            RespType::BulkString(kind.unsubscribe_reply().into()),
+            RespType::Null,
+            RespType::Integer(kind.count(client)),
+        ]);
+    }
+
+    let mut replies = Vec::<RespType>::with_capacity(names.len());
+    for name in names {
+        if kind.subscribed(client).remove(&name) {
+            kind.registry(server).unsubscribe(&name, client.id);
+        }
+
+        replies.push(client.push(vec![
+            RespType::BulkString(kind.unsubscribe_reply().into()),
+            RespType::BulkString(name),
+            RespType::Integer(kind.count(client)),
+        ]));
+    }
+
+    reply_many(client, replies)
+}
+
+/// SUBSCRIBE Command
+///
+/// Subscribe the client to the channel(s), the client will then receive every message published to it.
+///
+/// Currently implemented syntax
+/// `SUBSCRIBE channel [channel ...]`
+pub fn subscribe(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    subscribe_to(SubscriptionKind::Channel, args, client, server)
+}
+
+/// UNSUBSCRIBE Command
+///
+/// Unsubscribe the client from the channel(s), or from every channel if none is given.
+///
+/// Currently implemented syntax
+/// `UNSUBSCRIBE [channel [channel ...]]`
+pub fn unsubscribe(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    unsubscribe_from(SubscriptionKind::Channel, args, client, server)
+}
+
+/// PSUBSCRIBE Command
+///
+/// Subscribe the client to every channel matching the glob-style pattern(s).
+///
+/// Currently implemented syntax
+/// `PSUBSCRIBE pattern [pattern ...]`
+pub fn psubscribe(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    subscribe_to(SubscriptionKind::Pattern, args, client, server)
+}
+
+/// PUNSUBSCRIBE Command
+///
+/// Unsubscribe the client from the pattern(s), or from every pattern if none is given.
+///
+/// Currently implemented syntax
+/// `PUNSUBSCRIBE [pattern [pattern ...]]`
+pub fn punsubscribe(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    unsubscribe_from(SubscriptionKind::Pattern, args, client, server)
+}
+
+/// SSUBSCRIBE Command
+///
+/// Subscribe the client to the shard channel(s), every shard channel must be hashed to the same slot.
+///
+/// Currently implemented syntax
+/// `SSUBSCRIBE shardchannel [shardchannel ...]`
+pub fn ssubscribe(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    if let Err(err) = check_same_slot(args) {
+        return err;
+    }
+
+    subscribe_to(SubscriptionKind::ShardChannel, args, client, server)
+}
+
+/// SUNSUBSCRIBE Command
+///
+/// Unsubscribe the client from the shard channel(s), or from every shard channel if none is given.
+///
+/// Currently implemented syntax
+/// `SUNSUBSCRIBE [shardchannel [shardchannel ...]]`
+pub fn sunsubscribe(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    if let Err(err) = check_same_slot(args) {
+        return err;
+    }
+
+    unsubscribe_from(SubscriptionKind::ShardChannel, args, client, server)
+}
+
+/// PUBLISH Command
+///
+/// Publish a message to a channel, returning the number of clients that received the message.
+///
+/// Currently implemented syntax
+/// `PUBLISH channel message`
+pub fn publish(args: &[RespType], _client: &mut Client, server: &Server) -> RespType {
+    if let (Some(RespType::BulkString(channel)), Some(RespType::BulkString(message))) =
+        (args.first(), args.get(1))
+    {
+        RespType::Integer(server.pubsub.publish(channel, message))
+    } else {
+        RespType::Error("ARGERR channel and message are required for PUBLISH".into())
+    }
+}
+
+/// SPUBLISH Command
+///
+/// Publish a message to a shard channel, returning the number of clients that received the message.
+///
+/// Currently implemented syntax
+/// `SPUBLISH shardchannel message`
+pub fn spublish(args: &[RespType], _client: &mut Client, server: &Server) -> RespType {
+    if let (Some(RespType::BulkString(shard_channel)), Some(RespType::BulkString(message))) =
+        (args.first(), args.get(1))
+    {
+        RespType::Integer(server.pubsub.spublish(shard_channel, message))
+    } else {
+        RespType::Error("ARGERR shard channel and message are required for SPUBLISH".into())
+    }
+}
+
+/// Every channel with at least one subscriber, optionally filtered by the pattern in the first argument.
+fn active_channels(subscriptions: &Subscriptions, args: &[RespType]) -> RespType {
+    let pattern = match args.first() {
+        Some(RespType::BulkString(pattern)) => Some(pattern.as_str()),
+        _ => None,
+    };
+
+    RespType::Array(
+        subscriptions
+            .names(pattern)
+            .into_iter()
+            .map(RespType::BulkString)
+            .collect(),
+    )
+}
+
+/// Number of subscribers of every channel in the arguments, as a flat list of channel and count.
+fn subscriber_counts(subscriptions: &Subscriptions, args: &[RespType]) -> RespType {
+    let mut counts = Vec::<RespType>::with_capacity(args.len() * 2);
+    for channel in args {
+        if let RespType::BulkString(channel) = channel {
+            counts.push(RespType::BulkString(channel.into()));
+            counts.push(RespType::Integer(subscriptions.count(channel) as i64));
+        }
+    }
+
+    RespType::Array(counts)
+}
+
+/// PUBSUB Command
+///
+/// Inspect the state of the Pub/Sub subsystem.
+///
+/// Currently implemented syntax
+/// `PUBSUB CHANNELS [pattern]`
+/// `PUBSUB NUMSUB [channel [channel ...]]`
+/// `PUBSUB NUMPAT`
+/// `PUBSUB SHARDCHANNELS [pattern]`
+/// `PUBSUB SHARDNUMSUB [shardchannel [shardchannel ...]]`
+/// `PUBSUB HELP`
+pub fn pubsub(args: &[RespType], _client: &mut Client, server: &Server) -> RespType {
+    let subcommand = if let Some(RespType::BulkString(subcommand)) = args.first() {
+        subcommand.to_uppercase()
+    } else {
+        return RespType::Error("ERR wrong number of arguments for 'pubsub' command".into());
+    };
+    let subcommand_args = &args[1..];
+
+    match subcommand.as_str() {
+        "CHANNELS" => active_channels(&server.pubsub.channels, subcommand_args),
+        "NUMSUB" => subscriber_counts(&server.pubsub.channels, subcommand_args),
+        "SHARDCHANNELS" => active_channels(&server.pubsub.shard_channels, subcommand_args),
+        "SHARDNUMSUB" => subscriber_counts(&server.pubsub.shard_channels, subcommand_args),
+        "NUMPAT" => RespType::Integer(server.pubsub.patterns.len() as i64),
+        "HELP" if subcommand_args.is_empty() => help_reply(
+            "PUBSUB",
+            &[
+                "CHANNELS [<pattern>]",
+                "    Return the currently active channels matching a <pattern> (default: '*').",
+                "NUMPAT",
+                "    Return number of subscriptions to patterns.",
+                "NUMSUB [<channel> ...]",
+                "    Return the number of subscribers for the specified channels, excluding",
+                "    pattern subscriptions(default: no channels).",
+                "SHARDCHANNELS [<pattern>]",
+                "    Return the currently active shard level channels matching a <pattern>",
+                "    (default: '*').",
+                "SHARDNUMSUB [<shardchannel> ...]",
+                "    Return the number of subscribers for the specified shard level channel(s)",
+            ],
+        ),
+        "HELP" => RespType::Error("ERR wrong number of arguments for 'pubsub|help' command".into()),
+        _ => RespType::Error(format!(
+            "ERR unknown subcommand '{}'. Try PUBSUB HELP.",
+            subcommand
+        )),
+    }
+}
+
+#[cfg(test)]
+mod pubsub_tests {
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    #[test]
+    fn publish_reaches_channel_and_pattern_subscribers() {
+        let server = Server::new();
+        let mut subscriber = connect(&server);
+        let mut pattern_subscriber = connect(&server);
+        let mut publisher = connect(&server);
+
+        handle_commands(command(&["SUBSCRIBE", "news"]), &mut subscriber, &server);
+        handle_commands(
+            command(&["PSUBSCRIBE", "new*"]),
+            &mut pattern_subscriber,
+            &server,
+        );
+
+        assert_eq!(
+            handle_commands(command(&["PUBLISH", "news", "hi"]), &mut publisher, &server),
+            RespType::Integer(2)
+        );
+        assert_eq!(
+            subscriber.pending_messages(),
+            vec![RespType::Array(vec![
+                RespType::BulkString("message".into()),
+                RespType::BulkString("news".into()),
+                RespType::BulkString("hi".into()),
+            ])]
+        );
+        assert_eq!(
+            pattern_subscriber.pending_messages(),
+            vec![RespType::Array(vec![
+                RespType::BulkString("pmessage".into()),
+                RespType::BulkString("new*".into()),
+                RespType::BulkString("news".into()),
+                RespType::BulkString("hi".into()),
+            ])]
+        );
+    }
+
+    #[test]
+    fn subscribed_mode_only_allows_pubsub_commands() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        handle_commands(command(&["SUBSCRIBE", "a", "b"]), &mut client, &server);
+        assert_eq!(client.extra_replies.len(), 1);
+
+        assert!(matches!(
+            handle_commands(command(&["GET", "key"]), &mut client, &server),
+            RespType::Error(_)
+        ));
+
+        handle_commands(command(&["UNSUBSCRIBE"]), &mut client, &server);
+        assert_eq!(
+            handle_commands(command(&["GET", "key"]), &mut client, &server),
+            RespType::Null
+        );
+        assert!(server.pubsub.channels.is_empty());
+    }
+
+    #[test]
+    fn inspects_subscriptions() {
+        let server = Server::new();
+        let mut subscriber = connect(&server);
+        let mut client = connect(&server);
+
+        handle_commands(command(&["SUBSCRIBE", "news"]), &mut subscriber, &server);
+        assert_eq!(
+            handle_commands(command(&["PUBSUB", "CHANNELS"]), &mut client, &server),
+            RespType::Array(vec![RespType::BulkString("news".into())])
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["PUBSUB", "NUMSUB", "news", "other"]),
+                &mut client,
+                &server
+            ),
+            RespType::Array(vec![
+                RespType::BulkString("news".into()),
+                RespType::Integer(1),
+                RespType::BulkString("other".into()),
+                RespType::Integer(0),
+            ])
+        );
+
+        match handle_commands(command(&["PUBSUB", "HELP"]), &mut client, &server) {
+            RespType::Array(lines) => assert_eq!(
+                lines.first(),
+                Some(&RespType::String(
+                    "PUBSUB <subcommand> [<arg> [value] [opt] ...]. Subcommands are:".into()
+                ))
+            ),
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+    }
+
+    #[test]
+    fn shard_channels_are_separated_from_channels() {
+        let server = Server::new();
+        let mut subscriber = connect(&server);
+        let mut shard_subscriber = connect(&server);
+        let mut publisher = connect(&server);
+
+        handle_commands(command(&["SUBSCRIBE", "orders"]), &mut subscriber, &server);
+        assert_eq!(
+            handle_commands(
+                command(&["SSUBSCRIBE", "orders"]),
+                &mut shard_subscriber,
+                &server
+            ),
+            RespType::Array(vec![
+                RespType::BulkString("ssubscribe".into()),
+                RespType::BulkString("orders".into()),
+                RespType::Integer(1),
+            ])
+        );
+
+        assert_eq!(
+            handle_commands(
+                command(&["SPUBLISH", "orders", "new"]),
+                &mut publisher,
+                &server
+            ),
+            RespType::Integer(1)
+        );
+        assert!(subscriber.pending_messages().is_empty());
+        assert_eq!(shard_subscriber.pending_messages().len(), 1);
+
+        assert_eq!(
+            handle_commands(
+                command(&["SSUBSCRIBE", "{a}orders", "{b}orders"]),
+                &mut shard_subscriber,
+                &server
+            ),
+            RespType::Error("CROSSSLOT Keys in request don't hash to the same slot".into())
+        );
+    }
+}
@@ -0,0 +1,606 @@
+use std::{collections::HashMap, fmt};
+
+use crate::resp::RespType;
+
+use super::commands::{builtin_commands, CommandHandler};
+
+/// Flags of a command, describing how the command behaves, e.g. whether it writes to the storage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommandFlags(u32);
+
+impl CommandFlags {
+    /// Command may modify the storage.
+    pub const WRITE: Self = Self(1 << 0);
+    /// Command only reads from the storage.
+    pub const READONLY: Self = Self(1 << 1);
+    /// Command may use more memory, refused when the server is out of memory.
+    pub const DENYOOM: Self = Self(1 << 2);
+    /// Administrative command, e.g. `SAVE` or `REPLICAOF`.
+    pub const ADMIN: Self = Self(1 << 3);
+    /// Command related to Pub/Sub.
+    pub const PUBSUB: Self = Self(1 << 4);
+    /// Command can't be run by scripts.
+    pub const NOSCRIPT: Self = Self(1 << 5);
+    /// Command may block the client.
+    pub const BLOCKING: Self = Self(1 << 6);
+    /// Command is allowed while the data is loaded.
+    pub const LOADING: Self = Self(1 << 7);
+    /// Command is allowed on a follower with stale data.
+    pub const STALE: Self = Self(1 << 8);
+    /// Command is not shown to `MONITOR`.
+    pub const SKIP_MONITOR: Self = Self(1 << 9);
+    /// Command is not recorded in the slow log.
+    pub const SKIP_SLOWLOG: Self = Self(1 << 10);
+    /// Command runs in constant or logarithmic time.
+    pub const FAST: Self = Self(1 << 11);
+    /// Command can be run without being authenticated.
+    pub const NO_AUTH: Self = Self(1 << 12);
+    /// Command may be passed on to the followers, even though it's not a write command, e.g. `PUBLISH`.
+    pub const MAY_REPLICATE: Self = Self(1 << 13);
+    /// Command can't be queued in a transaction.
+    pub const NO_MULTI: Self = Self(1 << 14);
+    /// Keys of the command can't be found with the key spec alone, e.g. `EVAL`.
+    pub const MOVABLE_KEYS: Self = Self(1 << 15);
+    /// Command is allowed while a script runs for too long.
+    pub const ALLOW_BUSY: Self = Self(1 << 16);
+    /// Command can be run without any key, even though it may take keys.
+    pub const NO_MANDATORY_KEYS: Self = Self(1 << 17);
+
+    /// Names of every flag, the same ones used by Redis in `COMMAND INFO`.
+    pub const NAMES: [(&'static str, Self); 18] = [
+        ("write", Self::WRITE),
+        ("readonly", Self::READONLY),
+        ("denyoom", Self::DENYOOM),
+        ("admin", Self::ADMIN),
+        ("pubsub", Self::PUBSUB),
+        ("noscript", Self::NOSCRIPT),
+        ("blocking", Self::BLOCKING),
+        ("loading", Self::LOADING),
+        ("stale", Self::STALE),
+        ("skip_monitor", Self::SKIP_MONITOR),
+        ("skip_slowlog", Self::SKIP_SLOWLOG),
+        ("fast", Self::FAST),
+        ("no_auth", Self::NO_AUTH),
+        ("may_replicate", Self::MAY_REPLICATE),
+        ("no_multi", Self::NO_MULTI),
+        ("movablekeys", Self::MOVABLE_KEYS),
+        ("allow_busy", Self::ALLOW_BUSY),
+        ("no_mandatory_keys", Self::NO_MANDATORY_KEYS),
+    ];
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Names of every set flag.
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMES
+            .iter()
+            .filter(|(_, flag)| self.contains(*flag))
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+impl std::ops::BitOr for CommandFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// ACL categories of a command, used to allow or deny a group of commands at once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AclCategories(u32);
+
+impl AclCategories {
+    pub const KEYSPACE: Self = Self(1 << 0);
+    pub const READ: Self = Self(1 << 1);
+    pub const WRITE: Self = Self(1 << 2);
+    pub const SET: Self = Self(1 << 3);
+    pub const SORTEDSET: Self = Self(1 << 4);
+    pub const LIST: Self = Self(1 << 5);
+    pub const HASH: Self = Self(1 << 6);
+    pub const STRING: Self = Self(1 << 7);
+    pub const BITMAP: Self = Self(1 << 8);
+    pub const HYPERLOGLOG: Self = Self(1 << 9);
+    pub const GEO: Self = Self(1 << 10);
+    pub const STREAM: Self = Self(1 << 11);
+    pub const PUBSUB: Self = Self(1 << 12);
+    pub const ADMIN: Self = Self(1 << 13);
+    pub const FAST: Self = Self(1 << 14);
+    pub const SLOW: Self = Self(1 << 15);
+    pub const BLOCKING: Self = Self(1 << 16);
+    pub const DANGEROUS: Self = Self(1 << 17);
+    pub const CONNECTION: Self = Self(1 << 18);
+    pub const TRANSACTION: Self = Self(1 << 19);
+    pub const SCRIPTING: Self = Self(1 << 20);
+
+    /// Names of every category, without the `@` prefix.
+    pub const NAMES: [(&'static str, Self); 21] = [
+        ("keyspace", Self::KEYSPACE),
+        ("read", Self::READ),
+        ("write", Self::WRITE),
+        ("set", Self::SET),
+        ("sortedset", Self::SORTEDSET),
+        ("list", Self::LIST),
+        ("hash", Self::HASH),
+        ("string", Self::STRING),
+        ("bitmap", Self::BITMAP),
+        ("hyperloglog", Self::HYPERLOGLOG),
+        ("geo", Self::GEO),
+        ("stream", Self::STREAM),
+        ("pubsub", Self::PUBSUB),
+        ("admin", Self::ADMIN),
+        ("fast", Self::FAST),
+        ("slow", Self::SLOW),
+        ("blocking", Self::BLOCKING),
+        ("dangerous", Self::DANGEROUS),
+        ("connection", Self::CONNECTION),
+        ("transaction", Self::TRANSACTION),
+        ("scripting", Self::SCRIPTING),
+    ];
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Categories implied by the flags of a command, following Redis, e.g. a command without the `fast`
+    /// flag is in `@slow`.
+    pub fn implied_by(flags: CommandFlags) -> Self {
+        let mut categories = Self::empty();
+
+        if flags.contains(CommandFlags::WRITE) {
+            categories = categories | Self::WRITE;
+        }
+        if flags.contains(CommandFlags::READONLY) {
+            categories = categories | Self::READ;
+        }
+        if flags.contains(CommandFlags::ADMIN) {
+            categories = categories | Self::ADMIN | Self::DANGEROUS;
+        }
+        if flags.contains(CommandFlags::PUBSUB) {
+            categories = categories | Self::PUBSUB;
+        }
+        if flags.contains(CommandFlags::BLOCKING) {
+            categories = categories | Self::BLOCKING;
+        }
+
+        if flags.contains(CommandFlags::FAST) {
+            categories | Self::FAST
+        } else {
+            categories | Self::SLOW
+        }
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Names of every set category, without the `@` prefix.
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMES
+            .iter()
+            .filter(|(_, category)| self.contains(*category))
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+impl std::ops::BitOr for AclCategories {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Where the keys are in the arguments of a command, counting the command name itself.
+///
+/// Following Redis, `last` is negative to count from the end, e.g. `-1` for every argument up to the last
+/// one. A command without any key has every field set to `0`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeySpec {
+    pub first: i64,
+    pub last: i64,
+    pub step: i64,
+    /// Position of the number of keys, followed by the keys themselves, for commands with movable keys,
+    /// e.g. `EVAL`. `0` when the keys are found with `first`, `last`, and `step` instead.
+    pub numkeys: i64,
+    /// Flags of every key, the same as Redis, e.g. `RW`, `ACCESS`, and `UPDATE`.
+    pub flags: &'static [&'static str],
+}
+
+impl KeySpec {
+    /// Positions of every key in the command, `None` when the number of keys of the command is not valid.
+    pub fn positions(&self, command: &[RespType]) -> Option<Vec<usize>> {
+        let command_len = command.len() as i64;
+
+        if self.numkeys > 0 {
+            let numkeys = match command.get(self.numkeys as usize) {
+                Some(RespType::BulkString(numkeys)) => numkeys.parse::<i64>().ok()?,
+                _ => return None,
+            };
+            if numkeys < 0 || self.numkeys + numkeys >= command_len {
+                return None;
+            }
+
+            return Some(
+                (self.numkeys + 1..=self.numkeys + numkeys)
+                    .map(|position| position as usize)
+                    .collect(),
+            );
+        }
+
+        if self.first <= 0 || self.step <= 0 {
+            return Some(Vec::new());
+        }
+
+        let last = if self.last < 0 {
+            command_len + self.last
+        } else {
+            self.last.min(command_len - 1)
+        };
+
+        Some(
+            (self.first..=last)
+                .step_by(self.step as usize)
+                .map(|position| position as usize)
+                .collect(),
+        )
+    }
+}
+
+/// Documentation of a command, shown by `COMMAND DOCS`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommandDocs {
+    pub summary: &'static str,
+    /// Version of Redis the command is available since.
+    pub since: &'static str,
+    /// Group of the command, the same as Redis, e.g. `string` or `server`.
+    pub group: &'static str,
+    pub complexity: &'static str,
+}
+
+/// Subcommand of a container command, e.g. `GET` of `SLOWLOG`, shown by `COMMAND INFO` and `COMMAND DOCS`
+/// as `slowlog|get`. Everything else, e.g. the flags, is the same as the container command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subcommand {
+    /// Name of the subcommand, in lowercase.
+    pub name: &'static str,
+    /// Number of arguments, counting both the command name and the subcommand name.
+    pub arity: i64,
+    pub summary: &'static str,
+}
+
+impl Subcommand {
+    pub const fn new(name: &'static str, arity: i64, summary: &'static str) -> Self {
+        Self {
+            name,
+            arity,
+            summary,
+        }
+    }
+}
+
+/// A command that can be run by clients, once it's registered in the [`CommandRegistry`] of the server.
+pub trait Command: Send + Sync {
+    /// Name of the command, in lowercase.
+    fn name(&self) -> &str;
+
+    /// Number of arguments, counting the command name itself.
+    ///
+    /// Following Redis, positive arity means the command needs exactly that many arguments, while negative
+    /// arity means the command needs at least that many.
+    fn arity(&self) -> i64;
+
+    fn flags(&self) -> CommandFlags;
+
+    fn key_spec(&self) -> KeySpec;
+
+    /// ACL categories of the command, including the ones implied by the flags.
+    fn acl_categories(&self) -> AclCategories;
+
+    fn handler(&self) -> CommandHandler;
+
+    fn docs(&self) -> CommandDocs {
+        CommandDocs::default()
+    }
+
+    /// Subcommands of a container command, e.g. `CLIENT`, none for every other command.
+    fn subcommands(&self) -> &[Subcommand] {
+        &[]
+    }
+
+    /// Whether the command (including the command name) has the number of arguments the command needs.
+    fn accepts_arity(&self, command_len: usize) -> bool {
+        let arity = self.arity();
+        let command_len = command_len as i64;
+
+        (arity > 0 && command_len == arity) || (arity < 0 && command_len >= -arity)
+    }
+}
+
+/// Description of a command, used for every built-in command, and for custom commands by library users.
+#[derive(Debug, Clone)]
+pub struct CommandSpec {
+    name: String,
+    arity: i64,
+    flags: CommandFlags,
+    key_spec: KeySpec,
+    acl_categories: AclCategories,
+    handler: CommandHandler,
+    docs: CommandDocs,
+    subcommands: &'static [Subcommand],
+}
+
+impl CommandSpec {
+    /// Command without any flags, keys, or categories, other than the categories implied by the flags.
+    pub fn new(name: &str, arity: i64, handler: CommandHandler) -> Self {
+        Self {
+            name: name.to_lowercase(),
+            arity,
+            flags: CommandFlags::empty(),
+            key_spec: KeySpec::default(),
+            acl_categories: AclCategories::empty(),
+            handler,
+            docs: CommandDocs::default(),
+            subcommands: &[],
+        }
+    }
+
+    pub fn flags(mut self, flags: CommandFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn keys(
+        mut self,
+        first: i64,
+        last: i64,
+        step: i64,
+        flags: &'static [&'static str],
+    ) -> Self {
+        self.key_spec = KeySpec {
+            first,
+            last,
+            step,
+            numkeys: 0,
+            flags,
+        };
+        self
+    }
+
+    /// Keys that come right after the number of keys, at the `numkeys` position, e.g. `EVAL`.
+    pub fn numkeys(mut self, numkeys: i64, flags: &'static [&'static str]) -> Self {
+        self.key_spec = KeySpec {
+            numkeys,
+            flags,
+            ..KeySpec::default()
+        };
+        self
+    }
+
+    /// Categories of the command, other than the ones implied by the flags.
+    pub fn acl_categories(mut self, acl_categories: AclCategories) -> Self {
+        self.acl_categories = acl_categories;
+        self
+    }
+
+    pub fn docs(
+        mut self,
+        summary: &'static str,
+        since: &'static str,
+        group: &'static str,
+        complexity: &'static str,
+    ) -> Self {
+        self.docs = CommandDocs {
+            summary,
+            since,
+            group,
+            complexity,
+        };
+        self
+    }
+
+    pub fn subcommands(mut self, subcommands: &'static [Subcommand]) -> Self {
+        self.subcommands = subcommands;
+        self
+    }
+}
+
+impl Command for CommandSpec {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arity(&self) -> i64 {
+        self.arity
+    }
+
+    fn flags(&self) -> CommandFlags {
+        if self.key_spec.numkeys > 0 {
+            self.flags | CommandFlags::MOVABLE_KEYS
+        } else {
+            self.flags
+        }
+    }
+
+    fn key_spec(&self) -> KeySpec {
+        self.key_spec
+    }
+
+    fn acl_categories(&self) -> AclCategories {
+        self.acl_categories | AclCategories::implied_by(self.flags)
+    }
+
+    fn handler(&self) -> CommandHandler {
+        self.handler
+    }
+
+    fn docs(&self) -> CommandDocs {
+        self.docs
+    }
+
+    fn subcommands(&self) -> &[Subcommand] {
+        self.subcommands
+    }
+}
+
+/// Every command the server can run, looked up by name case-insensitively.
+///
+/// Filled with the built-in commands by default, custom commands should be registered before the server is
+/// shared with the connections, e.g.
+///
+/// ```
+/// use rust_eez::{
+///     commands::{commands::CommandHandler, registry::CommandSpec},
+///     resp::RespType,
+///     server::Server,
+/// };
+///
+/// let mut server = Server::new();
+/// server.commands.register(CommandSpec::new(
+///     "hi",
+///     1,
+///     CommandHandler::Client(|_, _| RespType::String("HELLO".into())),
+/// ));
+///
+/// assert!(server.commands.get("HI").is_some());
+/// ```
+pub struct CommandRegistry {
+    commands: HashMap<String, Box<dyn Command>>,
+}
+
+impl CommandRegistry {
+    /// Registry without any command, not even the built-in ones.
+    pub fn empty() -> Self {
+        Self {
+            commands: HashMap::new(),
+        }
+    }
+
+    /// Register the command, replacing the command registered with the same name if there's any.
+    pub fn register(&mut self, command: impl Command + 'static) {
+        self.commands
+            .insert(command.name().to_lowercase(), Box::new(command));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Command> {
+        self.commands
+            .get(&name.to_lowercase())
+            .map(|command| command.as_ref())
+    }
+
+    /// Every registered command, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Command> {
+        self.commands.values().map(|command| command.as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        for command in builtin_commands() {
+            registry.register(command);
+        }
+
+        registry
+    }
+}
+
+impl fmt::Debug for CommandRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.commands.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod registry_tests {
+    use super::{AclCategories, CommandSpec};
+    use crate::{
+        commands::commands::{handle_commands, CommandHandler},
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    #[test]
+    fn looks_up_commands_case_insensitively() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        handle_commands(command(&["set", "key", "value"]), &mut client, &server);
+        assert_eq!(
+            handle_commands(command(&["gEt", "key"]), &mut client, &server),
+            RespType::BulkString("value".into())
+        );
+
+        let get = server.commands.get("GET").unwrap();
+        assert_eq!(get.name(), "get");
+        assert_eq!(get.acl_categories().names(), vec!["read", "string", "fast"]);
+    }
+
+    #[test]
+    fn checks_arity_before_running() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        assert_eq!(
+            handle_commands(command(&["GET"]), &mut client, &server),
+            RespType::Error("ERR wrong number of arguments for 'get' command".into())
+        );
+        assert_eq!(
+            handle_commands(command(&["SET", "key"]), &mut client, &server),
+            RespType::Error("ERR wrong number of arguments for 'set' command".into())
+        );
+        assert_eq!(
+            handle_commands(command(&["NOPE"]), &mut client, &server),
+            RespType::Error("ERR unknown command 'NOPE'".into())
+        );
+    }
+
+    #[test]
+    fn runs_registered_commands() {
+        let mut server = Server::new();
+        let mut client = connect(&server);
+
+        server.commands.register(
+            CommandSpec::new(
+                "ECHOALL",
+                -2,
+                CommandHandler::Client(|args, _| RespType::Array(args.to_vec())),
+            )
+            .acl_categories(AclCategories::CONNECTION),
+        );
+
+        assert_eq!(
+            handle_commands(command(&["echoall", "a", "b"]), &mut client, &server),
+            RespType::Array(vec![
+                RespType::BulkString("a".into()),
+                RespType::BulkString("b".into())
+            ])
+        );
+        assert_eq!(
+            handle_commands(command(&["ECHOALL"]), &mut client, &server),
+            RespType::Error("ERR wrong number of arguments for 'echoall' command".into())
+        );
+    }
+}
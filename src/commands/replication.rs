@@ -0,0 +1,305 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    client::Client,
+    replication::{AckKind, Role},
+    resp::RespType,
+    server::Server,
+    storage::Storage,
+};
+
+/// How often `WAITAOF` checks if the append only file is flushed, when it can't be flushed right away.
+const AOF_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// REPLICAOF Command
+///
+/// Follow another server as its follower, or stop following and become a leader with `NO ONE`.
+/// Following a leader drops every key, to be replaced by the keys of the leader once synced.
+///
+/// Currently implemented syntax
+/// `REPLICAOF host port`
+/// `REPLICAOF NO ONE`
+pub fn replicaof(args: &[RespType], _client: &mut Client, server: &Server) -> RespType {
+    match args {
+        [RespType::BulkString(no), RespType::BulkString(one)]
+            if no.eq_ignore_ascii_case("NO") && one.eq_ignore_ascii_case("ONE") =>
+        {
+            server.replication.promote();
+
+            RespType::String("OK".into())
+        }
+        [RespType::BulkString(host), RespType::BulkString(port)] => {
+            let port = match port.parse::<u16>() {
+                Ok(port) => port,
+                Err(_) => return RespType::Error("ERR Invalid master port".into()),
+            };
+
+            if server.replication.follow(host, port) {
+                println!(
+                    "[Replication REPLICAOF] Following the leader {}:{}",
+                    host, port
+                );
+
+                RespType::String("OK".into())
+            } else {
+                RespType::String("OK Already connected to specified master".into())
+            }
+        }
+        _ => RespType::Error("ERR syntax error".into()),
+    }
+}
+
+/// ROLE Command
+///
+/// Role of the server in the replication, either `master` with its followers, or `slave` with the state
+/// of the link to its leader.
+///
+/// Currently implemented syntax
+/// `ROLE`
+pub fn role(_args: &[RespType], _client: &mut Client, server: &Server) -> RespType {
+    let offset = RespType::Integer(server.replication.offset() as i64);
+
+    match server.replication.role() {
+        Role::Leader => RespType::Array(vec![
+            RespType::BulkString("master".into()),
+            offset,
+            RespType::Array(
+                server
+                    .replication
+                    .replicas()
+                    .into_iter()
+                    .map(|replica| {
+                        RespType::Array(vec![
+                            RespType::BulkString(replica.ip),
+                            RespType::BulkString(replica.listening_port.to_string()),
+                            RespType::BulkString(replica.ack_offset.to_string()),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ]),
+        Role::Follower { host, port } => RespType::Array(vec![
+            RespType::BulkString("slave".into()),
+            RespType::BulkString(host),
+            RespType::Integer(port as i64),
+            RespType::BulkString(server.replication.link_state().name().into()),
+            offset,
+        ]),
+    }
+}
+
+/// PSYNC Command
+///
+/// Used by followers to sync with the leader, partially when the leader still have every write command
+/// the follower is missing, otherwise fully with a snapshot. Every write command is then streamed to the
+/// follower through the same connection.
+///
+/// Currently implemented syntax
+/// `PSYNC replicationid offset`
+pub fn psync(
+    args: &[RespType],
+    client: &mut Client,
+    server: &Server,
+    storage: &Storage,
+) -> RespType {
+    let (replid, offset) = match args {
+        [RespType::BulkString(replid), RespType::BulkString(offset)] => (replid, offset),
+        _ => return RespType::Error("ERR syntax error".into()),
+    };
+
+    // NOTE: Negative offset, usually `-1` along with the `?` id, means the follower has nothing yet
+    let offset = match offset.parse::<i64>() {
+        Ok(offset) => u64::try_from(offset).ok(),
+        Err(_) => return RespType::Error("ERR value is not an integer or out of range".into()),
+    };
+
+    match server
+        .replication
+        .attach_replica(client, replid, offset, || server.snapshot(storage))
+    {
+        Ok((reply, link)) => {
+            client.replica = Some(link);
+
+            reply
+        }
+        Err(err) => err,
+    }
+}
+
+/// REPLCONF Command
+///
+/// Used by followers to configure the replication with the leader, and to acknowledge the replication
+/// offset processed.
+///
+/// Currently implemented syntax
+/// `REPLCONF option value [option value ...]`
+///
+/// Supported options are `listening-port`, `capa`, `ack`, `fack`, and `getack`.
+pub fn replconf(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    if !args.len().is_multiple_of(2) {
+        return RespType::Error("ERR syntax error".into());
+    }
+
+    for option in args.chunks(2) {
+        let (name, value) = match option {
+            [RespType::BulkString(name), RespType::BulkString(value)] => (name, value),
+            _ => return RespType::Error("ERR syntax error".into()),
+        };
+
+        match name.to_lowercase().as_str() {
+            "listening-port" => match value.parse::<u16>() {
+                Ok(port) => client.listening_port = Some(port),
+                Err(_) => return RespType::Error("ERR Invalid listening port".into()),
+            },
+            // NOTE: Every capability is supported, or ignored
+            "capa" => {}
+            "ack" => match value.parse::<u64>() {
+                Ok(offset) => server
+                    .replication
+                    .acknowledge(client.id, Some(offset), None),
+                Err(_) => {
+                    return RespType::Error("ERR value is not an integer or out of range".into())
+                }
+            },
+            "fack" => match value.parse::<u64>() {
+                Ok(offset) => server
+                    .replication
+                    .acknowledge(client.id, None, Some(offset)),
+                Err(_) => {
+                    return RespType::Error("ERR value is not an integer or out of range".into())
+                }
+            },
+            // NOTE: Only meaningful on the link from the leader, handled by the follower itself
+            "getack" => {}
+            _ => {
+                return RespType::Error(format!("ERR Unrecognized REPLCONF option: {}", name));
+            }
+        }
+    }
+
+    RespType::String("OK".into())
+}
+
+/// WAIT Command
+///
+/// Block until at least `numreplicas` followers acknowledged every write command of the client so far, or
+/// until the timeout (in milliseconds, `0` to wait forever) passed. Replies with the number of followers
+/// that acknowledged them. Inside of a transaction, replies right away.
+///
+/// Currently implemented syntax
+/// `WAIT numreplicas timeout`
+pub fn wait(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    if matches!(server.replication.role(), Role::Follower { .. }) {
+        return RespType::Error("ERR WAIT cannot be used with replica instances. Please also note that since Redis 4.0 if a replica is configured to be writable (which is not the default) writes to replicas are just local and are not propagated.".into());
+    }
+
+    let (numreplicas, deadline) = match (parse_count(&args[0]), parse_deadline(&args[1])) {
+        (Ok(numreplicas), Ok(deadline)) => (numreplicas, deadline),
+        (Err(err), _) | (_, Err(err)) => return err,
+    };
+
+    let acked = if client.deny_blocking {
+        server
+            .replication
+            .acked_replicas(client.last_write_offset, AckKind::Processed)
+    } else {
+        server.replication.wait_for_acks(
+            client.last_write_offset,
+            numreplicas,
+            deadline,
+            AckKind::Processed,
+        )
+    };
+
+    RespType::Integer(acked as i64)
+}
+
+/// WAITAOF Command
+///
+/// Block until every write command of the client so far is flushed to the local append only file (when
+/// `numlocal` is `1`), and to the append only file of at least `numreplicas` followers, or until the
+/// timeout (in milliseconds, `0` to wait forever) passed. Replies with the number of local append only
+/// files and followers that flushed them. Inside of a transaction, replies right away.
+///
+/// Currently implemented syntax
+/// `WAITAOF numlocal numreplicas timeout`
+pub fn waitaof(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    if matches!(server.replication.role(), Role::Follower { .. }) {
+        return RespType::Error("ERR WAITAOF cannot be used with replica instances. Please also note that writes to replicas are just local and are not propagated.".into());
+    }
+
+    let (numlocal, numreplicas, deadline) = match (
+        parse_count(&args[0]),
+        parse_count(&args[1]),
+        parse_deadline(&args[2]),
+    ) {
+        (Ok(numlocal), Ok(numreplicas), Ok(deadline)) => (numlocal, numreplicas, deadline),
+        (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => return err,
+    };
+
+    if numlocal > 0 && !server.aof.is_enabled() {
+        return RespType::Error(
+            "ERR WAITAOF cannot be used when numlocal is set but appendonly is disabled.".into(),
+        );
+    }
+
+    let offset = client.last_write_offset;
+    let is_local_fsynced = || server.aof.is_enabled() && server.aof.fsynced_offset() >= offset;
+
+    // NOTE: Flushed right away whatever `appendfsync` is, rather than waiting for the next periodic flush
+    if numlocal > 0 && !client.deny_blocking {
+        loop {
+            server.fsync_aof(true);
+
+            if is_local_fsynced() || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+
+            thread::sleep(AOF_POLL_INTERVAL);
+        }
+    }
+
+    let local_acked = is_local_fsynced();
+    let replicas_acked = if client.deny_blocking || (numlocal > 0 && !local_acked) {
+        server.replication.acked_replicas(offset, AckKind::Fsynced)
+    } else {
+        server
+            .replication
+            .wait_for_acks(offset, numreplicas, deadline, AckKind::Fsynced)
+    };
+
+    RespType::Array(vec![
+        RespType::Integer(local_acked as i64),
+        RespType::Integer(replicas_acked as i64),
+    ])
+}
+
+/// Number of followers (or local append only files) to wait for, negative numbers are the same as `0`.
+fn parse_count(arg: &RespType) -> Result<usize, RespType> {
+    match arg {
+        RespType::BulkString(count) => count
+            .parse::<i64>()
+            .map(|count| count.max(0) as usize)
+            .map_err(|_| RespType::Error("ERR value is not an integer or out of range".into())),
+        _ => Err(RespType::Error("ERR syntax error".into())),
+    }
+}
+
+/// Deadline from a timeout in milliseconds, `None` for `0` as it means waiting forever.
+fn parse_deadline(arg: &RespType) -> Result<Option<Instant>, RespType> {
+    let timeout = match arg {
+        RespType::BulkString(timeout) => timeout
+            .parse::<i64>()
+            .map_err(|_| RespType::Error("ERR timeout is not an integer or out of range".into()))?,
+        _ => return Err(RespType::Error("ERR syntax error".into())),
+    };
+
+    match timeout {
+        0 => Ok(None),
+        timeout if timeout < 0 => Err(RespType::Error("ERR timeout is negative".into())),
+        timeout => Ok(Some(Instant::now() + Duration::from_millis(timeout as u64))),
+    }
+}
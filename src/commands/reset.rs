@@ -0,0 +1,113 @@
+use crate::{
+    acl::DEFAULT_USER,
+    client::{Client, ReplyMode},
+    resp::RespType,
+    server::Server,
+};
+
+/// RESET Command
+///
+/// Reset the connection back to the state it had when it was just connected. Discarding any transaction,
+/// unwatching every key, unsubscribing from every channel, turning off tracking and monitoring, switching
+/// back to RESP2 and the default user, and clearing the name and the flags set with `CLIENT`. The library
+/// name and version are kept, as it's still the same library behind the connection.
+///
+/// Currently implemented syntax
+/// `RESET`
+pub fn reset(_: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    client.transaction = None;
+    client.disconnect(server);
+    client.protocol = 2;
+    client.user = Some(DEFAULT_USER.into());
+    client.authenticated = !server.acl.requires_auth();
+    client.name.clear();
+    client.reply_mode = ReplyMode::On;
+    client.no_evict = false;
+    client.no_touch = false;
+
+    RespType::String("RESET".into())
+}
+
+#[cfg(test)]
+mod reset_tests {
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    #[test]
+    fn switches_back_to_default_user() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        for args in [
+            &["CONFIG", "SET", "requirepass", "secret"][..],
+            &[
+                "ACL",
+                "SETUSER",
+                "alice",
+                "on",
+                ">wonderland",
+                "+@all",
+                "~*",
+            ],
+            &["AUTH", "secret"],
+        ] {
+            assert_eq!(
+                handle_commands(command(args), &mut client, &server),
+                RespType::String("OK".into())
+            );
+        }
+
+        handle_commands(command(&["RESET"]), &mut client, &server);
+        assert_eq!(
+            handle_commands(command(&["GET", "key"]), &mut client, &server),
+            RespType::Error("NOAUTH Authentication required.".into())
+        );
+
+        handle_commands(
+            command(&["AUTH", "alice", "wonderland"]),
+            &mut client,
+            &server,
+        );
+        assert_eq!(
+            handle_commands(command(&["ACL", "WHOAMI"]), &mut client, &server),
+            RespType::BulkString("alice".into())
+        );
+        handle_commands(command(&["RESET"]), &mut client, &server);
+        handle_commands(command(&["AUTH", "secret"]), &mut client, &server);
+        assert_eq!(
+            handle_commands(command(&["ACL", "WHOAMI"]), &mut client, &server),
+            RespType::BulkString("default".into())
+        );
+    }
+
+    #[test]
+    fn clears_client_state() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        for args in [
+            &["CLIENT", "SETNAME", "foo"][..],
+            &["CLIENT", "SETINFO", "LIB-NAME", "lib"],
+            &["CLIENT", "NO-EVICT", "ON"],
+            &["CLIENT", "NO-TOUCH", "ON"],
+            &["CLIENT", "REPLY", "OFF"],
+        ] {
+            handle_commands(command(args), &mut client, &server);
+        }
+        assert!(!client.take_reply_mode());
+
+        handle_commands(command(&["RESET"]), &mut client, &server);
+        assert!(client.take_reply_mode());
+        assert!(!client.no_evict);
+        assert!(!client.no_touch);
+        assert_eq!(
+            handle_commands(command(&["CLIENT", "GETNAME"]), &mut client, &server),
+            RespType::Null
+        );
+        assert_eq!(client.lib_name, "lib");
+    }
+}
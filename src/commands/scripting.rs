@@ -0,0 +1,704 @@
+use crate::{
+    client::Client,
+    functions::RestorePolicy,
+    glob::glob_match,
+    rdb::{dump_functions, restore_functions, verify_dump_payload},
+    resp::{bytes_to_string, string_to_bytes, RespType},
+    scripting::{FunctionCall, Script, ScriptCall},
+    server::Server,
+    storage::Storage,
+};
+
+use super::{commands::help_reply, registry::Subcommand};
+
+/// Subcommands of `SCRIPT`, see [`script`].
+pub(super) const SCRIPT_SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand::new(
+        "exists",
+        -3,
+        "Determines whether server-side Lua scripts exist in the script cache.",
+    ),
+    Subcommand::new(
+        "flush",
+        -2,
+        "Removes all server-side Lua scripts from the script cache.",
+    ),
+    Subcommand::new(
+        "help",
+        2,
+        "Returns helpful text about the different subcommands.",
+    ),
+    Subcommand::new(
+        "kill",
+        2,
+        "Terminates a server-side Lua script during execution.",
+    ),
+    Subcommand::new(
+        "load",
+        3,
+        "Loads a server-side Lua script to the script cache.",
+    ),
+];
+
+/// Subcommands of `FUNCTION`, see [`function`].
+pub(super) const FUNCTION_SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand::new("delete", 3, "Deletes a library and its functions."),
+    Subcommand::new(
+        "dump",
+        2,
+        "Dumps all libraries into a serialized binary payload.",
+    ),
+    Subcommand::new("flush", -2, "Deletes all libraries and functions."),
+    Subcommand::new(
+        "help",
+        2,
+        "Returns helpful text about the different subcommands.",
+    ),
+    Subcommand::new("kill", 2, "Terminates a function during execution."),
+    Subcommand::new("list", -2, "Returns information about all libraries."),
+    Subcommand::new("load", -3, "Creates a library."),
+    Subcommand::new("restore", -3, "Restores all libraries from a payload."),
+    Subcommand::new(
+        "stats",
+        2,
+        "Returns information about a function during execution.",
+    ),
+];
+
+/// EVAL Command
+///
+/// Run a Lua script atomically, with the key names in `KEYS` and the rest of the arguments in `ARGV`.
+/// Commands are run from the script with `redis.call` or `redis.pcall`.
+///
+/// Currently implemented syntax
+/// `EVAL script numkeys [key [key ...]] [arg [arg ...]]`
+pub fn eval(
+    args: &[RespType],
+    client: &mut Client,
+    server: &Server,
+    storage: &mut Storage,
+) -> RespType {
+    run_script(args, false, false, client, server, storage)
+}
+
+/// EVALSHA Command
+///
+/// Same as `EVAL`, but running a script already loaded by `EVAL` or `SCRIPT LOAD`, using the SHA1 of the
+/// script.
+///
+/// Currently implemented syntax
+/// `EVALSHA sha1 numkeys [key [key ...]] [arg [arg ...]]`
+pub fn evalsha(
+    args: &[RespType],
+    client: &mut Client,
+    server: &Server,
+    storage: &mut Storage,
+) -> RespType {
+    run_script(args, true, false, client, server, storage)
+}
+
+/// EVAL_RO Command
+///
+/// Same as `EVAL`, but the script can't run any write command.
+///
+/// Currently implemented syntax
+/// `EVAL_RO script numkeys [key [key ...]] [arg [arg ...]]`
+pub fn eval_ro(
+    args: &[RespType],
+    client: &mut Client,
+    server: &Server,
+    storage: &mut Storage,
+) -> RespType {
+    run_script(args, false, true, client, server, storage)
+}
+
+/// EVALSHA_RO Command
+///
+/// Same as `EVALSHA`, but the script can't run any write command.
+///
+/// Currently implemented syntax
+/// `EVALSHA_RO sha1 numkeys [key [key ...]] [arg [arg ...]]`
+pub fn evalsha_ro(
+    args: &[RespType],
+    client: &mut Client,
+    server: &Server,
+    storage: &mut Storage,
+) -> RespType {
+    run_script(args, true, true, client, server, storage)
+}
+
+fn run_script(
+    args: &[RespType],
+    is_sha: bool,
+    read_only: bool,
+    client: &mut Client,
+    server: &Server,
+    storage: &mut Storage,
+) -> RespType {
+    let (script, keys, script_args) = match parse_keys(args) {
+        Ok(parsed) => parsed,
+        Err(err) => return err,
+    };
+
+    let script = if is_sha {
+        Script::Sha(script)
+    } else {
+        Script::Source(script)
+    };
+
+    server.scripting.eval(
+        ScriptCall {
+            script,
+            keys,
+            args: script_args,
+            read_only,
+        },
+        client,
+        server,
+        storage,
+    )
+}
+
+/// Split the arguments of `EVAL` and `FCALL` into the script (or function), the keys, and the rest of the
+/// arguments.
+fn parse_keys(args: &[RespType]) -> Result<(&String, &[RespType], &[RespType]), RespType> {
+    let (script, numkeys) = match args {
+        [RespType::BulkString(script), RespType::BulkString(numkeys), ..] => (script, numkeys),
+        _ => return Err(RespType::Error("ERR syntax error".into())),
+    };
+
+    let numkeys = match numkeys.parse::<i64>() {
+        Ok(numkeys) if numkeys < 0 => {
+            return Err(RespType::Error(
+                "ERR Number of keys can't be negative".into(),
+            ))
+        }
+        Ok(numkeys) if numkeys as usize > args.len() - 2 => {
+            return Err(RespType::Error(
+                "ERR Number of keys can't be greater than number of args".into(),
+            ))
+        }
+        Ok(numkeys) => numkeys as usize,
+        Err(_) => {
+            return Err(RespType::Error(
+                "ERR value is not an integer or out of range".into(),
+            ))
+        }
+    };
+    let (keys, args) = args[2..].split_at(numkeys);
+
+    Ok((script, keys, args))
+}
+
+/// SCRIPT Command
+///
+/// Manage the scripts cached by `EVAL` and `SCRIPT LOAD`, or kill the script running right now if it
+/// didn't write anything yet.
+///
+/// Currently implemented syntax
+/// `SCRIPT LOAD script`
+/// `SCRIPT EXISTS sha1 [sha1 ...]`
+/// `SCRIPT FLUSH [ASYNC | SYNC]`
+/// `SCRIPT KILL`
+/// `SCRIPT HELP`
+pub fn script(args: &[RespType], _client: &mut Client, server: &Server) -> RespType {
+    let subcommand = match args.first() {
+        Some(RespType::BulkString(subcommand)) => subcommand.to_uppercase(),
+        _ => return RespType::Error("ERR syntax error".into()),
+    };
+
+    match (subcommand.as_str(), &args[1..]) {
+        ("LOAD", [RespType::BulkString(script)]) => match server.scripting.load(script) {
+            Ok(sha) => RespType::BulkString(sha),
+            Err(err) => err,
+        },
+        ("EXISTS", shas) if !shas.is_empty() => RespType::Array(
+            shas.iter()
+                .map(|sha| match sha {
+                    RespType::BulkString(sha) => {
+                        RespType::Integer(server.scripting.exists(sha) as i64)
+                    }
+                    _ => RespType::Integer(0),
+                })
+                .collect(),
+        ),
+        // NOTE: Flushing is always done right away, as there's nothing to free in the background
+        ("FLUSH", modes) if is_flush_mode(modes) => {
+            server.scripting.flush();
+
+            RespType::String("OK".into())
+        }
+        ("KILL", []) => match server.scripting.kill(false) {
+            Ok(_) => RespType::String("OK".into()),
+            Err(err) => err,
+        },
+        ("HELP", []) => help_reply(
+            "SCRIPT",
+            &[
+                "EXISTS <sha1> [<sha1> ...]",
+                "    Return information about the existence of the scripts in the script cache.",
+                "FLUSH [ASYNC|SYNC]",
+                "    Flush the Lua scripts cache. Very dangerous on replicas.",
+                "    The cache is always flushed right away, whatever the mode is.",
+                "KILL",
+                "    Kill the currently executing Lua script.",
+                "LOAD <script>",
+                "    Load a script into the scripts cache without executing it.",
+            ],
+        ),
+        ("LOAD" | "EXISTS" | "FLUSH" | "KILL" | "HELP", _) => RespType::Error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try SCRIPT HELP.",
+            subcommand
+        )),
+        _ => RespType::Error(format!(
+            "ERR unknown subcommand '{}'. Try SCRIPT HELP.",
+            subcommand
+        )),
+    }
+}
+
+/// Whether the arguments of `SCRIPT FLUSH` are valid, either nothing or `ASYNC` or `SYNC`.
+fn is_flush_mode(args: &[RespType]) -> bool {
+    match args {
+        [] => true,
+        [RespType::BulkString(mode)] => {
+            mode.eq_ignore_ascii_case("ASYNC") || mode.eq_ignore_ascii_case("SYNC")
+        }
+        _ => false,
+    }
+}
+
+/// FCALL Command
+///
+/// Run a function of a library loaded with `FUNCTION LOAD` atomically, with the key names and the rest of
+/// the arguments given to the function.
+///
+/// Currently implemented syntax
+/// `FCALL function numkeys [key [key ...]] [arg [arg ...]]`
+pub fn fcall(
+    args: &[RespType],
+    client: &mut Client,
+    server: &Server,
+    storage: &mut Storage,
+) -> RespType {
+    run_function("FCALL", args, false, client, server, storage)
+}
+
+/// FCALL_RO Command
+///
+/// Same as `FCALL`, but only functions registered with the `no-writes` flag can be run.
+///
+/// Currently implemented syntax
+/// `FCALL_RO function numkeys [key [key ...]] [arg [arg ...]]`
+pub fn fcall_ro(
+    args: &[RespType],
+    client: &mut Client,
+    server: &Server,
+    storage: &mut Storage,
+) -> RespType {
+    run_function("FCALL_RO", args, true, client, server, storage)
+}
+
+fn run_function(
+    command_name: &str,
+    args: &[RespType],
+    read_only: bool,
+    client: &mut Client,
+    server: &Server,
+    storage: &mut Storage,
+) -> RespType {
+    let (name, keys, function_args) = match parse_keys(args) {
+        Ok(parsed) => parsed,
+        Err(err) => return err,
+    };
+
+    let command = [RespType::BulkString(command_name.into())]
+        .into_iter()
+        .chain(args.iter().cloned())
+        .collect::<Vec<_>>();
+
+    server.scripting.fcall(
+        FunctionCall {
+            name,
+            keys,
+            args: function_args,
+            read_only,
+            command: &command,
+        },
+        client,
+        server,
+        storage,
+    )
+}
+
+/// FUNCTION Command
+///
+/// Manage the function libraries run by `FCALL`, which are saved along with the storage, and passed on to
+/// the append only file and the followers.
+///
+/// Currently implemented syntax
+/// `FUNCTION LOAD [REPLACE] function-code`
+/// `FUNCTION LIST [LIBRARYNAME library-name-pattern] [WITHCODE]`
+/// `FUNCTION DELETE library-name`
+/// `FUNCTION FLUSH [ASYNC | SYNC]`
+/// `FUNCTION STATS`
+/// `FUNCTION KILL`
+/// `FUNCTION DUMP`
+/// `FUNCTION RESTORE serialized-value [FLUSH | APPEND | REPLACE]`
+/// `FUNCTION HELP`
+pub fn function(
+    args: &[RespType],
+    client: &mut Client,
+    server: &Server,
+    _storage: &mut Storage,
+) -> RespType {
+    let subcommand = match args.first() {
+        Some(RespType::BulkString(subcommand)) => subcommand.to_uppercase(),
+        _ => return RespType::Error("ERR syntax error".into()),
+    };
+
+    if matches!(subcommand.as_str(), "LOAD" | "DELETE" | "FLUSH" | "RESTORE")
+        && !client.ignores_read_only()
+        && server.replication.is_read_only_follower()
+    {
+        return RespType::Error("READONLY You can't write against a read only replica.".into());
+    }
+
+    let reply = match (subcommand.as_str(), &args[1..]) {
+        ("LOAD", [RespType::BulkString(code)]) => server.scripting.load_library(code, false),
+        ("LOAD", [RespType::BulkString(replace), RespType::BulkString(code)]) => {
+            if replace.eq_ignore_ascii_case("REPLACE") {
+                server.scripting.load_library(code, true)
+            } else {
+                Err(RespType::Error(format!(
+                    "ERR Unknown option given: {}",
+                    replace
+                )))
+            }
+        }
+        ("LOAD", [_, _, ..]) => Err(RespType::Error("ERR syntax error".into())),
+        ("LIST", options) => return function_list(options, client, server),
+        ("DELETE", [RespType::BulkString(name)]) => {
+            if server.scripting.delete_library(name) {
+                Ok(String::new())
+            } else {
+                Err(RespType::Error("ERR Library not found".into()))
+            }
+        }
+        // NOTE: Flushing is always done right away, the same as `SCRIPT FLUSH`
+        ("FLUSH", modes) if is_flush_mode(modes) => {
+            server.scripting.flush_libraries();
+
+            Ok(String::new())
+        }
+        ("STATS", []) => return function_stats(client, server),
+        ("KILL", []) => {
+            return match server.scripting.kill(true) {
+                Ok(_) => RespType::String("OK".into()),
+                Err(err) => err,
+            }
+        }
+        ("DUMP", []) => {
+            return RespType::BulkString(bytes_to_string(&dump_functions(
+                &server.scripting.library_codes(),
+            )))
+        }
+        ("RESTORE", [RespType::BulkString(payload), policy @ ..]) if policy.len() <= 1 => {
+            function_restore(payload, policy, server)
+        }
+        ("HELP", []) => return help_reply(
+            "FUNCTION",
+            &[
+                "LOAD [REPLACE] <FUNCTION CODE>",
+                "    Create a new library with the given library name and code.",
+                "DELETE <LIBRARY NAME>",
+                "    Delete the given library.",
+                "LIST [LIBRARYNAME PATTERN] [WITHCODE]",
+                "    Return general information on all the libraries:",
+                "    * Library name",
+                "    * The engine used to run the Library",
+                "    * Library description",
+                "    * Functions list",
+                "    * Library code (if WITHCODE is given)",
+                "    It also possible to get only function that matches a pattern using",
+                "    LIBRARYNAME argument.",
+                "STATS",
+                "    Return information about the current function running:",
+                "    * Function name",
+                "    * Command used to run the function",
+                "    * Duration in MS that the function is running",
+                "    If no function is running, return nil",
+                "    In addition, returns a list of available engines.",
+                "KILL",
+                "    Kill the current running function.",
+                "FLUSH [ASYNC|SYNC]",
+                "    Delete all the libraries, always right away whatever the mode is.",
+                "DUMP",
+                "    Return a serialized payload representing the current libraries, can be",
+                "    restored using FUNCTION RESTORE command",
+                "RESTORE <PAYLOAD> [FLUSH|APPEND|REPLACE]",
+                "    Restore the libraries represented by the given payload, it is possible to",
+                "    give a restore policy to control how to handle existing libraries (default",
+                "    APPEND):",
+                "    * FLUSH: delete all existing libraries.",
+                "    * APPEND: appends the restored libraries to the existing libraries. On",
+                "      collision, abort.",
+                "    * REPLACE: appends the restored libraries to the existing libraries. On",
+                "      collision, replace the old libraries with the new libraries (notice",
+                "      that even on this option there is a chance of failure in case of",
+                "      functions name collision with another library).",
+            ],
+        ),
+        ("LOAD" | "DELETE" | "FLUSH" | "STATS" | "KILL" | "DUMP" | "RESTORE" | "HELP", _) => {
+            Err(RespType::Error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try FUNCTION HELP.",
+                subcommand
+            )))
+        }
+        _ => Err(RespType::Error(format!(
+            "ERR unknown subcommand '{}'. Try FUNCTION HELP.",
+            subcommand
+        ))),
+    };
+
+    match reply {
+        Ok(name) => {
+            // NOTE: Passed on as is, so the libraries are the same on the append only file and followers
+            client.propagated = Some(vec![[RespType::BulkString("FUNCTION".into())]
+                .into_iter()
+                .chain(args.iter().cloned())
+                .collect()]);
+            server.persistence.add_changes(1);
+
+            if subcommand == "LOAD" {
+                RespType::BulkString(name)
+            } else {
+                RespType::String("OK".into())
+            }
+        }
+        Err(err) => err,
+    }
+}
+
+fn function_list(options: &[RespType], client: &Client, server: &Server) -> RespType {
+    let mut pattern = None;
+    let mut with_code = false;
+
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option {
+            RespType::BulkString(option) if option.eq_ignore_ascii_case("WITHCODE") => {
+                with_code = true
+            }
+            RespType::BulkString(option) if option.eq_ignore_ascii_case("LIBRARYNAME") => {
+                match options.next() {
+                    Some(RespType::BulkString(library_pattern)) => pattern = Some(library_pattern),
+                    _ => return RespType::Error("ERR library name argument was not given".into()),
+                }
+            }
+            RespType::BulkString(option) => {
+                return RespType::Error(format!("ERR Unknown argument {}", option))
+            }
+            _ => return RespType::Error("ERR syntax error".into()),
+        }
+    }
+
+    let libraries = server
+        .scripting
+        .libraries()
+        .into_values()
+        .filter(|library| pattern.is_none_or(|pattern| glob_match(pattern, &library.name)))
+        .map(|library| {
+            let functions = library
+                .functions
+                .into_values()
+                .map(|function| {
+                    map_reply(
+                        vec![
+                            ("name", RespType::BulkString(function.name)),
+                            (
+                                "description",
+                                function
+                                    .description
+                                    .map_or(RespType::Null, RespType::BulkString),
+                            ),
+                            (
+                                "flags",
+                                RespType::Array(
+                                    function
+                                        .flags
+                                        .iter()
+                                        .map(|flag| RespType::BulkString(flag.to_string()))
+                                        .collect(),
+                                ),
+                            ),
+                        ],
+                        client,
+                    )
+                })
+                .collect();
+
+            let mut entries = vec![
+                ("library_name", RespType::BulkString(library.name)),
+                ("engine", RespType::BulkString("LUA".into())),
+                ("functions", RespType::Array(functions)),
+            ];
+            if with_code {
+                entries.push(("library_code", RespType::BulkString(library.code)));
+            }
+
+            map_reply(entries, client)
+        })
+        .collect();
+
+    RespType::Array(libraries)
+}
+
+/// Reply of `FUNCTION STATS`, also used while a script runs for too long, as it doesn't need the storage.
+pub fn function_stats(client: &Client, server: &Server) -> RespType {
+    if server.scripting.is_busy() && !server.scripting.is_running_function() {
+        return RespType::Error(
+            "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE."
+                .into(),
+        );
+    }
+
+    let running = match server.scripting.running_function() {
+        Some(running) => map_reply(
+            vec![
+                ("name", RespType::BulkString(running.name)),
+                ("command", RespType::Array(running.command)),
+                (
+                    "duration_ms",
+                    RespType::Integer(running.duration.as_millis() as i64),
+                ),
+            ],
+            client,
+        ),
+        None => RespType::Null,
+    };
+
+    let libraries = server.scripting.libraries();
+    let functions_count = libraries
+        .values()
+        .map(|library| library.functions.len())
+        .sum::<usize>();
+
+    map_reply(
+        vec![
+            ("running_script", running),
+            (
+                "engines",
+                map_reply(
+                    vec![(
+                        "LUA",
+                        map_reply(
+                            vec![
+                                ("libraries_count", RespType::Integer(libraries.len() as i64)),
+                                ("functions_count", RespType::Integer(functions_count as i64)),
+                            ],
+                            client,
+                        ),
+                    )],
+                    client,
+                ),
+            ),
+        ],
+        client,
+    )
+}
+
+fn function_restore(
+    payload: &str,
+    policy: &[RespType],
+    server: &Server,
+) -> Result<String, RespType> {
+    let policy =
+        match policy {
+            [] => RestorePolicy::Append,
+            [RespType::BulkString(policy)] if policy.eq_ignore_ascii_case("FLUSH") => {
+                RestorePolicy::Flush
+            }
+            [RespType::BulkString(policy)] if policy.eq_ignore_ascii_case("APPEND") => {
+                RestorePolicy::Append
+            }
+            [RespType::BulkString(policy)] if policy.eq_ignore_ascii_case("REPLACE") => {
+                RestorePolicy::Replace
+            }
+            _ => return Err(RespType::Error(
+                "ERR Wrong restore policy given, value should be either FLUSH, APPEND or REPLACE."
+                    .into(),
+            )),
+        };
+
+    let payload = string_to_bytes(payload);
+    if !verify_dump_payload(&payload) {
+        return Err(RespType::Error(
+            "ERR payload version or checksum are wrong".into(),
+        ));
+    }
+
+    let codes = match restore_functions(&payload) {
+        Ok(codes) => codes
+            .iter()
+            .map(|code| bytes_to_string(code))
+            .collect::<Vec<_>>(),
+        Err(err) => return Err(RespType::Error(format!("ERR {}", err))),
+    };
+
+    server
+        .scripting
+        .restore_libraries(&codes, policy)
+        .map(|_| String::new())
+}
+
+/// Reply to a command sent while a script runs for too long. Only the running script can be killed, and
+/// the running function can be checked on, right away as the storage is locked by the script.
+pub fn while_busy(command: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    let running_function = server.scripting.is_running_function();
+
+    match command {
+        [RespType::BulkString(command_name), RespType::BulkString(subcommand)]
+            if command_name.eq_ignore_ascii_case("SCRIPT")
+                && subcommand.eq_ignore_ascii_case("KILL") =>
+        {
+            script(&command[1..], client, server)
+        }
+        [RespType::BulkString(command_name), RespType::BulkString(subcommand)]
+            if command_name.eq_ignore_ascii_case("FUNCTION")
+                && subcommand.eq_ignore_ascii_case("KILL") =>
+        {
+            match server.scripting.kill(true) {
+                Ok(_) => RespType::String("OK".into()),
+                Err(err) => err,
+            }
+        }
+        [RespType::BulkString(command_name), RespType::BulkString(subcommand)]
+            if command_name.eq_ignore_ascii_case("FUNCTION")
+                && subcommand.eq_ignore_ascii_case("STATS") =>
+        {
+            function_stats(client, server)
+        }
+        _ => RespType::Error(format!(
+            "BUSY Redis is busy running a script. You can only call {} KILL or SHUTDOWN NOSAVE.",
+            if running_function {
+                "FUNCTION"
+            } else {
+                "SCRIPT"
+            }
+        )),
+    }
+}
+
+/// Map for RESP3 clients, flattened into an array for RESP2 clients.
+pub fn map_reply(entries: Vec<(&str, RespType)>, client: &Client) -> RespType {
+    let entries = entries
+        .into_iter()
+        .map(|(key, value)| (RespType::BulkString(key.into()), value));
+
+    if client.protocol >= 3 {
+        RespType::Map(entries.collect())
+    } else {
+        RespType::Array(entries.flat_map(|(key, value)| [key, value]).collect())
+    }
+}
@@ -1,11 +1,12 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock},
-};
+use std::collections::HashMap;
 
-use crate::{resp::RespType, storage::StorageType};
+use crate::{
+    notification::NotifyFlags,
+    resp::RespType,
+    storage::{Storage, StorageType},
+};
 
-pub fn hset(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageType>>>) -> RespType {
+pub fn hset(args: &[RespType], storage: &mut Storage) -> RespType {
     let mut args_iter = args.iter();
 
     let key = if let Some(RespType::BulkString(key)) = args_iter.next() {
@@ -19,44 +20,32 @@ pub fn hset(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageType>>
         return RespType::Error("ARGERR at least one field are required for HSET".into());
     }
 
-    match storage.write() {
-        Ok(mut storage_locked) => {
-            let existing = storage_locked.get(key);
-            let mut insert_count = 0;
-
-            let mut existing_hash = if let Some(StorageType::HashMap(existing_hash)) = existing {
-                existing_hash.clone()
-            } else if existing.is_some() {
-                return RespType::Error(
-                    "WRONGTYPE operation against a key holding non-hashmap".into(),
-                );
-            } else {
-                HashMap::new()
-            };
-
-            while let (
-                Some(RespType::BulkString(field_key)),
-                Some(RespType::BulkString(field_value)),
-            ) = (args_iter.next(), args_iter.next())
-            {
-                existing_hash.insert(field_key.into(), field_value.into());
-
-                insert_count += 1;
-            }
-
-            storage_locked.insert(key.into(), StorageType::HashMap(existing_hash));
-
-            RespType::Integer(insert_count)
-        }
-        Err(err) => {
-            println!("[SetOp HSET] Got poisoned error for write: {:#?}", err);
+    let existing = storage.get(key);
+    let mut insert_count = 0;
 
-            RespType::Error("ERR system error while inserting data".into())
-        }
+    let mut existing_hash = if let Some(StorageType::HashMap(existing_hash)) = existing {
+        existing_hash.clone()
+    } else if existing.is_some() {
+        return RespType::Error("WRONGTYPE operation against a key holding non-hashmap".into());
+    } else {
+        HashMap::new()
+    };
+
+    while let (Some(RespType::BulkString(field_key)), Some(RespType::BulkString(field_value))) =
+        (args_iter.next(), args_iter.next())
+    {
+        existing_hash.insert(field_key.into(), field_value.into());
+
+        insert_count += 1;
     }
+
+    storage.insert(key.into(), StorageType::HashMap(existing_hash));
+    storage.notify(NotifyFlags::HASH, "hset", key);
+
+    RespType::Integer(insert_count)
 }
 
-pub fn hget(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageType>>>) -> RespType {
+pub fn hget(args: &[RespType], storage: &Storage) -> RespType {
     let mut args_iter = args.iter();
 
     let (key, field_key) =
@@ -68,32 +57,21 @@ pub fn hget(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageType>>
             return RespType::Error("ARGERR key and field is required for HGET".into());
         };
 
-    match storage.read() {
-        Ok(storage_locked) => {
-            let existing = storage_locked.get(key);
-            if let Some(StorageType::HashMap(existing_hash)) = existing {
-                if let Some(field_value) = existing_hash.get(field_key) {
-                    RespType::BulkString(field_value.into())
-                } else if existing.is_some() {
-                    return RespType::Error(
-                        "WRONGTYPE operation against a key holding non-hashmap".into(),
-                    );
-                } else {
-                    RespType::Null
-                }
-            } else {
-                RespType::Null
-            }
-        }
-        Err(err) => {
-            println!("[SetOp HGET] Got poisoned storage for read: {:#?}", err);
-
-            RespType::Error("ERR system error while getting data".into())
+    let existing = storage.lookup_read(key);
+    if let Some(StorageType::HashMap(existing_hash)) = existing {
+        if let Some(field_value) = existing_hash.get(field_key) {
+            RespType::BulkString(field_value.into())
+        } else {
+            RespType::Null
         }
+    } else if existing.is_some() {
+        RespType::Error("WRONGTYPE operation against a key holding non-hashmap".into())
+    } else {
+        RespType::Null
     }
 }
 
-pub fn hgetall(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageType>>>) -> RespType {
+pub fn hgetall(args: &[RespType], storage: &Storage) -> RespType {
     let key = if let Some(RespType::BulkString(key)) = args.first() {
         key
     } else {
@@ -102,31 +80,20 @@ pub fn hgetall(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageTyp
 
     let mut return_values = Vec::<RespType>::new();
 
-    match storage.read() {
-        Ok(storage_locked) => {
-            let existing = storage_locked.get(key);
-            if let Some(StorageType::HashMap(existing_hash)) = existing {
-                for (key, val) in existing_hash.iter() {
-                    return_values.push(RespType::BulkString(key.into()));
-                    return_values.push(RespType::BulkString(val.into()));
-                }
-            } else if existing.is_some() {
-                return RespType::Error(
-                    "WRONGTYPE operation against a key holding non-hashmap".into(),
-                );
-            }
-
-            RespType::Array(return_values)
-        }
-        Err(err) => {
-            println!("[SetOp HGETALL] Got poisoned storage for read: {:#?}", err);
-
-            RespType::Error("ERR system error while getting data".into())
+    let existing = storage.lookup_read(key);
+    if let Some(StorageType::HashMap(existing_hash)) = existing {
+        for (key, val) in existing_hash.iter() {
+            return_values.push(RespType::BulkString(key.into()));
+            return_values.push(RespType::BulkString(val.into()));
         }
+    } else if existing.is_some() {
+        return RespType::Error("WRONGTYPE operation against a key holding non-hashmap".into());
     }
+
+    RespType::Array(return_values)
 }
 
-pub fn hdel(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageType>>>) -> RespType {
+pub fn hdel(args: &[RespType], storage: &mut Storage) -> RespType {
     let mut args_iter = args.iter();
 
     let key = if let Some(RespType::BulkString(key)) = args_iter.next() {
@@ -140,39 +107,33 @@ pub fn hdel(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageType>>
         return RespType::Error("ARGERR at least one field are required for HDEL".into());
     }
 
-    match storage.write() {
-        Ok(mut storage_locked) => {
-            let mut del_count = 0;
-            let existing = storage_locked.get(key);
-
-            let mut existing_hash = if let Some(StorageType::HashMap(existing_hash)) = existing {
-                existing_hash.clone()
-            } else if existing.is_some() {
-                return RespType::Error(
-                    "WRONGTYPE operation against a key holding non-hashmap".into(),
-                );
-            } else {
-                return RespType::Integer(0);
-            };
-
-            while let Some(RespType::BulkString(key_to_delete)) = args_iter.next() {
-                if existing_hash.remove(key_to_delete).is_some() {
-                    del_count += 1;
-                }
-            }
-
-            if existing_hash.is_empty() {
-                storage_locked.remove(key);
-            } else {
-                storage_locked.insert(key.into(), StorageType::HashMap(existing_hash));
-            }
-
-            RespType::Integer(del_count)
-        }
-        Err(err) => {
-            println!("[SetOp HDEL] Got poisoned error for write: {:#?}", err);
+    let mut del_count = 0;
+    let existing = storage.get(key);
+
+    let mut existing_hash = if let Some(StorageType::HashMap(existing_hash)) = existing {
+        existing_hash.clone()
+    } else if existing.is_some() {
+        return RespType::Error("WRONGTYPE operation against a key holding non-hashmap".into());
+    } else {
+        return RespType::Integer(0);
+    };
 
-            RespType::Error("ERR system error while inserting data".into())
+    while let Some(RespType::BulkString(key_to_delete)) = args_iter.next() {
+        if existing_hash.remove(key_to_delete).is_some() {
+            del_count += 1;
         }
     }
+
+    if del_count > 0 {
+        storage.notify(NotifyFlags::HASH, "hdel", key);
+    }
+
+    if existing_hash.is_empty() {
+        storage.remove(key);
+        storage.notify(NotifyFlags::GENERIC, "del", key);
+    } else {
+        storage.insert(key.into(), StorageType::HashMap(existing_hash));
+    }
+
+    RespType::Integer(del_count)
 }
@@ -0,0 +1,184 @@
+use crate::{client::Client, resp::RespType, server::Server};
+
+use super::{commands::help_reply, registry::Subcommand};
+
+/// How many entries `SLOWLOG GET` shows when there's no count, the same as Redis.
+const DEFAULT_COUNT: usize = 10;
+
+/// Subcommands of `SLOWLOG`, see [`slowlog`].
+pub(super) const SUBCOMMANDS: &[Subcommand] = &[
+    Subcommand::new("get", -2, "Returns the slow log's entries."),
+    Subcommand::new(
+        "help",
+        2,
+        "Returns helpful text about the different subcommands.",
+    ),
+    Subcommand::new("len", 2, "Returns the number of entries in the slow log."),
+    Subcommand::new("reset", 2, "Clears all entries from the slow log."),
+];
+
+/// SLOWLOG Command
+///
+/// Read or clear the log of the commands that took longer than `slowlog-log-slower-than` microseconds.
+///
+/// Currently implemented syntax
+/// `SLOWLOG GET [count]`
+/// `SLOWLOG LEN`
+/// `SLOWLOG RESET`
+/// `SLOWLOG HELP`
+///
+/// Entries are shown newest first, as `[id, timestamp, duration, [command and arguments], client address,
+/// client name]`, a count of -1 shows every entry.
+pub fn slowlog(args: &[RespType], _client: &mut Client, server: &Server) -> RespType {
+    let subcommand = if let Some(RespType::BulkString(subcommand)) = args.first() {
+        subcommand.to_uppercase()
+    } else {
+        return RespType::Error("ERR wrong number of arguments for 'slowlog' command".into());
+    };
+
+    match (subcommand.as_str(), &args[1..]) {
+        ("GET", []) => get(Some(DEFAULT_COUNT), server),
+        ("GET", [RespType::BulkString(count)]) => match count.parse::<i64>() {
+            Ok(-1) => get(None, server),
+            Ok(count) if count >= 0 => get(Some(count as usize), server),
+            Ok(_) => RespType::Error("ERR count should be greater than or equal to -1".into()),
+            Err(_) => RespType::Error("ERR value is not an integer or out of range".into()),
+        },
+        ("LEN", []) => RespType::Integer(server.slowlog.len() as i64),
+        ("RESET", []) => {
+            server.slowlog.reset();
+
+            RespType::String("OK".into())
+        }
+        ("HELP", []) => help_reply(
+            "SLOWLOG",
+            &[
+                "GET [<count>]",
+                "    Return top <count> entries from the slowlog (default: 10, -1 mean all).",
+                "    Entries are made of:",
+                "    id, timestamp, time in microseconds, arguments array, client IP and port,",
+                "    client name",
+                "LEN",
+                "    Return the length of the slowlog.",
+                "RESET",
+                "    Reset the slowlog.",
+            ],
+        ),
+        ("GET" | "LEN" | "RESET" | "HELP", _) => RespType::Error(format!(
+            "ERR wrong number of arguments for 'slowlog|{}' command",
+            subcommand.to_lowercase()
+        )),
+        _ => RespType::Error(format!(
+            "ERR unknown subcommand '{}'. Try SLOWLOG HELP.",
+            subcommand
+        )),
+    }
+}
+
+fn get(count: Option<usize>, server: &Server) -> RespType {
+    RespType::Array(
+        server
+            .slowlog
+            .entries(count)
+            .into_iter()
+            .map(|entry| {
+                RespType::Array(vec![
+                    RespType::Integer(entry.id as i64),
+                    RespType::Integer(entry.timestamp as i64),
+                    RespType::Integer(entry.duration as i64),
+                    RespType::Array(entry.args.into_iter().map(RespType::BulkString).collect()),
+                    RespType::BulkString(entry.client_addr),
+                    RespType::BulkString(entry.client_name),
+                ])
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod slowlog_tests {
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    fn logged_args(reply: RespType) -> Vec<RespType> {
+        match reply {
+            RespType::Array(entries) => entries
+                .into_iter()
+                .map(|entry| match entry {
+                    RespType::Array(mut fields) => fields.remove(3),
+                    entry => panic!("Unexpected entry {:?}", entry),
+                })
+                .collect(),
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+    }
+
+    #[test]
+    fn logs_truncated_and_redacted_commands() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        handle_commands(
+            command(&["CONFIG", "SET", "slowlog-log-slower-than", "0"]),
+            &mut client,
+            &server,
+        );
+        handle_commands(command(&["SLOWLOG", "RESET"]), &mut client, &server);
+
+        let long = "a".repeat(200);
+        let mut many = vec!["DEL"];
+        many.extend(std::iter::repeat_n("x", 40));
+        handle_commands(command(&["SET", "key", &long]), &mut client, &server);
+        handle_commands(command(&many), &mut client, &server);
+        handle_commands(command(&["AUTH", "secret"]), &mut client, &server);
+
+        let logged = logged_args(handle_commands(
+            command(&["SLOWLOG", "GET", "3"]),
+            &mut client,
+            &server,
+        ));
+        assert_eq!(logged[0], RespType::Array(command(&["AUTH", "(redacted)"])));
+        match &logged[1] {
+            RespType::Array(args) => {
+                assert_eq!(args.len(), 32);
+                assert_eq!(
+                    args[31],
+                    RespType::BulkString("... (10 more arguments)".into())
+                );
+            }
+            args => panic!("Unexpected arguments {:?}", args),
+        }
+        assert_eq!(
+            logged[2],
+            RespType::Array(command(&[
+                "SET",
+                "key",
+                &format!("{}... (72 more bytes)", "a".repeat(128)),
+            ]))
+        );
+
+        handle_commands(
+            command(&["CONFIG", "SET", "slowlog-max-len", "1"]),
+            &mut client,
+            &server,
+        );
+        assert_eq!(
+            handle_commands(command(&["SLOWLOG", "LEN"]), &mut client, &server),
+            RespType::Integer(1)
+        );
+
+        match handle_commands(command(&["SLOWLOG", "HELP"]), &mut client, &server) {
+            RespType::Array(lines) => assert_eq!(
+                lines.first(),
+                Some(&RespType::String(
+                    "SLOWLOG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:".into()
+                ))
+            ),
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+    }
+}
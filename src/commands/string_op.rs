@@ -1,17 +1,16 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock},
+use crate::{
+    notification::NotifyFlags,
+    resp::RespType,
+    storage::{Storage, StorageType},
 };
 
-use crate::{resp::RespType, storage::StorageType};
-
 /// SET Command
 ///
 /// Handle SET command based on Redis syntax. Set a string key, with a string value.
 ///
 /// Currently implemented syntax
 /// `SET key value`
-pub fn set(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageType>>>) -> RespType {
+pub fn set(args: &[RespType], storage: &mut Storage) -> RespType {
     let mut args_iter = args.iter();
 
     let key_args = if let Some(RespType::BulkString(key_args)) = args_iter.next() {
@@ -26,24 +25,13 @@ pub fn set(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageType>>>
         return RespType::Error("ARGERR no value are given for SET command".into());
     };
 
-    match storage.write() {
-        Ok(mut storage_locked) => {
-            storage_locked.insert(
-                key_args.to_string(),
-                StorageType::String(value_args.to_string()),
-            );
-
-            RespType::String("OK".into())
-        }
-        Err(err) => {
-            println!(
-                "[StringOp SET] Got poisoned error on locking storage: {:#?}",
-                err
-            );
+    storage.insert(
+        key_args.to_string(),
+        StorageType::String(value_args.to_string()),
+    );
+    storage.notify(NotifyFlags::STRING, "set", key_args);
 
-            RespType::Error("ERR system error while inserting data".into())
-        }
-    }
+    RespType::String("OK".into())
 }
 
 /// GET Command
@@ -52,26 +40,16 @@ pub fn set(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageType>>>
 ///
 /// Currently implemented syntax
 /// `GET key value`
-pub fn get(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageType>>>) -> RespType {
+pub fn get(args: &[RespType], storage: &Storage) -> RespType {
     let key_args = if let Some(RespType::BulkString(key_args)) = args.first() {
         key_args
     } else {
         return RespType::Error("ARGERR no key are given for GET command".into());
     };
 
-    match storage.read() {
-        Ok(storage_locked) => match storage_locked.get(key_args) {
-            Some(StorageType::String(value)) => RespType::BulkString(value.to_string()),
-            _ => RespType::Null,
-        },
-        Err(err) => {
-            println!(
-                "[StringOp GET] Got poisoned error on locking storage: {:#?}",
-                err
-            );
-
-            RespType::Error("ERR system error while getting data".into())
-        }
+    match storage.lookup_read(key_args) {
+        Some(StorageType::String(value)) => RespType::BulkString(value.to_string()),
+        Some(_) | None => RespType::Null,
     }
 }
 
@@ -81,32 +59,21 @@ pub fn get(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageType>>>
 ///
 /// Currently implemented syntax
 /// `DEL key [key ...]`
-pub fn del(args: &[RespType], storage: Arc<RwLock<HashMap<String, StorageType>>>) -> RespType {
+pub fn del(args: &[RespType], storage: &mut Storage) -> RespType {
     if args.is_empty() {
         return RespType::Error("ARGERR no keys given for DEL command".into());
     }
 
     let mut deleted_count = 0;
 
-    match storage.write() {
-        Ok(mut storage_locked) => {
-            for key in args {
-                if let RespType::BulkString(key) = key {
-                    if storage_locked.remove(key).is_some() {
-                        deleted_count += 1;
-                    }
-                }
+    for key in args {
+        if let RespType::BulkString(key) = key {
+            if storage.remove(key).is_some() {
+                storage.notify(NotifyFlags::GENERIC, "del", key);
+                deleted_count += 1;
             }
-
-            RespType::Integer(deleted_count)
-        }
-        Err(err) => {
-            println!(
-                "[StringOp DEL] Got poisoned error on locking storage: {:#?}",
-                err
-            );
-
-            RespType::Error("ERR system error while deleting data".into())
         }
     }
+
+    RespType::Integer(deleted_count)
 }
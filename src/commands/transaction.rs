@@ -0,0 +1,397 @@
+use std::time::Instant;
+
+use crate::{
+    acl::LogContext,
+    client::{Client, Transaction},
+    monitor::Origin,
+    resp::RespType,
+    server::Server,
+    storage::Storage,
+};
+
+/// MULTI Command
+///
+/// Mark the start of a transaction, every command after this will be queued until `EXEC` or `DISCARD`.
+///
+/// Currently implemented syntax
+/// `MULTI`
+pub fn multi(_: &[RespType], client: &mut Client) -> RespType {
+    if client.transaction.is_some() {
+        return RespType::Error("ERR MULTI calls can not be nested".into());
+    }
+
+    client.transaction = Some(Transaction::default());
+
+    RespType::String("OK".into())
+}
+
+/// Queue a command sent in the middle of a transaction.
+///
+/// Command are checked before it's queued, unknown command or command with the wrong number of arguments
+/// will not be queued, and the whole transaction will be aborted on `EXEC`, see
+/// [`handle_commands`](super::commands::handle_commands).
+pub fn queue(command_arr: Vec<RespType>, transaction: &mut Transaction) -> RespType {
+    transaction.commands.push(command_arr);
+
+    RespType::String("QUEUED".into())
+}
+
+/// EXEC Command
+///
+/// Run every queued command in the transaction atomically, all of it run under one storage lock.
+/// Returns a null array instead when one of the watched keys have been modified since it's watched.
+///
+/// Currently implemented syntax
+/// `EXEC`
+pub fn exec(_: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    let transaction = if let Some(transaction) = client.transaction.take() {
+        transaction
+    } else {
+        return RespType::Error("ERR EXEC without MULTI".into());
+    };
+
+    match server.storage.write() {
+        Ok(mut storage_locked) => {
+            let watched_key_changed = client
+                .watched_keys
+                .iter()
+                .any(|(key, version)| storage_locked.version(key) != *version);
+            client.unwatch_all(&mut storage_locked);
+
+            if transaction.aborted {
+                return RespType::Error(
+                    "EXECABORT Transaction discarded because of previous errors.".into(),
+                );
+            }
+
+            if watched_key_changed {
+                return RespType::NullArray;
+            }
+
+            let mut written = Vec::new();
+            client.deny_blocking = true;
+            let results = transaction
+                .commands
+                .into_iter()
+                .map(|command| {
+                    let changes = storage_locked.pending_changes();
+                    let result = execute_queued(&command, client, server, &mut storage_locked);
+
+                    match client.propagated.take() {
+                        Some(propagated) => written.extend(propagated),
+                        None if storage_locked.pending_changes() > changes => written.push(command),
+                        None => {}
+                    }
+
+                    result
+                })
+                .collect();
+            client.deny_blocking = false;
+            if !written.is_empty() && !client.loading {
+                server.propagate_transaction(&written);
+                client.last_write_offset = server.replication.offset();
+            }
+
+            let effects = storage_locked.take_effects();
+            drop(storage_locked);
+
+            server.apply_storage_effects(effects, client);
+
+            RespType::Array(results)
+        }
+        Err(err) => {
+            println!(
+                "[Transaction EXEC] Got poisoned error for write: {:#?}",
+                err
+            );
+
+            RespType::Error("ERR system error while executing transaction".into())
+        }
+    }
+}
+
+fn execute_queued(
+    command_arr: &[RespType],
+    client: &mut Client,
+    server: &Server,
+    storage: &mut Storage,
+) -> RespType {
+    if let Some((RespType::BulkString(command_name), command_args)) = command_arr.split_first() {
+        match server.commands.get(command_name) {
+            // NOTE: UNWATCH is allowed to be queued, but it'll do nothing as every key are unwatched before
+            // the queued commands are run.
+            Some(command) if command.name() == "unwatch" => RespType::String("OK".into()),
+            Some(command) if client.loading => {
+                command
+                    .handler()
+                    .call_locked(command_args, client, server, storage)
+            }
+            Some(command) => {
+                // NOTE: Permissions could have been changed since the command is queued
+                if let Err(err) = server
+                    .acl
+                    .check(client, command, command_arr, LogContext::Multi)
+                {
+                    let reply = RespType::Error(format!("NOPERM {}", err));
+                    server.stats.record_rejected(command.name(), &reply);
+
+                    return reply;
+                }
+
+                server.monitor.feed(
+                    client,
+                    Origin::Client,
+                    command,
+                    command_arr,
+                    &server.clients,
+                );
+
+                let started = Instant::now();
+                let reply = command
+                    .handler()
+                    .call_locked(command_args, client, server, storage);
+                let duration = started.elapsed();
+                server.stats.record_call(command.name(), duration, &reply);
+                server
+                    .latency
+                    .add_command_sample_if_needed(command, duration);
+                // NOTE: EXEC isn't logged itself, every command it ran is logged instead, the same as Redis
+                server
+                    .slowlog
+                    .record(command, command_arr, client, duration);
+                server.tracking.remember_keys(client, command, command_arr);
+
+                reply
+            }
+            None => RespType::Error(format!("ERR unknown command '{}'", command_name)),
+        }
+    } else {
+        RespType::Error("WRONGTYPE wrong type, expected command name as a Bulk strings".into())
+    }
+}
+
+/// DISCARD Command
+///
+/// Throw away every queued command in the transaction, and unwatch every watched keys.
+///
+/// Currently implemented syntax
+/// `DISCARD`
+pub fn discard(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    if client.transaction.take().is_none() {
+        return RespType::Error("ERR DISCARD without MULTI".into());
+    }
+
+    unwatch(args, client, server)
+}
+
+/// WATCH Command
+///
+/// Watch key(s) to be checked on `EXEC`, if any of the key is modified before `EXEC` the transaction
+/// will not be run. Not allowed inside a transaction, the transaction is then aborted on `EXEC`.
+///
+/// Currently implemented syntax
+/// `WATCH key [key ...]`
+pub fn watch(args: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    if let Some(transaction) = client.transaction.as_mut() {
+        transaction.aborted = true;
+
+        return RespType::Error("ERR WATCH inside MULTI is not allowed".into());
+    }
+
+    match server.storage.write() {
+        Ok(mut storage_locked) => {
+            for key in args {
+                if let RespType::BulkString(key) = key {
+                    if !client.watched_keys.contains_key(key) {
+                        let version = storage_locked.watch(key);
+                        client.watched_keys.insert(key.into(), version);
+                    }
+                }
+            }
+
+            RespType::String("OK".into())
+        }
+        Err(err) => {
+            println!(
+                "[Transaction WATCH] Got poisoned error for write: {:#?}",
+                err
+            );
+
+            RespType::Error("ERR system error while watching keys".into())
+        }
+    }
+}
+
+/// UNWATCH Command
+///
+/// Forget every key watched by the client.
+///
+/// Currently implemented syntax
+/// `UNWATCH`
+pub fn unwatch(_: &[RespType], client: &mut Client, server: &Server) -> RespType {
+    if client.watched_keys.is_empty() {
+        return RespType::String("OK".into());
+    }
+
+    match server.storage.write() {
+        Ok(mut storage_locked) => {
+            client.unwatch_all(&mut storage_locked);
+
+            RespType::String("OK".into())
+        }
+        Err(err) => {
+            println!(
+                "[Transaction UNWATCH] Got poisoned error for write: {:#?}",
+                err
+            );
+
+            RespType::Error("ERR system error while unwatching keys".into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod transaction_tests {
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        storage::{Snapshot, StorageType},
+        test_util::{command, connect},
+    };
+
+    #[test]
+    fn exec_runs_queued_commands() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        assert_eq!(
+            handle_commands(command(&["MULTI"]), &mut client, &server),
+            RespType::String("OK".into())
+        );
+        assert_eq!(
+            handle_commands(command(&["SET", "key", "value"]), &mut client, &server),
+            RespType::String("QUEUED".into())
+        );
+        assert_eq!(
+            handle_commands(command(&["GET", "key"]), &mut client, &server),
+            RespType::String("QUEUED".into())
+        );
+        assert_eq!(
+            handle_commands(command(&["EXEC"]), &mut client, &server),
+            RespType::Array(vec![
+                RespType::String("OK".into()),
+                RespType::BulkString("value".into())
+            ])
+        );
+    }
+
+    #[test]
+    fn exec_aborts_on_queue_error() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        handle_commands(command(&["MULTI"]), &mut client, &server);
+        handle_commands(command(&["SET", "key", "value"]), &mut client, &server);
+        assert_eq!(
+            handle_commands(command(&["GET"]), &mut client, &server),
+            RespType::Error("ERR wrong number of arguments for 'get' command".into())
+        );
+        assert_eq!(
+            handle_commands(command(&["EXEC"]), &mut client, &server),
+            RespType::Error("EXECABORT Transaction discarded because of previous errors.".into())
+        );
+        assert!(!server.storage.read().unwrap().contains_key("key"));
+    }
+
+    #[test]
+    fn exec_aborts_on_watch_inside_multi() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        handle_commands(command(&["MULTI"]), &mut client, &server);
+        handle_commands(command(&["SET", "key", "value"]), &mut client, &server);
+        assert_eq!(
+            handle_commands(command(&["WATCH", "key"]), &mut client, &server),
+            RespType::Error("ERR WATCH inside MULTI is not allowed".into())
+        );
+        assert_eq!(
+            handle_commands(command(&["EXEC"]), &mut client, &server),
+            RespType::Error("EXECABORT Transaction discarded because of previous errors.".into())
+        );
+        assert!(!server.storage.read().unwrap().contains_key("key"));
+    }
+
+    #[test]
+    fn exec_fails_on_modified_watched_key() {
+        let server = Server::new();
+        let mut client = connect(&server);
+        let mut other_client = connect(&server);
+
+        handle_commands(command(&["WATCH", "balance"]), &mut client, &server);
+        handle_commands(command(&["MULTI"]), &mut client, &server);
+        handle_commands(command(&["SET", "balance", "10"]), &mut client, &server);
+
+        handle_commands(
+            command(&["SET", "balance", "20"]),
+            &mut other_client,
+            &server,
+        );
+
+        assert_eq!(
+            handle_commands(command(&["EXEC"]), &mut client, &server),
+            RespType::NullArray
+        );
+        assert_eq!(
+            handle_commands(command(&["GET", "balance"]), &mut client, &server),
+            RespType::BulkString("20".into())
+        );
+    }
+
+    #[test]
+    fn exec_fails_on_watched_key_replaced_by_snapshot() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        handle_commands(command(&["WATCH", "balance"]), &mut client, &server);
+        handle_commands(command(&["MULTI"]), &mut client, &server);
+        handle_commands(command(&["SET", "balance", "10"]), &mut client, &server);
+
+        // e.g. a full resynchronization with the leader
+        let snapshot = Snapshot {
+            values: [("balance".to_string(), StorageType::String("20".into()))]
+                .into_iter()
+                .collect(),
+            ..Snapshot::default()
+        };
+        server.restore_snapshot(&mut server.storage.write().unwrap(), snapshot);
+
+        assert_eq!(
+            handle_commands(command(&["EXEC"]), &mut client, &server),
+            RespType::NullArray
+        );
+        assert_eq!(
+            handle_commands(command(&["GET", "balance"]), &mut client, &server),
+            RespType::BulkString("20".into())
+        );
+    }
+
+    #[test]
+    fn discard_drops_queued_commands() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        handle_commands(command(&["MULTI"]), &mut client, &server);
+        handle_commands(command(&["SET", "key", "value"]), &mut client, &server);
+
+        assert_eq!(
+            handle_commands(command(&["DISCARD"]), &mut client, &server),
+            RespType::String("OK".into())
+        );
+        assert_eq!(
+            handle_commands(command(&["EXEC"]), &mut client, &server),
+            RespType::Error("ERR EXEC without MULTI".into())
+        );
+        assert!(!server.storage.read().unwrap().contains_key("key"));
+    }
+}
@@ -0,0 +1,860 @@
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    acl::DEFAULT_LOG_MAX_LEN,
+    aof::{AofConfig, AppendFsync},
+    notification::NotifyFlags,
+    persistence::{PersistenceConfig, SaveRule},
+    replication::DEFAULT_BACKLOG_SIZE,
+    scripting::DEFAULT_BUSY_REPLY_THRESHOLD,
+    slowlog,
+    tls::{TlsAuthClients, TlsConfig},
+};
+
+/// Port listened on when none is configured.
+pub const DEFAULT_PORT: u16 = 6969;
+
+/// How deep configuration files can `include` each other, so including a file from itself is an error.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Comment added by `CONFIG REWRITE` before the directives that weren't in the configuration file.
+const REWRITE_SIGNATURE: &str = "# Generated by CONFIG REWRITE";
+
+/// Parameter of the configuration, read with `CONFIG GET` and written with `CONFIG SET`.
+#[derive(Debug, Clone, Copy)]
+pub struct Parameter {
+    pub name: &'static str,
+    /// Whether it can be changed while the server is running, otherwise it's only used on startup.
+    pub mutable: bool,
+    /// Parameter this is another name of, e.g. `lua-time-limit` for `busy-reply-threshold`.
+    pub alias_of: Option<&'static str>,
+}
+
+impl Parameter {
+    const fn mutable(name: &'static str) -> Self {
+        Self {
+            name,
+            mutable: true,
+            alias_of: None,
+        }
+    }
+
+    const fn immutable(name: &'static str) -> Self {
+        Self {
+            name,
+            mutable: false,
+            alias_of: None,
+        }
+    }
+
+    const fn alias(name: &'static str, alias_of: &'static str) -> Self {
+        Self {
+            name,
+            mutable: true,
+            alias_of: Some(alias_of),
+        }
+    }
+
+    /// Name of the parameter this is another name of, or its own name.
+    pub fn canonical_name(&self) -> &'static str {
+        self.alias_of.unwrap_or(self.name)
+    }
+}
+
+/// Every parameter of the configuration, `replicaof` is only a directive as it's changed with `REPLICAOF`.
+pub const PARAMETERS: [Parameter; 33] = [
+    Parameter::immutable("bind"),
+    Parameter::immutable("port"),
+    Parameter::immutable("unixsocket"),
+    Parameter::immutable("unixsocketperm"),
+    Parameter::immutable("tls-port"),
+    Parameter::immutable("tls-cert-file"),
+    Parameter::immutable("tls-key-file"),
+    Parameter::immutable("tls-ca-cert-file"),
+    Parameter::immutable("tls-auth-clients"),
+    Parameter::immutable("databases"),
+    Parameter::mutable("maxclients"),
+    Parameter::mutable("maxmemory"),
+    Parameter::mutable("loglevel"),
+    Parameter::immutable("logfile"),
+    Parameter::mutable("save"),
+    Parameter::mutable("dir"),
+    Parameter::mutable("dbfilename"),
+    Parameter::mutable("appendonly"),
+    Parameter::mutable("appendfsync"),
+    Parameter::immutable("appenddirname"),
+    Parameter::immutable("appendfilename"),
+    Parameter::mutable("aof-load-truncated"),
+    Parameter::mutable("replica-read-only"),
+    Parameter::mutable("repl-backlog-size"),
+    Parameter::mutable("busy-reply-threshold"),
+    Parameter::alias("lua-time-limit", "busy-reply-threshold"),
+    Parameter::mutable("notify-keyspace-events"),
+    Parameter::mutable("requirepass"),
+    Parameter::immutable("aclfile"),
+    Parameter::mutable("acllog-max-len"),
+    Parameter::mutable("slowlog-log-slower-than"),
+    Parameter::mutable("slowlog-max-len"),
+    Parameter::mutable("latency-monitor-threshold"),
+];
+
+/// Find the parameter by its name, case-insensitive.
+pub fn find_parameter(name: &str) -> Option<&'static Parameter> {
+    PARAMETERS
+        .iter()
+        .find(|parameter| parameter.name.eq_ignore_ascii_case(name))
+}
+
+/// How much is logged, from `loglevel`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Debug,
+    Verbose,
+    #[default]
+    Notice,
+    Warning,
+    Nothing,
+}
+
+impl LogLevel {
+    pub fn parse(level: &str) -> Option<Self> {
+        match level.to_lowercase().as_str() {
+            "debug" => Some(Self::Debug),
+            "verbose" => Some(Self::Verbose),
+            "notice" => Some(Self::Notice),
+            "warning" => Some(Self::Warning),
+            "nothing" => Some(Self::Nothing),
+            _ => None,
+        }
+    }
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Debug => write!(f, "debug"),
+            Self::Verbose => write!(f, "verbose"),
+            Self::Notice => write!(f, "notice"),
+            Self::Warning => write!(f, "warning"),
+            Self::Nothing => write!(f, "nothing"),
+        }
+    }
+}
+
+/// Configuration of the server, read on startup from the configuration file and the command line.
+///
+/// The configuration file uses the same format as `redis.conf`, a directive with its arguments on each
+/// line, see [`split_args`]. Directives given on the command line as `--directive arg ...` are applied
+/// after the configuration file, e.g. `rust-eez /etc/rust-eez.conf --port 7000 --bind 127.0.0.1`.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Configuration file the configuration was read from.
+    pub file: Option<PathBuf>,
+    /// `bind`, addresses to listen on, `*` for every IPv4 address and `::*` for every IPv6 address. A
+    /// failure to listen on an address prefixed by `-` is ignored.
+    pub bind: Vec<String>,
+    /// `port`, TCP port to listen on, only the TLS port and the Unix socket are listened on when it's 0.
+    pub port: u16,
+    pub unixsocket: Option<PathBuf>,
+    /// `unixsocketperm`, permissions of the Unix socket file.
+    pub unixsocketperm: Option<u32>,
+    pub tls: TlsConfig,
+    pub databases: usize,
+    /// `maxclients`, how many clients can be connected at the same time.
+    pub maxclients: usize,
+    /// `maxmemory`, in bytes, 0 for no limit.
+    pub maxmemory: u64,
+    pub loglevel: LogLevel,
+    /// `logfile`, file the logs are appended to instead of the standard output.
+    pub logfile: Option<PathBuf>,
+    pub persistence: PersistenceConfig,
+    pub aof: AofConfig,
+    /// `replicaof`, leader to follow on startup.
+    pub replicaof: Option<(String, u16)>,
+    pub replica_read_only: bool,
+    pub repl_backlog_size: usize,
+    /// `busy-reply-threshold` (or `lua-time-limit`), in milliseconds.
+    pub busy_reply_threshold: u64,
+    pub notify_keyspace_events: NotifyFlags,
+    pub requirepass: String,
+    pub aclfile: Option<PathBuf>,
+    pub acllog_max_len: usize,
+    /// `slowlog-log-slower-than`, in microseconds.
+    pub slowlog_log_slower_than: i64,
+    pub slowlog_max_len: usize,
+    /// `latency-monitor-threshold`, in milliseconds.
+    pub latency_monitor_threshold: u64,
+    /// Whether a `save` directive was applied already, the first one replaces the default save rules and
+    /// every other one adds to them, the same as Redis.
+    save_configured: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            file: None,
+            bind: vec!["*".into()],
+            port: DEFAULT_PORT,
+            unixsocket: None,
+            unixsocketperm: None,
+            tls: TlsConfig::default(),
+            databases: 16,
+            maxclients: 10000,
+            maxmemory: 0,
+            loglevel: LogLevel::default(),
+            logfile: None,
+            persistence: PersistenceConfig::default(),
+            aof: AofConfig::default(),
+            replicaof: None,
+            replica_read_only: true,
+            repl_backlog_size: DEFAULT_BACKLOG_SIZE,
+            busy_reply_threshold: DEFAULT_BUSY_REPLY_THRESHOLD,
+            notify_keyspace_events: NotifyFlags::default(),
+            requirepass: String::new(),
+            aclfile: None,
+            acllog_max_len: DEFAULT_LOG_MAX_LEN,
+            slowlog_log_slower_than: slowlog::DEFAULT_LOG_SLOWER_THAN,
+            slowlog_max_len: slowlog::DEFAULT_MAX_LEN,
+            latency_monitor_threshold: 0,
+            save_configured: false,
+        }
+    }
+}
+
+impl Config {
+    /// Read the configuration from the arguments of the server, `[config-file] [--directive arg ...]`.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut args = args.into_iter().peekable();
+
+        if let Some(file) = args.next_if(|arg| !arg.starts_with("--")) {
+            config.load_file(Path::new(&file))?;
+            config.file = Some(file.into());
+        }
+
+        while let Some(arg) = args.next() {
+            let directive = match arg.strip_prefix("--") {
+                Some(directive) => directive.to_string(),
+                None => return Err(format!("Invalid argument '{}'", arg)),
+            };
+
+            let mut directive_args = Vec::new();
+            while let Some(arg) = args.next_if(|arg| !arg.starts_with("--")) {
+                directive_args.push(arg);
+            }
+
+            config
+                .apply(&directive, &directive_args)
+                .map_err(|err| format!("--{}: {}", directive, err))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Apply every directive of the configuration file, along with the files it includes.
+    pub fn load_file(&mut self, path: &Path) -> Result<(), String> {
+        self.load_file_included(path, 0)
+    }
+
+    fn load_file_included(&mut self, path: &Path, depth: usize) -> Result<(), String> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(format!("{}: Too many nested includes", path.display()));
+        }
+
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+
+        for (line_number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |err: String| {
+                format!(
+                    "{}:{}: {} in '{}'",
+                    path.display(),
+                    line_number + 1,
+                    err,
+                    line
+                )
+            };
+
+            let args = split_args(line).ok_or_else(|| error("Unbalanced quotes".into()))?;
+            let (directive, args) = match args.split_first() {
+                Some(split) => split,
+                None => continue,
+            };
+
+            if directive.eq_ignore_ascii_case("include") {
+                match args {
+                    [included] => self.load_file_included(Path::new(included), depth + 1)?,
+                    _ => return Err(error("Bad directive or wrong number of arguments".into())),
+                }
+                continue;
+            }
+
+            self.apply(directive, args).map_err(error)?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply a single directive, the name is case-insensitive.
+    pub fn apply(&mut self, directive: &str, args: &[String]) -> Result<(), String> {
+        let directive = directive.to_lowercase();
+
+        match (directive.as_str(), args) {
+            ("bind", addresses) if !addresses.is_empty() => self.bind = addresses.to_vec(),
+            ("port", [port]) => self.port = port.parse().map_err(|_| "Invalid port")?,
+            ("unixsocket", [path]) => self.unixsocket = parse_path(path),
+            ("unixsocketperm", [perm]) => {
+                self.unixsocketperm = Some(
+                    u32::from_str_radix(perm, 8).map_err(|_| "Invalid socket file permissions")?,
+                )
+            }
+            ("tls-port", [port]) => self.tls.port = port.parse().map_err(|_| "Invalid tls-port")?,
+            ("tls-cert-file", [path]) => self.tls.cert_file = parse_path(path),
+            ("tls-key-file", [path]) => self.tls.key_file = parse_path(path),
+            ("tls-ca-cert-file", [path]) => self.tls.ca_cert_file = parse_path(path),
+            ("tls-auth-clients", [value]) => {
+                self.tls.auth_clients = TlsAuthClients::parse(value)
+                    .ok_or("argument must be 'yes', 'no', or 'optional'")?
+            }
+            ("databases", [databases]) => {
+                self.databases = match databases.parse() {
+                    Ok(databases) if databases > 0 => databases,
+                    _ => return Err("Invalid number of databases".into()),
+                }
+            }
+            ("maxclients", [maxclients]) => {
+                self.maxclients = match maxclients.parse() {
+                    Ok(maxclients) if maxclients > 0 => maxclients,
+                    _ => return Err("Invalid max clients limit".into()),
+                }
+            }
+            ("maxmemory", [maxmemory]) => {
+                self.maxmemory = parse_memory(maxmemory).ok_or("Invalid maxmemory")?
+            }
+            ("loglevel", [level]) => {
+                self.loglevel = LogLevel::parse(level)
+                    .ok_or("argument must be one of debug, verbose, notice, warning, nothing")?
+            }
+            ("logfile", [path]) => self.logfile = parse_path(path),
+            ("save", rules) if !rules.is_empty() => {
+                let rules =
+                    SaveRule::parse_rules(&rules.join(" ")).ok_or("Invalid save parameters")?;
+
+                if !self.save_configured {
+                    self.persistence.save_rules.clear();
+                    self.save_configured = true;
+                }
+                self.persistence.save_rules.extend(rules);
+            }
+            ("dir", [dir]) => {
+                let dir = PathBuf::from(dir);
+                if !dir.is_dir() {
+                    return Err("No such file or directory".into());
+                }
+
+                self.persistence.dir = dir;
+            }
+            ("dbfilename", [dbfilename]) => {
+                if dbfilename.is_empty() || dbfilename.contains(['/', '\\']) {
+                    return Err("dbfilename can't be a path, just a filename".into());
+                }
+
+                self.persistence.dbfilename = dbfilename.clone();
+            }
+            ("appendonly", [value]) => self.aof.enabled = parse_yes_no(value)?,
+            ("appendfsync", [policy]) => {
+                self.aof.fsync = AppendFsync::parse(policy)
+                    .ok_or("argument must be one of always, everysec, no")?
+            }
+            ("appenddirname", [dirname]) => {
+                if dirname.is_empty() || dirname.contains(['/', '\\']) {
+                    return Err("appenddirname can't be a path, just a dirname".into());
+                }
+
+                self.aof.dirname = dirname.clone();
+            }
+            ("appendfilename", [filename]) => {
+                if filename.is_empty() || filename.contains(['/', '\\']) {
+                    return Err("appendfilename can't be a path, just a filename".into());
+                }
+
+                self.aof.filename = filename.clone();
+            }
+            ("aof-load-truncated", [value]) => self.aof.load_truncated = parse_yes_no(value)?,
+            ("replicaof" | "slaveof", [host, port]) => {
+                self.replicaof = Some((
+                    host.clone(),
+                    port.parse().map_err(|_| "Invalid master port")?,
+                ))
+            }
+            ("replica-read-only" | "slave-read-only", [value]) => {
+                self.replica_read_only = parse_yes_no(value)?
+            }
+            ("repl-backlog-size", [size]) => {
+                self.repl_backlog_size = match parse_memory(size) {
+                    Some(size) if size > 0 => size as usize,
+                    _ => return Err("Invalid repl-backlog-size".into()),
+                }
+            }
+            ("busy-reply-threshold" | "lua-time-limit", [threshold]) => {
+                self.busy_reply_threshold = threshold
+                    .parse()
+                    .map_err(|_| format!("Invalid {}", directive))?
+            }
+            ("notify-keyspace-events", [flags]) => {
+                self.notify_keyspace_events = NotifyFlags::parse(flags)
+                    .ok_or("Invalid event class character. Use 'Ag$lshzxeKEtmn'.")?
+            }
+            ("requirepass", [password]) => self.requirepass = password.clone(),
+            ("aclfile", [path]) => self.aclfile = parse_path(path),
+            ("acllog-max-len", [max_len]) => {
+                self.acllog_max_len = max_len.parse().map_err(|_| "Invalid acllog-max-len")?
+            }
+            ("slowlog-log-slower-than", [threshold]) => {
+                self.slowlog_log_slower_than = threshold
+                    .parse()
+                    .map_err(|_| "Invalid slowlog-log-slower-than")?
+            }
+            ("slowlog-max-len", [max_len]) => {
+                self.slowlog_max_len = max_len.parse().map_err(|_| "Invalid slowlog-max-len")?
+            }
+            ("latency-monitor-threshold", [threshold]) => {
+                self.latency_monitor_threshold = threshold
+                    .parse()
+                    .map_err(|_| "Invalid latency-monitor-threshold")?
+            }
+            _ => return Err("Bad directive or wrong number of arguments".into()),
+        }
+
+        Ok(())
+    }
+
+    /// Arguments of the directive of the parameter (or `replicaof`), `None` for an unknown parameter.
+    ///
+    /// An empty list means the directive isn't used, e.g. `replicaof` when the server isn't following any
+    /// leader. Joined by spaces, this is the value shown by `CONFIG GET`.
+    pub fn get(&self, parameter: &str) -> Option<Vec<String>> {
+        let value = match parameter {
+            "bind" => return Some(self.bind.clone()),
+            "save" => SaveRule::format_rules(&self.persistence.save_rules),
+            "replicaof" => {
+                return Some(
+                    self.replicaof
+                        .iter()
+                        .flat_map(|(host, port)| [host.clone(), port.to_string()])
+                        .collect(),
+                )
+            }
+            "port" => self.port.to_string(),
+            "unixsocket" => format_path(&self.unixsocket),
+            "unixsocketperm" => format!("{:o}", self.unixsocketperm.unwrap_or(0)),
+            "tls-port" => self.tls.port.to_string(),
+            "tls-cert-file" => format_path(&self.tls.cert_file),
+            "tls-key-file" => format_path(&self.tls.key_file),
+            "tls-ca-cert-file" => format_path(&self.tls.ca_cert_file),
+            "tls-auth-clients" => self.tls.auth_clients.to_string(),
+            "databases" => self.databases.to_string(),
+            "maxclients" => self.maxclients.to_string(),
+            "maxmemory" => self.maxmemory.to_string(),
+            "loglevel" => self.loglevel.to_string(),
+            "logfile" => format_path(&self.logfile),
+            "dir" => self.persistence.dir.to_string_lossy().into_owned(),
+            "dbfilename" => self.persistence.dbfilename.clone(),
+            "appendonly" => format_yes_no(self.aof.enabled),
+            "appendfsync" => self.aof.fsync.to_string(),
+            "appenddirname" => self.aof.dirname.clone(),
+            "appendfilename" => self.aof.filename.clone(),
+            "aof-load-truncated" => format_yes_no(self.aof.load_truncated),
+            "replica-read-only" => format_yes_no(self.replica_read_only),
+            "repl-backlog-size" => self.repl_backlog_size.to_string(),
+            "busy-reply-threshold" | "lua-time-limit" => self.busy_reply_threshold.to_string(),
+            "notify-keyspace-events" => self.notify_keyspace_events.to_string(),
+            "requirepass" => self.requirepass.clone(),
+            "aclfile" => format_path(&self.aclfile),
+            "acllog-max-len" => self.acllog_max_len.to_string(),
+            "slowlog-log-slower-than" => self.slowlog_log_slower_than.to_string(),
+            "slowlog-max-len" => self.slowlog_max_len.to_string(),
+            "latency-monitor-threshold" => self.latency_monitor_threshold.to_string(),
+            _ => return None,
+        };
+
+        // NOTE: `save` is written as a single directive with every rule, e.g. `save 3600 1 300 100`
+        if parameter == "save" && !value.is_empty() {
+            return Some(value.split(' ').map(String::from).collect());
+        }
+
+        Some(vec![value])
+    }
+
+    /// Write the configuration into the configuration file, keeping its comments and the order of its
+    /// directives, the same way as Redis.
+    ///
+    /// Directives in the file are replaced by their current value, and directives with a value other
+    /// than the default one are added at the end of the file. Files included by the configuration file
+    /// aren't modified.
+    pub fn rewrite(&self, path: &Path) -> std::io::Result<()> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+
+        let mut rewritten = Vec::<&str>::new();
+        let mut lines = Vec::new();
+        for line in content.lines() {
+            if line.trim() == REWRITE_SIGNATURE {
+                continue;
+            }
+
+            let name = match split_args(line.trim()).as_deref() {
+                Some([directive, ..]) => rewritten_name(directive),
+                _ => None,
+            };
+            let name = match name {
+                Some(name) => name,
+                None => {
+                    lines.push(line.to_string());
+                    continue;
+                }
+            };
+
+            // NOTE: Every directive is written once, where it's first used in the file
+            if !rewritten.contains(&name) {
+                rewritten.push(name);
+
+                if let Some(line) = self.directive_line(name) {
+                    lines.push(line);
+                }
+            }
+        }
+
+        let default = Config::default();
+        let mut signed = false;
+        for name in rewritten_names() {
+            if rewritten.contains(&name) || self.get(name) == default.get(name) {
+                continue;
+            }
+
+            if let Some(line) = self.directive_line(name) {
+                if !signed {
+                    lines.push(REWRITE_SIGNATURE.into());
+                    signed = true;
+                }
+                lines.push(line);
+            }
+        }
+
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+
+        let mut content = lines.join("\n");
+        content.push('\n');
+        fs::write(&temp_path, content)?;
+        fs::rename(&temp_path, path)
+    }
+
+    fn directive_line(&self, name: &str) -> Option<String> {
+        let args = self.get(name)?;
+        if args.is_empty() {
+            return None;
+        }
+
+        let args = args.iter().map(|arg| quote_arg(arg)).collect::<Vec<_>>();
+
+        Some(format!("{} {}", name, args.join(" ")))
+    }
+}
+
+/// Every directive written by `CONFIG REWRITE`, aliases are written with the name of their parameter.
+fn rewritten_names() -> impl Iterator<Item = &'static str> {
+    PARAMETERS
+        .iter()
+        .filter(|parameter| parameter.alias_of.is_none())
+        .map(|parameter| parameter.name)
+        .chain(["replicaof"])
+}
+
+fn rewritten_name(directive: &str) -> Option<&'static str> {
+    match directive.to_lowercase().as_str() {
+        "replicaof" | "slaveof" => Some("replicaof"),
+        "slave-read-only" => Some("replica-read-only"),
+        directive => find_parameter(directive).map(|parameter| parameter.canonical_name()),
+    }
+}
+
+/// Quote the argument when needed, so it's read back the same by [`split_args`].
+fn quote_arg(arg: &str) -> String {
+    let needs_quotes = arg.is_empty()
+        || arg.chars().any(|char| {
+            char.is_whitespace() || char.is_control() || matches!(char, '"' | '\'' | '\\')
+        });
+    if !needs_quotes {
+        return arg.into();
+    }
+
+    let mut quoted = String::from('"');
+    for char in arg.chars() {
+        match char {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\u{7}' => quoted.push_str("\\a"),
+            '\u{8}' => quoted.push_str("\\b"),
+            char if char.is_control() => quoted.push_str(&format!("\\x{:02x}", char as u32)),
+            char => quoted.push(char),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+/// Split a line of the configuration file into arguments, the same way as Redis.
+///
+/// Arguments are separated by whitespaces, and can be quoted. Double quoted arguments support the
+/// `\n`, `\r`, `\t`, `\b`, `\a`, `\\`, `\"`, and `\xHH` escapes, single quoted arguments only support `\'`.
+/// Returns `None` when the quotes are unbalanced, or a closing quote isn't followed by a whitespace.
+pub fn split_args(line: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|char| char.is_whitespace()).is_some() {}
+        let quote = match chars.peek() {
+            None => return Some(args),
+            Some(&quote @ ('"' | '\'')) => {
+                chars.next();
+                Some(quote)
+            }
+            Some(_) => None,
+        };
+
+        let mut arg = String::new();
+        loop {
+            match (quote, chars.next()) {
+                (None, None) => break,
+                (None, Some(char)) if char.is_whitespace() => break,
+                (None, Some(char)) => arg.push(char),
+                (Some(_), None) => return None,
+                (Some(quote), Some(char)) if char == quote => {
+                    // NOTE: Closing quote must be followed by a whitespace, e.g. `"foo"bar` is invalid
+                    if chars.peek().is_some_and(|char| !char.is_whitespace()) {
+                        return None;
+                    }
+                    break;
+                }
+                (Some('"'), Some('\\')) => match chars.next()? {
+                    'n' => arg.push('\n'),
+                    'r' => arg.push('\r'),
+                    't' => arg.push('\t'),
+                    'b' => arg.push('\u{8}'),
+                    'a' => arg.push('\u{7}'),
+                    'x' => {
+                        let hex = chars.clone().take(2).collect::<String>();
+                        match u8::from_str_radix(&hex, 16) {
+                            Ok(byte) if hex.len() == 2 => {
+                                chars.nth(1);
+                                arg.push(byte as char);
+                            }
+                            _ => arg.push('x'),
+                        }
+                    }
+                    char => arg.push(char),
+                },
+                (Some('\''), Some('\\')) if chars.peek() == Some(&'\'') => {
+                    chars.next();
+                    arg.push('\'');
+                }
+                (Some(_), Some(char)) => arg.push(char),
+            }
+        }
+
+        args.push(arg);
+    }
+}
+
+/// Parse an amount of memory the same way as Redis, e.g. `100`, `1k` (1000 bytes), `1kb` (1024 bytes),
+/// `5mb`, or `2gb`, the unit is case-insensitive.
+pub fn parse_memory(value: &str) -> Option<u64> {
+    let value = value.to_lowercase();
+    let digits = value
+        .find(|char: char| !char.is_ascii_digit())
+        .unwrap_or(value.len());
+
+    let multiplier = match &value[digits..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+
+    value[..digits].parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+fn parse_yes_no(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err("argument must be 'yes' or 'no'".into()),
+    }
+}
+
+fn format_yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.into()
+}
+
+fn format_path(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map_or_else(String::new, |path| path.to_string_lossy().into_owned())
+}
+
+/// Empty path means the option is not used, the same as Redis.
+fn parse_path(path: &str) -> Option<PathBuf> {
+    if path.is_empty() {
+        None
+    } else {
+        Some(path.into())
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use std::fs;
+
+    use super::{parse_memory, split_args, Config};
+    use crate::{persistence::SaveRule, tls::TlsAuthClients};
+
+    #[test]
+    fn splits_args_like_redis() {
+        assert_eq!(
+            split_args(r#"  requirepass "p\"a\x41ss\n" 'it\'s' plain"quote  "#),
+            Some(vec![
+                "requirepass".into(),
+                "p\"aAss\n".into(),
+                "it's".into(),
+                "plain\"quote".into(),
+            ])
+        );
+        assert_eq!(
+            split_args(r#"save """#),
+            Some(vec!["save".into(), "".into()])
+        );
+        assert_eq!(split_args(r#"dir "unbalanced"#), None);
+        assert_eq!(split_args(r#"dir "closed"early"#), None);
+
+        assert_eq!(parse_memory("100"), Some(100));
+        assert_eq!(parse_memory("1k"), Some(1000));
+        assert_eq!(parse_memory("2MB"), Some(2 * 1024 * 1024));
+        assert_eq!(parse_memory("1tb"), None);
+    }
+
+    #[test]
+    fn reads_config_file_with_overrides() {
+        let dir = std::env::temp_dir().join(format!("rust-eez-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let included = dir.join("included.conf");
+        fs::write(&included, "save 60 10000\ntls-auth-clients optional\n").unwrap();
+        let file = dir.join("rust-eez.conf");
+        fs::write(
+            &file,
+            format!(
+                "# Comment\n\nPORT 7000\nbind 127.0.0.1 -::1\nsave 3600 1\ninclude \"{}\"\nmaxmemory 100mb\n",
+                included.display()
+            ),
+        )
+        .unwrap();
+
+        let config = Config::from_args([
+            file.to_string_lossy().into_owned(),
+            "--port".into(),
+            "7001".into(),
+            "--appendonly".into(),
+            "yes".into(),
+            "--unixsocket".into(),
+            "/tmp/rust-eez.sock".into(),
+            "--unixsocketperm".into(),
+            "770".into(),
+        ])
+        .unwrap();
+        assert_eq!(config.file, Some(file.clone()));
+        assert_eq!(config.port, 7001);
+        assert_eq!(config.bind, vec!["127.0.0.1", "-::1"]);
+        assert_eq!(config.maxmemory, 100 * 1024 * 1024);
+        assert!(config.aof.enabled);
+        assert_eq!(config.unixsocket, Some("/tmp/rust-eez.sock".into()));
+        assert_eq!(config.unixsocketperm, Some(0o770));
+        assert_eq!(config.tls.auth_clients, TlsAuthClients::Optional);
+        assert_eq!(
+            config.persistence.save_rules,
+            vec![
+                SaveRule {
+                    seconds: 3600,
+                    changes: 1
+                },
+                SaveRule {
+                    seconds: 60,
+                    changes: 10000
+                },
+            ]
+        );
+
+        fs::write(&included, "port abc\n").unwrap();
+        assert_eq!(
+            Config::from_args([file.to_string_lossy().into_owned()]).unwrap_err(),
+            format!("{}:1: Invalid port in 'port abc'", included.display())
+        );
+        assert!(Config::from_args(["--unixsocketperm".to_string(), "999".into()]).is_err());
+        assert_eq!(
+            Config::from_args(["--nope".to_string(), "1".into()]).unwrap_err(),
+            "--nope: Bad directive or wrong number of arguments"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rewrites_config_file_in_place() {
+        let dir = std::env::temp_dir().join(format!("rust-eez-rewrite-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("rust-eez.conf");
+        fs::write(
+            &file,
+            "# Server\nport 7000\n\n# Limits\nlua-time-limit 100\nsave 60 1\nsave 300 10\n",
+        )
+        .unwrap();
+
+        let mut config = Config::from_args([file.to_string_lossy().into_owned()]).unwrap();
+        config.busy_reply_threshold = 200;
+        config.requirepass = "with space".into();
+        config.rewrite(&file).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "# Server\nport 7000\n\n# Limits\nbusy-reply-threshold 200\nsave 60 1 300 10\n# Generated by CONFIG REWRITE\nrequirepass \"with space\"\n"
+        );
+        let read_back = Config::from_args([file.to_string_lossy().into_owned()]).unwrap();
+        assert_eq!(read_back.requirepass, "with space");
+        assert_eq!(
+            read_back.persistence.save_rules,
+            config.persistence.save_rules
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
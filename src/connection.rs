@@ -0,0 +1,93 @@
+use std::io::{ErrorKind, Read, Write};
+
+/// Size of the buffer used to read from the stream.
+const READ_BUFFER_SIZE: usize = 4096;
+
+/// Buffered wrapper around the stream of a client.
+///
+/// Other than lowering the number of reads done on the stream, this also allow waiting for incoming
+/// commands without blocking forever. If the stream have a read timeout (e.g. with
+/// [`std::net::TcpStream::set_read_timeout`]), [`Connection::poll_readable`] returns every time the
+/// timeout passes, so messages pushed to an idle client (e.g. Pub/Sub messages) can still be written.
+/// Without a read timeout, pushed messages are only written after the client sends a command.
+pub struct Connection<S> {
+    stream: S,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+fn is_timeout(err: &std::io::Error) -> bool {
+    matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+impl<S: Read + Write> Connection<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            buffer: Vec::with_capacity(READ_BUFFER_SIZE),
+            position: 0,
+        }
+    }
+
+    /// Wait until there's something to read from the stream.
+    ///
+    /// Returns `false` when the stream read timed out before anything could be read, and an
+    /// [`ErrorKind::UnexpectedEof`] error when the stream is closed.
+    pub fn poll_readable(&mut self) -> std::io::Result<bool> {
+        if self.position < self.buffer.len() {
+            return Ok(true);
+        }
+
+        match self.fill_buffer() {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(_) => Ok(true),
+            Err(err) if is_timeout(&err) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn write_all(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.stream.write_all(bytes)?;
+        self.stream.flush()
+    }
+
+    fn fill_buffer(&mut self) -> std::io::Result<usize> {
+        self.buffer.resize(READ_BUFFER_SIZE, 0);
+        self.position = 0;
+
+        match self.stream.read(&mut self.buffer) {
+            Ok(read) => {
+                self.buffer.truncate(read);
+
+                Ok(read)
+            }
+            Err(err) => {
+                self.buffer.clear();
+
+                Err(err)
+            }
+        }
+    }
+}
+
+impl<S: Read + Write> Read for Connection<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position >= self.buffer.len() {
+            // NOTE: Timeout in the middle of a command only means the rest of the command is not here yet
+            loop {
+                match self.fill_buffer() {
+                    Err(err) if is_timeout(&err) => continue,
+                    Err(err) => return Err(err),
+                    Ok(0) => return Ok(0),
+                    Ok(_) => break,
+                }
+            }
+        }
+
+        let read = buf.len().min(self.buffer.len() - self.position);
+        buf[..read].copy_from_slice(&self.buffer[self.position..self.position + read]);
+        self.position += read;
+
+        Ok(read)
+    }
+}
@@ -0,0 +1,579 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use mlua::{Function, Lua, LuaString, MultiValue, Table, Value};
+
+use crate::{
+    resp::{bytes_to_string, string_to_bytes, RespType},
+    scripting::{create_log_function, error_message, new_lua, RunState, LOG_LEVELS},
+};
+
+/// Name of the chunk of every library, shown in the errors of the functions.
+pub const FUNCTION_CHUNK_NAME: &str = "@user_function";
+
+/// How long loading a library can take, the same as Redis.
+const LOAD_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Flags a function can be registered with, the same as Redis. Only `no-writes` changes anything for now,
+/// as the others are about memory limits, stale followers, and clusters.
+const FUNCTION_FLAGS: [&str; 5] = [
+    "no-writes",
+    "allow-oom",
+    "allow-stale",
+    "no-cluster",
+    "allow-cross-slot-keys",
+];
+
+/// Function registered by a library with `redis.register_function`.
+#[derive(Debug, Clone)]
+pub struct LibraryFunction {
+    pub name: String,
+    pub description: Option<String>,
+    pub flags: Vec<&'static str>,
+    pub callback: Function,
+}
+
+impl LibraryFunction {
+    /// Whether the function is registered with `no-writes`, so it can't run write commands.
+    pub fn is_read_only(&self) -> bool {
+        self.flags.contains(&"no-writes")
+    }
+}
+
+/// Library loaded with `FUNCTION LOAD`, along with every function it registered.
+#[derive(Debug, Clone)]
+pub struct Library {
+    pub name: String,
+    pub code: String,
+    pub functions: BTreeMap<String, LibraryFunction>,
+}
+
+/// What to do with the libraries already loaded when restoring libraries with `FUNCTION RESTORE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestorePolicy {
+    /// Drop every library already loaded.
+    Flush,
+    /// Keep every library already loaded, failing if a restored library is already loaded.
+    Append,
+    /// Keep every library already loaded, replacing those with the same name as a restored library.
+    Replace,
+}
+
+/// Lua VM of the function libraries, separated from the VM of the scripts run by `EVAL`.
+///
+/// Libraries are loaded in the same VM, with a `redis` library only having `redis.register_function`
+/// and `redis.log` while the library is loaded. Every function registered by the library is kept, to be
+/// called with `FCALL`, with the usual `redis` library.
+#[derive(Debug)]
+pub struct FunctionEngine {
+    lua: Lua,
+    run_state: Arc<RunState>,
+    /// `redis` library available to functions.
+    api: Table,
+    /// `redis` library available while a library is loaded.
+    load_api: Table,
+    libraries: BTreeMap<String, Library>,
+}
+
+impl FunctionEngine {
+    pub fn new(run_state: &Arc<RunState>) -> mlua::Result<Self> {
+        let lua = new_lua(run_state)?;
+        let api = lua.globals().raw_get("redis")?;
+
+        let load_api = lua.create_table()?;
+        load_api.set("log", create_log_function(&lua)?)?;
+        for (name, level) in LOG_LEVELS {
+            load_api.set(name, level)?;
+        }
+
+        Ok(Self {
+            lua,
+            run_state: Arc::clone(run_state),
+            api,
+            load_api,
+            libraries: BTreeMap::new(),
+        })
+    }
+
+    pub fn lua(&self) -> &Lua {
+        &self.lua
+    }
+
+    pub fn libraries(&self) -> &BTreeMap<String, Library> {
+        &self.libraries
+    }
+
+    pub fn function(&self, name: &str) -> Option<&LibraryFunction> {
+        self.libraries
+            .values()
+            .find_map(|library| library.functions.get(name))
+    }
+
+    /// Load the library, replacing the library with the same name only with `replace`, returning the name
+    /// of the library.
+    pub fn load(&mut self, code: &str, replace: bool) -> Result<String, RespType> {
+        let library = self.compile(code)?;
+        let name = library.name.clone();
+
+        if !replace && self.libraries.contains_key(&name) {
+            return Err(RespType::Error(format!(
+                "ERR Library '{}' already exists",
+                name
+            )));
+        }
+
+        let mut libraries = self.libraries.clone();
+        libraries.insert(name.clone(), library);
+        self.commit(libraries)?;
+
+        Ok(name)
+    }
+
+    /// Drop the library, returning whether there was one.
+    pub fn delete(&mut self, name: &str) -> bool {
+        self.libraries.remove(name).is_some()
+    }
+
+    /// Load every library from their code, all of them or none of them.
+    pub fn restore(&mut self, codes: &[String], policy: RestorePolicy) -> Result<(), RespType> {
+        let mut libraries = match policy {
+            RestorePolicy::Flush => BTreeMap::new(),
+            RestorePolicy::Append | RestorePolicy::Replace => self.libraries.clone(),
+        };
+
+        for code in codes {
+            let library = self.compile(code)?;
+
+            if policy == RestorePolicy::Append && libraries.contains_key(&library.name) {
+                return Err(RespType::Error(format!(
+                    "ERR Library {} already exists",
+                    library.name
+                )));
+            }
+
+            libraries.insert(library.name.clone(), library);
+        }
+
+        self.commit(libraries)
+    }
+
+    /// Replace every library, as long as no function is registered by more than one library.
+    fn commit(&mut self, libraries: BTreeMap<String, Library>) -> Result<(), RespType> {
+        let mut names = Vec::new();
+        for function in libraries
+            .values()
+            .flat_map(|library| library.functions.keys())
+        {
+            if names.contains(&function) {
+                return Err(RespType::Error(format!(
+                    "ERR Function {} already exists",
+                    function
+                )));
+            }
+
+            names.push(function);
+        }
+
+        self.libraries = libraries;
+
+        Ok(())
+    }
+
+    /// Run the code of the library, collecting every function it registers, without loading the library
+    /// yet.
+    fn compile(&self, code: &str) -> Result<Library, RespType> {
+        let (name, body) = parse_metadata(code)?;
+
+        let chunk = self
+            .lua
+            .load(string_to_bytes(body))
+            .set_name(FUNCTION_CHUNK_NAME)
+            .into_function()
+            .map_err(|err| {
+                let message = match err {
+                    mlua::Error::SyntaxError { message, .. } => message,
+                    err => err.to_string(),
+                };
+
+                RespType::Error(format!("ERR Error compiling function: {}", message))
+            })?;
+
+        let functions = RefCell::new(BTreeMap::new());
+
+        *self.run_state.load_deadline() = Some(Instant::now() + LOAD_TIMEOUT);
+        let result = self.run_library(&chunk, &functions);
+        *self.run_state.load_deadline() = None;
+
+        if let Err(err) = result {
+            let message = error_message(&err);
+            // NOTE: Errors raised by the code of the library come with a traceback, which is dropped
+            let message = message
+                .split("\nstack traceback:")
+                .next()
+                .unwrap_or_default();
+
+            return Err(RespType::Error(format!(
+                "ERR Error registering functions: {}",
+                message.strip_prefix("ERR ").unwrap_or(message)
+            )));
+        }
+
+        let functions = functions.into_inner();
+        if functions.is_empty() {
+            return Err(RespType::Error("ERR No functions registered".into()));
+        }
+
+        Ok(Library {
+            name: name.to_string(),
+            code: code.to_string(),
+            functions,
+        })
+    }
+
+    /// Run the code of the library with the `redis` library of loading libraries.
+    fn run_library(
+        &self,
+        chunk: &Function,
+        functions: &RefCell<BTreeMap<String, LibraryFunction>>,
+    ) -> mlua::Result<()> {
+        let globals = self.lua.globals();
+
+        globals.raw_set("redis", &self.load_api)?;
+        let result = self.lua.scope(|scope| {
+            let register_function = scope.create_function(|_, args: MultiValue| {
+                let function = parse_registration(args)?;
+
+                let mut functions = functions.borrow_mut();
+                if functions.contains_key(&function.name) {
+                    return Err(mlua::Error::runtime(
+                        "Function already exists in the library",
+                    ));
+                }
+                functions.insert(function.name.clone(), function);
+
+                Ok(())
+            })?;
+            self.load_api
+                .raw_set("register_function", register_function)?;
+
+            chunk.call::<()>(())
+        });
+        self.load_api.raw_set("register_function", Value::Nil)?;
+        globals.raw_set("redis", &self.api)?;
+
+        result
+    }
+}
+
+/// Name of the library from the first line of the code, e.g. `#!lua name=mylib`, along with the code
+/// after the first line.
+///
+/// The newline is kept in the code, so the line numbers in errors still match the whole code.
+fn parse_metadata(code: &str) -> Result<(&str, &str), RespType> {
+    if !code.starts_with("#!") {
+        return Err(RespType::Error("ERR Missing library metadata".into()));
+    }
+
+    let (shebang, body) = match code.find('\n') {
+        Some(end) => code.split_at(end),
+        None => return Err(RespType::Error("ERR Invalid library metadata".into())),
+    };
+
+    let mut parts = shebang[2..].split(' ').filter(|part| !part.is_empty());
+    let engine = parts.next().unwrap_or_default();
+
+    let mut name = None;
+    for part in parts {
+        match part.split_once('=') {
+            Some(("name", value)) => name = Some(value),
+            _ => {
+                return Err(RespType::Error(format!(
+                    "ERR Invalid metadata value given: {}",
+                    part
+                )))
+            }
+        }
+    }
+
+    let name = name.ok_or_else(|| RespType::Error("ERR Library name was not given".into()))?;
+
+    if !engine.eq_ignore_ascii_case("lua") {
+        return Err(RespType::Error(format!(
+            "ERR Engine '{}' not found",
+            engine
+        )));
+    }
+
+    if !is_valid_name(name) {
+        return Err(RespType::Error("ERR Library names can only contain letters, numbers, or underscores(_) and must be at least one character long".into()));
+    }
+
+    Ok((name, body))
+}
+
+/// Function registered with either `redis.register_function(name, callback)`, or
+/// `redis.register_function{function_name=name, callback=callback, flags={...}, description=...}`.
+fn parse_registration(args: MultiValue) -> mlua::Result<LibraryFunction> {
+    let mut args = args.into_iter();
+
+    let (name, callback, flags, description) = match (args.next(), args.next(), args.next()) {
+        (Some(Value::Table(named)), None, None) => {
+            let mut name = None;
+            let mut callback = None;
+            let mut flags = Vec::new();
+            let mut description = None;
+
+            for pair in named.pairs::<Value, Value>() {
+                let (key, value) = pair?;
+                let key = match &key {
+                    Value::String(key) => bytes_to_string(&key.as_bytes()),
+                    _ => String::new(),
+                };
+
+                match (key.as_str(), value) {
+                    ("function_name", Value::String(value)) => {
+                        name = Some(bytes_to_string(&value.as_bytes()))
+                    }
+                    ("function_name", _) => {
+                        return Err(mlua::Error::runtime(
+                            "function_name argument given to redis.register_function must be a string",
+                        ))
+                    }
+                    ("callback", Value::Function(value)) => callback = Some(value),
+                    ("callback", _) => {
+                        return Err(mlua::Error::runtime(
+                            "callback argument given to redis.register_function must be a function",
+                        ))
+                    }
+                    ("flags", Value::Table(value)) => flags = parse_flags(&value)?,
+                    ("flags", _) => {
+                        return Err(mlua::Error::runtime(
+                            "flags argument to redis.register_function must be a table representing function flags",
+                        ))
+                    }
+                    ("description", Value::String(value)) => {
+                        description = Some(bytes_to_string(&value.as_bytes()))
+                    }
+                    ("description", _) => {
+                        return Err(mlua::Error::runtime(
+                            "description argument given to redis.register_function must be a string",
+                        ))
+                    }
+                    _ => {
+                        return Err(mlua::Error::runtime(
+                            "unknown argument given to redis.register_function",
+                        ))
+                    }
+                }
+            }
+
+            let name = name.ok_or_else(|| {
+                mlua::Error::runtime("redis.register_function must get a function name argument")
+            })?;
+            let callback = callback.ok_or_else(|| {
+                mlua::Error::runtime("redis.register_function must get a callback argument")
+            })?;
+
+            (name, callback, flags, description)
+        }
+        (Some(name), Some(callback), None) => {
+            let name = match name {
+                Value::String(name) => bytes_to_string(&name.as_bytes()),
+                _ => {
+                    return Err(mlua::Error::runtime(
+                        "first argument to redis.register_function must be a string",
+                    ))
+                }
+            };
+            let callback = match callback {
+                Value::Function(callback) => callback,
+                _ => {
+                    return Err(mlua::Error::runtime(
+                        "second argument to redis.register_function must be a function",
+                    ))
+                }
+            };
+
+            (name, callback, Vec::new(), None)
+        }
+        _ => {
+            return Err(mlua::Error::runtime(
+                "wrong number of arguments to redis.register_function",
+            ))
+        }
+    };
+
+    if !is_valid_name(&name) {
+        return Err(mlua::Error::runtime("Function names can only contain letters, numbers, or underscores(_) and must be at least one character long"));
+    }
+
+    Ok(LibraryFunction {
+        name,
+        description,
+        flags,
+        callback,
+    })
+}
+
+fn parse_flags(flags: &Table) -> mlua::Result<Vec<&'static str>> {
+    let mut parsed = Vec::new();
+
+    for flag in flags.sequence_values::<LuaString>() {
+        let flag = flag.map_err(|_| mlua::Error::runtime("unknown flag given"))?;
+
+        match FUNCTION_FLAGS
+            .iter()
+            .find(|known| flag.as_bytes().as_ref() == known.as_bytes())
+        {
+            Some(known) if !parsed.contains(known) => parsed.push(*known),
+            Some(_) => {}
+            None => return Err(mlua::Error::runtime("unknown flag given")),
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Names of libraries and functions can only have letters, numbers, and underscores.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || character == '_')
+}
+
+#[cfg(test)]
+mod functions_tests {
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    const LIBRARY: &str = "#!lua name=lib\n\
+        redis.register_function('setget', function(keys, args)\n\
+            redis.call('SET', keys[1], args[1])\n\
+            return redis.call('GET', keys[1])\n\
+        end)\n\
+        redis.register_function{function_name='get', callback=function(keys)\n\
+            return redis.call('GET', keys[1])\n\
+        end, flags={'no-writes'}}";
+
+    #[test]
+    fn loads_and_calls_functions() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        assert_eq!(
+            handle_commands(
+                command(&["FUNCTION", "LOAD", LIBRARY]),
+                &mut client,
+                &server
+            ),
+            RespType::BulkString("lib".into())
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["FCALL", "setget", "1", "key", "value"]),
+                &mut client,
+                &server
+            ),
+            RespType::BulkString("value".into())
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["FCALL_RO", "get", "1", "key"]),
+                &mut client,
+                &server
+            ),
+            RespType::BulkString("value".into())
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["FCALL_RO", "setget", "1", "key", "other"]),
+                &mut client,
+                &server
+            ),
+            RespType::Error(
+                "ERR Can not execute a script with write flag using *_ro command.".into()
+            )
+        );
+
+        // Function names are shared by every library
+        assert_eq!(
+            handle_commands(
+                command(&[
+                    "FUNCTION",
+                    "LOAD",
+                    "#!lua name=other\nredis.register_function('get', function() end)"
+                ]),
+                &mut client,
+                &server
+            ),
+            RespType::Error("ERR Function get already exists".into())
+        );
+
+        handle_commands(
+            command(&["FUNCTION", "DELETE", "lib"]),
+            &mut client,
+            &server,
+        );
+        assert_eq!(
+            handle_commands(command(&["FCALL", "get", "1", "key"]), &mut client, &server),
+            RespType::Error("ERR Function not found".into())
+        );
+    }
+
+    #[test]
+    fn saves_and_restores_libraries() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        handle_commands(
+            command(&["FUNCTION", "LOAD", LIBRARY]),
+            &mut client,
+            &server,
+        );
+        let payload = handle_commands(command(&["FUNCTION", "DUMP"]), &mut client, &server);
+        let snapshot = server.snapshot(&server.storage.read().unwrap());
+        assert_eq!(snapshot.functions, vec![LIBRARY.to_string()]);
+
+        let payload = match payload {
+            RespType::BulkString(payload) => payload,
+            other => panic!("unexpected reply {:?}", other),
+        };
+        assert_eq!(
+            handle_commands(
+                command(&["FUNCTION", "RESTORE", &payload]),
+                &mut client,
+                &server
+            ),
+            RespType::Error("ERR Library lib already exists".into())
+        );
+        handle_commands(command(&["FUNCTION", "FLUSH"]), &mut client, &server);
+        assert_eq!(
+            handle_commands(
+                command(&["FUNCTION", "RESTORE", &payload]),
+                &mut client,
+                &server
+            ),
+            RespType::String("OK".into())
+        );
+
+        let restarted = Server::new();
+        restarted.restore_snapshot(&mut restarted.storage.write().unwrap(), snapshot);
+        assert_eq!(
+            handle_commands(
+                command(&["FCALL", "setget", "1", "key", "value"]),
+                &mut client,
+                &restarted
+            ),
+            RespType::BulkString("value".into())
+        );
+    }
+}
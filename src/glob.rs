@@ -0,0 +1,139 @@
+/// Match a string against a glob-style pattern, following the same rules Redis uses for `KEYS`,
+/// `PSUBSCRIBE`, and friends.
+///
+/// Supported pattern,
+/// - `*` matches any number of characters, including none
+/// - `?` matches exactly one character
+/// - `[abc]`, `[^abc]`, and `[a-z]` matches one character from (or not from) the set
+/// - `\x` matches `x` literally
+pub fn glob_match(pattern: &str, string: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let string: Vec<char> = string.chars().collect();
+
+    match_from(&pattern, &string)
+}
+
+fn match_from(pattern: &[char], string: &[char]) -> bool {
+    let (mut pattern_idx, mut string_idx) = (0, 0);
+
+    while pattern_idx < pattern.len() {
+        match pattern[pattern_idx] {
+            '*' => {
+                // Collapse consecutive stars, they match the same thing as one star
+                while pattern_idx + 1 < pattern.len() && pattern[pattern_idx + 1] == '*' {
+                    pattern_idx += 1;
+                }
+
+                if pattern_idx + 1 == pattern.len() {
+                    return true;
+                }
+
+                return (string_idx..=string.len())
+                    .any(|start| match_from(&pattern[pattern_idx + 1..], &string[start..]));
+            }
+            '?' => {
+                if string_idx >= string.len() {
+                    return false;
+                }
+
+                string_idx += 1;
+            }
+            '[' => {
+                if string_idx >= string.len() {
+                    return false;
+                }
+
+                let (matched, next_pattern_idx) =
+                    match_class(pattern, pattern_idx + 1, string[string_idx]);
+                if !matched {
+                    return false;
+                }
+
+                pattern_idx = next_pattern_idx;
+                string_idx += 1;
+
+                continue;
+            }
+            '\\' if pattern_idx + 1 < pattern.len() => {
+                pattern_idx += 1;
+
+                if string_idx >= string.len() || pattern[pattern_idx] != string[string_idx] {
+                    return false;
+                }
+
+                string_idx += 1;
+            }
+            literal => {
+                if string_idx >= string.len() || literal != string[string_idx] {
+                    return false;
+                }
+
+                string_idx += 1;
+            }
+        }
+
+        pattern_idx += 1;
+    }
+
+    string_idx == string.len()
+}
+
+/// Match a single character against a `[...]` class starting at `pattern_idx` (right after the `[`).
+///
+/// Returns whether the character matched, and the index of the pattern right after the closing `]`.
+fn match_class(pattern: &[char], mut pattern_idx: usize, character: char) -> (bool, usize) {
+    let negate = pattern.get(pattern_idx) == Some(&'^');
+    if negate {
+        pattern_idx += 1;
+    }
+
+    let mut matched = false;
+    while pattern_idx < pattern.len() && pattern[pattern_idx] != ']' {
+        if pattern[pattern_idx] == '\\' && pattern_idx + 1 < pattern.len() {
+            pattern_idx += 1;
+            matched |= pattern[pattern_idx] == character;
+        } else if pattern_idx + 2 < pattern.len() && pattern[pattern_idx + 1] == '-' {
+            let (mut start, mut end) = (pattern[pattern_idx], pattern[pattern_idx + 2]);
+            if start > end {
+                std::mem::swap(&mut start, &mut end);
+            }
+
+            matched |= (start..=end).contains(&character);
+            pattern_idx += 2;
+        } else {
+            matched |= pattern[pattern_idx] == character;
+        }
+
+        pattern_idx += 1;
+    }
+
+    // Skip the closing ']', an unclosed class is treated as if it was closed at the end of the pattern
+    (matched != negate, (pattern_idx + 1).min(pattern.len()))
+}
+
+#[cfg(test)]
+mod glob_tests {
+    use super::glob_match;
+
+    #[test]
+    fn matches_literal_and_wildcards() {
+        assert!(glob_match("news", "news"));
+        assert!(!glob_match("news", "newsletter"));
+        assert!(glob_match("news.*", "news.tech"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("h?llo", "hello"));
+        assert!(!glob_match("h?llo", "hllo"));
+        assert!(glob_match("*:*:end", "a:b:c:end"));
+    }
+
+    #[test]
+    fn matches_classes_and_escapes() {
+        assert!(glob_match("h[ae]llo", "hallo"));
+        assert!(!glob_match("h[ae]llo", "hillo"));
+        assert!(glob_match("h[^e]llo", "hallo"));
+        assert!(!glob_match("h[^e]llo", "hello"));
+        assert!(glob_match("h[a-c]llo", "hbllo"));
+        assert!(glob_match("h\\*llo", "h*llo"));
+        assert!(!glob_match("h\\*llo", "hello"));
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::commands::registry::{Command, CommandFlags};
+
+/// How many samples are kept for every event, one per second at most, the same as Redis.
+const HISTORY_LEN: usize = 160;
+
+/// Latency of an event, in milliseconds, at a time in seconds since the Unix epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySample {
+    pub time: u64,
+    pub latency: u64,
+}
+
+#[derive(Debug, Default)]
+struct EventHistory {
+    samples: VecDeque<LatencySample>,
+    /// Highest latency since the event was first seen, or since it was reset.
+    max: u64,
+}
+
+/// Latency spikes of the named events (e.g. `command` or `aof-fsync-always`), sampled only when they take
+/// at least `latency-monitor-threshold` milliseconds, shown by `LATENCY LATEST` and `LATENCY HISTORY`.
+#[derive(Debug, Default)]
+pub struct LatencyMonitor {
+    events: Mutex<BTreeMap<String, EventHistory>>,
+    /// `latency-monitor-threshold` in milliseconds, 0 disables the monitor.
+    pub threshold: AtomicU64,
+}
+
+impl LatencyMonitor {
+    /// Sample the event when it took at least `latency-monitor-threshold`.
+    pub fn add_sample_if_needed(&self, event: &str, duration: Duration) {
+        let threshold = self.threshold.load(Ordering::Relaxed);
+        let latency = duration.as_millis() as u64;
+        if threshold == 0 || latency < threshold {
+            return;
+        }
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let mut events = self.lock_events();
+        let history = events.entry(event.to_string()).or_default();
+        history.max = history.max.max(latency);
+
+        // NOTE: Only the highest latency is kept for every second
+        match history.samples.back_mut() {
+            Some(last) if last.time == time => last.latency = last.latency.max(latency),
+            _ => {
+                history.samples.push_back(LatencySample { time, latency });
+                if history.samples.len() > HISTORY_LEN {
+                    history.samples.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Sample the command as a `fast-command` or a `command` event, depending on whether it's flagged as
+    /// fast, the same as Redis.
+    pub fn add_command_sample_if_needed(&self, command: &dyn Command, duration: Duration) {
+        let event = if command.flags().contains(CommandFlags::FAST) {
+            "fast-command"
+        } else {
+            "command"
+        };
+
+        self.add_sample_if_needed(event, duration);
+    }
+
+    /// Latest sample of every event, along with the highest latency of the event, sorted by event.
+    pub fn latest(&self) -> Vec<(String, LatencySample, u64)> {
+        self.lock_events()
+            .iter()
+            .filter_map(|(event, history)| {
+                history
+                    .samples
+                    .back()
+                    .map(|sample| (event.clone(), *sample, history.max))
+            })
+            .collect()
+    }
+
+    /// Every sample kept for the event, oldest first.
+    pub fn history(&self, event: &str) -> Vec<LatencySample> {
+        self.lock_events()
+            .get(event)
+            .map(|history| history.samples.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forget the samples of the events, or of every event when there's none, returns the number of events
+    /// forgotten.
+    pub fn reset(&self, events: &[String]) -> usize {
+        let mut all_events = self.lock_events();
+        if events.is_empty() {
+            let count = all_events.len();
+            all_events.clear();
+
+            return count;
+        }
+
+        events
+            .iter()
+            .filter(|event| all_events.remove(event.as_str()).is_some())
+            .count()
+    }
+
+    /// Human readable analysis of the latency spikes, with advices depending on the events, in the spirit
+    /// of the report of Redis.
+    pub fn doctor(&self, slowlog_log_slower_than: i64) -> String {
+        let threshold = self.threshold.load(Ordering::Relaxed);
+        let events = self.lock_events();
+
+        if events.is_empty() {
+            if threshold == 0 {
+                return "I'm sorry, Dave, I can't do that. Latency monitoring is disabled in this \
+                    instance. You may use \"CONFIG SET latency-monitor-threshold <milliseconds>.\" in \
+                    order to enable it.\n"
+                    .into();
+            }
+
+            return "Dave, no latency spike was observed during the lifetime of this instance, not in \
+                the slightest bit. I honestly think you ought to sleep tonight.\n"
+                .into();
+        }
+
+        let mut report = String::from(
+            "Dave, I have observed latency spikes in this instance. You don't mind talking about it, \
+             do you Dave?\n\n",
+        );
+        for (index, (event, history)) in events.iter().enumerate() {
+            let latencies = history
+                .samples
+                .iter()
+                .map(|sample| sample.latency)
+                .collect::<Vec<_>>();
+            let count = latencies.len().max(1) as u64;
+            let average = latencies.iter().sum::<u64>() / count;
+            let deviation = latencies
+                .iter()
+                .map(|latency| latency.abs_diff(average))
+                .sum::<u64>()
+                / count;
+            let period = match (history.samples.front(), history.samples.back()) {
+                (Some(first), Some(last)) if latencies.len() > 1 => {
+                    (last.time - first.time) as f64 / (latencies.len() - 1) as f64
+                }
+                _ => 0.0,
+            };
+
+            let _ = writeln!(
+                report,
+                "{}. {}: {} latency spikes (average {}ms, mean deviation {}ms, period {:.2} sec). \
+                 Worst all time event {}ms.",
+                index + 1,
+                event,
+                latencies.len(),
+                average,
+                deviation,
+                period,
+                history.max
+            );
+        }
+
+        report.push_str("\nI have a few advices for you:\n\n");
+        let mut advices = Vec::new();
+        for event in events.keys() {
+            let advice = match event.as_str() {
+                "command" => {
+                    if slowlog_log_slower_than < 0
+                        || slowlog_log_slower_than as u64 > threshold.saturating_mul(1000)
+                    {
+                        advices.push(
+                            "- Your current Slow Log configuration only logs events that are slower \
+                             than your configured latency monitor threshold. Please use 'CONFIG SET \
+                             slowlog-log-slower-than <microseconds>' to log them.",
+                        );
+                    }
+                    "- Check your Slow Log to understand what are the commands you are running \
+                     which are too slow to execute, with 'SLOWLOG GET'."
+                }
+                "fast-command" => {
+                    "- The system is slow to execute code paths not containing slow commands. This \
+                     usually means the system is not able to provide the server with the CPU cycles \
+                     it needs, e.g. because of other processes or a noisy virtual machine."
+                }
+                "aof-write" => {
+                    "- Writing the append only file is slow, check whether the disk is busy with \
+                     other processes, or is too slow for the load."
+                }
+                "aof-fsync-always" => {
+                    "- Your fsync policy is set to 'always', flushing every write command to the disk \
+                     is slow, consider 'appendfsync everysec' if losing a second of writes is \
+                     acceptable."
+                }
+                _ => "- Check what the server is doing while the other events are slow.",
+            };
+            if !advices.contains(&advice) {
+                advices.push(advice);
+            }
+        }
+        for advice in advices {
+            report.push_str(advice);
+            report.push('\n');
+        }
+
+        report
+    }
+
+    fn lock_events(&self) -> MutexGuard<'_, BTreeMap<String, EventHistory>> {
+        self.events
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -1,33 +1,214 @@
 use std::{
-    collections::HashMap,
-    io::{Read, Write},
-    sync::{Arc, RwLock},
+    io::{ErrorKind, Read, Write},
+    sync::{atomic::Ordering, mpsc::TryRecvError, Arc},
 };
 
-use storage::StorageType;
+use client::Client;
+use connection::Connection;
+use server::Server;
 
-use crate::{commands::commands::handle_commands, resp::RespType};
+use crate::{
+    acl::DEFAULT_USER,
+    commands::commands::handle_commands,
+    rdb::encode_snapshot,
+    replication::{ReplicaLink, ReplicaSync},
+    resp::RespType,
+};
 
+pub mod acl;
+pub mod aof;
+pub mod client;
+pub mod cluster;
 pub mod commands;
+pub mod config;
+pub mod connection;
+pub mod functions;
+pub mod glob;
+pub mod latency;
+pub mod monitor;
+pub mod notification;
+pub mod persistence;
+pub mod pubsub;
+pub mod rdb;
+pub mod replication;
 pub mod resp;
+pub mod scripting;
+pub mod server;
+pub mod slowlog;
+pub mod stats;
 pub mod storage;
+pub mod tls;
+pub mod tracking;
+pub mod unix_socket;
 
+/// Handle every command sent through the stream, until the stream is closed.
+///
+/// Set a read timeout on the stream to have messages pushed to the client (e.g. Pub/Sub messages) written
+/// while the client is idle, see [`Connection`].
 pub fn handle_command_stream<S: Read + Write>(
     stream: S,
-    storage: Arc<RwLock<HashMap<String, StorageType>>>,
+    addr: String,
+    server: Arc<Server>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    server
+        .stats
+        .total_connections_received
+        .fetch_add(1, Ordering::Relaxed);
+    server
+        .stats
+        .connected_clients
+        .fetch_add(1, Ordering::Relaxed);
+
+    let mut connection = Connection::new(stream);
+    let mut client = Client::with_addr(addr);
+    client.user = Some(DEFAULT_USER.into());
+    client.authenticated = !server.acl.requires_auth();
+    server.clients.register(&client);
+
+    let result = handle_client_commands(&mut connection, &mut client, &server);
+
+    client.disconnect(&server);
+    server.clients.unregister(client.id);
+    server
+        .stats
+        .connected_clients
+        .fetch_sub(1, Ordering::Relaxed);
+
+    result
+}
+
+fn handle_client_commands<S: Read + Write>(
+    connection: &mut Connection<S>,
+    client: &mut Client,
+    server: &Server,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let (resp, mut stream) = RespType::deserialize(stream)?;
+    loop {
+        if client.is_killed() {
+            return Ok(());
+        }
+
+        for message in client.pending_messages() {
+            connection.write_all(&message.serialize())?;
+        }
+
+        match connection.poll_readable() {
+            Ok(true) => {}
+            Ok(false) => continue,
+            // Stream closed by the client, there's nothing more to handle
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+
+        let resp = match RespType::deserialize(&mut *connection) {
+            Ok((resp, _)) => resp,
+            Err(err) => {
+                if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+                    if io_err.kind() == ErrorKind::UnexpectedEof {
+                        return Ok(());
+                    }
+                }
+
+                return Err(err);
+            }
+        };
+
+        let response = if let RespType::Array(commands) = resp {
+            handle_commands(commands, client, server)
+        } else {
+            RespType::Error("WRONGTYPE array was expected".into())
+        };
+
+        // NOTE: `CLIENT CACHING` only applies to the next command, or to every command of a transaction
+        if client.last_command != "client|caching" && client.transaction.is_none() {
+            client.tracking_caching = None;
+        }
+        server.clients.update(client);
+
+        if client.take_reply_mode() {
+            connection.write_all(&response.serialize())?;
+
+            for reply in client.extra_replies.drain(..) {
+                connection.write_all(&reply.serialize())?;
+            }
+        } else {
+            client.extra_replies.clear();
+        }
+
+        // Accepted as a follower by `PSYNC`, the connection is only used for replication from now on
+        if let Some(link) = client.replica.take() {
+            client.is_replica = true;
+            server.clients.update(client);
+
+            return serve_replica(connection, client, server, link);
+        }
+    }
+}
+
+/// Sync the follower, then stream every write command to the follower until the connection is closed.
+///
+/// The follower only sends `REPLCONF ACK` back, which is handled without replying.
+fn serve_replica<S: Read + Write>(
+    connection: &mut Connection<S>,
+    client: &mut Client,
+    server: &Server,
+    link: ReplicaLink,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match link.sync {
+        ReplicaSync::Full(snapshot) => {
+            let mut payload = Vec::new();
+            encode_snapshot(&snapshot, &mut payload)?;
+
+            // NOTE: Following Redis, the snapshot is sent as a bulk string without the trailing CRLF
+            connection.write_all(format!("${}\r\n", payload.len()).as_bytes())?;
+            connection.write_all(&payload)?;
+        }
+        ReplicaSync::Partial(missing) => connection.write_all(&missing)?,
+    }
+
+    loop {
+        if client.is_killed() {
+            return Ok(());
+        }
+
+        loop {
+            match link.receiver.try_recv() {
+                Ok(bytes) => connection.write_all(&bytes)?,
+                Err(TryRecvError::Empty) => break,
+                // Dropped from the followers, e.g. when the server started following another leader
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+
+        match connection.poll_readable() {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+
+        if let (RespType::Array(command), _) = RespType::deserialize(&mut *connection)? {
+            handle_commands(command, client, server);
+        }
+    }
+}
 
-    println!("[Main Handler] Parsed TCP packet: `{:?}`", resp);
+#[cfg(test)]
+pub(crate) mod test_util {
+    use crate::{acl::DEFAULT_USER, client::Client, resp::RespType, server::Server};
 
-    let response = if let RespType::Array(commands) = resp {
-        handle_commands(commands, storage)
-    } else {
-        RespType::Error("WRONGTYPE array was expected".into())
-    };
+    /// Command sent by a client, every argument as a bulk string.
+    pub fn command(args: &[&str]) -> Vec<RespType> {
+        args.iter()
+            .map(|arg| RespType::BulkString(arg.to_string()))
+            .collect()
+    }
 
-    println!("[Main Handler] Responding with `{:#?}`", response);
-    stream.write_all(&response.serialize())?;
+    /// Client connected to the server, as set up when a connection is accepted.
+    pub fn connect(server: &Server) -> Client {
+        let mut client = Client::new();
+        client.user = Some(DEFAULT_USER.into());
+        client.authenticated = !server.acl.requires_auth();
 
-    Ok(())
+        client
+    }
 }
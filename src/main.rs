@@ -1,29 +1,201 @@
 use std::{
-    collections::HashMap,
+    env,
+    io::{Read, Write},
     net::TcpListener,
-    sync::{Arc, RwLock},
+    os::unix::net::UnixListener,
+    process,
+    sync::Arc,
+    thread,
+    time::Duration,
 };
 
-use rust_eez::{handle_command_stream, storage::StorageType};
+use rust_eez::{
+    config::Config, handle_command_stream, persistence::spawn_cron, replication::spawn_follower,
+    server::Server, tls, unix_socket::UnixSocketFile,
+};
+use rustls::ServerConfig;
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+
+/// How long a connection waits for a command before checking for messages pushed to the client.
+const PUSH_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 fn main() -> std::io::Result<()> {
-    let bind_address = "0.0.0.0:6969";
-    let listener = TcpListener::bind(bind_address)?;
+    let config = match Config::from_args(env::args().skip(1)) {
+        Ok(config) => config,
+        Err(err) => {
+            println!("Error reading the configuration: {}", err);
+
+            return Err(std::io::Error::other(err));
+        }
+    };
+
+    let tls_config = if config.tls.is_enabled() {
+        match config.tls.server_config() {
+            Ok(tls_config) => Some(tls_config),
+            Err(err) => {
+                println!("Error loading the TLS configuration: {}", err);
+
+                return Err(std::io::Error::other(err));
+            }
+        }
+    } else {
+        None
+    };
+
+    let server = Arc::new(Server::with_config(config.clone()));
+
+    if config.aclfile.is_some() {
+        if let Err(err) = server.acl.load_file(&server.commands) {
+            println!("Error loading the ACL file: {}", err);
+
+            return Err(std::io::Error::other(err));
+        }
+    }
+
+    if let Err(err) = server.load() {
+        println!("Error loading the data: {:#?}", err);
+
+        return Err(std::io::Error::other(err.to_string()));
+    }
+    spawn_cron(Arc::clone(&server));
+    spawn_follower(Arc::clone(&server));
+
+    let mut ports = Vec::new();
+    if config.port != 0 {
+        ports.push((config.port, None));
+    }
+    if let Some(tls_config) = tls_config {
+        ports.push((config.tls.port, Some(tls_config)));
+    }
+
+    let mut listeners = Vec::new();
+    for address in &config.bind {
+        // NOTE: Following Redis, failing to listen on an address prefixed by `-` is only logged
+        let (optional, address) = match address.strip_prefix('-') {
+            Some(address) => (true, address),
+            None => (false, address.as_str()),
+        };
+        let address = match address {
+            "*" => "0.0.0.0",
+            "::*" => "::",
+            address => address,
+        };
+
+        for (port, tls_config) in &ports {
+            match TcpListener::bind((address, *port)) {
+                Ok(listener) => listeners.push((listener, tls_config.clone())),
+                Err(err) if optional => {
+                    println!("Skipped listening on {}:{}: {}", address, port, err)
+                }
+                Err(err) => {
+                    println!("Error listening on {}:{}: {}", address, port, err);
+
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    let mut handles = listeners
+        .into_iter()
+        .map(|(listener, tls_config)| {
+            let server = Arc::clone(&server);
 
-    let storage: Arc<RwLock<HashMap<String, StorageType>>> = Arc::new(RwLock::new(HashMap::new()));
+            thread::spawn(move || accept_connections(listener, tls_config, server))
+        })
+        .collect::<Vec<_>>();
 
-    println!("Listening On: {}", bind_address);
+    // NOTE: Removed when dropped, on every return from here on
+    let unix_socket = match &config.unixsocket {
+        Some(path) => {
+            let (listener, file) = UnixSocketFile::bind(path, config.unixsocketperm)?;
+            let server = Arc::clone(&server);
+
+            handles.push(thread::spawn(move || {
+                accept_unix_connections(listener, server)
+            }));
+
+            Some(Arc::new(file))
+        }
+        None => None,
+    };
+
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    let shutdown_unix_socket = unix_socket.clone();
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            println!("Got signal {}, shutting down", signal);
+
+            // NOTE: Exiting doesn't drop anything, so the socket file is removed here instead
+            if let Some(file) = &shutdown_unix_socket {
+                file.remove();
+            }
+            process::exit(0);
+        }
+    });
+
+    for handle in handles {
+        if let Ok(Err(err)) = handle.join() {
+            println!("Error accepting connections: {:#?}", err);
+        }
+    }
+
+    Ok(())
+}
+
+/// Accept every client connecting to the Unix socket.
+fn accept_unix_connections(listener: UnixListener, server: Arc<Server>) -> std::io::Result<()> {
+    let path = listener
+        .local_addr()?
+        .as_pathname()
+        .map_or_else(String::new, |path| path.to_string_lossy().into_owned());
+    println!("Listening On: {}", path);
 
     for stream in listener.incoming() {
-        println!("Got Stream: {:#?}", stream);
+        match stream {
+            Ok(unix_stream) => {
+                unix_stream.set_read_timeout(Some(PUSH_POLL_INTERVAL))?;
+
+                // NOTE: Following Redis, clients of the Unix socket are shown with the path and a port of 0
+                spawn_connection(unix_stream, format!("{}:0", path), Arc::clone(&server));
+            }
+            Err(err) => println!("Error Unix Socket Data: {:#?}", err),
+        }
+    }
+
+    Ok(())
+}
+
+/// Accept every client connecting to the listener, through TLS when there's a TLS configuration.
+fn accept_connections(
+    listener: TcpListener,
+    tls_config: Option<Arc<ServerConfig>>,
+    server: Arc<Server>,
+) -> std::io::Result<()> {
+    println!(
+        "Listening On: {}{}",
+        listener.local_addr()?,
+        if tls_config.is_some() { " (TLS)" } else { "" }
+    );
 
+    for stream in listener.incoming() {
         match stream {
             Ok(tcp_stream) => {
-                if let Err(err) = handle_command_stream(tcp_stream, Arc::clone(&storage)) {
-                    println!(
-                        "[Main Handler] Error handling the command stream: {:#?}",
-                        err
-                    )
+                tcp_stream.set_read_timeout(Some(PUSH_POLL_INTERVAL))?;
+                let addr = tcp_stream
+                    .peer_addr()
+                    .map_or_else(|_| String::new(), |addr| addr.to_string());
+                let server = Arc::clone(&server);
+
+                match &tls_config {
+                    Some(config) => match tls::accept(tcp_stream, Arc::clone(config)) {
+                        Ok(tls_stream) => spawn_connection(tls_stream, addr, server),
+                        Err(err) => println!("Error TLS Data: {:#?}", err),
+                    },
+                    None => spawn_connection(tcp_stream, addr, server),
                 }
             }
             Err(err) => println!("Error TCP Data: {:#?}", err),
@@ -32,3 +204,19 @@ fn main() -> std::io::Result<()> {
 
     Ok(())
 }
+
+/// Handle the commands of a client on its own thread, as a connection can send many commands.
+fn spawn_connection<S: Read + Write + Send + 'static>(
+    stream: S,
+    addr: String,
+    server: Arc<Server>,
+) {
+    thread::spawn(move || {
+        if let Err(err) = handle_command_stream(stream, addr, server) {
+            println!(
+                "[Main Handler] Error handling the command stream: {:#?}",
+                err
+            )
+        }
+    });
+}
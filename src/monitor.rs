@@ -0,0 +1,127 @@
+use std::{
+    collections::HashSet,
+    fmt::Write,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    client::{Client, Clients},
+    commands::registry::{Command, CommandFlags},
+    resp::{string_to_bytes, RespType},
+    slowlog::loggable_args,
+};
+
+/// Where a command fed to the monitors comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Sent by the client itself.
+    Client,
+    /// Run by a script of the client, shown as `lua`, the same as Redis.
+    Script,
+}
+
+/// Clients in the `MONITOR` mode, receiving a line for every command run by the server, e.g.
+/// `+1700000000.123456 [0 127.0.0.1:50000] "SET" "key" "value"`.
+#[derive(Debug, Default)]
+pub struct Monitor {
+    clients: RwLock<HashSet<u64>>,
+}
+
+impl Monitor {
+    pub fn add(&self, client_id: u64) {
+        self.write_clients().insert(client_id);
+    }
+
+    pub fn remove(&self, client_id: u64) {
+        self.write_clients().remove(&client_id);
+    }
+
+    /// Send the command about to run to every monitor, other than the administrative commands.
+    ///
+    /// NOTE: Scripts are marked with `SKIP_MONITOR` in Redis to have them fed before the commands they
+    /// run, the commands are always fed before they run here, so the flag is not needed.
+    pub fn feed(
+        &self,
+        client: &Client,
+        origin: Origin,
+        command: &dyn Command,
+        command_arr: &[RespType],
+        clients: &Clients,
+    ) {
+        if self.read_clients().is_empty() || command.flags().contains(CommandFlags::ADMIN) {
+            return;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let source = match origin {
+            Origin::Client => client.addr.as_str(),
+            Origin::Script => "lua",
+        };
+        let mut line = format!(
+            "{}.{:06} [0 {}]",
+            timestamp.as_secs(),
+            timestamp.subsec_micros(),
+            source
+        );
+        for arg in loggable_args(command_arr) {
+            line.push(' ');
+            line.push_str(&escape(&arg));
+        }
+
+        for monitor_id in self.read_clients().iter() {
+            clients.send(*monitor_id, vec![RespType::String(line.clone())]);
+        }
+    }
+
+    fn read_clients(&self) -> RwLockReadGuard<'_, HashSet<u64>> {
+        self.clients
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write_clients(&self) -> RwLockWriteGuard<'_, HashSet<u64>> {
+        self.clients
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Quote the argument, escaping the quotes, backslashes, and every byte that isn't printable, the same as
+/// Redis.
+pub fn escape(arg: &str) -> String {
+    let mut escaped = String::with_capacity(arg.len() + 2);
+    escaped.push('"');
+    for byte in string_to_bytes(arg) {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            b'\n' => escaped.push_str("\\n"),
+            b'\r' => escaped.push_str("\\r"),
+            b'\t' => escaped.push_str("\\t"),
+            0x07 => escaped.push_str("\\a"),
+            0x08 => escaped.push_str("\\b"),
+            byte if byte.is_ascii_graphic() || byte == b' ' => escaped.push(byte as char),
+            byte => {
+                let _ = write!(escaped, "\\x{:02x}", byte);
+            }
+        }
+    }
+    escaped.push('"');
+
+    escaped
+}
+
+#[cfg(test)]
+mod monitor_tests {
+    use super::escape;
+
+    #[test]
+    fn escapes_raw_bytes() {
+        assert_eq!(escape("say \"hi\"\r\n"), "\"say \\\"hi\\\"\\r\\n\"");
+        // Read from RESP as one character per byte
+        assert_eq!(escape("caf\u{e9}\u{0}"), "\"caf\\xe9\\x00\"");
+    }
+}
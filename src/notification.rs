@@ -0,0 +1,249 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::pubsub::PubSub;
+
+/// Class of keyspace events to be published, configured with `notify-keyspace-events`.
+///
+/// Every flag have the same character as in Redis, e.g. `K` for [`NotifyFlags::KEYSPACE`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NotifyFlags(u32);
+
+impl NotifyFlags {
+    /// `K`, publish to `__keyspace@<db>__:<key>` with the event as the message.
+    pub const KEYSPACE: Self = Self(1 << 0);
+    /// `E`, publish to `__keyevent@<db>__:<event>` with the key as the message.
+    pub const KEYEVENT: Self = Self(1 << 1);
+    /// `g`, generic commands not specific to a type, e.g. `DEL`.
+    pub const GENERIC: Self = Self(1 << 2);
+    /// `$`, string commands.
+    pub const STRING: Self = Self(1 << 3);
+    /// `l`, list commands.
+    pub const LIST: Self = Self(1 << 4);
+    /// `s`, set commands.
+    pub const SET: Self = Self(1 << 5);
+    /// `h`, hash commands.
+    pub const HASH: Self = Self(1 << 6);
+    /// `z`, sorted set commands.
+    pub const ZSET: Self = Self(1 << 7);
+    /// `x`, key expired.
+    pub const EXPIRED: Self = Self(1 << 8);
+    /// `e`, key evicted.
+    pub const EVICTED: Self = Self(1 << 9);
+    /// `t`, stream commands.
+    pub const STREAM: Self = Self(1 << 10);
+    /// `m`, key miss on read.
+    pub const KEY_MISS: Self = Self(1 << 11);
+    /// `n`, new key created.
+    pub const NEW: Self = Self(1 << 12);
+    /// `A`, alias of `g$lshzxet`, key miss and new key are not included.
+    pub const ALL: Self = Self(
+        Self::GENERIC.0
+            | Self::STRING.0
+            | Self::LIST.0
+            | Self::SET.0
+            | Self::HASH.0
+            | Self::ZSET.0
+            | Self::EXPIRED.0
+            | Self::EVICTED.0
+            | Self::STREAM.0,
+    );
+
+    const CHARACTERS: [(char, Self); 13] = [
+        ('g', Self::GENERIC),
+        ('$', Self::STRING),
+        ('l', Self::LIST),
+        ('s', Self::SET),
+        ('h', Self::HASH),
+        ('z', Self::ZSET),
+        ('x', Self::EXPIRED),
+        ('e', Self::EVICTED),
+        ('t', Self::STREAM),
+        ('K', Self::KEYSPACE),
+        ('E', Self::KEYEVENT),
+        ('m', Self::KEY_MISS),
+        ('n', Self::NEW),
+    ];
+
+    /// Parse the flags from the `notify-keyspace-events` string, `None` if there's an unknown flag.
+    pub fn parse(flags: &str) -> Option<Self> {
+        let mut parsed = Self::default();
+
+        for flag in flags.chars() {
+            parsed = if flag == 'A' {
+                parsed | Self::ALL
+            } else {
+                let (_, class) = Self::CHARACTERS
+                    .iter()
+                    .find(|(character, _)| *character == flag)?;
+
+                parsed | *class
+            };
+        }
+
+        Some(parsed)
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl std::ops::BitOr for NotifyFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl fmt::Display for NotifyFlags {
+    /// Format the flags the same way Redis does, using `A` whenever every class in it is set.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let all = self.contains(Self::ALL);
+        if all {
+            write!(f, "A")?;
+        }
+
+        for (character, class) in Self::CHARACTERS {
+            if self.contains(class) && !(all && Self::ALL.contains(class)) {
+                write!(f, "{}", character)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A modification (or miss) on a key, recorded by the storage to be published after the command is done.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyspaceEvent {
+    pub class: NotifyFlags,
+    pub event: &'static str,
+    pub key: String,
+}
+
+/// Publisher of keyspace events to the Pub/Sub channels.
+#[derive(Debug, Default)]
+pub struct Notifier {
+    flags: AtomicU32,
+}
+
+impl Notifier {
+    pub fn flags(&self) -> NotifyFlags {
+        NotifyFlags(self.flags.load(Ordering::Relaxed))
+    }
+
+    pub fn set_flags(&self, flags: NotifyFlags) {
+        self.flags.store(flags.0, Ordering::Relaxed);
+    }
+
+    /// Publish every event with a class enabled in the flags.
+    ///
+    /// Following Redis, nothing is published unless at least one of `K` or `E` is set as well.
+    pub fn publish(&self, events: Vec<KeyspaceEvent>, pubsub: &PubSub) {
+        let flags = self.flags();
+        if !flags.contains(NotifyFlags::KEYSPACE) && !flags.contains(NotifyFlags::KEYEVENT) {
+            return;
+        }
+
+        for event in events {
+            if !flags.contains(event.class) {
+                continue;
+            }
+
+            // NOTE: There's only one database for now
+            if flags.contains(NotifyFlags::KEYSPACE) {
+                pubsub.publish(&format!("__keyspace@0__:{}", event.key), event.event);
+            }
+
+            if flags.contains(NotifyFlags::KEYEVENT) {
+                pubsub.publish(&format!("__keyevent@0__:{}", event.event), &event.key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod notification_tests {
+    use super::NotifyFlags;
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    fn message(pattern: &str, channel: &str, payload: &str) -> RespType {
+        RespType::Array(vec![
+            RespType::BulkString("pmessage".into()),
+            RespType::BulkString(pattern.into()),
+            RespType::BulkString(channel.into()),
+            RespType::BulkString(payload.into()),
+        ])
+    }
+
+    #[test]
+    fn publishes_enabled_events_only() {
+        let server = Server::new();
+        let mut subscriber = connect(&server);
+        let mut client = connect(&server);
+
+        handle_commands(command(&["PSUBSCRIBE", "__key*"]), &mut subscriber, &server);
+
+        handle_commands(command(&["SET", "key", "value"]), &mut client, &server);
+        assert!(subscriber.pending_messages().is_empty());
+
+        handle_commands(
+            command(&["CONFIG", "SET", "notify-keyspace-events", "Eh"]),
+            &mut client,
+            &server,
+        );
+        handle_commands(command(&["SET", "key", "value"]), &mut client, &server);
+        handle_commands(command(&["HSET", "hash", "a", "1"]), &mut client, &server);
+        handle_commands(command(&["HDEL", "hash", "a"]), &mut client, &server);
+
+        assert_eq!(
+            subscriber.pending_messages(),
+            vec![
+                message("__key*", "__keyevent@0__:hset", "hash"),
+                message("__key*", "__keyevent@0__:hdel", "hash"),
+            ]
+        );
+
+        handle_commands(
+            command(&["CONFIG", "SET", "notify-keyspace-events", "KA"]),
+            &mut client,
+            &server,
+        );
+        handle_commands(command(&["DEL", "key"]), &mut client, &server);
+
+        assert_eq!(
+            subscriber.pending_messages(),
+            vec![message("__key*", "__keyspace@0__:key", "del")]
+        );
+    }
+
+    #[test]
+    fn parses_and_formats_flags() {
+        assert_eq!(
+            NotifyFlags::parse("KEA").map(|flags| flags.to_string()),
+            Some("AKE".into())
+        );
+        assert_eq!(
+            NotifyFlags::parse("Kh$").map(|flags| flags.to_string()),
+            Some("$hK".into())
+        );
+        assert_eq!(
+            NotifyFlags::parse("").map(|flags| flags.is_empty()),
+            Some(true)
+        );
+        assert_eq!(NotifyFlags::parse("Kq"), None);
+    }
+}
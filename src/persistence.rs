@@ -0,0 +1,461 @@
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, ErrorKind},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    rdb::{decode_snapshot, encode_snapshot},
+    server::Server,
+    storage::Snapshot,
+};
+
+/// How long to wait before retrying a background save triggered by a save rule, after the last one failed.
+const SAVE_RETRY_DELAY: u64 = 5;
+
+/// How often the periodic persistence tasks are run, see [`spawn_cron`].
+const CRON_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Save the snapshot after `seconds` have passed, if there are at least `changes` since the last save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveRule {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+impl SaveRule {
+    /// Parse the rules from the `save` config, e.g. `3600 1 300 100`. Empty string means no rules at all.
+    pub fn parse_rules(rules: &str) -> Option<Vec<Self>> {
+        let numbers = rules
+            .split_whitespace()
+            .map(|number| number.parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+
+        if numbers.len() % 2 != 0 {
+            return None;
+        }
+
+        Some(
+            numbers
+                .chunks(2)
+                .map(|rule| Self {
+                    seconds: rule[0],
+                    changes: rule[1],
+                })
+                .collect(),
+        )
+    }
+
+    /// Format the rules the same way as the `save` config.
+    pub fn format_rules(rules: &[Self]) -> String {
+        rules
+            .iter()
+            .map(|rule| format!("{} {}", rule.seconds, rule.changes))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Configuration of where and when the snapshot is saved.
+#[derive(Debug, Clone)]
+pub struct PersistenceConfig {
+    pub dir: PathBuf,
+    pub dbfilename: String,
+    pub save_rules: Vec<SaveRule>,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("."),
+            dbfilename: "dump.rdb".into(),
+            // NOTE: Same defaults as Redis
+            save_rules: vec![
+                SaveRule {
+                    seconds: 3600,
+                    changes: 1,
+                },
+                SaveRule {
+                    seconds: 300,
+                    changes: 100,
+                },
+                SaveRule {
+                    seconds: 60,
+                    changes: 10000,
+                },
+            ],
+        }
+    }
+}
+
+/// Snapshot persistence of the storage, in the Redis RDB format.
+///
+/// Snapshots are written into a temporary file first, then renamed over the snapshot file, so the
+/// snapshot file is always a complete snapshot even when the server crashed in the middle of a save.
+#[derive(Debug)]
+pub struct Persistence {
+    pub config: RwLock<PersistenceConfig>,
+    /// Number of modifications made to the storage since the last successful save.
+    dirty: AtomicU64,
+    /// Unix time (in seconds) of the last successful save.
+    last_save: AtomicU64,
+    /// Unix time (in seconds) of the last attempt to save in the background.
+    last_background_attempt: AtomicU64,
+    last_save_ok: AtomicBool,
+    save_in_progress: AtomicBool,
+    background_save_scheduled: AtomicBool,
+}
+
+impl Default for Persistence {
+    fn default() -> Self {
+        Self {
+            config: RwLock::default(),
+            dirty: AtomicU64::new(0),
+            last_save: AtomicU64::new(unix_time()),
+            last_background_attempt: AtomicU64::new(0),
+            last_save_ok: AtomicBool::new(true),
+            save_in_progress: AtomicBool::new(false),
+            background_save_scheduled: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Persistence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_changes(&self, changes: u64) {
+        if changes > 0 {
+            self.dirty.fetch_add(changes, Ordering::Relaxed);
+        }
+    }
+
+    pub fn dirty(&self) -> u64 {
+        self.dirty.load(Ordering::Relaxed)
+    }
+
+    pub fn last_save(&self) -> u64 {
+        self.last_save.load(Ordering::Relaxed)
+    }
+
+    pub fn last_save_ok(&self) -> bool {
+        self.last_save_ok.load(Ordering::Relaxed)
+    }
+
+    pub fn is_saving(&self) -> bool {
+        self.save_in_progress.load(Ordering::Relaxed)
+    }
+
+    /// Path of the snapshot file, from the `dir` and `dbfilename` config.
+    pub fn snapshot_path(&self) -> PathBuf {
+        match self.config.read() {
+            Ok(config) => config.dir.join(&config.dbfilename),
+            Err(err) => {
+                println!("[Persistence] Got poisoned config for read: {:#?}", err);
+
+                PersistenceConfig::default().dbfilename.into()
+            }
+        }
+    }
+
+    /// Save the snapshot in the calling thread, fails if there's already a save in progress.
+    pub fn save(&self, snapshot: &Snapshot) -> io::Result<()> {
+        if !self.start_save() {
+            return Err(io::Error::new(
+                ErrorKind::WouldBlock,
+                "Background save already in progress",
+            ));
+        }
+
+        let result = self.write_snapshot(snapshot, self.dirty());
+        self.save_in_progress.store(false, Ordering::Release);
+
+        result
+    }
+
+    /// Save the snapshot in a new thread, returns `false` if there's already a save in progress.
+    ///
+    /// The storage can be modified while the snapshot is being written, as the snapshot is a copy of it.
+    /// `dirty` is the number of modifications included in the snapshot, read while the storage is still
+    /// locked for the snapshot, so modifications made after it are still counted once it's saved.
+    pub fn background_save(self: &Arc<Self>, snapshot: Snapshot, dirty: u64) -> bool {
+        self.last_background_attempt
+            .store(unix_time(), Ordering::Relaxed);
+
+        if !self.start_save() {
+            return false;
+        }
+
+        self.background_save_scheduled
+            .store(false, Ordering::Relaxed);
+
+        let persistence = Arc::clone(self);
+        thread::spawn(move || {
+            if let Err(err) = persistence.write_snapshot(&snapshot, dirty) {
+                println!(
+                    "[Persistence BGSAVE] Failed to save the snapshot: {:#?}",
+                    err
+                );
+            }
+
+            persistence.save_in_progress.store(false, Ordering::Release);
+        });
+
+        true
+    }
+
+    /// Have a background save started as soon as the one in progress is done.
+    pub fn schedule_background_save(&self) {
+        self.background_save_scheduled
+            .store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a background save should be started now, either it's scheduled or a save rule is met.
+    pub fn should_save(&self) -> bool {
+        if self.is_saving() {
+            return false;
+        }
+
+        if self.background_save_scheduled.load(Ordering::Relaxed) {
+            return true;
+        }
+
+        let now = unix_time();
+        let dirty = self.dirty();
+        let since_last_save = now.saturating_sub(self.last_save());
+
+        // NOTE: Following Redis, don't keep on retrying right away when the last save failed
+        if !self.last_save_ok()
+            && now.saturating_sub(self.last_background_attempt.load(Ordering::Relaxed))
+                <= SAVE_RETRY_DELAY
+        {
+            return false;
+        }
+
+        match self.config.read() {
+            Ok(config) => config
+                .save_rules
+                .iter()
+                .any(|rule| dirty >= rule.changes && since_last_save >= rule.seconds),
+            Err(err) => {
+                println!("[Persistence] Got poisoned config for read: {:#?}", err);
+
+                false
+            }
+        }
+    }
+
+    /// Read the snapshot file, `None` if there's no snapshot file yet.
+    pub fn load(&self) -> Result<Option<Snapshot>, Box<dyn Error>> {
+        let file = match File::open(self.snapshot_path()) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        decode_snapshot(BufReader::new(file)).map(Some)
+    }
+
+    fn start_save(&self) -> bool {
+        self.save_in_progress
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn write_snapshot(&self, snapshot: &Snapshot, dirty_at_start: u64) -> io::Result<()> {
+        let path = self.snapshot_path();
+        let temp_path = path.with_file_name(format!("temp-{}.rdb", std::process::id()));
+
+        let result = (|| {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            encode_snapshot(snapshot, &mut writer)?;
+            writer.into_inner()?.sync_all()?;
+
+            fs::rename(&temp_path, &path)
+        })();
+
+        match result {
+            Ok(_) => {
+                self.dirty.fetch_sub(dirty_at_start, Ordering::Relaxed);
+                self.last_save.store(unix_time(), Ordering::Relaxed);
+                self.last_save_ok.store(true, Ordering::Relaxed);
+            }
+            Err(_) => {
+                let _ = fs::remove_file(&temp_path);
+                self.last_save_ok.store(false, Ordering::Relaxed);
+            }
+        }
+
+        result
+    }
+}
+
+/// Run the periodic persistence tasks every second, flushing the append only file to the disk for
+/// `appendfsync everysec`, and starting a background save whenever one of the save rules is met.
+///
+/// Followers are also pinged from here once in a while, see [`Replication::ping_replicas`].
+///
+/// [`Replication::ping_replicas`]: crate::replication::Replication::ping_replicas
+pub fn spawn_cron(server: Arc<Server>) -> thread::JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(CRON_INTERVAL);
+
+        server.fsync_aof(false);
+        server.replication.ping_replicas();
+        server.stats.sample_ops();
+
+        if !server.persistence.should_save() {
+            continue;
+        }
+
+        match server.storage.read() {
+            Ok(storage_locked) => {
+                let snapshot = server.snapshot(&storage_locked);
+                let dirty = server.persistence.dirty();
+                drop(storage_locked);
+
+                server.persistence.background_save(snapshot, dirty);
+            }
+            Err(err) => println!("[Persistence] Got poisoned storage for read: {:#?}", err),
+        }
+    })
+}
+
+/// Current unix time, in seconds.
+pub fn unix_time() -> u64 {
+    unix_time_ms() / 1000
+}
+
+/// Current unix time, in milliseconds.
+pub fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod persistence_tests {
+    use std::{fs, path::Path, thread, time::Duration};
+
+    use super::SaveRule;
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    fn server_in(dir: &Path) -> Server {
+        let server = Server::new();
+        server.persistence.config.write().unwrap().dir = dir.to_path_buf();
+
+        server
+    }
+
+    fn wait_for_save(server: &Server) {
+        while server.persistence.is_saving() {
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn parses_save_rules() {
+        assert_eq!(
+            SaveRule::parse_rules("3600 1 60 10000"),
+            Some(vec![
+                SaveRule {
+                    seconds: 3600,
+                    changes: 1
+                },
+                SaveRule {
+                    seconds: 60,
+                    changes: 10000
+                },
+            ])
+        );
+        assert_eq!(SaveRule::parse_rules(""), Some(vec![]));
+        assert_eq!(SaveRule::parse_rules("3600"), None);
+        assert_eq!(SaveRule::parse_rules("3600 x"), None);
+    }
+
+    #[test]
+    fn saves_and_loads_on_startup() {
+        let dir = std::env::temp_dir().join(format!("rust-eez-save-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let server = server_in(&dir);
+        let mut client = connect(&server);
+        handle_commands(command(&["SET", "key", "value"]), &mut client, &server);
+        assert_eq!(server.persistence.dirty(), 1);
+
+        assert_eq!(
+            handle_commands(command(&["SAVE"]), &mut client, &server),
+            RespType::String("OK".into())
+        );
+        assert_eq!(server.persistence.dirty(), 0);
+        assert!(server.persistence.last_save_ok());
+        assert_eq!(
+            handle_commands(command(&["LASTSAVE"]), &mut client, &server),
+            RespType::Integer(server.persistence.last_save() as i64)
+        );
+
+        let restarted = server_in(&dir);
+        restarted.load().unwrap();
+        assert_eq!(
+            handle_commands(
+                command(&["GET", "key"]),
+                &mut connect(&restarted),
+                &restarted
+            ),
+            RespType::BulkString("value".into())
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn background_save_keeps_later_changes_dirty() {
+        let dir = std::env::temp_dir().join(format!("rust-eez-bgsave-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let server = server_in(&dir);
+        let mut client = connect(&server);
+        handle_commands(command(&["SET", "saved", "1"]), &mut client, &server);
+        assert_eq!(
+            handle_commands(command(&["BGSAVE"]), &mut client, &server),
+            RespType::String("Background saving started".into())
+        );
+        wait_for_save(&server);
+        assert_eq!(server.persistence.dirty(), 0);
+
+        // Modified after the snapshot is taken, but before the background save is started
+        handle_commands(command(&["SET", "snapshot", "1"]), &mut client, &server);
+        let snapshot = server.snapshot(&server.storage.read().unwrap());
+        let dirty = server.persistence.dirty();
+        handle_commands(command(&["SET", "unsaved", "1"]), &mut client, &server);
+
+        assert!(server.persistence.background_save(snapshot, dirty));
+        wait_for_save(&server);
+        assert_eq!(server.persistence.dirty(), 1);
+
+        let restarted = server_in(&dir);
+        restarted.load().unwrap();
+        let storage = restarted.storage.read().unwrap();
+        assert!(storage.contains_key("saved"));
+        assert!(storage.contains_key("snapshot"));
+        assert!(!storage.contains_key("unsaved"));
+        drop(storage);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
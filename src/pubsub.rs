@@ -0,0 +1,166 @@
+use std::{
+    collections::HashMap,
+    sync::{mpsc::Sender, RwLock},
+};
+
+use crate::{glob::glob_match, resp::RespType};
+
+/// Sending end of a client messages, every message is the content of a push sent to the client.
+pub type MessageSender = Sender<Vec<RespType>>;
+
+/// Map of channel (or pattern) name, to the clients subscribed to it.
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    subscribers: RwLock<HashMap<String, HashMap<u64, MessageSender>>>,
+}
+
+impl Subscriptions {
+    /// Subscribe a client, returns false if the client was already subscribed.
+    pub fn subscribe(&self, name: &str, client_id: u64, sender: &MessageSender) -> bool {
+        match self.subscribers.write() {
+            Ok(mut subscribers) => subscribers
+                .entry(name.to_string())
+                .or_default()
+                .insert(client_id, sender.clone())
+                .is_none(),
+            Err(err) => {
+                println!("[PubSub] Got poisoned subscribers for write: {:#?}", err);
+
+                false
+            }
+        }
+    }
+
+    /// Unsubscribe a client, returns false if the client was not subscribed.
+    pub fn unsubscribe(&self, name: &str, client_id: u64) -> bool {
+        match self.subscribers.write() {
+            Ok(mut subscribers) => {
+                let (removed, now_empty) = match subscribers.get_mut(name) {
+                    Some(clients) => (clients.remove(&client_id).is_some(), clients.is_empty()),
+                    None => (false, false),
+                };
+
+                if now_empty {
+                    subscribers.remove(name);
+                }
+
+                removed
+            }
+            Err(err) => {
+                println!("[PubSub] Got poisoned subscribers for write: {:#?}", err);
+
+                false
+            }
+        }
+    }
+
+    /// Number of clients subscribed to the exact name.
+    pub fn count(&self, name: &str) -> usize {
+        match self.subscribers.read() {
+            Ok(subscribers) => subscribers.get(name).map_or(0, |clients| clients.len()),
+            Err(_) => 0,
+        }
+    }
+
+    /// Every name with at least one subscriber, optionally filtered with a glob-style pattern.
+    pub fn names(&self, pattern: Option<&str>) -> Vec<String> {
+        match self.subscribers.read() {
+            Ok(subscribers) => subscribers
+                .keys()
+                .filter(|name| pattern.is_none_or(|pattern| glob_match(pattern, name)))
+                .cloned()
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.subscribers
+            .read()
+            .map_or(0, |subscribers| subscribers.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Send the message to every client subscribed to the exact name, returning how many clients received it.
+    fn send(&self, name: &str, message: &[RespType]) -> i64 {
+        let subscribers = match self.subscribers.read() {
+            Ok(subscribers) => subscribers,
+            Err(_) => return 0,
+        };
+
+        let mut receivers = 0;
+        if let Some(clients) = subscribers.get(name) {
+            for sender in clients.values() {
+                // NOTE: A failing send means the client is disconnecting, it'll unsubscribe itself shortly
+                if sender.send(message.to_vec()).is_ok() {
+                    receivers += 1;
+                }
+            }
+        }
+
+        receivers
+    }
+}
+
+/// Registry of every channel and pattern subscription, shared by every client.
+///
+/// Shard channels have their own registry, a message published with `SPUBLISH` only reaches clients
+/// subscribed with `SSUBSCRIBE`, and the other way around.
+#[derive(Debug, Default)]
+pub struct PubSub {
+    pub channels: Subscriptions,
+    pub patterns: Subscriptions,
+    pub shard_channels: Subscriptions,
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Publish a message to a channel, returning the number of clients that received the message.
+    ///
+    /// Following Redis, a client subscribed to both the channel and a matching pattern (or to multiple
+    /// matching patterns) receives the message once per subscription, and is counted that many times.
+    pub fn publish(&self, channel: &str, message: &str) -> i64 {
+        let mut receivers = self.channels.send(
+            channel,
+            &[
+                RespType::BulkString("message".into()),
+                RespType::BulkString(channel.into()),
+                RespType::BulkString(message.into()),
+            ],
+        );
+
+        for pattern in self.patterns.names(None) {
+            if glob_match(&pattern, channel) {
+                receivers += self.patterns.send(
+                    &pattern,
+                    &[
+                        RespType::BulkString("pmessage".into()),
+                        RespType::BulkString(pattern.clone()),
+                        RespType::BulkString(channel.into()),
+                        RespType::BulkString(message.into()),
+                    ],
+                );
+            }
+        }
+
+        receivers
+    }
+
+    /// Publish a message to a shard channel, returning the number of clients that received the message.
+    pub fn spublish(&self, shard_channel: &str, message: &str) -> i64 {
+        self.shard_channels.send(
+            shard_channel,
+            &[
+                RespType::BulkString("smessage".into()),
+                RespType::BulkString(shard_channel.into()),
+                RespType::BulkString(message.into()),
+            ],
+        )
+    }
+}
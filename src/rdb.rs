@@ -0,0 +1,1235 @@
+use std::{
+    error::Error,
+    io::{self, Cursor, ErrorKind, Read, Write},
+};
+
+use crate::{
+    persistence::unix_time_ms,
+    resp::{bytes_to_string, string_to_bytes},
+    storage::{Snapshot, StorageType},
+};
+
+/// Version of the RDB files written, the same as Redis 7.2.
+pub const RDB_VERSION: u16 = 11;
+/// Latest version of the RDB files that can be read, the same as Redis 7.4.
+const MAX_RDB_VERSION: u16 = 12;
+
+const OPCODE_SLOT_INFO: u8 = 0xF4;
+const OPCODE_FUNCTION2: u8 = 0xF5;
+const OPCODE_FUNCTION_PRE_GA: u8 = 0xF6;
+const OPCODE_MODULE_AUX: u8 = 0xF7;
+const OPCODE_IDLE: u8 = 0xF8;
+const OPCODE_FREQ: u8 = 0xF9;
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZEDB: u8 = 0xFB;
+const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+const OPCODE_SELECTDB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_SET_LISTPACK: u8 = 20;
+
+/// Special encodings of strings, marked by the two highest bits of the length being set.
+const ENCODING_INT8: u64 = 0;
+const ENCODING_INT16: u64 = 1;
+const ENCODING_INT32: u64 = 2;
+const ENCODING_LZF: u64 = 3;
+
+/// Quicklist node stored as a single element instead of a listpack.
+const QUICKLIST_NODE_PLAIN: u64 = 1;
+
+/// Member of a sorted set, along with its score.
+pub type ScoredMember = (Vec<u8>, f64);
+
+/// Value of a key in an RDB file, every type Redis can save is here, even those without a [`StorageType`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum RdbValue {
+    String(Vec<u8>),
+    List(Vec<Vec<u8>>),
+    Set(Vec<Vec<u8>>),
+    SortedSet(Vec<ScoredMember>),
+    Hash(Vec<(Vec<u8>, Vec<u8>)>),
+}
+
+impl RdbValue {
+    /// Map the value into the storage, `None` if the type is not supported by the storage yet.
+    pub fn into_storage_type(self) -> Option<StorageType> {
+        match self {
+            Self::String(string) => Some(StorageType::String(bytes_to_string(&string))),
+            Self::Hash(hash) => Some(StorageType::HashMap(
+                hash.iter()
+                    .map(|(field, value)| (bytes_to_string(field), bytes_to_string(value)))
+                    .collect(),
+            )),
+            Self::List(_) | Self::Set(_) | Self::SortedSet(_) => None,
+        }
+    }
+}
+
+/// A key read from an RDB file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RdbEntry {
+    pub db: u64,
+    pub key: Vec<u8>,
+    pub value: RdbValue,
+    /// Unix time (in milliseconds) the key expires at.
+    pub expire_at: Option<u64>,
+}
+
+/// Everything read from an RDB file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rdb {
+    pub version: u16,
+    /// Auxiliary fields, e.g. `redis-ver`.
+    pub aux: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Code of every function library.
+    pub functions: Vec<Vec<u8>>,
+    pub entries: Vec<RdbEntry>,
+}
+
+/// Write every value and every function library of the snapshot as an RDB file.
+pub fn encode_snapshot<W: Write>(snapshot: &Snapshot, writer: W) -> io::Result<()> {
+    let mut writer = Checksummed::new(writer);
+
+    writer.write_all(format!("REDIS{:04}", RDB_VERSION).as_bytes())?;
+    write_aux(&mut writer, "redis-ver", env!("CARGO_PKG_VERSION"))?;
+    write_aux(&mut writer, "redis-bits", &(usize::BITS).to_string())?;
+    write_aux(&mut writer, "ctime", &(unix_time_ms() / 1000).to_string())?;
+    write_aux(&mut writer, "aof-base", "0")?;
+    write_functions(&mut writer, &snapshot.functions)?;
+
+    // NOTE: There's only one database for now
+    if !snapshot.values.is_empty() {
+        writer.write_all(&[OPCODE_SELECTDB])?;
+        write_length(&mut writer, 0)?;
+        writer.write_all(&[OPCODE_RESIZEDB])?;
+        write_length(&mut writer, snapshot.values.len() as u64)?;
+        write_length(&mut writer, 0)?;
+
+        for (key, value) in &snapshot.values {
+            if let Some(expire_at) = snapshot.expire_at.get(key) {
+                writer.write_all(&[OPCODE_EXPIRETIME_MS])?;
+                writer.write_all(&expire_at.to_le_bytes())?;
+            }
+            write_value_type(&mut writer, value)?;
+            write_string(&mut writer, &string_to_bytes(key))?;
+            write_value(&mut writer, value)?;
+        }
+    }
+
+    writer.write_all(&[OPCODE_EOF])?;
+    let checksum = writer.crc;
+
+    writer.inner.write_all(&checksum.to_le_bytes())
+}
+
+/// Read an RDB file into a snapshot, along with every function library.
+///
+/// Keys that already expired are dropped, while keys of other databases than the first one, and keys
+/// with a type not supported by the storage are skipped, counted in [`Snapshot::skipped`]. Expiry of
+/// the other keys are kept in [`Snapshot::expire_at`].
+pub fn decode_snapshot<R: Read>(reader: R) -> Result<Snapshot, Box<dyn Error>> {
+    let rdb = decode(reader)?;
+    let now = unix_time_ms();
+
+    let mut snapshot = Snapshot {
+        functions: rdb
+            .functions
+            .iter()
+            .map(|code| bytes_to_string(code))
+            .collect(),
+        ..Snapshot::default()
+    };
+    for entry in rdb.entries {
+        if entry.expire_at.is_some_and(|expire_at| expire_at <= now) {
+            continue;
+        }
+
+        let value = match entry.value.into_storage_type() {
+            Some(value) if entry.db == 0 => value,
+            _ => {
+                snapshot.skipped += 1;
+                continue;
+            }
+        };
+
+        let key = bytes_to_string(&entry.key);
+        if let Some(expire_at) = entry.expire_at {
+            snapshot.expire_at.insert(key.clone(), expire_at);
+        }
+        snapshot.values.insert(key, value);
+    }
+
+    Ok(snapshot)
+}
+
+/// Read everything in an RDB file, checking the checksum at the end of it.
+pub fn decode<R: Read>(reader: R) -> Result<Rdb, Box<dyn Error>> {
+    let mut reader = Checksummed::new(reader);
+
+    let mut header = [0u8; 9];
+    reader.read_exact(&mut header)?;
+    if &header[..5] != b"REDIS" {
+        return Err("wrong signature trying to load DB from file".into());
+    }
+
+    let version = std::str::from_utf8(&header[5..])
+        .ok()
+        .and_then(|version| version.parse::<u16>().ok())
+        .filter(|version| (1..=MAX_RDB_VERSION).contains(version))
+        .ok_or("can't handle RDB format version")?;
+
+    let mut rdb = Rdb {
+        version,
+        ..Rdb::default()
+    };
+    let mut db = 0;
+    let mut expire_at = None;
+
+    loop {
+        match read_byte(&mut reader)? {
+            OPCODE_EXPIRETIME_MS => expire_at = Some(read_u64_le(&mut reader)?),
+            OPCODE_EXPIRETIME => {
+                let mut seconds = [0u8; 4];
+                reader.read_exact(&mut seconds)?;
+                expire_at = Some(u32::from_le_bytes(seconds) as u64 * 1000);
+            }
+            OPCODE_IDLE => {
+                read_length(&mut reader)?;
+            }
+            OPCODE_FREQ => {
+                read_byte(&mut reader)?;
+            }
+            OPCODE_SELECTDB => db = read_length(&mut reader)?,
+            OPCODE_RESIZEDB => {
+                read_length(&mut reader)?;
+                read_length(&mut reader)?;
+            }
+            OPCODE_SLOT_INFO => {
+                for _ in 0..3 {
+                    read_length(&mut reader)?;
+                }
+            }
+            OPCODE_AUX => {
+                let key = read_string(&mut reader)?;
+                rdb.aux.push((key, read_string(&mut reader)?));
+            }
+            OPCODE_FUNCTION2 => rdb.functions.push(read_string(&mut reader)?),
+            OPCODE_FUNCTION_PRE_GA => {
+                return Err("pre-release function format is not supported".into())
+            }
+            OPCODE_MODULE_AUX => return Err("module data is not supported".into()),
+            OPCODE_EOF => break,
+            value_type => {
+                let key = read_string(&mut reader)?;
+                let value = read_value(&mut reader, value_type)?;
+
+                rdb.entries.push(RdbEntry {
+                    db,
+                    key,
+                    value,
+                    expire_at: expire_at.take(),
+                });
+            }
+        }
+    }
+
+    // NOTE: Checksum is only there since version 5, and zero means the checksum is disabled
+    if version >= 5 {
+        let checksum = reader.crc;
+        let expected = read_u64_le(&mut reader.inner)?;
+
+        if expected != 0 && expected != checksum {
+            return Err("wrong RDB checksum".into());
+        }
+    }
+
+    Ok(rdb)
+}
+
+/// Write the type of the value, as it's written by [`write_value`].
+pub fn write_value_type<W: Write>(writer: &mut W, value: &StorageType) -> io::Result<()> {
+    let value_type = match value {
+        StorageType::String(_) => TYPE_STRING,
+        StorageType::HashMap(_) => TYPE_HASH,
+    };
+
+    writer.write_all(&[value_type])
+}
+
+/// Write the value without its type, see [`write_value_type`].
+pub fn write_value<W: Write>(writer: &mut W, value: &StorageType) -> io::Result<()> {
+    match value {
+        StorageType::String(string) => write_string(writer, &string_to_bytes(string)),
+        StorageType::HashMap(hash) => {
+            write_length(writer, hash.len() as u64)?;
+
+            for (field, value) in hash {
+                write_string(writer, &string_to_bytes(field))?;
+                write_string(writer, &string_to_bytes(value))?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Read a value of the given type, in any encoding Redis 7 can write, other than streams and modules.
+pub fn read_value<R: Read>(reader: &mut R, value_type: u8) -> Result<RdbValue, Box<dyn Error>> {
+    let value = match value_type {
+        TYPE_STRING => RdbValue::String(read_string(reader)?),
+        TYPE_LIST => RdbValue::List(read_strings(reader)?),
+        TYPE_SET => RdbValue::Set(read_strings(reader)?),
+        TYPE_ZSET | TYPE_ZSET_2 => {
+            let len = read_length(reader)?;
+
+            let mut members = Vec::new();
+            for _ in 0..len {
+                let member = read_string(reader)?;
+                let score = if value_type == TYPE_ZSET_2 {
+                    f64::from_bits(read_u64_le(reader)?)
+                } else {
+                    read_string_double(reader)?
+                };
+
+                members.push((member, score));
+            }
+
+            RdbValue::SortedSet(members)
+        }
+        TYPE_HASH => {
+            let len = read_length(reader)?;
+
+            let mut hash = Vec::new();
+            for _ in 0..len {
+                let field = read_string(reader)?;
+                hash.push((field, read_string(reader)?));
+            }
+
+            RdbValue::Hash(hash)
+        }
+        TYPE_HASH_ZIPMAP => RdbValue::Hash(read_zipmap(&read_string(reader)?)?),
+        TYPE_LIST_ZIPLIST => RdbValue::List(read_ziplist(&read_string(reader)?)?),
+        TYPE_SET_INTSET => RdbValue::Set(read_intset(&read_string(reader)?)?),
+        TYPE_ZSET_ZIPLIST => {
+            RdbValue::SortedSet(into_scores(read_ziplist(&read_string(reader)?)?)?)
+        }
+        TYPE_HASH_ZIPLIST => RdbValue::Hash(into_pairs(read_ziplist(&read_string(reader)?)?)?),
+        TYPE_LIST_QUICKLIST | TYPE_LIST_QUICKLIST_2 => {
+            let nodes = read_length(reader)?;
+
+            let mut list = Vec::new();
+            for _ in 0..nodes {
+                if value_type == TYPE_LIST_QUICKLIST {
+                    list.extend(read_ziplist(&read_string(reader)?)?);
+                } else if read_length(reader)? == QUICKLIST_NODE_PLAIN {
+                    list.push(read_string(reader)?);
+                } else {
+                    list.extend(read_listpack(&read_string(reader)?)?);
+                }
+            }
+
+            RdbValue::List(list)
+        }
+        TYPE_HASH_LISTPACK => RdbValue::Hash(into_pairs(read_listpack(&read_string(reader)?)?)?),
+        TYPE_ZSET_LISTPACK => {
+            RdbValue::SortedSet(into_scores(read_listpack(&read_string(reader)?)?)?)
+        }
+        TYPE_SET_LISTPACK => RdbValue::Set(read_listpack(&read_string(reader)?)?),
+        _ => return Err(format!("unsupported value type {}", value_type).into()),
+    };
+
+    Ok(value)
+}
+
+/// Serialize a value the same way as Redis `DUMP`, the type and the value, followed by the RDB version
+/// and the checksum of everything before it, both little endian.
+pub fn dump_value(value: &StorageType) -> Vec<u8> {
+    let mut payload = Vec::new();
+    // NOTE: Writing into a vector never fails
+    let _ = write_value_type(&mut payload, value).and_then(|_| write_value(&mut payload, value));
+
+    payload.extend_from_slice(&RDB_VERSION.to_le_bytes());
+    let checksum = crc64(0, &payload);
+    payload.extend_from_slice(&checksum.to_le_bytes());
+
+    payload
+}
+
+/// Whether the payload of `DUMP` have a supported RDB version and a valid checksum.
+pub fn verify_dump_payload(payload: &[u8]) -> bool {
+    if payload.len() < 10 {
+        return false;
+    }
+
+    let (data, checksum) = payload.split_at(payload.len() - 8);
+    let version = u16::from_le_bytes([data[data.len() - 2], data[data.len() - 1]]);
+
+    // NOTE: Checksum of zero means the checksum is disabled, the same as in RDB files
+    version <= MAX_RDB_VERSION
+        && checksum
+            .try_into()
+            .map(u64::from_le_bytes)
+            .is_ok_and(|checksum| checksum == 0 || checksum == crc64(0, data))
+}
+
+/// Read the value from the payload of `DUMP`, which should be checked with [`verify_dump_payload`] first.
+pub fn restore_value(payload: &[u8]) -> Result<RdbValue, Box<dyn Error>> {
+    let data = payload
+        .get(..payload.len().saturating_sub(10))
+        .filter(|data| !data.is_empty())
+        .ok_or("payload is too short")?;
+
+    let mut reader = Cursor::new(data);
+    let value_type = read_byte(&mut reader)?;
+    let value = read_value(&mut reader, value_type)?;
+
+    if reader.position() as usize != data.len() {
+        return Err("unexpected data after the value".into());
+    }
+
+    Ok(value)
+}
+
+/// Serialize the code of every function library the same way as Redis `FUNCTION DUMP`, followed by the
+/// RDB version and the checksum, the same as [`dump_value`].
+pub fn dump_functions(codes: &[String]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    // NOTE: Writing into a vector never fails
+    let _ = write_functions(&mut payload, codes);
+
+    payload.extend_from_slice(&RDB_VERSION.to_le_bytes());
+    let checksum = crc64(0, &payload);
+    payload.extend_from_slice(&checksum.to_le_bytes());
+
+    payload
+}
+
+/// Read the code of every function library from the payload of `FUNCTION DUMP`, which should be checked
+/// with [`verify_dump_payload`] first.
+pub fn restore_functions(payload: &[u8]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+    let data = &payload[..payload.len().saturating_sub(10)];
+
+    let mut reader = Cursor::new(data);
+    let mut codes = Vec::new();
+    while (reader.position() as usize) < data.len() {
+        match read_byte(&mut reader)? {
+            OPCODE_FUNCTION2 => codes.push(read_string(&mut reader)?),
+            _ => return Err("given type is not a function".into()),
+        }
+    }
+
+    Ok(codes)
+}
+
+fn write_functions<W: Write>(writer: &mut W, codes: &[String]) -> io::Result<()> {
+    for code in codes {
+        writer.write_all(&[OPCODE_FUNCTION2])?;
+        write_string(writer, &string_to_bytes(code))?;
+    }
+
+    Ok(())
+}
+
+fn write_aux<W: Write>(writer: &mut W, key: &str, value: &str) -> io::Result<()> {
+    writer.write_all(&[OPCODE_AUX])?;
+    write_string(writer, key.as_bytes())?;
+    write_string(writer, value.as_bytes())
+}
+
+/// Write a length, using as few bytes as the length needs.
+fn write_length<W: Write>(writer: &mut W, len: u64) -> io::Result<()> {
+    if len < 1 << 6 {
+        writer.write_all(&[len as u8])
+    } else if len < 1 << 14 {
+        writer.write_all(&[0x40 | (len >> 8) as u8, len as u8])
+    } else if len <= u32::MAX as u64 {
+        writer.write_all(&[0x80])?;
+        writer.write_all(&(len as u32).to_be_bytes())
+    } else {
+        writer.write_all(&[0x81])?;
+        writer.write_all(&len.to_be_bytes())
+    }
+}
+
+/// Write a string, encoded as an integer or compressed with LZF whenever it's smaller, the same as Redis.
+fn write_string<W: Write>(writer: &mut W, string: &[u8]) -> io::Result<()> {
+    if string.len() <= 11 {
+        if let Some(integer) = std::str::from_utf8(string)
+            .ok()
+            .and_then(|string| string.parse::<i32>().ok())
+            .filter(|integer| integer.to_string().as_bytes() == string)
+        {
+            return if let Ok(integer) = i8::try_from(integer) {
+                writer.write_all(&[0xC0 | ENCODING_INT8 as u8])?;
+                writer.write_all(&integer.to_le_bytes())
+            } else if let Ok(integer) = i16::try_from(integer) {
+                writer.write_all(&[0xC0 | ENCODING_INT16 as u8])?;
+                writer.write_all(&integer.to_le_bytes())
+            } else {
+                writer.write_all(&[0xC0 | ENCODING_INT32 as u8])?;
+                writer.write_all(&integer.to_le_bytes())
+            };
+        }
+    }
+
+    if string.len() > 20 {
+        if let Some(compressed) =
+            lzf_compress(string).filter(|compressed| compressed.len() + 4 <= string.len())
+        {
+            writer.write_all(&[0xC0 | ENCODING_LZF as u8])?;
+            write_length(writer, compressed.len() as u64)?;
+            write_length(writer, string.len() as u64)?;
+
+            return writer.write_all(&compressed);
+        }
+    }
+
+    write_length(writer, string.len() as u64)?;
+    writer.write_all(string)
+}
+
+/// Read a length, along with whether it's a special encoding of a string instead of a length.
+fn read_length_encoding<R: Read>(reader: &mut R) -> io::Result<(u64, bool)> {
+    let first = read_byte(reader)?;
+
+    match first >> 6 {
+        0 => Ok(((first & 0x3F) as u64, false)),
+        1 => Ok((
+            (((first & 0x3F) as u64) << 8) | read_byte(reader)? as u64,
+            false,
+        )),
+        2 if first == 0x80 => {
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+
+            Ok((u32::from_be_bytes(len) as u64, false))
+        }
+        2 if first == 0x81 => {
+            let mut len = [0u8; 8];
+            reader.read_exact(&mut len)?;
+
+            Ok((u64::from_be_bytes(len), false))
+        }
+        2 => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "unknown length encoding",
+        )),
+        _ => Ok(((first & 0x3F) as u64, true)),
+    }
+}
+
+fn read_length<R: Read>(reader: &mut R) -> io::Result<u64> {
+    match read_length_encoding(reader)? {
+        (len, false) => Ok(len),
+        (_, true) => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "length was expected",
+        )),
+    }
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let string = match read_length_encoding(reader)? {
+        (len, false) => read_bytes(reader, len)?,
+        (ENCODING_INT8, true) => (read_byte(reader)? as i8).to_string().into_bytes(),
+        (ENCODING_INT16, true) => {
+            let mut integer = [0u8; 2];
+            reader.read_exact(&mut integer)?;
+
+            i16::from_le_bytes(integer).to_string().into_bytes()
+        }
+        (ENCODING_INT32, true) => {
+            let mut integer = [0u8; 4];
+            reader.read_exact(&mut integer)?;
+
+            i32::from_le_bytes(integer).to_string().into_bytes()
+        }
+        (ENCODING_LZF, true) => {
+            let compressed_len = read_length(reader)?;
+            let len = read_length(reader)?;
+            let compressed = read_bytes(reader, compressed_len)?;
+
+            lzf_decompress(&compressed, len as usize)
+                .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "invalid LZF string"))?
+        }
+        _ => {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "unknown string encoding",
+            ))
+        }
+    };
+
+    Ok(string)
+}
+
+fn read_strings<R: Read>(reader: &mut R) -> io::Result<Vec<Vec<u8>>> {
+    (0..read_length(reader)?)
+        .map(|_| read_string(reader))
+        .collect()
+}
+
+/// Read a score of the old sorted set type, written as a length prefixed string.
+fn read_string_double<R: Read>(reader: &mut R) -> Result<f64, Box<dyn Error>> {
+    let score = match read_byte(reader)? {
+        253 => f64::NAN,
+        254 => f64::INFINITY,
+        255 => f64::NEG_INFINITY,
+        len => parse_double(&read_bytes(reader, len as u64)?)?,
+    };
+
+    Ok(score)
+}
+
+fn parse_double(bytes: &[u8]) -> Result<f64, Box<dyn Error>> {
+    Ok(std::str::from_utf8(bytes)?.parse::<f64>()?)
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+
+    Ok(byte[0])
+}
+
+fn read_u64_le<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut integer = [0u8; 8];
+    reader.read_exact(&mut integer)?;
+
+    Ok(u64::from_le_bytes(integer))
+}
+
+/// Read exactly `len` bytes. The length comes from the data itself (e.g. the payload of `RESTORE`), so
+/// nothing is allocated up front, the bytes are only kept as they're actually read.
+fn read_bytes<R: Read>(reader: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+
+    if bytes.len() as u64 != len {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(bytes)
+}
+
+fn read_signed<R: Read, const N: usize>(reader: &mut R) -> io::Result<i64> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+
+    // Sign extend from the highest bit of the last (little endian) byte
+    let mut integer = [if bytes[N - 1] & 0x80 != 0 { 0xFF } else { 0 }; 8];
+    integer[..N].copy_from_slice(&bytes);
+
+    Ok(i64::from_le_bytes(integer))
+}
+
+/// Every element of a ziplist, the compact list encoding used before Redis 7.
+fn read_ziplist(ziplist: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let mut reader = Cursor::new(ziplist);
+    // NOTE: Total bytes, offset to the last element, and number of elements, none of it is needed
+    read_bytes(&mut reader, 10)?;
+
+    let mut elements = Vec::new();
+    loop {
+        let previous_len = read_byte(&mut reader)?;
+        if previous_len == 0xFF {
+            return Ok(elements);
+        }
+        if previous_len == 0xFE {
+            read_bytes(&mut reader, 4)?;
+        }
+
+        let encoding = read_byte(&mut reader)?;
+        let element = match encoding >> 6 {
+            0 => read_bytes(&mut reader, (encoding & 0x3F) as u64)?,
+            1 => {
+                let len = (((encoding & 0x3F) as u64) << 8) | read_byte(&mut reader)? as u64;
+
+                read_bytes(&mut reader, len)?
+            }
+            2 => {
+                let mut len = [0u8; 4];
+                reader.read_exact(&mut len)?;
+
+                read_bytes(&mut reader, u32::from_be_bytes(len) as u64)?
+            }
+            _ => {
+                let integer = match encoding {
+                    0xC0 => read_signed::<_, 2>(&mut reader)?,
+                    0xD0 => read_signed::<_, 4>(&mut reader)?,
+                    0xE0 => read_signed::<_, 8>(&mut reader)?,
+                    0xF0 => read_signed::<_, 3>(&mut reader)?,
+                    0xFE => read_signed::<_, 1>(&mut reader)?,
+                    0xF1..=0xFD => (encoding & 0x0F) as i64 - 1,
+                    _ => {
+                        return Err(io::Error::new(
+                            ErrorKind::InvalidData,
+                            "unknown ziplist encoding",
+                        ))
+                    }
+                };
+
+                integer.to_string().into_bytes()
+            }
+        };
+
+        elements.push(element);
+    }
+}
+
+/// Every element of a listpack, the compact encoding of small collections since Redis 7.
+fn read_listpack(listpack: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let mut reader = Cursor::new(listpack);
+    // NOTE: Total bytes and number of elements, none of it is needed
+    read_bytes(&mut reader, 6)?;
+
+    let mut elements = Vec::new();
+    loop {
+        let encoding = read_byte(&mut reader)?;
+        if encoding == 0xFF {
+            return Ok(elements);
+        }
+
+        let (element, entry_len) = if encoding & 0x80 == 0 {
+            ((encoding & 0x7F).to_string().into_bytes(), 1)
+        } else if encoding & 0xC0 == 0x80 {
+            let len = (encoding & 0x3F) as u64;
+
+            (read_bytes(&mut reader, len)?, 1 + len)
+        } else if encoding & 0xE0 == 0xC0 {
+            let unsigned = (((encoding & 0x1F) as i64) << 8) | read_byte(&mut reader)? as i64;
+            // Sign extend from 13 bits
+            let integer = if unsigned & 0x1000 != 0 {
+                unsigned - (1 << 13)
+            } else {
+                unsigned
+            };
+
+            (integer.to_string().into_bytes(), 2)
+        } else if encoding & 0xF0 == 0xE0 {
+            let len = (((encoding & 0x0F) as u64) << 8) | read_byte(&mut reader)? as u64;
+
+            (read_bytes(&mut reader, len)?, 2 + len)
+        } else {
+            let (integer, len) = match encoding {
+                0xF0 => {
+                    let mut len = [0u8; 4];
+                    reader.read_exact(&mut len)?;
+                    let len = u32::from_le_bytes(len) as u64;
+
+                    elements.push(read_bytes(&mut reader, len)?);
+                    skip_listpack_backlen(&mut reader, 5 + len)?;
+
+                    continue;
+                }
+                0xF1 => (read_signed::<_, 2>(&mut reader)?, 2),
+                0xF2 => (read_signed::<_, 3>(&mut reader)?, 3),
+                0xF3 => (read_signed::<_, 4>(&mut reader)?, 4),
+                0xF4 => (read_signed::<_, 8>(&mut reader)?, 8),
+                _ => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "unknown listpack encoding",
+                    ))
+                }
+            };
+
+            (integer.to_string().into_bytes(), 1 + len)
+        };
+
+        elements.push(element);
+        skip_listpack_backlen(&mut reader, entry_len)?;
+    }
+}
+
+/// Skip the length of the entry written after every listpack entry, used to iterate backward.
+fn skip_listpack_backlen<R: Read>(reader: &mut R, entry_len: u64) -> io::Result<()> {
+    let backlen_len = match entry_len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    };
+
+    read_bytes(reader, backlen_len).map(|_| ())
+}
+
+/// Every integer of an intset, the encoding of sets with only integers.
+fn read_intset(intset: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let mut reader = Cursor::new(intset);
+
+    let mut header = [0u8; 8];
+    reader.read_exact(&mut header)?;
+    let encoding = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+    (0..len)
+        .map(|_| {
+            let integer = match encoding {
+                2 => read_signed::<_, 2>(&mut reader)?,
+                4 => read_signed::<_, 4>(&mut reader)?,
+                8 => read_signed::<_, 8>(&mut reader)?,
+                _ => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "unknown intset encoding",
+                    ))
+                }
+            };
+
+            Ok(integer.to_string().into_bytes())
+        })
+        .collect()
+}
+
+/// Every field and value of a zipmap, the encoding of small hashes before Redis 2.6.
+fn read_zipmap(zipmap: &[u8]) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    fn read_zipmap_len<R: Read>(reader: &mut R) -> io::Result<Option<u64>> {
+        match read_byte(reader)? {
+            0xFF => Ok(None),
+            0xFE => {
+                let mut len = [0u8; 4];
+                reader.read_exact(&mut len)?;
+
+                Ok(Some(u32::from_le_bytes(len) as u64))
+            }
+            len => Ok(Some(len as u64)),
+        }
+    }
+
+    let mut reader = Cursor::new(zipmap);
+    read_byte(&mut reader)?;
+
+    let mut hash = Vec::new();
+    while let Some(field_len) = read_zipmap_len(&mut reader)? {
+        let field = read_bytes(&mut reader, field_len)?;
+        let value_len = read_zipmap_len(&mut reader)?
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "zipmap value was expected"))?;
+        let free = read_byte(&mut reader)?;
+        let value = read_bytes(&mut reader, value_len)?;
+        read_bytes(&mut reader, free as u64)?;
+
+        hash.push((field, value));
+    }
+
+    Ok(hash)
+}
+
+/// Pair up flattened elements, e.g. field and value of a hash.
+fn into_pairs(elements: Vec<Vec<u8>>) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    if !elements.len().is_multiple_of(2) {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "odd number of elements",
+        ));
+    }
+
+    let mut elements = elements.into_iter();
+    let mut pairs = Vec::new();
+    while let (Some(first), Some(second)) = (elements.next(), elements.next()) {
+        pairs.push((first, second));
+    }
+
+    Ok(pairs)
+}
+
+fn into_scores(elements: Vec<Vec<u8>>) -> Result<Vec<ScoredMember>, Box<dyn Error>> {
+    into_pairs(elements)?
+        .into_iter()
+        .map(|(member, score)| Ok((member, parse_double(&score)?)))
+        .collect()
+}
+
+/// Compress with LZF, the compression used for strings in RDB files.
+///
+/// Returns `None` if the input is too short to be compressed.
+pub fn lzf_compress(input: &[u8]) -> Option<Vec<u8>> {
+    const HASH_LOG: usize = 14;
+    const MAX_OFFSET: usize = 1 << 13;
+    const MAX_LITERAL: usize = 32;
+    const MAX_MATCH: usize = 264;
+
+    if input.len() < 3 {
+        return None;
+    }
+
+    // Position (plus one) of the last 3 bytes sequence with the same hash, zero means nothing yet
+    let mut table = vec![0usize; 1 << HASH_LOG];
+    let mut output = Vec::with_capacity(input.len());
+    let mut literal_start = output.len();
+    let mut literal_len = 0;
+    output.push(0);
+
+    let mut position = 0;
+    while position < input.len() {
+        let matched = if position + 2 < input.len() {
+            let sequence = &input[position..position + 3];
+            let hash =
+                ((sequence[0] as usize) << 16 | (sequence[1] as usize) << 8 | sequence[2] as usize)
+                    .wrapping_mul(2654435761)
+                    >> 8
+                    & ((1 << HASH_LOG) - 1);
+            let candidate = table[hash];
+            table[hash] = position + 1;
+
+            candidate
+                .checked_sub(1)
+                .filter(|candidate| position - candidate <= MAX_OFFSET)
+                .filter(|candidate| &input[*candidate..*candidate + 3] == sequence)
+        } else {
+            None
+        };
+
+        match matched {
+            Some(candidate) => {
+                let mut len = 3;
+                while len < MAX_MATCH
+                    && position + len < input.len()
+                    && input[candidate + len] == input[position + len]
+                {
+                    len += 1;
+                }
+
+                if literal_len > 0 {
+                    output[literal_start] = (literal_len - 1) as u8;
+                } else {
+                    output.pop();
+                }
+
+                let offset = position - candidate - 1;
+                let stored_len = len - 2;
+                if stored_len < 7 {
+                    output.push(((stored_len << 5) | (offset >> 8)) as u8);
+                } else {
+                    output.push(((7 << 5) | (offset >> 8)) as u8);
+                    output.push((stored_len - 7) as u8);
+                }
+                output.push(offset as u8);
+
+                position += len;
+                literal_start = output.len();
+                literal_len = 0;
+                output.push(0);
+            }
+            None => {
+                output.push(input[position]);
+                literal_len += 1;
+                position += 1;
+
+                if literal_len == MAX_LITERAL {
+                    output[literal_start] = (MAX_LITERAL - 1) as u8;
+                    literal_start = output.len();
+                    literal_len = 0;
+                    output.push(0);
+                }
+            }
+        }
+    }
+
+    if literal_len > 0 {
+        output[literal_start] = (literal_len - 1) as u8;
+    } else {
+        output.pop();
+    }
+
+    Some(output)
+}
+
+/// Decompress LZF compressed bytes, `None` if it's not valid or not of the expected length.
+///
+/// The expected length is read from the same untrusted data (e.g. the payload of `RESTORE`), so only as
+/// much as the input can usually expand to is allocated up front.
+pub fn lzf_decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(len.min(input.len().saturating_mul(8)));
+    let mut position = 0;
+
+    while position < input.len() {
+        let control = input[position] as usize;
+        position += 1;
+
+        if control < 32 {
+            let literal = input.get(position..position + control + 1)?;
+            output.extend_from_slice(literal);
+            position += control + 1;
+        } else {
+            let mut match_len = control >> 5;
+            if match_len == 7 {
+                match_len += *input.get(position)? as usize;
+                position += 1;
+            }
+
+            let offset = ((control & 0x1F) << 8) + *input.get(position)? as usize + 1;
+            position += 1;
+
+            let start = output.len().checked_sub(offset)?;
+            // NOTE: Byte by byte, as the match can overlap with what's being written
+            for index in start..start + match_len + 2 {
+                output.push(output[index]);
+            }
+        }
+
+        if output.len() > len {
+            return None;
+        }
+    }
+
+    (output.len() == len).then_some(output)
+}
+
+/// CRC64 (Jones variant) lookup table, computed at compile time.
+const CRC64_TABLE: [u64; 256] = {
+    // NOTE: Polynomial 0xad93d23594c935a9, reflected
+    const POLYNOMIAL: u64 = 0x95ac9329ac4bc9b5;
+
+    let mut table = [0u64; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[index] = crc;
+        index += 1;
+    }
+
+    table
+};
+
+/// CRC64 (Jones variant) used by Redis to checksum RDB files, continuing from a previous `crc`.
+pub fn crc64(crc: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(crc, |crc, byte| {
+        CRC64_TABLE[((crc ^ *byte as u64) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Reader or writer keeping the checksum of every byte that goes through it.
+struct Checksummed<T> {
+    inner: T,
+    crc: u64,
+}
+
+impl<T> Checksummed<T> {
+    fn new(inner: T) -> Self {
+        Self { inner, crc: 0 }
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.crc = crc64(self.crc, &buf[..len]);
+
+        Ok(len)
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.crc = crc64(self.crc, &buf[..len]);
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod rdb_tests {
+    use std::collections::HashMap;
+
+    use super::{
+        crc64, decode, decode_snapshot, encode_snapshot, lzf_compress, lzf_decompress, RdbValue,
+    };
+    use crate::storage::{Snapshot, StorageType};
+
+    /// RDB file around the given body, with the header and the checksum.
+    fn rdb_file(body: &[u8]) -> Vec<u8> {
+        let mut file = b"REDIS0011".to_vec();
+        file.extend_from_slice(body);
+        file.push(0xFF);
+        let checksum = crc64(0, &file);
+        file.extend_from_slice(&checksum.to_le_bytes());
+
+        file
+    }
+
+    #[test]
+    fn crc64_matches_reference() {
+        assert_eq!(crc64(0, b"123456789"), 0xe9c6d914c4b8d9ca);
+    }
+
+    #[test]
+    fn lzf_round_trip() {
+        let input = b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa hello hello hello world";
+        let compressed = lzf_compress(input).unwrap();
+        assert!(compressed.len() < input.len());
+        assert_eq!(lzf_decompress(&compressed, input.len()).unwrap(), input);
+
+        // Literal "abc", then a back reference of 6 bytes overlapping with itself
+        assert_eq!(
+            lzf_decompress(&[0x02, b'a', b'b', b'c', 0x80, 0x02], 9).unwrap(),
+            b"abcabcabc"
+        );
+        assert_eq!(
+            lzf_decompress(&[0x02, b'a', b'b', b'c', 0x80, 0x02], 1 << 62),
+            None
+        );
+    }
+
+    #[test]
+    fn decodes_redis_7_encodings() {
+        let body = [
+            // AUX redis-ver 7.2.4
+            &[0xFA, 0x09][..],
+            b"redis-ver",
+            &[0x05],
+            b"7.2.4",
+            // SELECTDB 0, RESIZEDB
+            &[0xFE, 0x00, 0xFB, 0x05, 0x01],
+            // String encoded as 16 bits integer
+            &[0x00, 0x03],
+            b"int",
+            &[0xC1, 0x39, 0x30],
+            // Hash as listpack: field "a", value 1, field "b", value -2
+            &[0x10, 0x01],
+            b"h",
+            &[
+                0x12, 0x12, 0x00, 0x00, 0x00, 0x04, 0x00, 0x81, b'a', 0x02, 0x01, 0x01, 0x81, b'b',
+                0x02, 0xDF, 0xFE, 0x02, 0xFF,
+            ],
+            // List as quicklist with a listpack node
+            &[0x12, 0x01],
+            b"l",
+            &[
+                0x01, 0x02, 0x0C, 0x0C, 0x00, 0x00, 0x00, 0x02, 0x00, 0x81, b'x', 0x02, 0x05, 0x01,
+                0xFF,
+            ],
+            // Set as intset, with 16 bits integers
+            &[0x0B, 0x01],
+            b"s",
+            &[
+                0x0C, 0x02, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0x01, 0x00,
+            ],
+            // String with an expiry long gone
+            &[
+                0xFC, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+            ],
+            b"e",
+            &[0x01],
+            b"v",
+        ]
+        .concat();
+        let file = rdb_file(&body);
+
+        let rdb = decode(&file[..]).unwrap();
+        assert_eq!(rdb.version, 11);
+        assert_eq!(rdb.aux, vec![(b"redis-ver".to_vec(), b"7.2.4".to_vec())]);
+        assert_eq!(
+            rdb.entries
+                .iter()
+                .map(|entry| entry.value.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                RdbValue::String(b"12345".to_vec()),
+                RdbValue::Hash(vec![
+                    (b"a".to_vec(), b"1".to_vec()),
+                    (b"b".to_vec(), b"-2".to_vec()),
+                ]),
+                RdbValue::List(vec![b"x".to_vec(), b"5".to_vec()]),
+                RdbValue::Set(vec![b"-1".to_vec(), b"1".to_vec()]),
+                RdbValue::String(b"v".to_vec()),
+            ]
+        );
+        assert_eq!(rdb.entries[4].expire_at, Some(1));
+
+        let snapshot = decode_snapshot(&file[..]).unwrap();
+        assert_eq!(snapshot.values.len(), 2);
+        // The list and the set
+        assert_eq!(snapshot.skipped, 2);
+        assert!(snapshot.expire_at.is_empty());
+        assert!(
+            matches!(snapshot.values.get("int"), Some(StorageType::String(string)) if string == "12345")
+        );
+
+        let mut corrupted = file.clone();
+        let len = corrupted.len();
+        corrupted[len - 1] ^= 1;
+        assert!(decode(&corrupted[..]).is_err());
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let long = "compress me ".repeat(10);
+        let mut snapshot = Snapshot {
+            functions: vec!["#!lua name=lib\nredis.register_function('f', function() end)".into()],
+            ..Snapshot::default()
+        };
+        snapshot
+            .values
+            .insert("string".into(), StorageType::String("caf\u{e9}\r\n".into()));
+        snapshot
+            .values
+            .insert("integer".into(), StorageType::String("-1000000".into()));
+        snapshot
+            .values
+            .insert("long".into(), StorageType::String(long.clone()));
+        snapshot.values.insert(
+            "hash".into(),
+            StorageType::HashMap(HashMap::from([("field".into(), "value".into())])),
+        );
+        snapshot.expire_at.insert("long".into(), u64::MAX);
+
+        let mut bytes = Vec::new();
+        encode_snapshot(&snapshot, &mut bytes).unwrap();
+        assert!(bytes.starts_with(b"REDIS0011"));
+
+        let decoded = decode_snapshot(&bytes[..]).unwrap();
+        assert_eq!(decoded.functions, snapshot.functions);
+        assert_eq!(decoded.expire_at, snapshot.expire_at);
+        assert_eq!(decoded.skipped, 0);
+        let decoded = decoded.values;
+        assert_eq!(decoded.len(), 4);
+        assert!(matches!(
+            decoded.get("string"),
+            Some(StorageType::String(string)) if string == "caf\u{e9}\r\n"
+        ));
+        assert!(matches!(
+            decoded.get("integer"),
+            Some(StorageType::String(string)) if string == "-1000000"
+        ));
+        assert!(
+            matches!(decoded.get("long"), Some(StorageType::String(string)) if *string == long)
+        );
+        assert!(matches!(
+            decoded.get("hash"),
+            Some(StorageType::HashMap(hash)) if hash.get("field").map(String::as_str) == Some("value")
+        ));
+    }
+}
@@ -0,0 +1,895 @@
+use std::{
+    collections::{hash_map::RandomState, VecDeque},
+    error::Error,
+    hash::{BuildHasher, Hasher},
+    io::{self, Read},
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicBool, AtomicU16, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    client::Client, commands::commands::handle_commands, connection::Connection,
+    rdb::decode_snapshot, resp::RespType, server::Server, storage::Snapshot,
+};
+
+/// Default size of the replication backlog, the same as Redis.
+pub const DEFAULT_BACKLOG_SIZE: usize = 1024 * 1024;
+
+/// How long the follower waits before connecting to the leader again, after the link is lost.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the follower acknowledges the replication offset it processed to the leader.
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the leader pings its followers, so followers can tell an idle leader from a dead one.
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+/// How long the follower waits for anything from the leader before dropping the link.
+const LINK_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the follower waits for data from the leader before checking if it's still a follower.
+const LINK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Role of the server in the replication topology.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Role {
+    Leader,
+    Follower { host: String, port: u16 },
+}
+
+/// State of the link from a follower to its leader, named the same as Redis in `ROLE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// Waiting to connect to the leader.
+    Connect,
+    /// Connected, doing the handshake.
+    Connecting,
+    /// Receiving the snapshot of a full sync.
+    Sync,
+    /// Receiving every write command from the leader.
+    Connected,
+}
+
+impl LinkState {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Connect => "connect",
+            Self::Connecting => "connecting",
+            Self::Sync => "sync",
+            Self::Connected => "connected",
+        }
+    }
+}
+
+/// How a follower is synced with the leader, before the write commands after it are streamed.
+#[derive(Debug)]
+pub enum ReplicaSync {
+    /// Snapshot of every key, to be sent in the RDB format.
+    Full(Snapshot),
+    /// Every byte in the backlog the follower is missing.
+    Partial(Vec<u8>),
+}
+
+/// Link from the leader to a follower, set on the client of the follower once it's accepted by `PSYNC`.
+#[derive(Debug)]
+pub struct ReplicaLink {
+    pub sync: ReplicaSync,
+    /// Every write command propagated after the sync, already serialized.
+    pub receiver: Receiver<Vec<u8>>,
+}
+
+/// A follower connected to this server.
+#[derive(Debug, Clone)]
+pub struct ReplicaInfo {
+    pub client_id: u64,
+    pub ip: String,
+    pub listening_port: u16,
+    /// Replication offset acknowledged by the follower with `REPLCONF ACK`.
+    pub ack_offset: u64,
+    /// Replication offset flushed to the append only file of the follower, acknowledged with `FACK`.
+    pub aof_ack_offset: u64,
+    sender: Sender<Vec<u8>>,
+}
+
+/// What followers acknowledged, waited for by `WAIT` and `WAITAOF`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckKind {
+    /// Write commands processed by the follower.
+    Processed,
+    /// Write commands flushed to the append only file of the follower.
+    Fsynced,
+}
+
+#[derive(Debug)]
+struct ReplicationState {
+    role: Role,
+    link_state: LinkState,
+    /// Bumped whenever the role changes, so the link to the previous leader knows it should stop.
+    generation: u64,
+    /// Id of the replication history, shared by the leader and every follower.
+    replid: String,
+    /// Id of the previous replication history, e.g. before a follower is promoted, so followers of the
+    /// previous leader can still partially resync with the promoted follower.
+    replid2: String,
+    /// Last offset (plus one) of the previous replication history.
+    second_replid_offset: Option<u64>,
+    /// Number of bytes of write commands propagated in the replication history.
+    offset: u64,
+    /// Last bytes of write commands propagated, for followers to partially resync.
+    backlog: VecDeque<u8>,
+    backlog_size: usize,
+    replicas: Vec<ReplicaInfo>,
+    last_ping: Instant,
+}
+
+/// Leader/follower replication.
+///
+/// The leader propagate every write command to its followers, after a full sync (a snapshot of every key)
+/// or a partial sync (the missing bytes from the backlog) when a follower connects with `PSYNC`. Followers
+/// then stream the exact same bytes to their own followers, so the replication offset is the same
+/// everywhere.
+#[derive(Debug)]
+pub struct Replication {
+    state: Mutex<ReplicationState>,
+    role_changed: Condvar,
+    /// Notified whenever a follower acknowledges its replication offset.
+    acked: Condvar,
+    /// `replica-read-only`, whether followers refuse write commands from clients other than the leader.
+    pub read_only: AtomicBool,
+    /// Port this server listens on, announced to the leader with `REPLCONF listening-port`.
+    pub listening_port: AtomicU16,
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(ReplicationState {
+                role: Role::Leader,
+                link_state: LinkState::Connect,
+                generation: 0,
+                replid: random_replid(),
+                replid2: "0".repeat(40),
+                second_replid_offset: None,
+                offset: 0,
+                backlog: VecDeque::new(),
+                backlog_size: DEFAULT_BACKLOG_SIZE,
+                replicas: Vec::new(),
+                last_ping: Instant::now(),
+            }),
+            role_changed: Condvar::new(),
+            acked: Condvar::new(),
+            read_only: AtomicBool::new(true),
+            listening_port: AtomicU16::new(0),
+        }
+    }
+}
+
+impl Replication {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn role(&self) -> Role {
+        self.lock_state().role.clone()
+    }
+
+    pub fn link_state(&self) -> LinkState {
+        self.lock_state().link_state
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.lock_state().offset
+    }
+
+    pub fn replid(&self) -> String {
+        self.lock_state().replid.clone()
+    }
+
+    pub fn replicas(&self) -> Vec<ReplicaInfo> {
+        self.lock_state().replicas.clone()
+    }
+
+    pub fn backlog_size(&self) -> usize {
+        self.lock_state().backlog_size
+    }
+
+    pub fn set_backlog_size(&self, size: usize) {
+        let mut state = self.lock_state();
+        state.backlog_size = size;
+
+        let excess = state.backlog.len().saturating_sub(size);
+        state.backlog.drain(..excess);
+    }
+
+    /// Whether write commands from clients (other than the leader) should be refused.
+    pub fn is_read_only_follower(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+            && matches!(self.lock_state().role, Role::Follower { .. })
+    }
+
+    /// Propagate a write command to the followers, only done by the leader, as followers stream what they
+    /// get from their leader as is, see [`Replication::feed`].
+    pub fn propagate(&self, command: &[RespType]) {
+        let mut state = self.lock_state();
+
+        if state.role == Role::Leader {
+            feed_locked(&mut state, &RespType::Array(command.to_vec()).serialize());
+        }
+    }
+
+    /// Add bytes to the replication history, streaming it to every follower.
+    pub fn feed(&self, bytes: &[u8]) {
+        feed_locked(&mut self.lock_state(), bytes);
+    }
+
+    /// Ping the followers every once in a while, so followers know the leader is still alive.
+    pub fn ping_replicas(&self) {
+        let mut state = self.lock_state();
+
+        if state.role == Role::Leader
+            && !state.replicas.is_empty()
+            && state.last_ping.elapsed() >= PING_INTERVAL
+        {
+            state.last_ping = Instant::now();
+            feed_locked(
+                &mut state,
+                &RespType::Array(vec![RespType::BulkString("PING".into())]).serialize(),
+            );
+        }
+    }
+
+    /// Accept a follower requesting a sync with `PSYNC`, partially if the follower's history is still in
+    /// the backlog, otherwise fully with a snapshot of the storage.
+    ///
+    /// Should be called while the storage is locked, so the snapshot and the replication offset matches.
+    /// The snapshot is only taken for a full sync.
+    pub fn attach_replica(
+        &self,
+        client: &Client,
+        replid: &str,
+        psync_offset: Option<u64>,
+        snapshot: impl FnOnce() -> Snapshot,
+    ) -> Result<(RespType, ReplicaLink), RespType> {
+        let mut state = self.lock_state();
+
+        if matches!(state.role, Role::Follower { .. }) && state.link_state != LinkState::Connected {
+            return Err(RespType::Error(
+                "NOMASTERLINK Can't SYNC while not connected with my master".into(),
+            ));
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let backlog_start = state.offset + 1 - state.backlog.len() as u64;
+
+        let same_history = replid == state.replid
+            || (replid == state.replid2
+                && psync_offset
+                    .zip(state.second_replid_offset)
+                    .is_some_and(|(offset, second_offset)| offset <= second_offset));
+
+        let (reply, sync) = match psync_offset {
+            Some(offset)
+                if same_history && backlog_start <= offset && offset <= state.offset + 1 =>
+            {
+                let missing = state
+                    .backlog
+                    .iter()
+                    .skip((offset - backlog_start) as usize)
+                    .copied()
+                    .collect();
+
+                (
+                    RespType::String(format!("CONTINUE {}", state.replid)),
+                    ReplicaSync::Partial(missing),
+                )
+            }
+            _ => (
+                RespType::String(format!("FULLRESYNC {} {}", state.replid, state.offset)),
+                ReplicaSync::Full(snapshot()),
+            ),
+        };
+
+        state
+            .replicas
+            .retain(|replica| replica.client_id != client.id);
+        state.replicas.push(ReplicaInfo {
+            client_id: client.id,
+            ip: client.ip().to_string(),
+            listening_port: client.listening_port.unwrap_or_default(),
+            ack_offset: 0,
+            aof_ack_offset: 0,
+            sender,
+        });
+
+        Ok((reply, ReplicaLink { sync, receiver }))
+    }
+
+    /// Record the replication offset acknowledged by a follower, processed and/or flushed to its append
+    /// only file.
+    pub fn acknowledge(&self, client_id: u64, offset: Option<u64>, aof_offset: Option<u64>) {
+        let mut state = self.lock_state();
+
+        if let Some(replica) = state
+            .replicas
+            .iter_mut()
+            .find(|replica| replica.client_id == client_id)
+        {
+            replica.ack_offset = replica.ack_offset.max(offset.unwrap_or_default());
+            replica.aof_ack_offset = replica.aof_ack_offset.max(aof_offset.unwrap_or_default());
+            self.acked.notify_all();
+        }
+    }
+
+    /// Number of followers that acknowledged the replication offset.
+    pub fn acked_replicas(&self, offset: u64, kind: AckKind) -> usize {
+        count_acked(&self.lock_state(), offset, kind)
+    }
+
+    /// Wait until at least `numreplicas` followers acknowledged the replication offset, or until the
+    /// deadline passed (`None` to wait forever), returning the number of followers that acknowledged it.
+    ///
+    /// Followers are asked to acknowledge right away with `REPLCONF GETACK`, instead of waiting for them
+    /// to acknowledge on their own every second.
+    pub fn wait_for_acks(
+        &self,
+        offset: u64,
+        numreplicas: usize,
+        deadline: Option<Instant>,
+        kind: AckKind,
+    ) -> usize {
+        let mut state = self.lock_state();
+        let mut getack_sent = false;
+
+        loop {
+            let acked = count_acked(&state, offset, kind);
+            if acked >= numreplicas {
+                return acked;
+            }
+
+            if !getack_sent {
+                feed_locked(
+                    &mut state,
+                    &command(&["REPLCONF", "GETACK", "*"]).serialize(),
+                );
+                getack_sent = true;
+            }
+
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return acked;
+                    }
+
+                    self.acked
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0
+                }
+                None => self
+                    .acked
+                    .wait(state)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            };
+        }
+    }
+
+    pub fn detach_replica(&self, client_id: u64) {
+        self.lock_state()
+            .replicas
+            .retain(|replica| replica.client_id != client_id);
+    }
+
+    /// Become a follower of the leader, dropping every follower of this server so they sync again.
+    ///
+    /// Returns `false` if this server is already following the same leader.
+    pub fn follow(&self, host: &str, port: u16) -> bool {
+        let mut state = self.lock_state();
+
+        let role = Role::Follower {
+            host: host.to_string(),
+            port,
+        };
+        if state.role == role {
+            return false;
+        }
+
+        state.role = role;
+        state.link_state = LinkState::Connect;
+        state.generation += 1;
+        state.replicas.clear();
+        self.role_changed.notify_all();
+
+        true
+    }
+
+    /// Stop following the leader and become a leader, starting a new replication history.
+    ///
+    /// Followers of the previous leader can still partially resync, as long as they're not ahead of this
+    /// server.
+    pub fn promote(&self) {
+        let mut state = self.lock_state();
+
+        if state.role == Role::Leader {
+            return;
+        }
+
+        state.role = Role::Leader;
+        state.generation += 1;
+        state.replid2 = std::mem::replace(&mut state.replid, random_replid());
+        state.second_replid_offset = Some(state.offset + 1);
+        self.role_changed.notify_all();
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, ReplicationState> {
+        // NOTE: Nothing in the state can be left half updated, so it's fine to keep on using it
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Current leader to follow, along with the generation of the role, waiting until there's one.
+    fn wait_for_leader(&self) -> (String, u16, u64) {
+        let mut state = self.lock_state();
+
+        loop {
+            if let Role::Follower { host, port } = &state.role {
+                return (host.clone(), *port, state.generation);
+            }
+
+            state = self
+                .role_changed
+                .wait(state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    fn is_current(&self, generation: u64) -> bool {
+        self.lock_state().generation == generation
+    }
+
+    fn set_link_state(&self, generation: u64, link_state: LinkState) {
+        let mut state = self.lock_state();
+
+        if state.generation == generation {
+            state.link_state = link_state;
+        }
+    }
+
+    /// Id and offset to ask the leader with `PSYNC`, to partially resync from where this server is at.
+    fn psync_position(&self) -> (String, u64) {
+        let state = self.lock_state();
+
+        (state.replid.clone(), state.offset + 1)
+    }
+
+    /// Start over the replication history from the leader, after a full sync.
+    fn reset_history(&self, replid: String, offset: u64) {
+        let mut state = self.lock_state();
+
+        state.replid = replid;
+        state.replid2 = "0".repeat(40);
+        state.second_replid_offset = None;
+        state.offset = offset;
+        state.backlog.clear();
+        state.replicas.clear();
+    }
+
+    /// Continue on the leader's new replication history, after a partial sync with a new id.
+    fn switch_history(&self, replid: String) {
+        let mut state = self.lock_state();
+
+        if state.replid != replid {
+            state.replid2 = std::mem::replace(&mut state.replid, replid);
+            state.second_replid_offset = Some(state.offset + 1);
+            state.replicas.clear();
+        }
+    }
+}
+
+fn count_acked(state: &ReplicationState, offset: u64, kind: AckKind) -> usize {
+    state
+        .replicas
+        .iter()
+        .filter(|replica| match kind {
+            AckKind::Processed => replica.ack_offset >= offset,
+            AckKind::Fsynced => replica.aof_ack_offset >= offset,
+        })
+        .count()
+}
+
+fn feed_locked(state: &mut ReplicationState, bytes: &[u8]) {
+    state.offset += bytes.len() as u64;
+
+    state.backlog.extend(bytes);
+    let excess = state.backlog.len().saturating_sub(state.backlog_size);
+    state.backlog.drain(..excess);
+
+    // Followers whose connection is closed are dropped along the way
+    state
+        .replicas
+        .retain(|replica| replica.sender.send(bytes.to_vec()).is_ok());
+}
+
+/// Random 40 characters hex id of a replication history.
+fn random_replid() -> String {
+    (0..3)
+        .map(|_| format!("{:016x}", RandomState::new().build_hasher().finish()))
+        .collect::<String>()[..40]
+        .to_string()
+}
+
+/// Keep this server in sync with its leader, whenever this server is a follower.
+///
+/// The link is started again whenever it's lost, or when the leader is changed with `REPLICAOF`.
+pub fn spawn_follower(server: Arc<Server>) -> thread::JoinHandle<()> {
+    thread::spawn(move || loop {
+        let (host, port, generation) = server.replication.wait_for_leader();
+
+        if let Err(err) = follow_leader(&server, &host, port, generation) {
+            println!(
+                "[Replication] Lost the link with the leader {}:{}: {}",
+                host, port, err
+            );
+        }
+
+        server
+            .replication
+            .set_link_state(generation, LinkState::Connect);
+        if server.replication.is_current(generation) {
+            thread::sleep(RECONNECT_INTERVAL);
+        }
+    })
+}
+
+/// Sync with the leader, then apply every write command streamed by the leader, until the link is lost or
+/// this server is not following the leader anymore.
+fn follow_leader(
+    server: &Server,
+    host: &str,
+    port: u16,
+    generation: u64,
+) -> Result<(), Box<dyn Error>> {
+    let stream = TcpStream::connect((host, port))?;
+    stream.set_read_timeout(Some(LINK_POLL_INTERVAL))?;
+    let mut connection = Connection::new(stream);
+
+    server
+        .replication
+        .set_link_state(generation, LinkState::Connecting);
+
+    let listening_port = server.replication.listening_port.load(Ordering::Relaxed);
+    request(&mut connection, &["PING"])?;
+    request(
+        &mut connection,
+        &["REPLCONF", "listening-port", &listening_port.to_string()],
+    )?;
+    request(&mut connection, &["REPLCONF", "capa", "psync2"])?;
+
+    let (replid, offset) = server.replication.psync_position();
+    let reply = request(&mut connection, &["PSYNC", &replid, &offset.to_string()])?;
+
+    let mut words = reply.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (Some("FULLRESYNC"), Some(replid), Some(offset)) => {
+            server
+                .replication
+                .set_link_state(generation, LinkState::Sync);
+
+            let snapshot = decode_snapshot(&read_rdb_payload(&mut connection)?[..])?;
+            match server.storage.write() {
+                Ok(mut storage_locked) => {
+                    server.restore_snapshot(&mut storage_locked, snapshot);
+                    server
+                        .replication
+                        .reset_history(replid.to_string(), offset.parse()?);
+
+                    // NOTE: The append only file no longer matches the storage, so it's rewritten
+                    if server.aof.is_enabled() {
+                        server.aof.background_rewrite(
+                            &server.aof.dir(server),
+                            server.snapshot(&storage_locked),
+                        )?;
+                    }
+                }
+                Err(err) => return Err(format!("poisoned storage: {}", err).into()),
+            }
+        }
+        (Some("CONTINUE"), new_replid, _) => {
+            if let Some(new_replid) = new_replid {
+                server.replication.switch_history(new_replid.to_string());
+            }
+        }
+        _ => return Err(format!("unexpected reply to PSYNC: {}", reply).into()),
+    }
+
+    server
+        .replication
+        .set_link_state(generation, LinkState::Connected);
+    println!("[Replication] Synced with the leader {}:{}", host, port);
+
+    let mut leader = Client::with_addr(format!("{}:{}", host, port));
+    leader.from_leader = true;
+    server.clients.register(&leader);
+    let result = stream_from_leader(server, &mut connection, &mut leader, generation);
+    leader.disconnect(server);
+    server.clients.unregister(leader.id);
+
+    result
+}
+
+fn stream_from_leader(
+    server: &Server,
+    connection: &mut Connection<TcpStream>,
+    leader: &mut Client,
+    generation: u64,
+) -> Result<(), Box<dyn Error>> {
+    let mut last_ack = Instant::now();
+    let mut last_received = Instant::now();
+
+    while server.replication.is_current(generation) {
+        if leader.is_killed() {
+            return Err("link killed with CLIENT KILL".into());
+        }
+
+        if last_ack.elapsed() >= ACK_INTERVAL {
+            send_ack(server, connection)?;
+            last_ack = Instant::now();
+        }
+
+        if !connection.poll_readable()? {
+            if last_received.elapsed() >= LINK_TIMEOUT {
+                return Err("timeout, nothing received from the leader".into());
+            }
+
+            continue;
+        }
+        last_received = Instant::now();
+
+        let mut recording = Recording {
+            inner: &mut *connection,
+            bytes: Vec::new(),
+        };
+        let command = match RespType::deserialize(&mut recording)? {
+            (RespType::Array(command), _) => command,
+            (other, _) => {
+                return Err(format!("unexpected data from the leader: {:?}", other).into())
+            }
+        };
+        let bytes = recording.bytes;
+
+        let is_getack = matches!(
+            (command.first(), command.get(1)),
+            (Some(RespType::BulkString(name)), Some(RespType::BulkString(subcommand)))
+                if name.eq_ignore_ascii_case("REPLCONF") && subcommand.eq_ignore_ascii_case("GETACK")
+        );
+
+        // NOTE: The offset acknowledged to `GETACK` doesn't include the `GETACK` itself, the same as Redis
+        if is_getack {
+            send_ack(server, connection)?;
+            last_ack = Instant::now();
+        } else {
+            handle_commands(command, leader, server);
+            server.clients.update(leader);
+        }
+
+        server.replication.feed(&bytes);
+    }
+
+    Ok(())
+}
+
+fn send_ack(server: &Server, connection: &mut Connection<TcpStream>) -> io::Result<()> {
+    let offset = server.replication.offset().to_string();
+
+    // NOTE: Without the append only file, nothing is ever flushed to it
+    let aof_offset = if server.aof.is_enabled() {
+        server.aof.fsynced_offset()
+    } else {
+        0
+    };
+
+    connection.write_all(
+        &command(&["REPLCONF", "ACK", &offset, "FACK", &aof_offset.to_string()]).serialize(),
+    )
+}
+
+fn command(args: &[&str]) -> RespType {
+    RespType::Array(
+        args.iter()
+            .map(|arg| RespType::BulkString(arg.to_string()))
+            .collect(),
+    )
+}
+
+/// Send a command to the leader during the handshake, returning the reply.
+fn request(
+    connection: &mut Connection<TcpStream>,
+    args: &[&str],
+) -> Result<String, Box<dyn Error>> {
+    connection.write_all(&command(args).serialize())?;
+
+    match RespType::deserialize(&mut *connection)? {
+        (RespType::String(reply), _) => Ok(reply),
+        (RespType::Error(err), _) => Err(format!("`{}` failed: {}", args.join(" "), err).into()),
+        (other, _) => Err(format!("unexpected reply to `{}`: {:?}", args.join(" "), other).into()),
+    }
+}
+
+/// Read the snapshot sent by the leader on a full sync, a bulk string without the trailing CRLF.
+fn read_rdb_payload<R: Read>(reader: &mut R) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut byte = [0u8; 1];
+
+    // NOTE: Redis sends newlines to keep the connection alive while the snapshot is being prepared
+    loop {
+        reader.read_exact(&mut byte)?;
+        if byte[0] != b'\n' {
+            break;
+        }
+    }
+    if byte[0] != b'$' {
+        return Err("bulk string of the snapshot was expected".into());
+    }
+
+    let mut len = String::new();
+    loop {
+        reader.read_exact(&mut byte)?;
+        match byte[0] {
+            b'\r' => {}
+            b'\n' => break,
+            digit => len.push(digit as char),
+        }
+    }
+
+    let mut payload = vec![0u8; len.parse()?];
+    reader.read_exact(&mut payload)?;
+
+    Ok(payload)
+}
+
+/// Reader keeping every byte read through it, to stream the exact bytes from the leader to the followers.
+struct Recording<'a, R> {
+    inner: &'a mut R,
+    bytes: Vec<u8>,
+}
+
+impl<R: Read> Read for Recording<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes.extend_from_slice(&buf[..read]);
+
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod replication_tests {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{AckKind, ReplicaSync, Replication, Role};
+    use crate::{client::Client, resp::RespType, storage::Snapshot};
+
+    fn set(key: &str) -> Vec<RespType> {
+        ["SET", key, "value"]
+            .iter()
+            .map(|arg| RespType::BulkString(arg.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn partial_sync_from_backlog() {
+        let replication = Replication::new();
+        let client = Client::new();
+        let replid = replication.replid();
+
+        replication.propagate(&set("a"));
+        let offset = replication.offset();
+        replication.propagate(&set("b"));
+
+        let (reply, link) = replication
+            .attach_replica(&client, &replid, Some(offset + 1), Snapshot::default)
+            .unwrap();
+        assert_eq!(reply, RespType::String(format!("CONTINUE {}", replid)));
+        assert!(matches!(
+            link.sync,
+            ReplicaSync::Partial(missing) if missing == RespType::Array(set("b")).serialize()
+        ));
+
+        replication.propagate(&set("c"));
+        assert_eq!(
+            link.receiver.try_recv().unwrap(),
+            RespType::Array(set("c")).serialize()
+        );
+
+        // Unknown history, or an offset no longer in the backlog, needs a full sync
+        let (reply, _) = replication
+            .attach_replica(&client, "unknown", Some(1), Snapshot::default)
+            .unwrap();
+        assert_eq!(
+            reply,
+            RespType::String(format!("FULLRESYNC {} {}", replid, replication.offset()))
+        );
+
+        replication.set_backlog_size(4);
+        let (reply, _) = replication
+            .attach_replica(&client, &replid, Some(offset + 1), Snapshot::default)
+            .unwrap();
+        assert!(matches!(reply, RespType::String(reply) if reply.starts_with("FULLRESYNC")));
+    }
+
+    #[test]
+    fn waits_for_acknowledgement() {
+        let replication = Replication::new();
+        let client = Client::new();
+
+        let (_, link) = replication
+            .attach_replica(&client, "?", None, Snapshot::default)
+            .unwrap();
+        replication.propagate(&set("a"));
+        let offset = replication.offset();
+
+        let deadline = Some(Instant::now() + Duration::from_millis(10));
+        assert_eq!(
+            replication.wait_for_acks(offset, 1, deadline, AckKind::Processed),
+            0
+        );
+        // Asked the follower to acknowledge right away
+        assert!(link.receiver.try_iter().any(|bytes| bytes
+            == RespType::Array(
+                ["REPLCONF", "GETACK", "*"]
+                    .iter()
+                    .map(|arg| RespType::BulkString(arg.to_string()))
+                    .collect()
+            )
+            .serialize()));
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                replication.acknowledge(client.id, Some(offset), None);
+            });
+
+            assert_eq!(
+                replication.wait_for_acks(offset, 1, None, AckKind::Processed),
+                1
+            );
+        });
+        assert_eq!(replication.acked_replicas(offset, AckKind::Fsynced), 0);
+    }
+
+    #[test]
+    fn promoted_follower_keeps_previous_history() {
+        let replication = Replication::new();
+        let client = Client::new();
+
+        assert!(replication.follow("127.0.0.1", 6379));
+        assert!(!replication.follow("127.0.0.1", 6379));
+        assert!(replication.is_read_only_follower());
+
+        replication.feed(&RespType::Array(set("a")).serialize());
+        let previous_replid = replication.replid();
+        let offset = replication.offset();
+
+        replication.promote();
+        assert_eq!(replication.role(), Role::Leader);
+        assert_ne!(replication.replid(), previous_replid);
+
+        let (reply, _) = replication
+            .attach_replica(
+                &client,
+                &previous_replid,
+                Some(offset + 1),
+                Snapshot::default,
+            )
+            .unwrap();
+        assert_eq!(
+            reply,
+            RespType::String(format!("CONTINUE {}", replication.replid()))
+        );
+    }
+}
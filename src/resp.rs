@@ -1,9 +1,27 @@
-use std::{i64, io::Read};
+use std::io::Read;
+
+/// Bytes of a string read from the wire.
+///
+/// Every string deserialized from RESP have one character per byte (see
+/// [`RespType::deserialize_bulk_string`]), this gets back the original bytes.
+pub fn string_to_bytes(string: &str) -> Vec<u8> {
+    string.chars().map(|character| character as u8).collect()
+}
+
+/// String from raw bytes, with one character per byte, the same way strings are deserialized from RESP.
+pub fn bytes_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| *byte as char).collect()
+}
+
+/// Most elements allocated up front for an array, map, or bulk string, the size is sent by the client (before
+/// it's authenticated), so anything bigger grows as the elements are actually read instead.
+const MAX_PREALLOCATED: usize = 1024;
 
 /// RESP2 Compatible Enum
 ///
-/// This enum should be able to represent
-#[derive(Debug, PartialEq)]
+/// This enum should be able to represent every RESP2 type, with a few RESP3 types used by clients that
+/// switched the protocol with `HELLO 3`.
+#[derive(Debug, Clone, PartialEq)]
 pub enum RespType {
     /// Simple string
     String(String),
@@ -20,6 +38,15 @@ pub enum RespType {
     /// Bulk String with negative size is considered to be a null Bulk String.
     Null,
     Array(Vec<RespType>),
+    /// Similar with [`RespType::Null`], an array with negative size is considered to be a null Array.
+    ///
+    /// Used by Redis on some commands to differentiate an empty reply with no reply, e.g. aborted `EXEC`.
+    NullArray,
+    /// RESP3 Map, only sent to client using RESP3.
+    Map(Vec<(RespType, RespType)>),
+    /// RESP3 Push, out of band data sent to the client (e.g. Pub/Sub messages), only sent to client using RESP3.
+    /// Client using RESP2 will get the same data as [`RespType::Array`] instead.
+    Push(Vec<RespType>),
 }
 
 impl RespType {
@@ -37,25 +64,32 @@ impl RespType {
             b':' => RespType::deserialize_integer(stream),
             b'$' => RespType::deserialize_bulk_string(stream),
             b'*' => RespType::deserialize_array(stream),
-            _ => {
-                println!("[RespType] Getting un-supported type: `{:?}`", byte as char);
-                unimplemented!()
-            }
+            b'%' => RespType::deserialize_map(stream),
+            b'>' => match RespType::deserialize_array(stream) {
+                Ok((RespType::Array(content), stream)) => Ok((RespType::Push(content), stream)),
+                other => other,
+            },
+            _ => Err(format!(
+                "Protocol error: unsupported type `{}`",
+                (byte as char).escape_default()
+            )
+            .into()),
         }
     }
 
     fn deserialize_array<S: Read>(stream: S) -> Result<(Self, S), Box<dyn std::error::Error>> {
         let (size, stream) = RespType::deserialize_number(stream)?;
-        let mut array_content = Vec::<RespType>::with_capacity(size.try_into()?);
+
+        if size < 0 {
+            return Ok((RespType::NullArray, stream));
+        }
+
+        let mut array_content =
+            Vec::<RespType>::with_capacity(usize::try_from(size)?.min(MAX_PREALLOCATED));
 
         let mut stream = stream;
         for _ in 0..size {
             let (deserialized_content, new_stream) = RespType::deserialize(stream)?;
-            println!(
-                "[RespType] Serialize result in: {:#?}",
-                deserialized_content
-            );
-
             array_content.push(deserialized_content);
 
             stream = new_stream;
@@ -64,17 +98,34 @@ impl RespType {
         Ok((RespType::Array(array_content), stream))
     }
 
+    fn deserialize_map<S: Read>(stream: S) -> Result<(Self, S), Box<dyn std::error::Error>> {
+        let (size, stream) = RespType::deserialize_number(stream)?;
+        let mut map_content = Vec::<(RespType, RespType)>::with_capacity(
+            usize::try_from(size)?.min(MAX_PREALLOCATED),
+        );
+
+        let mut stream = stream;
+        for _ in 0..size {
+            let (key, new_stream) = RespType::deserialize(stream)?;
+            let (value, new_stream) = RespType::deserialize(new_stream)?;
+
+            map_content.push((key, value));
+
+            stream = new_stream;
+        }
+
+        Ok((RespType::Map(map_content), stream))
+    }
+
     fn deserialize_bulk_string<S: Read>(
         stream: S,
     ) -> Result<(Self, S), Box<dyn std::error::Error>> {
         let (size, mut stream) = RespType::deserialize_number(stream)?;
-        println!("[RespType BulkString] Reading for size: {:#?}", size);
-
         if size < 0 {
             return Ok((RespType::Null, stream));
         }
 
-        let mut final_string = String::with_capacity(size.try_into()?);
+        let mut final_string = String::with_capacity(usize::try_from(size)?.min(MAX_PREALLOCATED));
 
         for _ in 0..size {
             let mut byte = 0u8;
@@ -83,11 +134,6 @@ impl RespType {
             final_string.push(byte as char);
         }
 
-        println!(
-            "[RespType BulkString] Reading final string: {:#?}",
-            final_string
-        );
-
         // Read the remaining "\r\n"
         stream.read_exact(&mut [0u8; 2])?;
 
@@ -112,7 +158,9 @@ impl RespType {
         let mut sign = true;
         let mut str_integer = String::new();
 
-        while stream.read_exact(std::slice::from_mut(&mut byte)).is_ok() {
+        loop {
+            stream.read_exact(std::slice::from_mut(&mut byte))?;
+
             match byte as char {
                 '+' => sign = true,
                 '-' => sign = false,
@@ -160,8 +208,12 @@ impl RespType {
         let mut byte = 0u8;
         let mut final_string = String::new();
 
-        while stream.read(std::slice::from_mut(&mut byte)).is_ok() {
-            if byte == b'\r' && stream.read(std::slice::from_mut(&mut byte)).is_ok() {
+        loop {
+            stream.read_exact(std::slice::from_mut(&mut byte))?;
+
+            if byte == b'\r' {
+                stream.read_exact(std::slice::from_mut(&mut byte))?;
+
                 if byte == b'\n' {
                     break;
                 } else {
@@ -180,9 +232,12 @@ impl RespType {
             Self::Error(err) => Self::serialize_simple_string(b'-', err),
             Self::Integer(num) => Self::serialize_simple_integer(num),
             Self::BulkString(str) => Self::serialize_bulk_string(str),
-            Self::Array(arr) => Self::serialize_array(arr),
+            Self::Array(arr) => Self::serialize_array(b'*', arr),
+            Self::Push(arr) => Self::serialize_array(b'>', arr),
+            Self::Map(map) => Self::serialize_map(map),
             // Special case, all Null will be a bulk string with minus length
             Self::Null => "$-1\r\n".into(),
+            Self::NullArray => "*-1\r\n".into(),
         }
     }
 
@@ -191,7 +246,7 @@ impl RespType {
         let mut bytes = Vec::<u8>::with_capacity(str.len() + 3);
 
         bytes.push(prefix);
-        bytes.append(&mut str.into_bytes());
+        bytes.append(&mut string_to_bytes(&str));
         bytes.append(&mut "\r\n".into());
 
         bytes
@@ -204,6 +259,9 @@ impl RespType {
     }
 
     fn serialize_bulk_string(str: String) -> Vec<u8> {
+        // NOTE: Strings have one character per byte (see `RespType::deserialize_bulk_string`), so the
+        // original bytes are written back instead of the UTF-8 encoding of the string
+        let str = string_to_bytes(&str);
         let str_len = str.len().to_string();
 
         // Array of bytes with length of the string representation of the length + the string length + 2 CRLF ("\r\n") + 1 prefix ('$')
@@ -217,13 +275,13 @@ impl RespType {
         bytes.append(&mut "\r\n".into());
 
         // String data
-        bytes.append(&mut str.into_bytes());
+        bytes.extend_from_slice(&str);
         bytes.append(&mut "\r\n".into());
 
         bytes
     }
 
-    fn serialize_array(arr: Vec<Self>) -> Vec<u8> {
+    fn serialize_array(prefix: u8, arr: Vec<Self>) -> Vec<u8> {
         let str_len = arr.len().to_string();
 
         // Array of bytes with length of at least the prefix ('*') + number of elements + 1 CRLF
@@ -231,7 +289,7 @@ impl RespType {
         let mut bytes = Vec::<u8>::with_capacity(str_len.len() + 3);
 
         // Prefix
-        bytes.push(b'*');
+        bytes.push(prefix);
 
         // Array Length
         bytes.append(&mut str_len.into_bytes());
@@ -244,6 +302,26 @@ impl RespType {
 
         bytes
     }
+
+    fn serialize_map(map: Vec<(Self, Self)>) -> Vec<u8> {
+        let str_len = map.len().to_string();
+
+        let mut bytes = Vec::<u8>::with_capacity(str_len.len() + 3);
+
+        // Prefix
+        bytes.push(b'%');
+
+        // Map Length, counted by the number of key value pairs
+        bytes.append(&mut str_len.into_bytes());
+        bytes.append(&mut "\r\n".into());
+
+        for (key, value) in map {
+            bytes.append(&mut Self::serialize(key));
+            bytes.append(&mut Self::serialize(value));
+        }
+
+        bytes
+    }
 }
 
 #[cfg(test)]
@@ -326,4 +404,51 @@ mod resp_tests {
         RespType::Null,
         "Valid Null should be able to be serialize/deserialize!"
     );
+
+    test_valid_serialization_deserialization!(
+        working_null_array_deserializer,
+        working_null_array_serializer,
+        "*-1\r\n",
+        RespType::NullArray,
+        "Valid Null Array should be able to be serialize/deserialize!"
+    );
+
+    test_valid_serialization_deserialization!(
+        working_map_deserializer,
+        working_map_serializer,
+        "%1\r\n$5\r\nproto\r\n:3\r\n",
+        RespType::Map(vec![(
+            RespType::BulkString("proto".into()),
+            RespType::Integer(3)
+        )]),
+        "Valid Map should be able to be serialize/deserialize!"
+    );
+
+    test_valid_serialization_deserialization!(
+        working_push_deserializer,
+        working_push_serializer,
+        ">2\r\n$7\r\nmessage\r\n$2\r\nHI\r\n",
+        RespType::Push(vec![
+            RespType::BulkString("message".into()),
+            RespType::BulkString("HI".into())
+        ]),
+        "Valid Push should be able to be serialize/deserialize!"
+    );
+
+    #[test]
+    fn rejects_unsupported_type() {
+        let error = RespType::deserialize(VecDeque::from(b"PING\r\n".to_vec())).unwrap_err();
+        assert_eq!(error.to_string(), "Protocol error: unsupported type `P`");
+    }
+
+    #[test]
+    fn does_not_trust_sizes_sent() {
+        for raw in [
+            "*4611686018427387904\r\n",
+            "%4611686018427387904\r\n",
+            "$4611686018427387904\r\nHI",
+        ] {
+            assert!(RespType::deserialize(VecDeque::from(raw.as_bytes().to_vec())).is_err());
+        }
+    }
 }
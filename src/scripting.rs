@@ -0,0 +1,1016 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use mlua::{
+    Function, HookTriggers, IntoLuaMulti, Lua, LuaOptions, LuaString, MultiValue, StdLib, Table,
+    Value, Variadic, VmState,
+};
+
+use crate::{
+    acl::LogContext,
+    client::Client,
+    commands::registry::CommandFlags,
+    functions::{FunctionEngine, Library, RestorePolicy, FUNCTION_CHUNK_NAME},
+    monitor::Origin,
+    resp::{bytes_to_string, string_to_bytes, RespType},
+    server::Server,
+    storage::Storage,
+};
+
+/// Default of `busy-reply-threshold`, the same as Redis.
+pub const DEFAULT_BUSY_REPLY_THRESHOLD: u64 = 5000;
+
+/// How often a running script checks if it's been killed with `SCRIPT KILL`, in number of instructions.
+const KILL_CHECK_INSTRUCTIONS: u32 = 100_000;
+
+/// Name of the chunk of every script, shown in the errors of the script.
+const SCRIPT_CHUNK_NAME: &str = "@user_script";
+
+/// Levels of `redis.log`, the same as Redis.
+pub const LOG_LEVELS: [(&str, i64); 4] = [
+    ("LOG_DEBUG", 0),
+    ("LOG_VERBOSE", 1),
+    ("LOG_NOTICE", 2),
+    ("LOG_WARNING", 3),
+];
+
+/// Make every global read only, and accessing a global that doesn't exist an error, so scripts can't leak
+/// anything to the next scripts.
+const PROTECT_GLOBALS: &str = r#"
+setmetatable(_G, {
+    __index = function(_, name)
+        error("Script attempted to access nonexistent global variable '" .. tostring(name) .. "'", 2)
+    end,
+    __newindex = function(_, name)
+        error("Attempt to modify a readonly table", 2)
+    end,
+})
+"#;
+
+/// State of the script running right now, shared with the other clients so they can check on it (e.g.
+/// `SCRIPT KILL`) without waiting for the script to finish.
+#[derive(Debug, Default)]
+pub struct RunState {
+    running: Mutex<Option<RunningScript>>,
+    /// Whether the running script modified the storage, as it can't be killed anymore when it did.
+    wrote: AtomicBool,
+    kill_requested: AtomicBool,
+    /// When the library being loaded by `FUNCTION LOAD` is stopped, as it should only register functions.
+    load_deadline: Mutex<Option<Instant>>,
+}
+
+impl RunState {
+    fn running(&self) -> MutexGuard<'_, Option<RunningScript>> {
+        // NOTE: Nothing can be left half updated in there, so it's fine to keep on using it
+        self.running
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn load_deadline(&self) -> MutexGuard<'_, Option<Instant>> {
+        self.load_deadline
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Script running right now.
+#[derive(Debug, Clone)]
+struct RunningScript {
+    started: Instant,
+    /// Function run by `FCALL`, `None` for scripts run by `EVAL`.
+    function: Option<RunningFunction>,
+}
+
+/// Function run by `FCALL`, as shown by `FUNCTION STATS`.
+#[derive(Debug, Clone)]
+pub struct RunningFunction {
+    pub name: String,
+    pub command: Vec<RespType>,
+    pub duration: Duration,
+}
+
+/// Create a Lua VM with the libraries and the `redis` library available to scripts, other than
+/// `redis.call` and `redis.pcall`, which are only there while a script is running.
+///
+/// The running script is stopped once it's killed with `SCRIPT KILL` (or `FUNCTION KILL`), or once the
+/// library being loaded with `FUNCTION LOAD` took too long.
+pub fn new_lua(run_state: &Arc<RunState>) -> mlua::Result<Lua> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+        LuaOptions::default(),
+    )?;
+    let globals = lua.globals();
+
+    // NOTE: Scripts can't touch the file system
+    globals.raw_set("dofile", Value::Nil)?;
+    globals.raw_set("loadfile", Value::Nil)?;
+
+    let redis = lua.create_table()?;
+    redis.set(
+        "sha1hex",
+        lua.create_function(|_, script: LuaString| Ok(sha1hex(&script.as_bytes())))?,
+    )?;
+    redis.set(
+        "error_reply",
+        lua.create_function(|lua, message: LuaString| lua.create_table_from([("err", message)]))?,
+    )?;
+    redis.set(
+        "status_reply",
+        lua.create_function(|lua, message: LuaString| lua.create_table_from([("ok", message)]))?,
+    )?;
+    redis.set("log", create_log_function(&lua)?)?;
+    for (name, level) in LOG_LEVELS {
+        redis.set(name, level)?;
+    }
+    globals.raw_set("redis", redis)?;
+
+    lua.load(PROTECT_GLOBALS).exec()?;
+
+    let run_state = Arc::clone(run_state);
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(KILL_CHECK_INSTRUCTIONS),
+        move |_, _| {
+            if run_state.kill_requested.load(Ordering::Relaxed) {
+                return Err(mlua::Error::runtime(
+                    "Script killed by user with SCRIPT KILL...",
+                ));
+            }
+
+            if run_state
+                .load_deadline()
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Err(mlua::Error::runtime("FUNCTION LOAD timeout"));
+            }
+
+            Ok(VmState::Continue)
+        },
+    )?;
+
+    Ok(lua)
+}
+
+/// `redis.log`, printing the messages along with the logs of the server.
+pub fn create_log_function(lua: &Lua) -> mlua::Result<Function> {
+    lua.create_function(|_, (level, messages): (i64, Variadic<LuaString>)| {
+        if !(0..=3).contains(&level) {
+            return Err(mlua::Error::runtime("Invalid debug level."));
+        }
+
+        let message = messages
+            .iter()
+            .map(|message| bytes_to_string(&message.as_bytes()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        println!("[Scripting] {}", message);
+
+        Ok(())
+    })
+}
+
+/// Lua VM along with every script loaded into it, compiled and keyed by the SHA1 of the script.
+#[derive(Debug)]
+struct ScriptEngine {
+    lua: Lua,
+    scripts: HashMap<String, Function>,
+}
+
+impl ScriptEngine {
+    fn new(run_state: &Arc<RunState>) -> mlua::Result<Self> {
+        Ok(Self {
+            lua: new_lua(run_state)?,
+            scripts: HashMap::new(),
+        })
+    }
+
+    /// Compile the script if it's not loaded yet, returning its SHA1.
+    fn load(&mut self, script: &str) -> Result<String, RespType> {
+        let bytes = string_to_bytes(script);
+        let sha = sha1hex(&bytes);
+
+        if !self.scripts.contains_key(&sha) {
+            let function = self
+                .lua
+                .load(bytes)
+                .set_name(SCRIPT_CHUNK_NAME)
+                .into_function()
+                .map_err(|err| {
+                    let message = match err {
+                        mlua::Error::SyntaxError { message, .. } => message,
+                        err => err.to_string(),
+                    };
+
+                    RespType::Error(format!(
+                        "ERR Error compiling script (new function): {}",
+                        message
+                    ))
+                })?;
+
+            self.scripts.insert(sha.clone(), function);
+        }
+
+        Ok(sha)
+    }
+}
+
+/// Error reply of a command run with `redis.call`, raised as is.
+#[derive(Debug)]
+struct ErrorReply(String);
+
+impl fmt::Display for ErrorReply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ErrorReply {}
+
+/// Script to run, either its source or the SHA1 of an already loaded script.
+#[derive(Debug, Clone, Copy)]
+pub enum Script<'a> {
+    Source(&'a str),
+    Sha(&'a str),
+}
+
+/// Function to run with `FCALL`, called with the keys and arguments given.
+#[derive(Debug, Clone, Copy)]
+pub struct FunctionCall<'a> {
+    pub name: &'a str,
+    pub keys: &'a [RespType],
+    pub args: &'a [RespType],
+    /// Whether only functions registered with `no-writes` can be run, e.g. `FCALL_RO`.
+    pub read_only: bool,
+    /// The whole command, shown by `FUNCTION STATS` while the function is running.
+    pub command: &'a [RespType],
+}
+
+/// Script to run, with `KEYS` and `ARGV` set to the keys and arguments given.
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptCall<'a> {
+    pub script: Script<'a>,
+    pub keys: &'a [RespType],
+    pub args: &'a [RespType],
+    /// Whether the script is not allowed to run write commands, e.g. `EVAL_RO`.
+    pub read_only: bool,
+}
+
+/// Everything a script needs to run commands with `redis.call`.
+struct ScriptContext<'a> {
+    client: &'a mut Client,
+    server: &'a Server,
+    storage: &'a mut Storage,
+    read_only: bool,
+    run_state: &'a RunState,
+    /// Write commands that modified the storage, passed on in place of the script itself.
+    written: Vec<Vec<RespType>>,
+}
+
+impl ScriptContext<'_> {
+    /// Run a command from the script, the same way as it's run inside of a transaction.
+    fn call(&mut self, command: Vec<RespType>) -> RespType {
+        let command_name = match command.first() {
+            Some(RespType::BulkString(command_name)) => command_name,
+            _ => {
+                return RespType::Error(
+                    "ERR Please specify at least one argument for this redis lib call".into(),
+                )
+            }
+        };
+
+        let registered = match self.server.commands.get(command_name) {
+            Some(registered) => registered,
+            None => return RespType::Error("ERR Unknown Redis command called from script".into()),
+        };
+
+        if registered.flags().contains(CommandFlags::NOSCRIPT) {
+            return RespType::Error("ERR This Redis command is not allowed from script".into());
+        }
+
+        if !registered.accepts_arity(command.len()) {
+            return RespType::Error(
+                "ERR Wrong number of args calling Redis command from script".into(),
+            );
+        }
+
+        if let Err(err) = self
+            .server
+            .acl
+            .check(self.client, registered, &command, LogContext::Lua)
+        {
+            return RespType::Error(format!("ERR ACL failure in script: {}", err));
+        }
+
+        if registered.flags().contains(CommandFlags::WRITE) {
+            if self.read_only {
+                return RespType::Error(
+                    "ERR Write commands are not allowed from read-only scripts.".into(),
+                );
+            }
+
+            if !self.client.ignores_read_only() && self.server.replication.is_read_only_follower() {
+                return RespType::Error(
+                    "READONLY You can't write against a read only replica.".into(),
+                );
+            }
+        }
+
+        self.server.monitor.feed(
+            self.client,
+            Origin::Script,
+            registered,
+            &command,
+            &self.server.clients,
+        );
+
+        let changes = self.storage.pending_changes();
+        let started = Instant::now();
+        let reply =
+            registered
+                .handler()
+                .call_locked(&command[1..], self.client, self.server, self.storage);
+        let duration = started.elapsed();
+        self.server
+            .stats
+            .record_call(registered.name(), duration, &reply);
+        self.server
+            .latency
+            .add_command_sample_if_needed(registered, duration);
+        self.server
+            .tracking
+            .remember_keys(self.client, registered, &command);
+
+        if self.storage.pending_changes() > changes {
+            self.written.push(command);
+            self.run_state.wrote.store(true, Ordering::Relaxed);
+        }
+
+        reply
+    }
+}
+
+/// Lua scripting, with `EVAL` and friends, and the function libraries run with `FCALL`.
+///
+/// Every script runs in the same Lua VM, one at a time, while the storage is locked, so a script is as
+/// atomic as a transaction. Scripts are cached by their SHA1, to be run again with `EVALSHA`. Function
+/// libraries are loaded into a VM of their own, see [`FunctionEngine`].
+#[derive(Debug)]
+pub struct Scripting {
+    engine: Mutex<ScriptEngine>,
+    functions: Mutex<FunctionEngine>,
+    run_state: Arc<RunState>,
+    /// `busy-reply-threshold` in milliseconds, how long a script runs before other clients are told the
+    /// server is busy, `0` to never tell them.
+    pub busy_reply_threshold: AtomicU64,
+}
+
+impl Default for Scripting {
+    fn default() -> Self {
+        let run_state = Arc::new(RunState::default());
+
+        Self {
+            engine: Mutex::new(ScriptEngine::new(&run_state).expect("Failed to create the Lua VM")),
+            functions: Mutex::new(
+                FunctionEngine::new(&run_state).expect("Failed to create the Lua VM"),
+            ),
+            run_state,
+            busy_reply_threshold: AtomicU64::new(DEFAULT_BUSY_REPLY_THRESHOLD),
+        }
+    }
+}
+
+impl Scripting {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compile and cache the script, returning its SHA1.
+    pub fn load(&self, script: &str) -> Result<String, RespType> {
+        self.lock_engine().load(script)
+    }
+
+    pub fn exists(&self, sha: &str) -> bool {
+        self.lock_engine().scripts.contains_key(&sha.to_lowercase())
+    }
+
+    /// Drop every cached script, starting over with a new Lua VM.
+    pub fn flush(&self) {
+        let mut engine = self.lock_engine();
+
+        match ScriptEngine::new(&self.run_state) {
+            Ok(new_engine) => *engine = new_engine,
+            Err(err) => println!("[Scripting] Failed to create the Lua VM: {:#?}", err),
+        }
+    }
+
+    /// Load the function library, replacing the library with the same name only with `replace`,
+    /// returning the name of the library.
+    pub fn load_library(&self, code: &str, replace: bool) -> Result<String, RespType> {
+        self.lock_functions().load(code, replace)
+    }
+
+    /// Drop the function library, returning whether there was one.
+    pub fn delete_library(&self, name: &str) -> bool {
+        self.lock_functions().delete(name)
+    }
+
+    /// Load every function library from their code, all of them or none of them, see [`RestorePolicy`].
+    pub fn restore_libraries(
+        &self,
+        codes: &[String],
+        policy: RestorePolicy,
+    ) -> Result<(), RespType> {
+        let mut functions = self.lock_functions();
+
+        // NOTE: Starting over with a new Lua VM, rather than keeping the dropped libraries around in it
+        if policy == RestorePolicy::Flush {
+            let mut new_functions = FunctionEngine::new(&self.run_state)
+                .map_err(|err| RespType::Error(format!("ERR {}", err)))?;
+            new_functions.restore(codes, policy)?;
+            *functions = new_functions;
+
+            return Ok(());
+        }
+
+        functions.restore(codes, policy)
+    }
+
+    /// Drop every function library, starting over with a new Lua VM.
+    pub fn flush_libraries(&self) {
+        if let Err(err) = self.restore_libraries(&[], RestorePolicy::Flush) {
+            println!("[Scripting] Failed to create the Lua VM: {:#?}", err);
+        }
+    }
+
+    /// Every function library loaded, by name.
+    pub fn libraries(&self) -> BTreeMap<String, Library> {
+        self.lock_functions().libraries().clone()
+    }
+
+    /// Code of every function library loaded, to be saved along with the storage.
+    pub fn library_codes(&self) -> Vec<String> {
+        self.lock_functions()
+            .libraries()
+            .values()
+            .map(|library| library.code.clone())
+            .collect()
+    }
+
+    /// Whether a script is running for longer than `busy-reply-threshold`.
+    pub fn is_busy(&self) -> bool {
+        let threshold = self.busy_reply_threshold.load(Ordering::Relaxed);
+
+        threshold > 0
+            && self.run_state.running().as_ref().is_some_and(|running| {
+                running.started.elapsed() >= Duration::from_millis(threshold)
+            })
+    }
+
+    /// Whether the script running right now is a function run by `FCALL`.
+    pub fn is_running_function(&self) -> bool {
+        self.run_state
+            .running()
+            .as_ref()
+            .is_some_and(|running| running.function.is_some())
+    }
+
+    /// Function running right now, along with how long it's been running.
+    pub fn running_function(&self) -> Option<RunningFunction> {
+        self.run_state.running().as_ref().and_then(|running| {
+            running.function.clone().map(|function| RunningFunction {
+                duration: running.started.elapsed(),
+                ..function
+            })
+        })
+    }
+
+    /// Stop the running script (with `function`, the running function), only if it didn't modify the
+    /// storage.
+    pub fn kill(&self, function: bool) -> Result<(), RespType> {
+        let running = self.run_state.running();
+
+        if running
+            .as_ref()
+            .is_none_or(|running| running.function.is_some() != function)
+        {
+            return Err(RespType::Error(
+                "NOTBUSY No scripts in execution right now.".into(),
+            ));
+        }
+
+        if self.run_state.wrote.load(Ordering::Relaxed) {
+            return Err(RespType::Error("UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command.".into()));
+        }
+
+        self.run_state.kill_requested.store(true, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Run a script, should be called while the storage is locked.
+    ///
+    /// Write commands run by the script are left in [`Client::propagated`], to be passed on in place of
+    /// the script itself.
+    pub fn eval(
+        &self,
+        call: ScriptCall,
+        client: &mut Client,
+        server: &Server,
+        storage: &mut Storage,
+    ) -> RespType {
+        let mut engine = self.lock_engine();
+
+        let sha = match call.script {
+            Script::Source(source) => match engine.load(source) {
+                Ok(sha) => sha,
+                Err(err) => return err,
+            },
+            Script::Sha(sha) => sha.to_lowercase(),
+        };
+        let function = match engine.scripts.get(&sha) {
+            Some(function) => function.clone(),
+            None => return RespType::Error("NOSCRIPT No matching script. Please use EVAL.".into()),
+        };
+
+        let lua = &engine.lua;
+        let globals = lua.globals();
+        if let Err(err) = resp_args_to_lua(lua, call.keys)
+            .and_then(|keys| globals.raw_set("KEYS", keys))
+            .and_then(|_| resp_args_to_lua(lua, call.args))
+            .and_then(|args| globals.raw_set("ARGV", args))
+        {
+            return RespType::Error(format!("ERR Error running script: {}", err));
+        }
+
+        let mut context = ScriptContext {
+            client,
+            server,
+            storage,
+            read_only: call.read_only,
+            run_state: &self.run_state,
+            written: Vec::new(),
+        };
+
+        self.run(lua, &function, MultiValue::new(), &sha, None, &mut context)
+    }
+
+    /// Run a function of a library with `FCALL`, should be called while the storage is locked, the same
+    /// as [`Scripting::eval`].
+    pub fn fcall(
+        &self,
+        call: FunctionCall,
+        client: &mut Client,
+        server: &Server,
+        storage: &mut Storage,
+    ) -> RespType {
+        // NOTE: The VM is not locked while the function is running, so `FUNCTION STATS` can still be
+        // run, functions can't be loaded while a function is running anyway
+        let (lua, function) = {
+            let functions = self.lock_functions();
+
+            match functions.function(call.name) {
+                Some(function) => (functions.lua().clone(), function.clone()),
+                None => return RespType::Error("ERR Function not found".into()),
+            }
+        };
+
+        if call.read_only && !function.is_read_only() {
+            return RespType::Error(
+                "ERR Can not execute a script with write flag using *_ro command.".into(),
+            );
+        }
+
+        if !function.is_read_only()
+            && !client.ignores_read_only()
+            && server.replication.is_read_only_follower()
+        {
+            return RespType::Error("READONLY You can't write against a read only replica.".into());
+        }
+
+        let params = match resp_args_to_lua(&lua, call.keys)
+            .and_then(|keys| Ok((keys, resp_args_to_lua(&lua, call.args)?)))
+            .and_then(|params| params.into_lua_multi(&lua))
+        {
+            Ok(params) => params,
+            Err(err) => return RespType::Error(format!("ERR Error running script: {}", err)),
+        };
+
+        let mut context = ScriptContext {
+            client,
+            server,
+            storage,
+            read_only: function.is_read_only(),
+            run_state: &self.run_state,
+            written: Vec::new(),
+        };
+
+        self.run(
+            &lua,
+            &function.callback,
+            params,
+            call.name,
+            Some(RunningFunction {
+                name: call.name.to_string(),
+                command: call.command.to_vec(),
+                duration: Duration::ZERO,
+            }),
+            &mut context,
+        )
+    }
+
+    /// Run the script or the function (named by its SHA1 or its name), keeping track of it while it's
+    /// running.
+    fn run(
+        &self,
+        lua: &Lua,
+        function: &Function,
+        params: MultiValue,
+        name: &str,
+        running_function: Option<RunningFunction>,
+        context: &mut ScriptContext,
+    ) -> RespType {
+        *self.run_state.running() = Some(RunningScript {
+            started: Instant::now(),
+            function: running_function,
+        });
+        let result = run_function(lua, function, params, context);
+
+        let mut running = self.run_state.running();
+        *running = None;
+        self.run_state.wrote.store(false, Ordering::Relaxed);
+        let killed = self.run_state.kill_requested.swap(false, Ordering::Relaxed);
+        drop(running);
+
+        context.client.propagated = Some(std::mem::take(&mut context.written));
+
+        if killed {
+            return RespType::Error("ERR Script killed by user with SCRIPT KILL...".into());
+        }
+
+        match result {
+            Ok(Ok(value)) => lua_to_resp(&value),
+            Ok(Err(err)) => script_error(&err, name),
+            Err(err) => {
+                println!("[Scripting] Failed to run the script: {:#?}", err);
+
+                RespType::Error(format!("ERR Error running script: {}", err))
+            }
+        }
+    }
+
+    fn lock_engine(&self) -> MutexGuard<'_, ScriptEngine> {
+        // NOTE: A script is never left half run in the VM, so it's fine to keep on using it
+        self.engine
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_functions(&self) -> MutexGuard<'_, FunctionEngine> {
+        // NOTE: Libraries are only replaced once they're fully loaded, so it's fine to keep on using it
+        self.functions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Run the compiled script or function with the parameters given, returning the value returned by it, or
+/// the error raised by it.
+fn run_function(
+    lua: &Lua,
+    function: &Function,
+    params: MultiValue,
+    context: &mut ScriptContext,
+) -> mlua::Result<Result<Value, Value>> {
+    let globals = lua.globals();
+
+    let xpcall: Function = globals.raw_get("xpcall")?;
+    let redis: Table = globals.raw_get("redis")?;
+    // NOTE: `xpcall` of Lua 5.1 can't pass parameters to the function
+    let function = function.bind(params)?;
+
+    let context = RefCell::new(context);
+
+    lua.scope(|scope| {
+        let pcall = scope.create_function(|lua, args: MultiValue| {
+            let reply = call_from_lua(lua, args, &context)?;
+
+            resp_to_lua(lua, reply)
+        })?;
+        // NOTE: Error replies are raised from Rust, rather than from a Lua wrapper around `redis.pcall`,
+        // as `return redis.call(...)` would be a tail call dropping the location of the error
+        let call = scope.create_function(|lua, args: MultiValue| {
+            match call_from_lua(lua, args, &context)? {
+                RespType::Error(err) => Err(mlua::Error::external(ErrorReply(err))),
+                reply => resp_to_lua(lua, reply),
+            }
+        })?;
+
+        redis.raw_set("pcall", pcall)?;
+        redis.raw_set("call", call)?;
+
+        let error_handler =
+            scope.create_function(|lua, err: Value| error_with_location(lua, err))?;
+        let mut results = xpcall
+            .call::<MultiValue>((function, error_handler))?
+            .into_iter();
+
+        let ok = matches!(results.next(), Some(Value::Boolean(true)));
+        let value = results.next().unwrap_or(Value::Nil);
+
+        Ok(if ok { Ok(value) } else { Err(value) })
+    })
+}
+
+/// Run the command given to `redis.call` or `redis.pcall`.
+fn call_from_lua(
+    lua: &Lua,
+    args: MultiValue,
+    context: &RefCell<&mut ScriptContext>,
+) -> mlua::Result<RespType> {
+    let command = args
+        .into_iter()
+        .map(|arg| match arg {
+            Value::String(_) | Value::Integer(_) | Value::Number(_) => lua
+                .coerce_string(arg)
+                .map(|arg| arg.map(|arg| RespType::BulkString(bytes_to_string(&arg.as_bytes())))),
+            _ => Ok(None),
+        })
+        .collect::<mlua::Result<Option<Vec<_>>>>()?;
+
+    Ok(match command {
+        Some(command) => context.borrow_mut().call(command),
+        None => RespType::Error(
+            "ERR Lua redis lib command arguments must be strings or integers".into(),
+        ),
+    })
+}
+
+/// Turn whatever is raised by the script into an error table, with the location where it's raised.
+fn error_with_location(lua: &Lua, err: Value) -> mlua::Result<Table> {
+    let table = match err {
+        Value::Table(table) if table.raw_get::<Value>("err")?.is_string() => table,
+        err => {
+            let message = match err {
+                Value::Error(err) => error_message(&err),
+                err => {
+                    let tostring: Function = lua.globals().raw_get("tostring")?;
+                    let message: LuaString = tostring.call(err)?;
+
+                    format!("ERR {}", bytes_to_string(&message.as_bytes()))
+                }
+            };
+
+            lua.create_table_from([("err", message)])?
+        }
+    };
+
+    // NOTE: The error might be raised by a function called by the script (e.g. `error`), so the location
+    // is the first one in the script itself
+    for level in 1.. {
+        match lua.inspect_stack(level, |debug| {
+            let source = debug.source().source.map(|source| source.into_owned());
+            (source, debug.current_line())
+        }) {
+            Some((Some(source), line))
+                if source == SCRIPT_CHUNK_NAME || source == FUNCTION_CHUNK_NAME =>
+            {
+                table.raw_set("source", source)?;
+                table.raw_set("line", line)?;
+                break;
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+
+    Ok(table)
+}
+
+/// Message of an error raised from Rust, the error reply itself for `redis.call`.
+pub fn error_message(err: &mlua::Error) -> String {
+    match err {
+        mlua::Error::CallbackError { cause, .. } => error_message(cause),
+        mlua::Error::ExternalError(external) => match external.downcast_ref::<ErrorReply>() {
+            Some(ErrorReply(message)) => message.clone(),
+            None => format!("ERR {}", external),
+        },
+        mlua::Error::RuntimeError(message) => format!("ERR {}", message),
+        err => format!("ERR {}", err),
+    }
+}
+
+/// Error reply of an error raised by the script.
+fn script_error(err: &Value, sha: &str) -> RespType {
+    let table = match err {
+        Value::Table(table) => table,
+        _ => return RespType::Error(format!("ERR Error running script, script: {}", sha)),
+    };
+
+    let message = match table.raw_get::<LuaString>("err") {
+        Ok(message) => bytes_to_string(&message.as_bytes()),
+        Err(_) => "ERR Error running script".into(),
+    };
+
+    match (
+        table.raw_get::<Option<String>>("source"),
+        table.raw_get::<Option<i64>>("line"),
+    ) {
+        (Ok(Some(source)), Ok(Some(line))) => RespType::Error(format!(
+            "{} script: {}, on {}:{}.",
+            message, sha, source, line
+        )),
+        _ => RespType::Error(format!("{} script: {}", message, sha)),
+    }
+}
+
+fn resp_args_to_lua(lua: &Lua, args: &[RespType]) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+
+    for (index, arg) in args.iter().enumerate() {
+        if let RespType::BulkString(arg) = arg {
+            table.raw_set(index + 1, lua.create_string(string_to_bytes(arg))?)?;
+        }
+    }
+
+    Ok(table)
+}
+
+/// Convert a reply into a Lua value, the same way as Redis.
+///
+/// Simple strings and errors are turned into a table with a single `ok` or `err` field, while null is
+/// turned into `false`.
+pub fn resp_to_lua(lua: &Lua, resp: RespType) -> mlua::Result<Value> {
+    let value = match resp {
+        RespType::Integer(integer) => Value::Number(integer as f64),
+        RespType::BulkString(string) => Value::String(lua.create_string(string_to_bytes(&string))?),
+        RespType::String(string) => Value::Table(
+            lua.create_table_from([("ok", lua.create_string(string_to_bytes(&string))?)])?,
+        ),
+        RespType::Error(err) => Value::Table(
+            lua.create_table_from([("err", lua.create_string(string_to_bytes(&err))?)])?,
+        ),
+        RespType::Null | RespType::NullArray => Value::Boolean(false),
+        RespType::Array(values) | RespType::Push(values) => Value::Table(
+            lua.create_sequence_from(
+                values
+                    .into_iter()
+                    .map(|value| resp_to_lua(lua, value))
+                    .collect::<mlua::Result<Vec<_>>>()?,
+            )?,
+        ),
+        // NOTE: Scripts use RESP2, where maps are flattened into arrays
+        RespType::Map(entries) => Value::Table(
+            lua.create_sequence_from(
+                entries
+                    .into_iter()
+                    .flat_map(|(key, value)| [key, value])
+                    .map(|value| resp_to_lua(lua, value))
+                    .collect::<mlua::Result<Vec<_>>>()?,
+            )?,
+        ),
+    };
+
+    Ok(value)
+}
+
+/// Convert a Lua value into a reply, the same way as Redis.
+///
+/// Numbers are truncated into integers, `true` is turned into `1` and `false` into null. Tables with an
+/// `ok` or `err` field are turned into a simple string or an error, other tables are turned into an array
+/// up to the first `nil`.
+pub fn lua_to_resp(value: &Value) -> RespType {
+    match value {
+        Value::Boolean(true) => RespType::Integer(1),
+        Value::Integer(integer) => RespType::Integer(*integer),
+        Value::Number(number) => RespType::Integer(*number as i64),
+        Value::String(string) => RespType::BulkString(bytes_to_string(&string.as_bytes())),
+        Value::Table(table) => {
+            if let Ok(err) = table.raw_get::<LuaString>("err") {
+                return RespType::Error(bytes_to_string(&err.as_bytes()));
+            }
+            if let Ok(ok) = table.raw_get::<LuaString>("ok") {
+                return RespType::String(bytes_to_string(&ok.as_bytes()));
+            }
+
+            let mut values = Vec::new();
+            for index in 1.. {
+                match table.raw_get::<Value>(index) {
+                    Ok(Value::Nil) | Err(_) => break,
+                    Ok(value) => values.push(lua_to_resp(&value)),
+                }
+            }
+
+            RespType::Array(values)
+        }
+        _ => RespType::Null,
+    }
+}
+
+/// SHA1 of the bytes, in lowercase hex.
+pub fn sha1hex(bytes: &[u8]) -> String {
+    sha1_smol::Sha1::from(bytes).digest().to_string()
+}
+
+#[cfg(test)]
+mod scripting_tests {
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    #[test]
+    fn converts_lua_values_to_replies() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        assert_eq!(
+            handle_commands(
+                command(&[
+                    "EVAL",
+                    "return {1, 2.9, 'x', true, false, nil, 'ignored'}",
+                    "0"
+                ]),
+                &mut client,
+                &server
+            ),
+            RespType::Array(vec![
+                RespType::Integer(1),
+                RespType::Integer(2),
+                RespType::BulkString("x".into()),
+                RespType::Integer(1),
+                RespType::Null,
+            ])
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["EVAL", "return redis.status_reply('FINE')", "0"]),
+                &mut client,
+                &server
+            ),
+            RespType::String("FINE".into())
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["EVAL", "return redis.pcall('GET', 'missing')", "0"]),
+                &mut client,
+                &server
+            ),
+            RespType::Null
+        );
+    }
+
+    #[test]
+    fn runs_commands_from_scripts() {
+        let server = Server::new();
+        let mut client = connect(&server);
+
+        let sha = match handle_commands(
+            command(&[
+                "SCRIPT",
+                "LOAD",
+                "redis.call('set', KEYS[1], ARGV[1]) return redis.call('get', KEYS[1])",
+            ]),
+            &mut client,
+            &server,
+        ) {
+            RespType::BulkString(sha) => sha,
+            other => panic!("unexpected reply {:?}", other),
+        };
+
+        assert_eq!(
+            handle_commands(
+                command(&["EVALSHA", &sha, "1", "key", "value"]),
+                &mut client,
+                &server
+            ),
+            RespType::BulkString("value".into())
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["EVALSHA_RO", &sha, "1", "key", "other"]),
+                &mut client,
+                &server
+            ),
+            RespType::Error(format!(
+                "ERR Write commands are not allowed from read-only scripts. script: {}, on @user_script:1.",
+                sha
+            ))
+        );
+
+        handle_commands(command(&["SCRIPT", "FLUSH"]), &mut client, &server);
+        assert_eq!(
+            handle_commands(command(&["EVALSHA", &sha, "0"]), &mut client, &server),
+            RespType::Error("NOSCRIPT No matching script. Please use EVAL.".into())
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use std::sync::{atomic::Ordering, Arc, RwLock};
+
+use crate::{
+    acl::Acl,
+    aof::{self, Aof},
+    client::{Client, Clients},
+    commands::registry::CommandRegistry,
+    config::Config,
+    functions::RestorePolicy,
+    latency::LatencyMonitor,
+    monitor::Monitor,
+    notification::Notifier,
+    persistence::Persistence,
+    pubsub::PubSub,
+    replication::{Replication, Role},
+    resp::RespType,
+    scripting::Scripting,
+    slowlog::Slowlog,
+    stats::Stats,
+    storage::{Snapshot, Storage, StorageEffects},
+    tracking::Tracking,
+};
+
+/// Every state shared by all the connections.
+#[derive(Debug, Default)]
+pub struct Server {
+    pub storage: RwLock<Storage>,
+    pub pubsub: PubSub,
+    pub notifier: Notifier,
+    pub persistence: Arc<Persistence>,
+    pub aof: Arc<Aof>,
+    pub replication: Replication,
+    pub scripting: Scripting,
+    pub commands: CommandRegistry,
+    pub acl: Acl,
+    /// Configuration of the server, the parameters kept by their own state (e.g. [`Persistence::config`])
+    /// are only up to date in [`Server::running_config`].
+    pub config: RwLock<Config>,
+    pub stats: Stats,
+    pub clients: Clients,
+    pub tracking: Tracking,
+    pub slowlog: Slowlog,
+    pub monitor: Monitor,
+    pub latency: LatencyMonitor,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Server using the configuration, the keys aren't loaded yet, see [`Server::load`].
+    pub fn with_config(config: Config) -> Self {
+        let server = Self::default();
+
+        *server
+            .persistence
+            .config
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = config.persistence.clone();
+        *server
+            .aof
+            .config
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = config.aof.clone();
+        server.notifier.set_flags(config.notify_keyspace_events);
+
+        server
+            .replication
+            .listening_port
+            .store(config.port, Ordering::Relaxed);
+        server
+            .replication
+            .read_only
+            .store(config.replica_read_only, Ordering::Relaxed);
+        server
+            .replication
+            .set_backlog_size(config.repl_backlog_size);
+        if let Some((host, port)) = &config.replicaof {
+            server.replication.follow(host, *port);
+        }
+
+        server
+            .scripting
+            .busy_reply_threshold
+            .store(config.busy_reply_threshold, Ordering::Relaxed);
+
+        server.acl.set_requirepass(&config.requirepass);
+        server
+            .acl
+            .log_max_len
+            .store(config.acllog_max_len, Ordering::Relaxed);
+        *server
+            .acl
+            .file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = config.aclfile.clone();
+
+        server
+            .slowlog
+            .log_slower_than
+            .store(config.slowlog_log_slower_than, Ordering::Relaxed);
+        server.slowlog.set_max_len(config.slowlog_max_len);
+
+        server
+            .latency
+            .threshold
+            .store(config.latency_monitor_threshold, Ordering::Relaxed);
+
+        *server
+            .config
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = config;
+
+        server
+    }
+
+    /// Fill the configuration (usually [`Server::config`]) with the parameters kept by their own state,
+    /// e.g. the leader changed by `REPLICAOF`.
+    pub fn running_config(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+
+        config.persistence = self
+            .persistence
+            .config
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        config.aof = self
+            .aof
+            .config
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        config.notify_keyspace_events = self.notifier.flags();
+
+        config.replicaof = match self.replication.role() {
+            Role::Follower { host, port } => Some((host, port)),
+            Role::Leader => None,
+        };
+        config.replica_read_only = self.replication.read_only.load(Ordering::Relaxed);
+        config.repl_backlog_size = self.replication.backlog_size();
+
+        config.busy_reply_threshold = self.scripting.busy_reply_threshold.load(Ordering::Relaxed);
+
+        config.requirepass = self.acl.requirepass();
+        config.acllog_max_len = self.acl.log_max_len.load(Ordering::Relaxed);
+
+        config.slowlog_log_slower_than = self.slowlog.log_slower_than.load(Ordering::Relaxed);
+        config.slowlog_max_len = self.slowlog.max_len.load(Ordering::Relaxed);
+
+        config.latency_monitor_threshold = self.latency.threshold.load(Ordering::Relaxed);
+
+        config
+    }
+
+    /// Handle everything recorded by the storage while running command(s), should be called after the
+    /// storage lock is released.
+    ///
+    /// The keys modified are invalidated for the clients tracking them, other than for the client that
+    /// modified them with `NOLOOP`.
+    pub fn apply_storage_effects(&self, effects: StorageEffects, client: &Client) {
+        self.persistence.add_changes(effects.changes);
+        if !effects.modified.is_empty() {
+            self.tracking
+                .invalidate(&effects.modified, client.id, &self.clients);
+        }
+        self.stats
+            .keyspace_hits
+            .fetch_add(effects.hits, Ordering::Relaxed);
+        self.stats
+            .keyspace_misses
+            .fetch_add(effects.misses, Ordering::Relaxed);
+
+        if !effects.events.is_empty() {
+            self.notifier.publish(effects.events, &self.pubsub);
+        }
+    }
+
+    /// Pass a write command that modified the storage on to the append only file and the followers.
+    ///
+    /// Should be called while the storage lock is still held, so commands are passed on in the same order
+    /// they're run.
+    pub fn propagate(&self, command: &[RespType]) {
+        self.aof.append(command, &self.latency);
+        self.replication.propagate(command);
+    }
+
+    /// Pass the write commands on, wrapped in a transaction if there's more than one, so they're run
+    /// atomically, e.g. the write commands of a transaction or a script.
+    pub fn propagate_transaction(&self, written: &[Vec<RespType>]) {
+        if written.len() > 1 {
+            self.propagate(&[RespType::BulkString("MULTI".into())]);
+        }
+
+        for command in written {
+            self.propagate(command);
+        }
+
+        if written.len() > 1 {
+            self.propagate(&[RespType::BulkString("EXEC".into())]);
+        }
+    }
+
+    /// Flush the append only file to the disk, see [`Aof::fsync`].
+    ///
+    /// The replication offset is read before flushing, so every write command up to it is appended
+    /// already, as write commands are appended before being passed on to the followers.
+    pub fn fsync_aof(&self, force: bool) {
+        self.aof.fsync(self.replication.offset(), force);
+    }
+
+    /// Take a point-in-time copy of the storage along with the function libraries, should be called while
+    /// the storage is locked, so nothing is modified in between.
+    pub fn snapshot(&self, storage: &Storage) -> Snapshot {
+        Snapshot {
+            values: storage.values(),
+            functions: self.scripting.library_codes(),
+            ..Snapshot::default()
+        }
+    }
+
+    /// Replace the storage and the function libraries with the snapshot, should be called while the
+    /// storage is locked.
+    ///
+    /// Keys skipped while loading the snapshot, and the expiry of every key, are reported as they are lost.
+    pub fn restore_snapshot(&self, storage: &mut Storage, snapshot: Snapshot) {
+        if snapshot.skipped > 0 {
+            println!(
+                "[Server] Skipped {} key(s) of the snapshot, only strings and hashes of the first \
+                 database are supported",
+                snapshot.skipped
+            );
+        }
+        if !snapshot.expire_at.is_empty() {
+            println!(
+                "[Server] Dropped the expiry of {} key(s) of the snapshot, keys cannot expire yet",
+                snapshot.expire_at.len()
+            );
+        }
+
+        storage.replace(snapshot.values);
+        self.tracking.invalidate_all(&self.clients);
+
+        if let Err(err) = self
+            .scripting
+            .restore_libraries(&snapshot.functions, RestorePolicy::Flush)
+        {
+            println!("[Server] Failed to load the function libraries: {:#?}", err);
+        }
+    }
+
+    /// Load the append only file when it's enabled, otherwise the snapshot file.
+    ///
+    /// Meant to be called once on startup, before any client is connected.
+    pub fn load(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.aof.is_enabled() {
+            let dir = self.aof.dir(self);
+            let manifest = aof::load(self, &dir)?;
+
+            self.aof.open(&dir, manifest.unwrap_or_default())?;
+
+            return Ok(());
+        }
+
+        self.load_snapshot()
+    }
+
+    /// Replace the storage with the snapshot file, if there's one.
+    pub fn load_snapshot(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(snapshot) = self.persistence.load()? {
+            match self.storage.write() {
+                Ok(mut storage_locked) => self.restore_snapshot(&mut storage_locked, snapshot),
+                Err(err) => return Err(format!("poisoned storage: {}", err).into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl From<Storage> for Server {
+    fn from(storage: Storage) -> Self {
+        Self {
+            storage: RwLock::new(storage),
+            ..Self::default()
+        }
+    }
+}
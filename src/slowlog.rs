@@ -0,0 +1,226 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    client::Client,
+    commands::registry::{Command, CommandFlags},
+    resp::RespType,
+};
+
+/// Default of `slowlog-log-slower-than`, in microseconds.
+pub const DEFAULT_LOG_SLOWER_THAN: i64 = 10000;
+
+/// Default of `slowlog-max-len`.
+pub const DEFAULT_MAX_LEN: usize = 128;
+
+/// How many arguments of a command are kept in an entry, the same as Redis.
+const MAX_ARGS: usize = 32;
+
+/// How many bytes of an argument are kept in an entry, the same as Redis.
+const MAX_ARG_LEN: usize = 128;
+
+/// Command that took longer than `slowlog-log-slower-than`, shown by `SLOWLOG GET`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlowlogEntry {
+    pub id: u64,
+    /// When the command ran, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// How long the command took, in microseconds.
+    pub duration: u64,
+    /// The command along with its arguments, truncated so a huge command can't take all the memory.
+    pub args: Vec<String>,
+    pub client_addr: String,
+    pub client_name: String,
+}
+
+/// Log of the slowest commands, only the latest `slowlog-max-len` entries are kept.
+#[derive(Debug)]
+pub struct Slowlog {
+    /// Newest entry first.
+    entries: Mutex<VecDeque<SlowlogEntry>>,
+    next_id: AtomicU64,
+    /// `slowlog-log-slower-than` in microseconds, a negative value disables the log, and 0 logs every
+    /// command.
+    pub log_slower_than: AtomicI64,
+    /// `slowlog-max-len`.
+    pub max_len: AtomicUsize,
+}
+
+impl Default for Slowlog {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::new()),
+            next_id: AtomicU64::new(0),
+            log_slower_than: AtomicI64::new(DEFAULT_LOG_SLOWER_THAN),
+            max_len: AtomicUsize::new(DEFAULT_MAX_LEN),
+        }
+    }
+}
+
+impl Slowlog {
+    /// Log the command if it took longer than `slowlog-log-slower-than`.
+    pub fn record(
+        &self,
+        command: &dyn Command,
+        command_arr: &[RespType],
+        client: &Client,
+        duration: Duration,
+    ) {
+        let log_slower_than = self.log_slower_than.load(Ordering::Relaxed);
+        let duration = duration.as_micros() as u64;
+        if log_slower_than < 0
+            || duration < log_slower_than as u64
+            || command.flags().contains(CommandFlags::SKIP_SLOWLOG)
+        {
+            return;
+        }
+
+        let args = loggable_args(command_arr);
+        let argc = args.len();
+        let args = args
+            .into_iter()
+            .take(MAX_ARGS)
+            .enumerate()
+            .map(|(index, arg)| {
+                if argc > MAX_ARGS && index == MAX_ARGS - 1 {
+                    format!("... ({} more arguments)", argc - MAX_ARGS + 1)
+                } else {
+                    truncate(arg)
+                }
+            })
+            .collect();
+
+        let entry = SlowlogEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            duration,
+            args,
+            client_addr: client.addr.clone(),
+            client_name: client.name.clone(),
+        };
+
+        let max_len = self.max_len.load(Ordering::Relaxed);
+        let mut entries = self.lock_entries();
+        entries.push_front(entry);
+        entries.truncate(max_len);
+    }
+
+    /// The `count` newest entries, newest first, or every entry when there's no count.
+    pub fn entries(&self, count: Option<usize>) -> Vec<SlowlogEntry> {
+        let entries = self.lock_entries();
+
+        entries
+            .iter()
+            .take(count.unwrap_or(entries.len()))
+            .cloned()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock_entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock_entries().is_empty()
+    }
+
+    pub fn reset(&self) {
+        self.lock_entries().clear();
+    }
+
+    /// Apply `slowlog-max-len` to the entries already logged.
+    pub fn set_max_len(&self, max_len: usize) {
+        self.max_len.store(max_len, Ordering::Relaxed);
+        self.lock_entries().truncate(max_len);
+    }
+
+    fn lock_entries(&self) -> MutexGuard<'_, VecDeque<SlowlogEntry>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// The command along with its arguments as they're logged by `SLOWLOG` and `MONITOR`, with the secrets
+/// replaced by `(redacted)`, e.g. the password of `AUTH`, the same as Redis.
+pub fn loggable_args(command_arr: &[RespType]) -> Vec<String> {
+    let mut args = command_arr
+        .iter()
+        .map(|arg| match arg {
+            RespType::BulkString(arg) => arg.clone(),
+            arg => format!("{:?}", arg),
+        })
+        .collect::<Vec<_>>();
+
+    let name = args
+        .first()
+        .map(|arg| arg.to_lowercase())
+        .unwrap_or_default();
+    let subcommand = args
+        .get(1)
+        .map(|arg| arg.to_lowercase())
+        .unwrap_or_default();
+    let redacted = match (name.as_str(), subcommand.as_str()) {
+        ("auth", _) => (1..args.len()).collect(),
+        ("acl", "setuser") => (3..args.len()).collect(),
+        // NOTE: Only the username and password following `AUTH` are secrets
+        ("hello", _) => match args.iter().position(|arg| arg.eq_ignore_ascii_case("auth")) {
+            Some(index) => (index + 1..args.len().min(index + 3)).collect(),
+            None => Vec::new(),
+        },
+        ("config", "set") => (2..args.len())
+            .step_by(2)
+            .filter(|index| args[*index].eq_ignore_ascii_case("requirepass"))
+            .map(|index| index + 1)
+            .filter(|index| *index < args.len())
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    for index in redacted {
+        args[index] = "(redacted)".into();
+    }
+
+    args
+}
+
+/// Keep the first [`MAX_ARG_LEN`] bytes of the argument, the same as Redis, as strings hold one
+/// character per byte, see [`crate::resp::string_to_bytes`].
+fn truncate(arg: String) -> String {
+    let len = arg.chars().count();
+    if len <= MAX_ARG_LEN {
+        return arg;
+    }
+
+    format!(
+        "{}... ({} more bytes)",
+        arg.chars().take(MAX_ARG_LEN).collect::<String>(),
+        len - MAX_ARG_LEN
+    )
+}
+
+#[cfg(test)]
+mod slowlog_tests {
+    use super::{truncate, MAX_ARG_LEN};
+
+    #[test]
+    fn truncates_by_byte() {
+        let arg = "\u{e9}".repeat(MAX_ARG_LEN + 3);
+        assert_eq!(
+            truncate(arg),
+            format!("{}... (3 more bytes)", "\u{e9}".repeat(MAX_ARG_LEN))
+        );
+
+        let arg = "\u{e9}".repeat(MAX_ARG_LEN);
+        assert_eq!(truncate(arg.clone()), arg);
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::resp::RespType;
+
+/// How many samples of the number of commands per second are averaged into `instantaneous_ops_per_sec`.
+const OPS_SAMPLES: usize = 5;
+
+/// How many kinds of errors are counted, the same as Redis, so errors can't take all the memory.
+const MAX_ERROR_KINDS: usize = 128;
+
+/// How many buckets the latency of a command is counted in, each twice as long as the previous one, from
+/// 1024 nanoseconds up to about a second, the same range as Redis.
+const LATENCY_BUCKETS: usize = 21;
+
+/// Calls of a command, shown by `INFO commandstats`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CommandStats {
+    pub calls: u64,
+    /// Total time spent running the command, in microseconds.
+    pub usec: u64,
+    /// Calls refused before running the command, e.g. with the wrong number of arguments.
+    pub rejected_calls: u64,
+    /// Calls that ran but replied with an error.
+    pub failed_calls: u64,
+    /// Number of calls by how long they took, see [`CommandStats::latency_histogram`].
+    latencies: [u64; LATENCY_BUCKETS],
+}
+
+impl CommandStats {
+    /// Cumulative number of calls that took at most each duration in microseconds, shown by `LATENCY
+    /// HISTOGRAM`, a duration is only listed when there's more calls than for the previous one.
+    ///
+    /// NOTE: Unlike the HDR histograms of Redis, durations are only counted to the next power of 2
+    pub fn latency_histogram(&self) -> Vec<(u64, u64)> {
+        let mut cumulative = 0;
+
+        self.latencies
+            .iter()
+            .enumerate()
+            .filter_map(|(bucket, calls)| {
+                cumulative += calls;
+                (*calls > 0).then_some(((1024 << bucket) / 1000, cumulative))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct OpsSamples {
+    last_time: Instant,
+    last_commands: u64,
+    samples: VecDeque<f64>,
+}
+
+/// Statistics of the server collected by the command dispatcher, shown by `INFO`.
+///
+/// Every counter is cleared by `CONFIG RESETSTAT`, other than the ones describing the current state,
+/// e.g. `connected_clients`.
+#[derive(Debug)]
+pub struct Stats {
+    pub started: Instant,
+    pub connected_clients: AtomicU64,
+    /// Number of connections accepted, from every listener.
+    pub total_connections_received: AtomicU64,
+    /// Number of commands run, including the ones run by transactions and scripts.
+    pub total_commands_processed: AtomicU64,
+    /// Number of error replies, including the commands refused before running.
+    pub total_error_replies: AtomicU64,
+    pub keyspace_hits: AtomicU64,
+    pub keyspace_misses: AtomicU64,
+    commands: Mutex<HashMap<String, CommandStats>>,
+    /// Number of error replies by the first word of the error, e.g. `ERR` or `WRONGTYPE`.
+    errors: Mutex<HashMap<String, u64>>,
+    ops: Mutex<OpsSamples>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            connected_clients: AtomicU64::new(0),
+            total_connections_received: AtomicU64::new(0),
+            total_commands_processed: AtomicU64::new(0),
+            total_error_replies: AtomicU64::new(0),
+            keyspace_hits: AtomicU64::new(0),
+            keyspace_misses: AtomicU64::new(0),
+            commands: Mutex::new(HashMap::new()),
+            errors: Mutex::new(HashMap::new()),
+            ops: Mutex::new(OpsSamples {
+                last_time: Instant::now(),
+                last_commands: 0,
+                samples: VecDeque::new(),
+            }),
+        }
+    }
+}
+
+impl Stats {
+    /// Record a command that ran, along with its reply.
+    pub fn record_call(&self, command: &str, duration: Duration, reply: &RespType) {
+        self.total_commands_processed
+            .fetch_add(1, Ordering::Relaxed);
+
+        let failed = self.record_error(reply);
+        let mut commands = self.lock_commands();
+        let stats = commands.entry(command.to_string()).or_default();
+        stats.calls += 1;
+        stats.usec += duration.as_micros() as u64;
+        let nanos = duration.as_nanos().saturating_sub(1) >> 10;
+        let bucket = (u128::BITS - nanos.leading_zeros()) as usize;
+        stats.latencies[bucket.min(LATENCY_BUCKETS - 1)] += 1;
+        if failed {
+            stats.failed_calls += 1;
+        }
+    }
+
+    /// Record a command refused before running, along with the error it got.
+    pub fn record_rejected(&self, command: &str, reply: &RespType) {
+        self.record_error(reply);
+        self.lock_commands()
+            .entry(command.to_string())
+            .or_default()
+            .rejected_calls += 1;
+    }
+
+    /// Count the reply when it's an error, returning whether it's one.
+    pub fn record_error(&self, reply: &RespType) -> bool {
+        let message = match reply {
+            RespType::Error(message) => message,
+            _ => return false,
+        };
+
+        self.total_error_replies.fetch_add(1, Ordering::Relaxed);
+
+        let kind = message.split(' ').next().unwrap_or_default();
+        let mut errors = self
+            .errors
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(count) = errors.get_mut(kind) {
+            *count += 1;
+        } else if errors.len() < MAX_ERROR_KINDS {
+            errors.insert(kind.to_string(), 1);
+        }
+
+        true
+    }
+
+    /// Stats of every command called at least once, sorted by the name of the command.
+    pub fn command_stats(&self) -> Vec<(String, CommandStats)> {
+        let mut commands = self
+            .lock_commands()
+            .iter()
+            .map(|(name, stats)| (name.clone(), *stats))
+            .collect::<Vec<_>>();
+        commands.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        commands
+    }
+
+    /// Number of error replies of every kind of error, sorted by the kind.
+    pub fn error_stats(&self) -> Vec<(String, u64)> {
+        let mut errors = self
+            .errors
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .map(|(kind, count)| (kind.clone(), *count))
+            .collect::<Vec<_>>();
+        errors.sort();
+
+        errors
+    }
+
+    /// Sample the number of commands run per second since the last sample, meant to be called every
+    /// second or so, see [`Stats::instantaneous_ops_per_sec`].
+    pub fn sample_ops(&self) {
+        let commands = self.total_commands_processed.load(Ordering::Relaxed);
+        let mut ops = self
+            .ops
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let elapsed = ops.last_time.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            let per_second = commands.saturating_sub(ops.last_commands) as f64 / elapsed;
+
+            ops.samples.push_back(per_second);
+            if ops.samples.len() > OPS_SAMPLES {
+                ops.samples.pop_front();
+            }
+        }
+
+        ops.last_time = Instant::now();
+        ops.last_commands = commands;
+    }
+
+    /// Average of the last samples of the number of commands run per second.
+    pub fn instantaneous_ops_per_sec(&self) -> u64 {
+        let ops = self
+            .ops
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if ops.samples.is_empty() {
+            return 0;
+        }
+
+        (ops.samples.iter().sum::<f64>() / ops.samples.len() as f64).round() as u64
+    }
+
+    pub fn reset(&self) {
+        for counter in [
+            &self.total_connections_received,
+            &self.total_commands_processed,
+            &self.total_error_replies,
+            &self.keyspace_hits,
+            &self.keyspace_misses,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+
+        self.lock_commands().clear();
+        self.errors
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
+
+        let mut ops = self
+            .ops
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        ops.samples.clear();
+        ops.last_time = Instant::now();
+        ops.last_commands = 0;
+    }
+
+    fn lock_commands(&self) -> std::sync::MutexGuard<'_, HashMap<String, CommandStats>> {
+        self.commands
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
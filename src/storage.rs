@@ -1,7 +1,228 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::notification::{KeyspaceEvent, NotifyFlags};
 
 #[derive(Debug, Clone)]
 pub enum StorageType {
     String(String),
     HashMap(HashMap<String, String>),
 }
+
+/// Every value in the storage.
+///
+/// The storage values are kept in a persistent map, so taking a copy of them is cheap, and the copy can
+/// be read (e.g. written into a file) while the storage keeps on being modified.
+pub type Values = im::HashMap<String, StorageType>;
+
+/// Point-in-time copy of every value in the storage, along with the code of every function library, as
+/// they're saved along with the storage.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub values: Values,
+    pub functions: Vec<String>,
+    /// Unix time (in milliseconds) keys expire at, as keys cannot expire yet, these are only kept from a
+    /// loaded snapshot file, and dropped once the snapshot is restored into the storage.
+    pub expire_at: HashMap<String, u64>,
+    /// Number of keys in a loaded snapshot file that are not part of the snapshot, see
+    /// [`crate::rdb::decode_snapshot`].
+    pub skipped: usize,
+}
+
+/// Modification version of a key that is being watched by at least one client.
+#[derive(Debug)]
+struct WatchedKey {
+    version: u64,
+    watchers: usize,
+}
+
+/// Everything recorded by the storage while running command(s), to be handled by the command dispatcher
+/// once the storage lock is released.
+#[derive(Debug, Default)]
+pub struct StorageEffects {
+    /// Keyspace events to be published.
+    pub events: Vec<KeyspaceEvent>,
+    /// Number of modifications made to the storage.
+    pub changes: u64,
+    /// Every key modified, to be invalidated for the clients tracking them, see [`crate::tracking::Tracking`].
+    pub modified: Vec<String>,
+    /// Number of keys found by read commands, see [`Storage::lookup_read`].
+    pub hits: u64,
+    /// Number of keys not found by read commands.
+    pub misses: u64,
+}
+
+/// Key value storage shared by every connection.
+///
+/// Every modification goes through [`Storage::insert`] or [`Storage::remove`], so the storage can keep
+/// track of the modification version of keys that are `WATCH`-ed. Versions are only kept for keys with
+/// at least one watcher, keys nobody is watching does not cost anything extra.
+///
+/// Keyspace events are recorded with [`Storage::notify`], and along with the number of modifications,
+/// handled by the command dispatcher once the command is done, see [`Storage::take_effects`].
+#[derive(Debug, Default)]
+pub struct Storage {
+    values: Values,
+    watched: HashMap<String, WatchedKey>,
+    last_version: u64,
+    // NOTE: Behind a mutex, as read only commands need to record key miss events as well
+    effects: Mutex<StorageEffects>,
+}
+
+impl Storage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&StorageType> {
+        self.values.get(key)
+    }
+
+    /// Get the value of a key read by a read command, counted as a keyspace hit or miss, a miss is also
+    /// notified as a `keymiss` event.
+    pub fn lookup_read(&self, key: &str) -> Option<&StorageType> {
+        let value = self.values.get(key);
+
+        if let Ok(mut effects) = self.effects.lock() {
+            if value.is_some() {
+                effects.hits += 1;
+            } else {
+                effects.misses += 1;
+            }
+        }
+        if value.is_none() {
+            self.notify(NotifyFlags::KEY_MISS, "keymiss", key);
+        }
+
+        value
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.values.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: String, value: StorageType) -> Option<StorageType> {
+        self.touch(&key);
+
+        if !self.values.contains_key(&key) {
+            self.notify(NotifyFlags::NEW, "new", &key);
+        }
+
+        self.values.insert(key, value)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<StorageType> {
+        let removed = self.values.remove(key);
+        if removed.is_some() {
+            self.touch(key);
+        }
+
+        removed
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Take a point-in-time copy of every value, see [`Values`].
+    pub fn values(&self) -> Values {
+        self.values.clone()
+    }
+
+    /// Replace every value, e.g. with a snapshot loaded from the leader, keeping the keys that are being
+    /// watched, which are all considered modified.
+    pub fn replace(&mut self, values: Values) {
+        self.values = values;
+
+        for watched in self.watched.values_mut() {
+            self.last_version += 1;
+            watched.version = self.last_version;
+        }
+    }
+
+    /// Start watching a key, returning the current modification version of the key.
+    ///
+    /// Every call should be paired with a call to [`Storage::unwatch`], otherwise the version of the key
+    /// will be kept around forever.
+    pub fn watch(&mut self, key: &str) -> u64 {
+        let watched = self.watched.entry(key.to_string()).or_insert(WatchedKey {
+            version: 0,
+            watchers: 0,
+        });
+        watched.watchers += 1;
+
+        watched.version
+    }
+
+    pub fn unwatch(&mut self, key: &str) {
+        if let Some(watched) = self.watched.get_mut(key) {
+            watched.watchers -= 1;
+
+            if watched.watchers == 0 {
+                self.watched.remove(key);
+            }
+        }
+    }
+
+    /// Current modification version of a watched key, unwatched keys are always on version 0.
+    pub fn version(&self, key: &str) -> u64 {
+        self.watched.get(key).map_or(0, |watched| watched.version)
+    }
+
+    /// Record a keyspace event, to be published once the current command is done.
+    pub fn notify(&self, class: NotifyFlags, event: &'static str, key: &str) {
+        if let Ok(mut effects) = self.effects.lock() {
+            effects.events.push(KeyspaceEvent {
+                class,
+                event,
+                key: key.to_string(),
+            });
+        }
+    }
+
+    /// Number of modifications recorded since the last call to [`Storage::take_effects`].
+    pub fn pending_changes(&self) -> u64 {
+        self.effects.lock().map_or(0, |effects| effects.changes)
+    }
+
+    /// Take everything recorded since the last call, leaving nothing behind.
+    pub fn take_effects(&self) -> StorageEffects {
+        self.effects
+            .lock()
+            .map(|mut effects| std::mem::take(&mut *effects))
+            .unwrap_or_default()
+    }
+
+    fn touch(&mut self, key: &str) {
+        if let Ok(effects) = self.effects.get_mut() {
+            effects.changes += 1;
+            effects.modified.push(key.to_string());
+        }
+
+        if let Some(watched) = self.watched.get_mut(key) {
+            self.last_version += 1;
+            watched.version = self.last_version;
+        }
+    }
+}
+
+impl From<HashMap<String, StorageType>> for Storage {
+    fn from(values: HashMap<String, StorageType>) -> Self {
+        Self {
+            values: values.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+}
+
+impl From<Values> for Storage {
+    fn from(values: Values) -> Self {
+        Self {
+            values,
+            ..Self::default()
+        }
+    }
+}
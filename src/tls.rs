@@ -0,0 +1,274 @@
+use std::{fmt::Display, net::TcpStream, path::PathBuf, sync::Arc};
+
+use rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig, ServerConnection, StreamOwned,
+};
+
+/// Stream of a client connected to the TLS port, encrypted and decrypted transparently.
+///
+/// The handshake is done on the first read, so a read timeout on the TCP stream is kept working.
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+/// Whether the clients connecting to the TLS port have to send a certificate, from `tls-auth-clients`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TlsAuthClients {
+    /// Every client has to send a certificate signed by the CA.
+    #[default]
+    Yes,
+    /// Certificates sent by the clients are ignored.
+    No,
+    /// Clients may not send a certificate, but the ones sent have to be signed by the CA.
+    Optional,
+}
+
+impl TlsAuthClients {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "yes" => Some(Self::Yes),
+            "no" => Some(Self::No),
+            "optional" => Some(Self::Optional),
+            _ => None,
+        }
+    }
+}
+
+impl Display for TlsAuthClients {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Yes => write!(f, "yes"),
+            Self::No => write!(f, "no"),
+            Self::Optional => write!(f, "optional"),
+        }
+    }
+}
+
+/// Configuration of the TLS port, every file is in the PEM format.
+#[derive(Debug, Default, Clone)]
+pub struct TlsConfig {
+    /// Port to listen on for TLS connections, from `tls-port`, disabled when it's 0.
+    pub port: u16,
+    /// Certificate (chain) of the server, from `tls-cert-file`.
+    pub cert_file: Option<PathBuf>,
+    /// Private key of the certificate of the server, from `tls-key-file`.
+    pub key_file: Option<PathBuf>,
+    /// CA certificate(s) used to verify the certificates of the clients, from `tls-ca-cert-file`.
+    pub ca_cert_file: Option<PathBuf>,
+    pub auth_clients: TlsAuthClients,
+}
+
+impl TlsConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.port != 0
+    }
+
+    /// Load the certificates and the key, to be shared by every TLS connection.
+    pub fn server_config(&self) -> Result<Arc<ServerConfig>, String> {
+        let cert_file = self
+            .cert_file
+            .as_ref()
+            .ok_or("tls-cert-file is needed to use TLS")?;
+        let key_file = self
+            .key_file
+            .as_ref()
+            .ok_or("tls-key-file is needed to use TLS")?;
+
+        let certs = CertificateDer::pem_file_iter(cert_file)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|err| format!("Failed to load {}: {}", cert_file.display(), err))?;
+        let key = PrivateKeyDer::from_pem_file(key_file)
+            .map_err(|err| format!("Failed to load {}: {}", key_file.display(), err))?;
+
+        let builder =
+            ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(|err| err.to_string())?;
+
+        let builder = match self.auth_clients {
+            TlsAuthClients::No => builder.with_no_client_auth(),
+            TlsAuthClients::Yes | TlsAuthClients::Optional => {
+                let ca_cert_file = self
+                    .ca_cert_file
+                    .as_ref()
+                    .ok_or("tls-ca-cert-file is needed to authenticate the clients")?;
+
+                let mut roots = RootCertStore::empty();
+                for cert in CertificateDer::pem_file_iter(ca_cert_file)
+                    .map_err(|err| format!("Failed to load {}: {}", ca_cert_file.display(), err))?
+                {
+                    let cert = cert.map_err(|err| {
+                        format!("Failed to load {}: {}", ca_cert_file.display(), err)
+                    })?;
+                    roots.add(cert).map_err(|err| {
+                        format!("Invalid CA certificate {}: {}", ca_cert_file.display(), err)
+                    })?;
+                }
+
+                let mut verifier = WebPkiClientVerifier::builder_with_provider(
+                    Arc::new(roots),
+                    Arc::new(rustls::crypto::ring::default_provider()),
+                );
+                if self.auth_clients == TlsAuthClients::Optional {
+                    verifier = verifier.allow_unauthenticated();
+                }
+
+                builder.with_client_cert_verifier(verifier.build().map_err(|err| err.to_string())?)
+            }
+        };
+
+        builder
+            .with_single_cert(certs, key)
+            .map(Arc::new)
+            .map_err(|err| format!("Invalid certificate or key: {}", err))
+    }
+}
+
+/// Wrap a client connected to the TLS port, see [`TlsStream`].
+pub fn accept(stream: TcpStream, config: Arc<ServerConfig>) -> std::io::Result<TlsStream> {
+    let connection = ServerConnection::new(config).map_err(std::io::Error::other)?;
+
+    Ok(StreamOwned::new(connection, stream))
+}
+
+#[cfg(test)]
+mod tls_tests {
+    use std::{
+        fs,
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        path::Path,
+        sync::Arc,
+        thread,
+    };
+
+    use rcgen::{
+        BasicConstraints, Certificate, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+    use rustls::{
+        pki_types::{pem::PemObject, PrivateKeyDer, ServerName},
+        ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+    };
+
+    use super::{accept, TlsAuthClients, TlsConfig};
+    use crate::{handle_command_stream, server::Server};
+
+    /// Self-signed CA, along with the certificates it signed for the server and for a client.
+    struct Certificates {
+        ca: Certificate,
+        client: (Certificate, KeyPair),
+    }
+
+    fn generate_certificates(dir: &Path) -> Certificates {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let server_key = KeyPair::generate().unwrap();
+        let server = CertificateParams::new(vec!["localhost".into()])
+            .unwrap()
+            .signed_by(&server_key, &ca, &ca_key)
+            .unwrap();
+
+        let client_key = KeyPair::generate().unwrap();
+        let mut client_params = CertificateParams::new(Vec::new()).unwrap();
+        client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let client = client_params.signed_by(&client_key, &ca, &ca_key).unwrap();
+
+        fs::write(dir.join("ca.crt"), ca.pem()).unwrap();
+        fs::write(dir.join("server.crt"), server.pem()).unwrap();
+        fs::write(dir.join("server.key"), server_key.serialize_pem()).unwrap();
+
+        Certificates {
+            ca,
+            client: (client, client_key),
+        }
+    }
+
+    /// Serve the connections of the listener through TLS, the same way the server does.
+    fn serve(config: &TlsConfig) -> u16 {
+        let server_config = config.server_config().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = Arc::new(Server::new());
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let stream = accept(stream, Arc::clone(&server_config)).unwrap();
+                let server = Arc::clone(&server);
+
+                thread::spawn(move || {
+                    let _ = handle_command_stream(stream, String::new(), server);
+                });
+            }
+        });
+
+        port
+    }
+
+    /// Send `PING` through TLS, with the client certificate if there's one.
+    fn ping(port: u16, certificates: &Certificates, with_cert: bool) -> std::io::Result<String> {
+        let mut roots = RootCertStore::empty();
+        roots.add(certificates.ca.der().clone()).unwrap();
+
+        let builder =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots);
+        let config = if with_cert {
+            let (cert, key) = &certificates.client;
+            builder
+                .with_client_auth_cert(
+                    vec![cert.der().clone()],
+                    PrivateKeyDer::from_pem_slice(key.serialize_pem().as_bytes()).unwrap(),
+                )
+                .unwrap()
+        } else {
+            builder.with_no_client_auth()
+        };
+
+        let connection =
+            ClientConnection::new(Arc::new(config), ServerName::try_from("localhost").unwrap())
+                .unwrap();
+        let mut stream = StreamOwned::new(connection, TcpStream::connect(("127.0.0.1", port))?);
+
+        stream.write_all(b"*1\r\n$4\r\nPING\r\n")?;
+        let mut reply = [0; 7];
+        stream.read_exact(&mut reply)?;
+
+        Ok(String::from_utf8_lossy(&reply).into())
+    }
+
+    #[test]
+    fn serves_clients_through_tls() {
+        let dir = std::env::temp_dir().join(format!("rust-eez-tls-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let certificates = generate_certificates(&dir);
+
+        let mut config = TlsConfig {
+            port: 1,
+            cert_file: Some(dir.join("server.crt")),
+            key_file: Some(dir.join("server.key")),
+            ca_cert_file: Some(dir.join("ca.crt")),
+            auth_clients: TlsAuthClients::Yes,
+        };
+        let port = serve(&config);
+        assert_eq!(ping(port, &certificates, true).unwrap(), "+PONG\r\n");
+        assert!(ping(port, &certificates, false).is_err());
+
+        config.auth_clients = TlsAuthClients::Optional;
+        let port = serve(&config);
+        assert_eq!(ping(port, &certificates, true).unwrap(), "+PONG\r\n");
+        assert_eq!(ping(port, &certificates, false).unwrap(), "+PONG\r\n");
+
+        config.ca_cert_file = None;
+        assert!(config.server_config().is_err());
+        config.auth_clients = TlsAuthClients::No;
+        let port = serve(&config);
+        assert_eq!(ping(port, &certificates, false).unwrap(), "+PONG\r\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::{
+    client::{Client, Clients},
+    commands::registry::{Command, CommandFlags},
+    resp::RespType,
+};
+
+/// Channel the invalidation messages are sent on to RESP2 clients, see [`TrackingOptions::redirect`].
+pub const INVALIDATE_CHANNEL: &str = "__redis__:invalidate";
+
+/// Options of a client tracking the keys it reads, set with `CLIENT TRACKING ON`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TrackingOptions {
+    /// Client receiving the invalidation messages instead, needed by RESP2 clients as they can't receive
+    /// pushes along with the replies, the messages are then sent on [`INVALIDATE_CHANNEL`].
+    pub redirect: Option<u64>,
+    /// Broadcasting mode, every key modified matching one of the prefixes is invalidated, whether the
+    /// client read it or not. Every key is matched when there's no prefix.
+    pub bcast: bool,
+    pub prefixes: Vec<String>,
+    /// Only the keys read right after `CLIENT CACHING yes` are tracked.
+    pub optin: bool,
+    /// Every key read is tracked, other than the ones read right after `CLIENT CACHING no`.
+    pub optout: bool,
+    /// Keys modified by the client itself aren't invalidated.
+    pub noloop: bool,
+}
+
+#[derive(Debug)]
+struct TrackingClient {
+    options: TrackingOptions,
+    /// Set once the client receiving the invalidation messages is gone.
+    broken_redirect: bool,
+}
+
+/// Server-assisted client side caching, telling the clients when the keys they cached are modified.
+///
+/// In the default mode, the keys read by a client are remembered, and forgotten once they're invalidated,
+/// so a client is only told once about a key until it reads the key again. In the broadcasting mode,
+/// nothing is remembered, and every key modified matching the prefixes of a client is invalidated.
+#[derive(Debug, Default)]
+pub struct Tracking {
+    clients: Mutex<HashMap<u64, TrackingClient>>,
+    /// Keys read by the clients in the default mode, to the clients that read them.
+    keys: Mutex<HashMap<String, HashSet<u64>>>,
+}
+
+impl Tracking {
+    /// Start tracking the keys read by the client, or update the options when it's already tracking.
+    pub fn enable(&self, client_id: u64, options: TrackingOptions) {
+        self.lock_clients().insert(
+            client_id,
+            TrackingClient {
+                options,
+                broken_redirect: false,
+            },
+        );
+    }
+
+    /// Stop tracking the keys read by the client, keys already remembered are forgotten once they're
+    /// modified.
+    pub fn disable(&self, client_id: u64) {
+        self.lock_clients().remove(&client_id);
+    }
+
+    pub fn is_redirect_broken(&self, client_id: u64) -> bool {
+        self.lock_clients()
+            .get(&client_id)
+            .is_some_and(|tracking| tracking.broken_redirect)
+    }
+
+    /// Remember the keys read by the command for the client, if it's tracking them in the default mode.
+    pub fn remember_keys(&self, client: &Client, command: &dyn Command, command_arr: &[RespType]) {
+        let options = match &client.tracking {
+            Some(options) if !options.bcast => options,
+            _ => return,
+        };
+        if !command.flags().contains(CommandFlags::READONLY)
+            || (options.optin && client.tracking_caching != Some(true))
+            || (options.optout && client.tracking_caching == Some(false))
+        {
+            return;
+        }
+
+        let positions = command
+            .key_spec()
+            .positions(command_arr)
+            .unwrap_or_default();
+        let mut keys = self.lock_keys();
+        for position in positions {
+            if let Some(RespType::BulkString(key)) = command_arr.get(position) {
+                keys.entry(key.clone()).or_default().insert(client.id);
+            }
+        }
+    }
+
+    /// Tell the clients tracking the keys that they were modified by the client `writer_id`.
+    pub fn invalidate(&self, modified: &[String], writer_id: u64, clients: &Clients) {
+        let mut tracking_clients = self.lock_clients();
+        if tracking_clients.is_empty() {
+            return;
+        }
+
+        let mut invalidated = HashMap::<u64, Vec<String>>::new();
+        {
+            let mut keys = self.lock_keys();
+            for key in modified.iter().collect::<HashSet<_>>() {
+                for client_id in keys.remove(key).unwrap_or_default() {
+                    invalidated.entry(client_id).or_default().push(key.clone());
+                }
+
+                for (client_id, tracking) in tracking_clients.iter() {
+                    let options = &tracking.options;
+                    if options.bcast
+                        && (options.prefixes.is_empty()
+                            || options
+                                .prefixes
+                                .iter()
+                                .any(|prefix| key.starts_with(prefix)))
+                    {
+                        invalidated.entry(*client_id).or_default().push(key.clone());
+                    }
+                }
+            }
+        }
+
+        for (client_id, keys) in invalidated {
+            let tracking = match tracking_clients.get_mut(&client_id) {
+                Some(tracking) => tracking,
+                // NOTE: Keys read before the client stopped tracking are only forgotten here
+                None => continue,
+            };
+            if tracking.options.noloop && client_id == writer_id {
+                continue;
+            }
+
+            let keys = RespType::Array(keys.into_iter().map(RespType::BulkString).collect());
+            send(client_id, tracking, keys, clients);
+        }
+    }
+
+    /// Tell every tracking client that every key was modified, e.g. when the storage is replaced, with a
+    /// null in place of the keys, the same as Redis.
+    pub fn invalidate_all(&self, clients: &Clients) {
+        self.lock_keys().clear();
+
+        for (client_id, tracking) in self.lock_clients().iter_mut() {
+            send(*client_id, tracking, RespType::Null, clients);
+        }
+    }
+
+    fn lock_clients(&self) -> MutexGuard<'_, HashMap<u64, TrackingClient>> {
+        self.clients
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_keys(&self) -> MutexGuard<'_, HashMap<String, HashSet<u64>>> {
+        self.keys
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Send the invalidation message to the client, or to the client it's redirected to. The tracking
+/// client is told once when the client it's redirected to is gone.
+fn send(client_id: u64, tracking: &mut TrackingClient, keys: RespType, clients: &Clients) {
+    let message = vec![RespType::BulkString("invalidate".into()), keys];
+
+    match tracking.options.redirect {
+        Some(redirect) => {
+            if !clients.send(redirect, message) && !tracking.broken_redirect {
+                tracking.broken_redirect = true;
+                clients.send(
+                    client_id,
+                    vec![
+                        RespType::BulkString("tracking-redir-broken".into()),
+                        RespType::Integer(redirect as i64),
+                    ],
+                );
+            }
+        }
+        None => {
+            clients.send(client_id, message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tracking_tests {
+    use crate::{
+        commands::commands::handle_commands,
+        resp::RespType,
+        server::Server,
+        test_util::{command, connect},
+    };
+
+    fn invalidate(keys: &[&str]) -> Vec<RespType> {
+        vec![
+            RespType::BulkString("invalidate".into()),
+            RespType::Array(command(keys)),
+        ]
+    }
+
+    #[test]
+    fn invalidates_keys_read_by_tracking_clients() {
+        let server = Server::new();
+        let mut tracking = connect(&server);
+        let mut writer = connect(&server);
+        tracking.protocol = 3;
+        server.clients.register(&tracking);
+        server.clients.register(&writer);
+
+        for args in [
+            &["CLIENT", "TRACKING", "ON", "NOLOOP"][..],
+            &["GET", "read"],
+            &["SET", "read", "1"],
+            &["SET", "unread", "1"],
+        ] {
+            handle_commands(command(args), &mut tracking, &server);
+        }
+        assert_eq!(tracking.pending_messages(), Vec::new());
+
+        handle_commands(command(&["GET", "read"]), &mut tracking, &server);
+        handle_commands(command(&["SET", "read", "2"]), &mut writer, &server);
+        handle_commands(command(&["SET", "read", "3"]), &mut writer, &server);
+        assert_eq!(
+            tracking.pending_messages(),
+            vec![RespType::Push(invalidate(&["read"]))]
+        );
+    }
+
+    #[test]
+    fn redirects_broadcast_invalidations_to_resp2_subscribers() {
+        let server = Server::new();
+        let mut tracking = connect(&server);
+        let mut redirect = connect(&server);
+        server.clients.register(&tracking);
+        server.clients.register(&redirect);
+
+        handle_commands(
+            command(&["SUBSCRIBE", "__redis__:invalidate"]),
+            &mut redirect,
+            &server,
+        );
+        assert_eq!(
+            handle_commands(
+                command(&[
+                    "CLIENT",
+                    "TRACKING",
+                    "ON",
+                    "BCAST",
+                    "PREFIX",
+                    "user:",
+                    "REDIRECT",
+                    &redirect.id.to_string(),
+                ]),
+                &mut tracking,
+                &server,
+            ),
+            RespType::String("OK".into())
+        );
+        assert_eq!(
+            handle_commands(
+                command(&["CLIENT", "TRACKING", "ON", "BCAST", "PREFIX", "user:1"]),
+                &mut tracking,
+                &server,
+            ),
+            RespType::Error("ERR Prefix 'user:1' overlaps with an existing prefix 'user:'. Prefixes for a single client must not overlap.".into())
+        );
+
+        handle_commands(command(&["SET", "user:1", "a"]), &mut tracking, &server);
+        handle_commands(command(&["SET", "post:1", "a"]), &mut tracking, &server);
+
+        let mut message = invalidate(&["user:1"]);
+        message[0] = RespType::BulkString("message".into());
+        message.insert(1, RespType::BulkString("__redis__:invalidate".into()));
+        assert_eq!(redirect.pending_messages(), vec![RespType::Array(message)]);
+        assert_eq!(
+            handle_commands(command(&["CLIENT", "GETREDIR"]), &mut tracking, &server),
+            RespType::Integer(redirect.id as i64)
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    os::unix::{fs::PermissionsExt, net::UnixListener},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Socket file of the Unix socket listener, from `unixsocket`, removed once the server shuts down.
+///
+/// The file is removed when it's dropped, e.g. when `main` returns, early or not. Exiting the process
+/// skips the drop, so [`UnixSocketFile::remove`] should be called before that, e.g. on a signal.
+#[derive(Debug)]
+pub struct UnixSocketFile {
+    path: PathBuf,
+    removed: AtomicBool,
+}
+
+impl UnixSocketFile {
+    /// Bind the Unix socket, replacing the socket file left behind by a previous run if there's one, and
+    /// setting the permissions of the file from `unixsocketperm`.
+    pub fn bind(path: &Path, perm: Option<u32>) -> io::Result<(UnixListener, Self)> {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+
+        let listener = UnixListener::bind(path)?;
+        let file = Self {
+            path: path.to_path_buf(),
+            removed: AtomicBool::new(false),
+        };
+
+        if let Some(perm) = perm {
+            fs::set_permissions(path, fs::Permissions::from_mode(perm))?;
+        }
+
+        Ok((listener, file))
+    }
+
+    /// Remove the socket file, only the first call does anything.
+    pub fn remove(&self) {
+        if self.removed.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        if let Err(err) = fs::remove_file(&self.path) {
+            println!("Error removing the Unix socket: {:#?}", err);
+        }
+    }
+}
+
+impl Drop for UnixSocketFile {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+#[cfg(test)]
+mod unix_socket_tests {
+    use std::{
+        fs,
+        io::{Read, Write},
+        os::unix::{
+            fs::{FileTypeExt, PermissionsExt},
+            net::UnixStream,
+        },
+        sync::Arc,
+        thread,
+    };
+
+    use super::UnixSocketFile;
+    use crate::{handle_command_stream, server::Server};
+
+    #[test]
+    fn binds_with_permissions_and_removes_on_drop() {
+        let dir = std::env::temp_dir().join(format!("rust-eez-unix-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rust-eez.sock");
+
+        // Left behind by a previous run
+        fs::write(&path, "stale").unwrap();
+
+        let (listener, file) = UnixSocketFile::bind(&path, Some(0o700)).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o700);
+
+        let server = Arc::new(Server::new());
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle_command_stream(stream, String::new(), server).unwrap();
+        });
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        client.write_all(b"*1\r\n$4\r\nPING\r\n").unwrap();
+        let mut reply = [0u8; 7];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b"+PONG\r\n");
+
+        file.remove();
+        assert!(!path.exists());
+        // Replaced by something else in between, which is not removed a second time
+        fs::write(&path, "other").unwrap();
+        drop(file);
+        assert!(path.exists());
+
+        let (_listener, file) = UnixSocketFile::bind(&path, None).unwrap();
+        drop(file);
+        assert!(!path.exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}